@@ -27,6 +27,8 @@ class Not(object):
 
 StrOrNot = Union[str, Not]
 IntOrNot = Union[int, Not]
+BoolOrNot = Union[bool, Not]
+FloatOrNot = Union[float, Not]
 
 
 class Has(object):
@@ -190,6 +190,11 @@ def deserialize_prop(value, ty: "PropType"):
             return set([str(v) for v in value])
         else:
             return str(value)
+    if ty.primitive is PropPrimitive.Float:
+        if ty.is_set:
+            return set([float(v) for v in value])
+        else:
+            return float(value)
 
     raise NotImplementedError
 
@@ -1,6 +1,7 @@
 from __future__ import annotations
 
 import dataclasses
+import enum
 from typing import Mapping, Sequence, Union, cast
 
 from graplinc.grapl.api.graph.v1beta1.types_pb2 import (
@@ -19,6 +20,15 @@ from graplinc.grapl.api.graph.v1beta1.types_pb2 import (
 )
 from graplinc.grapl.api.graph.v1beta1.types_pb2 import IdentifiedNode as _IdentifiedNode
 from graplinc.grapl.api.graph.v1beta1.types_pb2 import IdStrategy as _IdStrategy
+from graplinc.grapl.api.graph.v1beta1.types_pb2 import (
+    ImmutableBoolProp as _ImmutableBoolProp,
+)
+from graplinc.grapl.api.graph.v1beta1.types_pb2 import (
+    ImmutableBytesProp as _ImmutableBytesProp,
+)
+from graplinc.grapl.api.graph.v1beta1.types_pb2 import (
+    ImmutableFloatProp as _ImmutableFloatProp,
+)
 from graplinc.grapl.api.graph.v1beta1.types_pb2 import (
     ImmutableIntProp as _ImmutableIntProp,
 )
@@ -44,9 +54,26 @@ from graplinc.grapl.api.graph.v1beta1.types_pb2 import (
 from graplinc.grapl.api.graph.v1beta1.types_pb2 import NodeProperty as _NodeProperty
 from graplinc.grapl.api.graph.v1beta1.types_pb2 import Session as _Session
 from graplinc.grapl.api.graph.v1beta1.types_pb2 import Static as _Static
+from graplinc.grapl.api.graph.v1beta1.types_pb2 import StrSetProp as _StrSetProp
+from graplinc.grapl.api.graph.v1beta1.types_pb2 import TimestampMerge as _TimestampMerge
+from graplinc.grapl.api.graph.v1beta1.types_pb2 import TimestampProp as _TimestampProp
+from graplinc.grapl.api.graph.v1beta1.types_pb2 import TimeUnit as _TimeUnit
 from python_proto import SerDe
 
 
+class TimeUnit(enum.Enum):
+    TIME_UNIT_UNSPECIFIED = "TIME_UNIT_UNSPECIFIED"
+    TIME_UNIT_SECONDS = "TIME_UNIT_SECONDS"
+    TIME_UNIT_MILLISECONDS = "TIME_UNIT_MILLISECONDS"
+    TIME_UNIT_WINDOWS_FILETIME = "TIME_UNIT_WINDOWS_FILETIME"
+
+
+class TimestampMerge(enum.Enum):
+    TIMESTAMP_MERGE_UNSPECIFIED = "TIMESTAMP_MERGE_UNSPECIFIED"
+    TIMESTAMP_MERGE_EARLIEST = "TIMESTAMP_MERGE_EARLIEST"
+    TIMESTAMP_MERGE_LATEST = "TIMESTAMP_MERGE_LATEST"
+
+
 @dataclasses.dataclass(frozen=True)
 class Session(SerDe):
     primary_key_properties: Sequence[str]
@@ -54,6 +81,7 @@ class Session(SerDe):
     create_time: int
     last_seen_time: int
     terminate_time: int
+    time_unit: TimeUnit
 
     @staticmethod
     def deserialize(bytes_: bytes) -> Session:
@@ -69,6 +97,7 @@ class Session(SerDe):
             create_time=proto_session.create_time,
             last_seen_time=proto_session.last_seen_time,
             terminate_time=proto_session.terminate_time,
+            time_unit=TimeUnit(_TimeUnit.Name(proto_session.time_unit)),
         )
 
     def into_proto(self) -> _Session:
@@ -79,6 +108,7 @@ class Session(SerDe):
         proto_session.create_time = self.create_time
         proto_session.last_seen_time = self.last_seen_time
         proto_session.terminate_time = self.terminate_time
+        proto_session.time_unit = _TimeUnit.Value(self.time_unit.value)
         return proto_session
 
 
@@ -302,6 +332,122 @@ class ImmutableStrProp(SerDe):
         return proto_immutable_str_prop
 
 
+@dataclasses.dataclass(frozen=True)
+class ImmutableBoolProp(SerDe):
+    prop: bool
+
+    @staticmethod
+    def deserialize(bytes_: bytes) -> ImmutableBoolProp:
+        proto_immutable_bool_prop = _ImmutableBoolProp()
+        proto_immutable_bool_prop.ParseFromString(bytes_)
+        return ImmutableBoolProp.from_proto(
+            proto_immutable_bool_prop=proto_immutable_bool_prop
+        )
+
+    @staticmethod
+    def from_proto(proto_immutable_bool_prop: _ImmutableBoolProp) -> ImmutableBoolProp:
+        return ImmutableBoolProp(prop=proto_immutable_bool_prop.prop)
+
+    def into_proto(self) -> _ImmutableBoolProp:
+        proto_immutable_bool_prop = _ImmutableBoolProp()
+        proto_immutable_bool_prop.prop = self.prop
+        return proto_immutable_bool_prop
+
+
+@dataclasses.dataclass(frozen=True)
+class ImmutableFloatProp(SerDe):
+    prop: float
+
+    @staticmethod
+    def deserialize(bytes_: bytes) -> ImmutableFloatProp:
+        proto_immutable_float_prop = _ImmutableFloatProp()
+        proto_immutable_float_prop.ParseFromString(bytes_)
+        return ImmutableFloatProp.from_proto(
+            proto_immutable_float_prop=proto_immutable_float_prop
+        )
+
+    @staticmethod
+    def from_proto(
+        proto_immutable_float_prop: _ImmutableFloatProp,
+    ) -> ImmutableFloatProp:
+        return ImmutableFloatProp(prop=proto_immutable_float_prop.prop)
+
+    def into_proto(self) -> _ImmutableFloatProp:
+        proto_immutable_float_prop = _ImmutableFloatProp()
+        proto_immutable_float_prop.prop = self.prop
+        return proto_immutable_float_prop
+
+
+@dataclasses.dataclass(frozen=True)
+class ImmutableBytesProp(SerDe):
+    prop: bytes
+
+    @staticmethod
+    def deserialize(bytes_: bytes) -> ImmutableBytesProp:
+        proto_immutable_bytes_prop = _ImmutableBytesProp()
+        proto_immutable_bytes_prop.ParseFromString(bytes_)
+        return ImmutableBytesProp.from_proto(
+            proto_immutable_bytes_prop=proto_immutable_bytes_prop
+        )
+
+    @staticmethod
+    def from_proto(
+        proto_immutable_bytes_prop: _ImmutableBytesProp,
+    ) -> ImmutableBytesProp:
+        return ImmutableBytesProp(prop=proto_immutable_bytes_prop.prop)
+
+    def into_proto(self) -> _ImmutableBytesProp:
+        proto_immutable_bytes_prop = _ImmutableBytesProp()
+        proto_immutable_bytes_prop.prop = self.prop
+        return proto_immutable_bytes_prop
+
+
+@dataclasses.dataclass(frozen=True)
+class TimestampProp(SerDe):
+    millis: int
+    merge: TimestampMerge
+
+    @staticmethod
+    def deserialize(bytes_: bytes) -> TimestampProp:
+        proto_timestamp_prop = _TimestampProp()
+        proto_timestamp_prop.ParseFromString(bytes_)
+        return TimestampProp.from_proto(proto_timestamp_prop=proto_timestamp_prop)
+
+    @staticmethod
+    def from_proto(proto_timestamp_prop: _TimestampProp) -> TimestampProp:
+        return TimestampProp(
+            millis=proto_timestamp_prop.millis,
+            merge=TimestampMerge(_TimestampMerge.Name(proto_timestamp_prop.merge)),
+        )
+
+    def into_proto(self) -> _TimestampProp:
+        proto_timestamp_prop = _TimestampProp()
+        proto_timestamp_prop.millis = self.millis
+        proto_timestamp_prop.merge = _TimestampMerge.Value(self.merge.value)
+        return proto_timestamp_prop
+
+
+@dataclasses.dataclass(frozen=True)
+class StrSetProp(SerDe):
+    props: Sequence[str]
+
+    @staticmethod
+    def deserialize(bytes_: bytes) -> StrSetProp:
+        proto_str_set_prop = _StrSetProp()
+        proto_str_set_prop.ParseFromString(bytes_)
+        return StrSetProp.from_proto(proto_str_set_prop=proto_str_set_prop)
+
+    @staticmethod
+    def from_proto(proto_str_set_prop: _StrSetProp) -> StrSetProp:
+        return StrSetProp(props=proto_str_set_prop.props)
+
+    def into_proto(self) -> _StrSetProp:
+        proto_str_set_prop = _StrSetProp()
+        for prop in self.props:
+            proto_str_set_prop.props.append(prop)
+        return proto_str_set_prop
+
+
 @dataclasses.dataclass(frozen=True)
 class NodeProperty(SerDe):
     property_: Union[
@@ -312,6 +458,11 @@ class NodeProperty(SerDe):
         DecrementOnlyIntProp,
         ImmutableIntProp,
         ImmutableStrProp,
+        ImmutableBoolProp,
+        ImmutableFloatProp,
+        ImmutableBytesProp,
+        TimestampProp,
+        StrSetProp,
     ]
 
     @staticmethod
@@ -360,6 +511,32 @@ class NodeProperty(SerDe):
             return NodeProperty(
                 property_=ImmutableStrProp.from_proto(proto_node_property.immutable_str)
             )
+        elif proto_node_property.HasField("immutable_bool"):
+            return NodeProperty(
+                property_=ImmutableBoolProp.from_proto(
+                    proto_node_property.immutable_bool
+                )
+            )
+        elif proto_node_property.HasField("immutable_float"):
+            return NodeProperty(
+                property_=ImmutableFloatProp.from_proto(
+                    proto_node_property.immutable_float
+                )
+            )
+        elif proto_node_property.HasField("immutable_bytes"):
+            return NodeProperty(
+                property_=ImmutableBytesProp.from_proto(
+                    proto_node_property.immutable_bytes
+                )
+            )
+        elif proto_node_property.HasField("timestamp"):
+            return NodeProperty(
+                property_=TimestampProp.from_proto(proto_node_property.timestamp)
+            )
+        elif proto_node_property.HasField("str_set"):
+            return NodeProperty(
+                property_=StrSetProp.from_proto(proto_node_property.str_set)
+            )
         else:
             raise Exception("Encountered unknown type")
 
@@ -393,6 +570,26 @@ class NodeProperty(SerDe):
             proto_node_property.immutable_str.CopyFrom(
                 cast(_ImmutableStrProp, self.property_.into_proto())
             )
+        elif type(self.property_) is ImmutableBoolProp:
+            proto_node_property.immutable_bool.CopyFrom(
+                cast(_ImmutableBoolProp, self.property_.into_proto())
+            )
+        elif type(self.property_) is ImmutableFloatProp:
+            proto_node_property.immutable_float.CopyFrom(
+                cast(_ImmutableFloatProp, self.property_.into_proto())
+            )
+        elif type(self.property_) is ImmutableBytesProp:
+            proto_node_property.immutable_bytes.CopyFrom(
+                cast(_ImmutableBytesProp, self.property_.into_proto())
+            )
+        elif type(self.property_) is TimestampProp:
+            proto_node_property.timestamp.CopyFrom(
+                cast(_TimestampProp, self.property_.into_proto())
+            )
+        elif type(self.property_) is StrSetProp:
+            proto_node_property.str_set.CopyFrom(
+                cast(_StrSetProp, self.property_.into_proto())
+            )
         else:
             raise Exception("Encountered unknown type")
         return proto_node_property
@@ -508,6 +705,7 @@ class Edge(SerDe):
     from_node_key: str
     to_node_key: str
     edge_name: str
+    properties: Mapping[str, NodeProperty]
 
     @staticmethod
     def deserialize(bytes_: bytes) -> Edge:
@@ -521,6 +719,10 @@ class Edge(SerDe):
             from_node_key=proto_edge.from_node_key,
             to_node_key=proto_edge.to_node_key,
             edge_name=proto_edge.edge_name,
+            properties={
+                k: NodeProperty.from_proto(proto_edge.properties[k])
+                for k in proto_edge.properties
+            },
         )
 
     def into_proto(self) -> _Edge:
@@ -528,6 +730,8 @@ class Edge(SerDe):
         proto_edge.from_node_key = self.from_node_key
         proto_edge.to_node_key = self.to_node_key
         proto_edge.edge_name = self.edge_name
+        for k, v in self.properties.items():
+            proto_edge.properties[k].CopyFrom(v.into_proto())
         return proto_edge
 
 
@@ -559,6 +763,7 @@ class MergedEdge(SerDe):
     to_uid: str
     to_node_key: str
     edge_name: str
+    properties: Mapping[str, NodeProperty]
 
     @staticmethod
     def deserialize(bytes_: bytes) -> MergedEdge:
@@ -574,6 +779,10 @@ class MergedEdge(SerDe):
             to_uid=proto_merged_edge.to_uid,
             to_node_key=proto_merged_edge.to_node_key,
             edge_name=proto_merged_edge.edge_name,
+            properties={
+                k: NodeProperty.from_proto(proto_merged_edge.properties[k])
+                for k in proto_merged_edge.properties
+            },
         )
 
     def into_proto(self) -> _MergedEdge:
@@ -583,6 +792,8 @@ class MergedEdge(SerDe):
         proto_merged_edge.to_uid = self.to_uid
         proto_merged_edge.to_node_key = self.to_node_key
         proto_merged_edge.edge_name = self.edge_name
+        for k, v in self.properties.items():
+            proto_merged_edge.properties[k].CopyFrom(v.into_proto())
         return proto_merged_edge
 
 
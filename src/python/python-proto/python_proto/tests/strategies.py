@@ -11,6 +11,9 @@ from python_proto.api import (
     IdentifiedGraph,
     IdentifiedNode,
     IdStrategy,
+    ImmutableBoolProp,
+    ImmutableBytesProp,
+    ImmutableFloatProp,
     ImmutableIntProp,
     ImmutableStrProp,
     ImmutableUintProp,
@@ -24,6 +27,10 @@ from python_proto.api import (
     NodeProperty,
     Session,
     Static,
+    StrSetProp,
+    TimestampMerge,
+    TimestampProp,
+    TimeUnit,
 )
 from python_proto.metrics import (
     Counter,
@@ -96,6 +103,7 @@ def sessions(
     terminate_times: st.SearchStrategy[int] = st.integers(
         min_value=UINT64_MIN, max_value=UINT64_MAX
     ),
+    time_units: st.SearchStrategy[TimeUnit] = st.sampled_from(TimeUnit),
 ) -> st.SearchStrategy[Session]:
     return st.builds(
         Session,
@@ -104,6 +112,7 @@ def sessions(
         create_time=create_times,
         last_seen_time=last_seen_times,
         terminate_time=terminate_times,
+        time_unit=time_units,
     )
 
 
@@ -185,6 +194,41 @@ def immutable_str_props(
     return st.builds(ImmutableStrProp, prop=props)
 
 
+def immutable_bool_props(
+    props: st.SearchStrategy[bool] = st.booleans(),
+) -> st.SearchStrategy[ImmutableBoolProp]:
+    return st.builds(ImmutableBoolProp, prop=props)
+
+
+def immutable_float_props(
+    props: st.SearchStrategy[float] = st.floats(allow_nan=False),
+) -> st.SearchStrategy[ImmutableFloatProp]:
+    return st.builds(ImmutableFloatProp, prop=props)
+
+
+def immutable_bytes_props(
+    props: st.SearchStrategy[bytes] = st.binary(),
+) -> st.SearchStrategy[ImmutableBytesProp]:
+    return st.builds(ImmutableBytesProp, prop=props)
+
+
+def timestamp_props(
+    millis: st.SearchStrategy[int] = st.integers(
+        min_value=UINT64_MIN, max_value=UINT64_MAX
+    ),
+    merges: st.SearchStrategy[TimestampMerge] = st.sampled_from(TimestampMerge),
+) -> st.SearchStrategy[TimestampProp]:
+    return st.builds(TimestampProp, millis=millis, merge=merges)
+
+
+def str_set_props(
+    props: st.SearchStrategy[Sequence[str]] = st.lists(
+        st.text(), max_size=MAX_LIST_SIZE, unique=True
+    ).map(sorted),
+) -> st.SearchStrategy[StrSetProp]:
+    return st.builds(StrSetProp, props=props)
+
+
 def node_properties(
     properties: st.SearchStrategy[
         Union[
@@ -195,6 +239,11 @@ def node_properties(
             DecrementOnlyIntProp,
             ImmutableIntProp,
             ImmutableStrProp,
+            ImmutableBoolProp,
+            ImmutableFloatProp,
+            ImmutableBytesProp,
+            TimestampProp,
+            StrSetProp,
         ]
     ] = st.one_of(
         increment_only_uint_props(),
@@ -204,6 +253,11 @@ def node_properties(
         decrement_only_int_props(),
         immutable_int_props(),
         immutable_str_props(),
+        immutable_bool_props(),
+        immutable_float_props(),
+        immutable_bytes_props(),
+        timestamp_props(),
+        str_set_props(),
     )
 ) -> st.SearchStrategy[NodeProperty]:
     return st.builds(NodeProperty, property_=properties)
@@ -266,12 +320,16 @@ def edges(
     from_node_keys: st.SearchStrategy[str] = st.text(),
     to_node_keys: st.SearchStrategy[str] = st.text(),
     edge_names: st.SearchStrategy[str] = st.text(),
+    properties: st.SearchStrategy[Mapping[str, NodeProperty]] = st.dictionaries(
+        keys=st.text(), values=node_properties(), max_size=MAX_LIST_SIZE
+    ),
 ) -> st.SearchStrategy[Edge]:
     return st.builds(
         Edge,
         from_node_key=from_node_keys,
         to_node_key=to_node_keys,
         edge_name=edge_names,
+        properties=properties,
     )
 
 
@@ -292,6 +350,9 @@ def merged_edges(
     to_uids: st.SearchStrategy[str] = st.text(),
     to_node_keys: st.SearchStrategy[str] = st.text(),
     edge_names: st.SearchStrategy[str] = st.text(),
+    properties: st.SearchStrategy[Mapping[str, NodeProperty]] = st.dictionaries(
+        keys=st.text(), values=node_properties(), max_size=MAX_LIST_SIZE
+    ),
 ) -> st.SearchStrategy[MergedEdge]:
     return st.builds(
         MergedEdge,
@@ -300,6 +361,7 @@ def merged_edges(
         to_uid=to_uids,
         to_node_key=to_node_keys,
         edge_name=edge_names,
+        properties=properties,
     )
 
 
@@ -12,6 +12,9 @@ from python_proto.tests.strategies import (
     id_strategies,
     identified_graphs,
     identified_nodes,
+    immutable_bool_props,
+    immutable_bytes_props,
+    immutable_float_props,
     immutable_int_props,
     immutable_str_props,
     immutable_uint_props,
@@ -25,6 +28,8 @@ from python_proto.tests.strategies import (
     node_properties,
     sessions,
     statics,
+    str_set_props,
+    timestamp_props,
 )
 
 
@@ -72,6 +77,26 @@ def test_immutable_uint_prop_encode_decode() -> None:
     check_encode_decode_invariant(immutable_uint_props())
 
 
+def test_immutable_bool_prop_encode_decode() -> None:
+    check_encode_decode_invariant(immutable_bool_props())
+
+
+def test_immutable_float_prop_encode_decode() -> None:
+    check_encode_decode_invariant(immutable_float_props())
+
+
+def test_immutable_bytes_prop_encode_decode() -> None:
+    check_encode_decode_invariant(immutable_bytes_props())
+
+
+def test_timestamp_prop_encode_decode() -> None:
+    check_encode_decode_invariant(timestamp_props())
+
+
+def test_str_set_prop_encode_decode() -> None:
+    check_encode_decode_invariant(str_set_props())
+
+
 def test_increment_only_int_prop_encode_decode() -> None:
     check_encode_decode_invariant(increment_only_int_props())
 
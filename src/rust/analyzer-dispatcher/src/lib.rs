@@ -0,0 +1,228 @@
+use std::{
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::Arc,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use failure::{
+    bail,
+    Error,
+};
+use log::{
+    info,
+    warn,
+};
+use rusoto_s3::{
+    ListObjectsRequest,
+    S3,
+};
+use rust_proto::graph_descriptions::*;
+use sqs_executor::{
+    errors::{
+        CheckedError,
+        Recoverable,
+    },
+    event_handler::{
+        CompletedEvents,
+        EventHandler,
+    },
+};
+
+use crate::dispatch_event::{
+    AnalyzerDispatchEvent,
+    AnalyzerDispatchEvents,
+};
+
+pub mod dispatch_event;
+
+/// Where the analyzers to dispatch to are listed
+#[derive(Debug)]
+pub enum AnalyzerSource<S>
+where
+    S: S3 + Send + Sync + 'static,
+{
+    /// The `GRAPL_ANALYZERS_BUCKET`
+    Bucket(Arc<S>),
+    /// A directory laid out like the analyzers bucket, for running without S3
+    LocalDir(PathBuf),
+}
+
+impl<S> Clone for AnalyzerSource<S>
+where
+    S: S3 + Send + Sync + 'static,
+{
+    fn clone(&self) -> Self {
+        match self {
+            Self::Bucket(s3_client) => Self::Bucket(s3_client.clone()),
+            Self::LocalDir(dir) => Self::LocalDir(dir.clone()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct AnalyzerDispatcher<S>
+where
+    S: S3 + Send + Sync + 'static,
+{
+    analyzers: AnalyzerSource<S>,
+}
+
+impl<S> AnalyzerDispatcher<S>
+where
+    S: S3 + Send + Sync + 'static,
+{
+    pub fn new(analyzers: AnalyzerSource<S>) -> Self {
+        Self { analyzers }
+    }
+}
+
+impl<S> Clone for AnalyzerDispatcher<S>
+where
+    S: S3 + Send + Sync + 'static,
+{
+    fn clone(&self) -> Self {
+        Self {
+            analyzers: self.analyzers.clone(),
+        }
+    }
+}
+
+async fn get_s3_keys(
+    s3_client: &impl S3,
+    bucket: impl Into<String>,
+) -> Result<impl IntoIterator<Item = Result<String, Error>>, Error> {
+    let bucket = bucket.into();
+
+    let list_res = tokio::time::timeout(
+        Duration::from_secs(2),
+        s3_client.list_objects(ListObjectsRequest {
+            bucket,
+            ..Default::default()
+        }),
+    )
+    .await??;
+
+    let contents = match list_res.contents {
+        Some(contents) => contents,
+        None => {
+            warn!("List response returned nothing");
+            Vec::new()
+        }
+    };
+
+    Ok(contents.into_iter().map(|object| match object.key {
+        Some(key) => Ok(key),
+        None => bail!("S3Object is missing key"),
+    }))
+}
+
+/// Lists the files under `dir` as keys relative to it, the way they'd be listed in S3
+fn get_local_keys(dir: &Path) -> Result<Vec<Result<String, Error>>, Error> {
+    let mut keys = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+
+    while let Some(next_dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&next_dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            keys.push(match path.strip_prefix(dir)?.to_str() {
+                Some(key) => Ok(key.replace(std::path::MAIN_SEPARATOR, "/")),
+                None => Err(failure::format_err!("Non-UTF8 analyzer path: {:?}", path)),
+            });
+        }
+    }
+
+    Ok(keys)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum AnalyzerDispatcherError {
+    #[error("Unexpected")]
+    Unexpected(String),
+}
+
+impl CheckedError for AnalyzerDispatcherError {
+    fn error_type(&self) -> Recoverable {
+        Recoverable::Transient
+    }
+}
+
+#[async_trait]
+impl<S> EventHandler for AnalyzerDispatcher<S>
+where
+    S: S3 + Send + Sync + 'static,
+{
+    type InputEvent = MergedGraph;
+    type OutputEvent = AnalyzerDispatchEvents;
+    type Error = AnalyzerDispatcherError;
+
+    async fn handle_event(
+        &mut self,
+        subgraph: Self::InputEvent,
+        _completed: &mut CompletedEvents,
+    ) -> Result<Self::OutputEvent, Result<(Self::OutputEvent, Self::Error), Self::Error>> {
+        if subgraph.is_empty() {
+            warn!("Attempted to handle empty subgraph");
+            return Ok(AnalyzerDispatchEvents::new());
+        }
+
+        let keys: Vec<_> = match &self.analyzers {
+            AnalyzerSource::Bucket(s3_client) => {
+                let bucket =
+                    std::env::var("GRAPL_ANALYZERS_BUCKET").expect("GRAPL_ANALYZERS_BUCKET");
+
+                info!("Retrieving S3 keys");
+                match get_s3_keys(s3_client.as_ref(), bucket.clone()).await {
+                    Ok(keys) => keys.into_iter().collect(),
+                    Err(e) => {
+                        return Err(Err(AnalyzerDispatcherError::Unexpected(format!(
+                            "Failed to list bucket: {} with {:?}",
+                            bucket, e
+                        ))));
+                    }
+                }
+            }
+            AnalyzerSource::LocalDir(dir) => match get_local_keys(dir) {
+                Ok(keys) => keys,
+                Err(e) => {
+                    return Err(Err(AnalyzerDispatcherError::Unexpected(format!(
+                        "Failed to list directory: {:?} with {:?}",
+                        dir, e
+                    ))));
+                }
+            },
+        };
+
+        let mut dispatch_events = Vec::new();
+
+        let mut failed = None;
+        for key in keys {
+            let key = match key {
+                Ok(key) => key,
+                Err(e) => {
+                    warn!("Failed to retrieve key with {:?}", e);
+                    failed = Some(e);
+                    continue;
+                }
+            };
+
+            dispatch_events.push(AnalyzerDispatchEvent::new(key, subgraph.clone()));
+        }
+
+        if let Some(e) = failed {
+            Err(Ok((
+                AnalyzerDispatchEvents::from(dispatch_events),
+                AnalyzerDispatcherError::Unexpected(e.to_string()),
+            )))
+        } else {
+            Ok(AnalyzerDispatchEvents::from(dispatch_events))
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use std::time::SystemTime;
+
 use derive_dynamic_node::{
     GraplSessionId,
     GraplStaticId,
@@ -21,6 +23,12 @@ pub struct SpecialProcess {
     pub terminate_time: u64,
     #[grapl(pseudo_key, immutable)]
     pub process_id: u64,
+    #[grapl(decrement)]
+    pub first_seen_at: SystemTime,
+    #[grapl(immutable)]
+    pub is_elevated: bool,
+    #[grapl(set_union)]
+    pub command_line_args: Vec<String>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
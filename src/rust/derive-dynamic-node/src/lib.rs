@@ -23,13 +23,16 @@ const TERMINATE_TIME: &str = "terminate_time";
 const IMMUTABLE: &str = "immutable";
 const INCREMENT: &str = "increment";
 const DECREMENT: &str = "decrement";
+const SET_UNION: &str = "set_union";
 
 fn name_and_ty(field: &Field) -> (&Ident, &Type, String) {
     let mut resolution = None;
     for attr in &field.attrs {
         on_grapl_attrs(attr, |attr| {
             match attr {
-                IMMUTABLE | INCREMENT | DECREMENT => resolution = Some(attr.to_string()),
+                IMMUTABLE | INCREMENT | DECREMENT | SET_UNION => {
+                    resolution = Some(attr.to_string())
+                }
                 _ => (),
             };
         });
@@ -268,6 +271,14 @@ pub fn derive_grapl_session(input: TokenStream) -> TokenStream {
     let q = quote!(
         impl #node_name {
             pub fn session_strategy() -> IdStrategy {
+                #node_name :: session_strategy_with_time_unit(
+                    rust_proto::graph_descriptions::TimeUnit::Milliseconds
+                )
+            }
+
+            pub fn session_strategy_with_time_unit(
+                time_unit: rust_proto::graph_descriptions::TimeUnit
+            ) -> IdStrategy {
                 Session {
                     create_time: 0 ,
                     last_seen_time: 0 ,
@@ -276,6 +287,7 @@ pub fn derive_grapl_session(input: TokenStream) -> TokenStream {
                     primary_key_properties: vec![
                         #id_fields
                     ],
+                    time_unit: time_unit as i32,
                 }.into()
             }
 
@@ -333,11 +345,40 @@ fn assert_meta_attr_combo(field: &Field, meta_attr_match_a: &str, meta_attr_matc
     }
 }
 
-fn resolvable_type_from(
-    property_type: &Type,
-    resolution_name: &str,
-) -> Option<(syn::Type, syn::Ident)> {
-    let (return_type, method_ident): (syn::Type, syn::Ident) = match property_type {
+/// How a property's setter, getter and underlying `NodeProperty` are generated
+struct ResolvedProperty {
+    /// The type that the `with_` setter accepts via `impl Into<_>`
+    param_type: syn::Type,
+    /// The type that the `get_` getter returns
+    return_type: syn::Type,
+    /// The method on `NodeProperty` that the getter calls
+    method_ident: syn::Ident,
+    /// Converts the setter's argument into `return_type`
+    constructor: Option<syn::Path>,
+}
+
+impl ResolvedProperty {
+    fn new(return_type: syn::Type, method_ident: syn::Ident) -> Self {
+        Self {
+            param_type: return_type.clone(),
+            return_type,
+            method_ident,
+            constructor: None,
+        }
+    }
+
+    fn timestamp(constructor: syn::Path) -> Self {
+        Self {
+            param_type: parse_quote!(u64),
+            return_type: parse_quote!(rust_proto::graph_descriptions::TimestampProp),
+            method_ident: parse_quote!(as_timestamp),
+            constructor: Some(constructor),
+        }
+    }
+}
+
+fn resolvable_type_from(property_type: &Type, resolution_name: &str) -> Option<ResolvedProperty> {
+    let resolved = match property_type {
         // janky way to get String="fully::qualified::path::Type" given a TypePath
         Type::Path(typepath) => {
             let typepath = typepath
@@ -349,38 +390,66 @@ fn resolvable_type_from(
                 .join("::");
             match (typepath.as_ref(), resolution_name) {
                 /* underlying struct field type    maps to this type   via this method on NodeProperty */
-                ("String", IMMUTABLE) => (
+                ("String", IMMUTABLE) => ResolvedProperty::new(
                     parse_quote!(rust_proto::graph_descriptions::ImmutableStrProp),
                     parse_quote!(as_immutable_str),
                 ),
-                ("std::string::String", IMMUTABLE) => (
+                ("std::string::String", IMMUTABLE) => ResolvedProperty::new(
                     parse_quote!(rust_proto::graph_descriptions::ImmutableStrProp),
                     parse_quote!(as_immutable_str),
                 ),
-                ("u64", IMMUTABLE) => (
+                ("u64", IMMUTABLE) => ResolvedProperty::new(
                     parse_quote!(rust_proto::graph_descriptions::ImmutableUintProp),
                     parse_quote!(as_immutable_uint),
                 ),
-                ("u64", INCREMENT) => (
+                ("u64", INCREMENT) => ResolvedProperty::new(
                     parse_quote!(rust_proto::graph_descriptions::IncrementOnlyUintProp),
                     parse_quote!(as_increment_only_uint),
                 ),
-                ("u64", DECREMENT) => (
+                ("u64", DECREMENT) => ResolvedProperty::new(
                     parse_quote!(rust_proto::graph_descriptions::DecrementOnlyUintProp),
                     parse_quote!(as_decrement_only_uint),
                 ),
-                ("i64", IMMUTABLE) => (
+                ("i64", IMMUTABLE) => ResolvedProperty::new(
                     parse_quote!(rust_proto::graph_descriptions::ImmutableIntProp),
                     parse_quote!(as_immutable_int),
                 ),
-                ("i64", INCREMENT) => (
+                ("i64", INCREMENT) => ResolvedProperty::new(
                     parse_quote!(rust_proto::graph_descriptions::IncrementOnlyIntProp),
                     parse_quote!(as_increment_only_int),
                 ),
-                ("i64", DECREMENT) => (
+                ("i64", DECREMENT) => ResolvedProperty::new(
                     parse_quote!(rust_proto::graph_descriptions::DecrementOnlyIntProp),
                     parse_quote!(as_decrement_only_int),
                 ),
+                ("bool", IMMUTABLE) => ResolvedProperty::new(
+                    parse_quote!(rust_proto::graph_descriptions::ImmutableBoolProp),
+                    parse_quote!(as_immutable_bool),
+                ),
+                ("f64", IMMUTABLE) => ResolvedProperty::new(
+                    parse_quote!(rust_proto::graph_descriptions::ImmutableFloatProp),
+                    parse_quote!(as_immutable_float),
+                ),
+                // Generic arguments aren't part of the joined path, so `Vec<u8>` and
+                // `Vec<String>` are told apart by their resolution
+                ("Vec", IMMUTABLE) => ResolvedProperty::new(
+                    parse_quote!(rust_proto::graph_descriptions::ImmutableBytesProp),
+                    parse_quote!(as_immutable_bytes),
+                ),
+                ("Vec", SET_UNION) => ResolvedProperty::new(
+                    parse_quote!(rust_proto::graph_descriptions::StrSetProp),
+                    parse_quote!(as_str_set),
+                ),
+                // Timestamps are set as milliseconds since the unix epoch
+                ("SystemTime" | "std::time::SystemTime", IMMUTABLE) => ResolvedProperty::timestamp(
+                    parse_quote!(rust_proto::graph_descriptions::TimestampProp::immutable),
+                ),
+                ("SystemTime" | "std::time::SystemTime", INCREMENT) => ResolvedProperty::timestamp(
+                    parse_quote!(rust_proto::graph_descriptions::TimestampProp::latest),
+                ),
+                ("SystemTime" | "std::time::SystemTime", DECREMENT) => ResolvedProperty::timestamp(
+                    parse_quote!(rust_proto::graph_descriptions::TimestampProp::earliest),
+                ),
                 _ => return None,
             }
         }
@@ -391,7 +460,7 @@ fn resolvable_type_from(
         _ => panic!("Tried to dynamically construct getter for unrecognized type!"),
     };
 
-    Some((return_type, method_ident))
+    Some(resolved)
 }
 
 fn identity_prop_setter(field: &Field, property_name: &Ident) -> TS2 {
@@ -452,14 +521,24 @@ fn property_methods(field: &Field) -> TS2 {
     let set_identity_prop = identity_prop_setter(field, &inner_property_name);
     let mut implementation: TS2 = quote!();
 
-    let (return_type, method_ident) = match resolvable_type_from(property_type, &resolution_name) {
-        Some(property_type) => property_type,
+    let ResolvedProperty {
+        param_type,
+        return_type,
+        method_ident,
+        constructor,
+    } = match resolvable_type_from(property_type, &resolution_name) {
+        Some(resolved) => resolved,
         None => return implementation,
     };
 
+    let into_property = match constructor {
+        Some(constructor) => quote!(#constructor(#property_name .into())),
+        None => quote!(#property_name .into()),
+    };
+
     let with_method_implementation = quote!(
-        fn #with_method_name(&mut self, #property_name: impl Into<#return_type>) -> &mut Self {
-            let #inner_property_name = #property_name .into();
+        fn #with_method_name(&mut self, #property_name: impl Into<#param_type>) -> &mut Self {
+            let #inner_property_name: #return_type = #into_property;
             let mut_self = self.get_mut_dynamic_node();
 
             mut_self.properties.insert(
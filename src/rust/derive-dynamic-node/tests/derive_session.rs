@@ -52,3 +52,25 @@ fn test_session() {
     assert_eq!(strategy.last_seen_time, 1u64);
     assert_eq!(strategy.terminate_time, 2u64);
 }
+
+#[test]
+fn test_session_time_unit() {
+    let strategy = SpecialProcessNode::session_strategy_with_time_unit(TimeUnit::Seconds);
+
+    let strategy = match strategy.strategy.unwrap() {
+        rust_proto::graph_descriptions::id_strategy::Strategy::Session(strategy) => strategy,
+        _ => panic!("Expected session"),
+    };
+    assert_eq!(strategy.time_unit(), TimeUnit::Seconds);
+
+    let special_proc = SpecialProcessNode::new(SpecialProcessNode::session_strategy());
+    let strategy = match special_proc.get_dynamic_node().id_strategy[0]
+        .strategy
+        .as_ref()
+        .unwrap()
+    {
+        rust_proto::graph_descriptions::id_strategy::Strategy::Session(strategy) => strategy,
+        _ => panic!("Expected session"),
+    };
+    assert_eq!(strategy.time_unit(), TimeUnit::Milliseconds);
+}
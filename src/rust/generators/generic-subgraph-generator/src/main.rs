@@ -6,8 +6,10 @@ use std::str::FromStr;
 
 use grapl_config::{
     env_helpers::{
+        local_fs_emitters_from_env,
+        local_fs_message_source_from_env,
         s3_event_emitters_from_env,
-        FromEnv,
+        sqs_message_source_from_env,
     },
     event_caches,
 };
@@ -18,10 +20,14 @@ use grapl_service::{
 };
 use rusoto_core::Region;
 use rusoto_s3::S3Client;
-use rusoto_sqs::SqsClient;
 use sqs_executor::{
     cache::NopCache,
-    make_ten,
+    key_creator::zstd_proto_key_fn,
+    local_fs::{
+        LocalFsPayloadRetriever,
+        LocalFsPayloadStore,
+    },
+    make_workers,
     s3_event_retriever::S3PayloadRetriever,
     time_based_key_fn,
 };
@@ -35,38 +41,77 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     info!("Starting generic-subgraph-generator");
 
-    let sqs_client = SqsClient::from_env();
-
     let cache = &mut event_caches(&env).await;
 
-    let generic_subgraph_generator =
-        &mut make_ten(async { GenericSubgraphGenerator::new(NopCache {}) }).await;
+    let generic_subgraph_generator = &mut make_workers(grapl_config::worker_count(), async {
+        GenericSubgraphGenerator::new(NopCache {})
+    })
+    .await;
+
+    let serializer = &mut make_workers(grapl_config::worker_count(), async {
+        GraphDescriptionSerializer::default()
+    })
+    .await;
+    let heartbeat = grapl_config::health_server()
+        .with_check(cache[0].clone())
+        .spawn();
 
-    let serializer = &mut make_ten(async { GraphDescriptionSerializer::default() }).await;
-    let s3_emitter = &mut s3_event_emitters_from_env(&env, time_based_key_fn).await;
+    if let Some((input_dir, output_dir)) = grapl_config::local_fs_dirs() {
+        let payload_retriever = &mut make_workers(grapl_config::worker_count(), async {
+            LocalFsPayloadRetriever::new(
+                LocalFsPayloadStore,
+                JsonDecoder::default(),
+                MetricReporter::new(&env.service_name),
+            )
+        })
+        .await;
+        let emitter = &mut local_fs_emitters_from_env(&env, output_dir, time_based_key_fn).await;
 
-    let s3_payload_retriever = &mut make_ten(async {
-        S3PayloadRetriever::new(
-            |region_str| S3Client::new(Region::from_str(&region_str).expect("region_str")),
-            JsonDecoder::default(),
+        info!("Starting process_loop over {:?}", input_dir);
+        sqs_executor::process_loop(
+            local_fs_message_source_from_env(&env, input_dir),
+            cache,
+            generic_subgraph_generator,
+            payload_retriever,
+            emitter,
+            serializer,
+            grapl_config::retry_policy(),
+            grapl_config::concurrency_policy(),
+            grapl_config::shutdown(),
+            heartbeat,
             MetricReporter::new(&env.service_name),
         )
-    })
-    .await;
+        .await;
+    } else {
+        // Keyed by the zstd dictionary the serializer compressed each payload with
+        let key_fn = zstd_proto_key_fn(env.service_name.clone());
+        let s3_emitter = &mut s3_event_emitters_from_env(&env, key_fn).await;
 
-    info!("Starting process_loop");
-    sqs_executor::process_loop(
-        grapl_config::source_queue_url(),
-        grapl_config::dead_letter_queue_url(),
-        cache,
-        sqs_client.clone(),
-        generic_subgraph_generator,
-        s3_payload_retriever,
-        s3_emitter,
-        serializer,
-        MetricReporter::new(&env.service_name),
-    )
-    .await;
+        let s3_payload_retriever = &mut make_workers(grapl_config::worker_count(), async {
+            S3PayloadRetriever::new(
+                |region_str| S3Client::new(Region::from_str(&region_str).expect("region_str")),
+                JsonDecoder::default(),
+                MetricReporter::new(&env.service_name),
+            )
+        })
+        .await;
+
+        info!("Starting process_loop");
+        sqs_executor::process_loop(
+            sqs_message_source_from_env(&env),
+            cache,
+            generic_subgraph_generator,
+            s3_payload_retriever,
+            s3_emitter,
+            serializer,
+            grapl_config::retry_policy(),
+            grapl_config::concurrency_policy(),
+            grapl_config::shutdown(),
+            heartbeat,
+            MetricReporter::new(&env.service_name),
+        )
+        .await;
+    }
 
     info!("Exiting");
 
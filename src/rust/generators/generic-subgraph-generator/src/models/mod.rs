@@ -13,6 +13,7 @@ use serde::{
     Deserialize,
     Serialize,
 };
+use sqs_executor::cache::Cacheable;
 
 use crate::models::{
     file::{
@@ -52,6 +53,14 @@ pub enum GenericEvent {
     ProcessPortBindLog(ProcessPortBindLog),
 }
 
+/// Events are cached by their JSON encoding, whose fields are always written in the order
+/// they're declared in. Only the skipped variants, which are never decoded, fail to serialize.
+impl Cacheable for GenericEvent {
+    fn write_canonical_bytes(&self, bytes: &mut Vec<u8>) {
+        serde_json::to_writer(bytes, self).expect("GenericEvent failed to serialize")
+    }
+}
+
 impl TryFrom<GenericEvent> for GraphDescription {
     type Error = String;
 
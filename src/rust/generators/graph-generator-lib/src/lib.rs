@@ -9,14 +9,25 @@ use grapl_config::{
 };
 pub use grapl_observe::metric_reporter::MetricReporter;
 use grapl_service::serialization::GraphDescriptionSerializer;
-use rusoto_s3::S3Client;
-use rusoto_sqs::SqsClient;
 pub use rust_proto::graph_descriptions::*;
 use sqs_executor::{
     errors::CheckedError,
-    event_decoder::PayloadDecoder,
-    event_handler::EventHandler,
-    make_ten,
+    event_decoder::{
+        BatchedDecoder,
+        PayloadDecoder,
+        StreamingPayloadDecoder,
+        DEFAULT_EVENT_BATCH_SIZE,
+    },
+    event_handler::{
+        BatchedEventHandler,
+        EventHandler,
+    },
+    key_creator::zstd_proto_key_fn,
+    local_fs::{
+        LocalFsPayloadRetriever,
+        LocalFsPayloadStore,
+    },
+    make_workers,
     redis_cache::RedisCache,
     s3_event_retriever::S3PayloadRetriever,
     time_based_key_fn,
@@ -24,8 +35,10 @@ use sqs_executor::{
 use tracing::info;
 
 use crate::grapl_config::env_helpers::{
+    local_fs_emitters_from_env,
+    local_fs_message_source_from_env,
     s3_event_emitters_from_env,
-    FromEnv,
+    sqs_message_source_from_env,
 };
 
 pub async fn run_graph_generator<
@@ -56,39 +69,126 @@ pub async fn run_graph_generator<
         PayloadDecoder<InputEventT, DecoderError = DecoderErrorT> + Send + Sync + Clone + 'static,
     DecoderErrorT: CheckedError + Send + 'static,
 {
-    let sqs_client = SqsClient::from_env();
-    let _s3_client = S3Client::from_env();
     let cache = &mut event_caches(&env).await;
 
-    let subgraph_generator = &mut make_ten(async { (init_generator)(cache[0].clone()) }).await;
+    let subgraph_generator = &mut make_workers(grapl_config::worker_count(), async {
+        (init_generator)(cache[0].clone())
+    })
+    .await;
+
+    let serializer = &mut make_workers(grapl_config::worker_count(), async {
+        GraphDescriptionSerializer::default()
+    })
+    .await;
 
-    let serializer = &mut make_ten(async { GraphDescriptionSerializer::default() }).await;
+    let heartbeat = grapl_config::health_server()
+        .with_check(cache[0].clone())
+        .spawn();
 
-    let s3_emitter = &mut s3_event_emitters_from_env(&env, time_based_key_fn).await;
+    if let Some((input_dir, output_dir)) = grapl_config::local_fs_dirs() {
+        let payload_retriever = &mut make_workers(grapl_config::worker_count(), async {
+            LocalFsPayloadRetriever::new(
+                LocalFsPayloadStore,
+                payload_decoder,
+                MetricReporter::new(&env.service_name),
+            )
+        })
+        .await;
+        let emitter = &mut local_fs_emitters_from_env(&env, output_dir, time_based_key_fn).await;
 
-    let s3_payload_retriever = &mut make_ten(async {
-        S3PayloadRetriever::new(
-            |region_str| {
-                info!("Initializing new s3 client: {}", &region_str);
-                grapl_config::env_helpers::init_s3_client(&region_str)
-            },
-            payload_decoder,
+        info!("Starting process_loop over {:?}", input_dir);
+        sqs_executor::process_loop(
+            local_fs_message_source_from_env(&env, input_dir),
+            cache,
+            subgraph_generator,
+            payload_retriever,
+            emitter,
+            serializer,
+            grapl_config::retry_policy(),
+            grapl_config::concurrency_policy(),
+            grapl_config::shutdown(),
+            heartbeat,
             MetricReporter::new(&env.service_name),
         )
-    })
-    .await;
+        .await;
+    } else {
+        // Keyed by the zstd dictionary the serializer compressed each payload with
+        let key_fn = zstd_proto_key_fn(env.service_name.clone());
+        let s3_emitter = &mut s3_event_emitters_from_env(&env, key_fn).await;
+
+        let s3_payload_retriever = &mut make_workers(grapl_config::worker_count(), async {
+            S3PayloadRetriever::new(
+                |region_str| {
+                    info!("Initializing new s3 client: {}", &region_str);
+                    grapl_config::env_helpers::init_s3_client(&region_str)
+                },
+                payload_decoder,
+                MetricReporter::new(&env.service_name),
+            )
+        })
+        .await;
+
+        info!("Starting process_loop");
+        sqs_executor::process_loop(
+            sqs_message_source_from_env(&env),
+            cache,
+            subgraph_generator,
+            s3_payload_retriever,
+            s3_emitter,
+            serializer,
+            grapl_config::retry_policy(),
+            grapl_config::concurrency_policy(),
+            grapl_config::shutdown(),
+            heartbeat,
+            MetricReporter::new(&env.service_name),
+        )
+        .await;
+    }
+}
 
-    info!("Starting process_loop");
-    sqs_executor::process_loop(
-        grapl_config::source_queue_url(),
-        grapl_config::dead_letter_queue_url(),
-        cache,
-        sqs_client.clone(),
-        subgraph_generator,
-        s3_payload_retriever,
-        s3_emitter,
-        serializer,
-        MetricReporter::new(&env.service_name),
+/// Like `run_graph_generator`, but each payload is decoded as it's handled, a batch of
+/// `EVENT_BATCH_SIZE` events at a time, so that a large payload's events aren't all in
+/// memory at once. Each batch's subgraph is emitted as the payload is handled, rather than
+/// being merged into the payload's.
+///
+/// Emitted subgraphs aren't taken back if a later batch fails, so when the payload is retried
+/// they're emitted again. That's safe because merging a subgraph into the graph is idempotent.
+pub async fn run_batched_graph_generator<
+    EventT,
+    HandlerErrorT,
+    InitGenerator,
+    PayloadDecoderT,
+    DecoderErrorT,
+    EventHandlerT,
+>(
+    env: ServiceEnv,
+    init_generator: InitGenerator,
+    payload_decoder: PayloadDecoderT,
+) where
+    EventT: Send + 'static,
+    InitGenerator: Clone + Send + 'static + Fn(RedisCache) -> EventHandlerT,
+    EventHandlerT: EventHandler<
+            InputEvent = Vec<EventT>,
+            OutputEvent = GraphDescription,
+            Error = HandlerErrorT,
+        >
+        + Send
+        + Sync
+        + 'static
+        + Clone,
+    HandlerErrorT: Debug + CheckedError + Send + Sync + 'static,
+    PayloadDecoderT: StreamingPayloadDecoder<EventT, DecoderError = DecoderErrorT>
+        + Send
+        + Sync
+        + Clone
+        + 'static,
+    DecoderErrorT: Debug + CheckedError + Send + Sync + 'static,
+{
+    let batch_size = grapl_config::event_batch_size().unwrap_or(DEFAULT_EVENT_BATCH_SIZE);
+    run_graph_generator(
+        env,
+        move |cache| BatchedEventHandler::<_, DecoderErrorT>::new((init_generator)(cache)),
+        BatchedDecoder::new(payload_decoder, batch_size),
     )
-    .await;
+    .await
 }
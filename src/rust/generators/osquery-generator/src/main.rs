@@ -14,7 +14,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         name =% service_name
     );
 
-    run_graph_generator(
+    run_batched_graph_generator(
         env,
         move |cache| OSQueryGenerator::new(cache, OSQueryGeneratorMetrics::new(&service_name)),
         grapl_service::decoder::NdjsonDecoder::default(),
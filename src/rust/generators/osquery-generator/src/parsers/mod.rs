@@ -3,6 +3,7 @@ use serde::{
     Deserialize,
     Serialize,
 };
+use sqs_executor::cache::Cacheable;
 
 mod grapl_pack;
 
@@ -25,6 +26,14 @@ pub(self) enum OSQueryAction {
     Other(String),
 }
 
+/// Events are cached by their JSON encoding, whose fields are always written in the order
+/// they're declared in
+impl Cacheable for OSQueryEvent {
+    fn write_canonical_bytes(&self, bytes: &mut Vec<u8>) {
+        serde_json::to_writer(bytes, self).expect("OSQueryEvent failed to serialize")
+    }
+}
+
 impl From<OSQueryEvent> for GraphDescription {
     fn from(event: OSQueryEvent) -> Self {
         match event {
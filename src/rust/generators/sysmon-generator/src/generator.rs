@@ -1,7 +1,11 @@
 use async_trait::async_trait;
 use rust_proto::graph_descriptions::*;
 use sqs_executor::{
-    cache::Cache,
+    cache::{
+        write_canonical_field,
+        Cache,
+        Cacheable,
+    },
     errors::{
         CheckedError,
         Recoverable,
@@ -38,6 +42,28 @@ impl CheckedError for SysmonGeneratorError {
     }
 }
 
+/// A sysmon event, cached by the fields that identify it within its Windows event log
+#[derive(Clone)]
+struct CachedEvent(sysmon::Event);
+
+impl Cacheable for CachedEvent {
+    fn write_canonical_bytes(&self, bytes: &mut Vec<u8>) {
+        let system = match &self.0 {
+            sysmon::Event::ProcessCreate(event) => &event.system,
+            sysmon::Event::FileCreate(event) => &event.system,
+            sysmon::Event::InboundNetwork(event) | sysmon::Event::OutboundNetwork(event) => {
+                &event.system
+            }
+        };
+        write_canonical_field(bytes, system.computer.computer.as_bytes());
+        write_canonical_field(bytes, system.channel.value.as_bytes());
+        write_canonical_field(bytes, system.provider.provider_guid.as_bytes());
+        write_canonical_field(bytes, &[system.event_id.event_id]);
+        write_canonical_field(bytes, &system.event_record_id.event_record_id.to_le_bytes());
+        write_canonical_field(bytes, system.time_created.system_time.as_bytes());
+    }
+}
+
 #[derive(Clone)]
 pub struct SysmonGenerator<C>
 where
@@ -77,6 +103,7 @@ where
         );
 
         // Skip events we've successfully processed and stored in the event cache.
+        let events: Vec<_> = events.into_iter().map(CachedEvent).collect();
         let events = self.cache.filter_cached(&events).await;
 
         let mut last_error: Option<SysmonGeneratorError> = None;
@@ -84,7 +111,7 @@ where
         let subgraphs: Vec<_> = events
             .into_iter()
             .filter_map(|event| {
-                let result: Result<GraphDescription, _> = SysmonTryFrom::try_from(event.clone());
+                let result: Result<GraphDescription, _> = SysmonTryFrom::try_from(event.0.clone());
                 self.metrics.report_subgraph_generation(&result);
                 match result {
                     Ok(graph) => {
@@ -1,4 +1,4 @@
-use graph_generator_lib::run_graph_generator;
+use graph_generator_lib::run_batched_graph_generator;
 pub use grapl_service::serialization::{
     GraphDescriptionSerializer,
     GraphDescriptionSerializerError,
@@ -20,7 +20,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         name =% service_name
     );
 
-    run_graph_generator(
+    run_batched_graph_generator(
         env,
         move |cache| SysmonGenerator::new(cache, SysmonGeneratorMetrics::new(&service_name)),
         SysmonDecoder::default(),
@@ -1,12 +1,19 @@
 use std::str::FromStr;
 
-use grapl_service::decoder::decompress::PayloadDecompressionError;
+use grapl_service::decoder::{
+    decompress::PayloadDecompressionError,
+    stream::decode_lines,
+};
 use sqs_executor::{
     errors::{
         CheckedError,
         Recoverable,
     },
-    event_decoder::PayloadDecoder,
+    event_decoder::{
+        EventStream,
+        PayloadDecoder,
+        StreamingPayloadDecoder,
+    },
 };
 use sysmon::Event;
 
@@ -24,7 +31,7 @@ impl CheckedError for SysmonDecoderError {
     fn error_type(&self) -> Recoverable {
         match self {
             Self::DeserializeError(_) => Recoverable::Persistent,
-            Self::DecompressionError(_) => Recoverable::Persistent,
+            Self::DecompressionError(e) => e.error_type(),
             Self::TimeError(_) => Recoverable::Persistent,
         }
     }
@@ -33,62 +40,50 @@ impl CheckedError for SysmonDecoderError {
 #[derive(Debug, Clone, Default)]
 pub struct SysmonDecoder;
 
-impl PayloadDecoder<Vec<Event>> for SysmonDecoder {
+impl StreamingPayloadDecoder<Event> for SysmonDecoder {
     type DecoderError = SysmonDecoderError;
 
-    fn decode(&mut self, body: Vec<u8>) -> Result<Vec<Event>, Self::DecoderError> {
-        let decompressed = grapl_service::decoder::decompress::maybe_decompress(body.as_slice())?;
-
-        let mut first_deserialization_error: Option<SysmonDecoderError> = None;
+    fn decode_stream(
+        &mut self,
+        body: Vec<u8>,
+    ) -> Result<EventStream<Event, Self::DecoderError>, Self::DecoderError> {
+        let reader = grapl_service::decoder::decompress::maybe_decompress_reader(body)?;
 
         /*
-           This iterator is taking a set of bytes of the logs, splitting the logs on newlines,
-           converting the byte sequences to utf-8 strings, and then filtering on supported event
-           types: Process Creation, Network Connection, and File Creation.
+           This stream is reading the logs a line at a time, converting each line to a utf-8
+           string, and then filtering on supported event types: Process Creation, Network
+           Connection, and File Creation.
 
            https://docs.microsoft.com/en-us/sysinternals/downloads/sysmon#events
         */
-        let events: Vec<_> = decompressed
-            .split(|byte| *byte == b'\n')
-            .map(String::from_utf8_lossy)
-            .map(|s| s.to_string())
-            .filter_map(|event_str| {
-                let parsed_event = Event::from_str(&event_str);
-                match parsed_event {
-                    Ok(event) => {
-                        tracing::debug!(message = "Deserialized sysmon event");
-                        Some(event)
-                    }
-                    Err(error) => {
-                        tracing::error!(
-                            message = "Unable to deserialize Sysmon event",
-                            error =? error,
-                            event_str =% event_str
-                        );
-
-                        if first_deserialization_error.is_none() {
-                            first_deserialization_error =
-                                Some(SysmonDecoderError::DeserializeError(error.to_string()))
-                        }
-                        None
-                    }
+        Ok(decode_lines(reader, |line| {
+            let event_str = String::from_utf8_lossy(line);
+            match Event::from_str(&event_str) {
+                Ok(event) => {
+                    tracing::debug!(message = "Deserialized sysmon event");
+                    let supported = event.is_process_create()
+                        || event.is_file_create()
+                        || event.is_inbound_network()
+                        || event.is_outbound_network();
+                    supported.then(|| Ok(event))
+                }
+                Err(error) => {
+                    tracing::error!(
+                        message = "Unable to deserialize Sysmon event",
+                        error =? error,
+                        event_str =% event_str
+                    );
+                    Some(Err(SysmonDecoderError::DeserializeError(error.to_string())))
                 }
-            })
-            .filter(|event| {
-                event.is_process_create()
-                    || event.is_file_create()
-                    || event.is_inbound_network()
-                    || event.is_outbound_network()
-            })
-            .collect();
+            }
+        }))
+    }
+}
 
-        // This is a bit awkward at the moment, due to interfaces to the sqs-executor. If some of
-        // our events successfully parsed then we want to continue and send those to the event
-        // handler. Only if all parsing has failed and we have no events do we want to return an
-        // error here.
-        match first_deserialization_error {
-            Some(error) if events.is_empty() => Err(error),
-            _ => Ok(events),
-        }
+impl PayloadDecoder<Vec<Event>> for SysmonDecoder {
+    type DecoderError = SysmonDecoderError;
+
+    fn decode(&mut self, body: Vec<u8>) -> Result<Vec<Event>, Self::DecoderError> {
+        self.decode_stream(body)?.collect()
     }
 }
@@ -0,0 +1,377 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use dgraph_query_lib::{
+    condition::{
+        Condition,
+        ConditionValue,
+    },
+    predicate::{
+        Field,
+        Predicate,
+    },
+    query::QueryBuilder,
+    queryblock::{
+        QueryBlock,
+        QueryBlockBuilder,
+        QueryBlockType,
+    },
+    ToQueryString,
+};
+use dgraph_tonic::{
+    Client as DgraphClient,
+    Mutate,
+    Query,
+};
+use futures::StreamExt;
+use futures_retry::{
+    FutureRetry,
+    RetryPolicy,
+};
+use grapl_utils::iter_ext::GraplIterExt;
+use rand::Rng;
+use rust_proto::graph_descriptions::{
+    IdentifiedNode,
+    MergedEdge,
+};
+
+use crate::{
+    graph_store::{
+        GraphStore,
+        GraphStoreError,
+    },
+    upsert_util,
+};
+
+const DGRAPH_CONCURRENCY_UPSERTS: usize = 8;
+// DGraph Live Loader uses a size of 1,000 elements and they claim this has relatively good performance
+const DGRAPH_UPSERT_CHUNK_SIZE: usize = 1024;
+
+/// A `GraphStore` backed by a Dgraph cluster, using RDF upserts
+#[derive(Clone)]
+pub struct DgraphGraphStore {
+    dgraph_client: Arc<DgraphClient>,
+}
+
+impl DgraphGraphStore {
+    pub fn new(dgraph_client: Arc<DgraphClient>) -> Self {
+        Self { dgraph_client }
+    }
+
+    /// Reads the facets the `edges` already have and sets the edges with their properties
+    /// merged into them, in one transaction. Another transaction writing the same edges in
+    /// between aborts this one at commit, so that it's retried against the new facets.
+    async fn upsert_edge_chunk(
+        dgraph_client: Arc<DgraphClient>,
+        edges: Vec<MergedEdge>,
+    ) -> Result<dgraph_tonic::Response, anyhow::Error> {
+        let query_name = |index: usize| format!("edge_{}", index);
+        let query_blocks: String = edges
+            .iter()
+            .enumerate()
+            .map(|(index, edge)| upsert_util::edge_facets_query(&query_name(index), edge))
+            .collect();
+
+        let mut txn = dgraph_client.new_mutated_txn();
+        let query_response = txn.query(format!("{{ {} }}", query_blocks)).await?;
+        let query_response: serde_json::Value = serde_json::from_slice(&query_response.json)?;
+
+        let mutation: Vec<serde_json::Value> = edges
+            .iter()
+            .enumerate()
+            .map(|(index, edge)| {
+                let existing_facets = upsert_util::existing_edge_facets(
+                    &query_response,
+                    &query_name(index),
+                    &edge.edge_name,
+                );
+                upsert_util::edge_mutation_json(edge, &existing_facets)
+            })
+            .collect();
+        let mut dgraph_mutation = dgraph_tonic::Mutation::new();
+        dgraph_mutation.set_set_json(&mutation)?;
+
+        let response = txn.mutate(dgraph_mutation).await?;
+        txn.commit().await?;
+        Ok(response)
+    }
+
+    async fn enforce_transaction<Factory, Txn>(
+        f: Factory,
+    ) -> Result<dgraph_tonic::Response, GraphStoreError>
+    where
+        Factory: FnMut() -> Txn + 'static + Unpin,
+        Txn: std::future::Future<Output = Result<dgraph_tonic::Response, anyhow::Error>>,
+    {
+        let handle_upsert_err = UpsertErrorHandler {};
+        let (response, attempts) = FutureRetry::new(f, handle_upsert_err)
+            .await
+            .map_err(|(e, _attempts)| e)?;
+
+        tracing::info!(message = "Performed upsert", attempts = attempts);
+
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl GraphStore for DgraphGraphStore {
+    async fn upsert_nodes(
+        &self,
+        nodes: &[IdentifiedNode],
+    ) -> Result<HashMap<String, u64>, GraphStoreError> {
+        let mut key_to_query_name = HashMap::new();
+        let mut node_upserts = Vec::with_capacity(nodes.len());
+        for (unique_id, node) in nodes.iter().enumerate() {
+            let (query, upserts) = upsert_util::build_upserts(
+                unique_id as u128,
+                &node.node_key,
+                &node.node_type,
+                &node.properties,
+                &mut key_to_query_name,
+            );
+            node_upserts.push((query, upserts));
+        }
+
+        let dgraph_client = self.dgraph_client.clone();
+        let responses: Vec<Result<dgraph_tonic::Response, GraphStoreError>> =
+            futures::stream::iter(
+                node_upserts
+                    .into_iter()
+                    .chunks_owned(DGRAPH_UPSERT_CHUNK_SIZE),
+            )
+            .map(move |upsert_chunk| {
+                let mut combined_query = String::new();
+                let mut all_mutations = Vec::new();
+                for (query_block, mutations) in upsert_chunk.iter() {
+                    combined_query.push_str(query_block);
+                    all_mutations.extend_from_slice(mutations);
+                }
+
+                let combined_query = format!(
+                    r"
+            {{
+                    {}
+            }}
+            ",
+                    combined_query
+                );
+
+                tracing::debug!(message="Generated query for upsert", combined_query=?combined_query);
+
+                let dgraph_client = dgraph_client.clone();
+                Self::enforce_transaction(move || {
+                    let txn = dgraph_client.new_mutated_txn();
+                    txn.upsert_and_commit_now(combined_query.clone(), all_mutations.clone())
+                })
+            })
+            .buffer_unordered(DGRAPH_CONCURRENCY_UPSERTS)
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut node_key_map_to_uid = HashMap::new();
+        let mut uids = HashMap::new();
+        for response in responses {
+            let response = response?;
+            let query_responses: serde_json::Value = match serde_json::from_slice(&response.json) {
+                Ok(response) => response,
+                Err(e) => {
+                    tracing::error!(message="Failed to parse JSON response for upsert", error=?e);
+                    continue;
+                }
+            };
+            tracing::debug!(
+                message="Received upsert response",
+                query_response=?query_responses,
+                uids=?response.uids,
+            );
+            uids.extend(response.uids);
+            extract_node_key_map_uid(&query_responses, &mut node_key_map_to_uid);
+        }
+
+        // Nodes that were created by this upsert won't be in the query responses, but their
+        // uids will have been assigned to the blank node named after their query
+        for node in nodes {
+            if node_key_map_to_uid.contains_key(&node.node_key) {
+                continue;
+            }
+            if let Some(uid) = uid_from_uids(&node.node_key, &key_to_query_name, &uids) {
+                node_key_map_to_uid.insert(node.node_key.clone(), uid);
+            }
+        }
+
+        Ok(node_key_map_to_uid)
+    }
+
+    async fn upsert_edges(&self, edges: &[MergedEdge]) -> Result<(), GraphStoreError> {
+        let dgraph_client = self.dgraph_client.clone();
+        let responses: Vec<Result<dgraph_tonic::Response, GraphStoreError>> =
+            futures::stream::iter(edges.iter().cloned().chunks_owned(DGRAPH_UPSERT_CHUNK_SIZE))
+                .map(|edges| {
+                    let dgraph_client = dgraph_client.clone();
+                    Self::enforce_transaction(move || {
+                        Box::pin(Self::upsert_edge_chunk(
+                            dgraph_client.clone(),
+                            edges.clone(),
+                        ))
+                    })
+                })
+                .buffer_unordered(DGRAPH_CONCURRENCY_UPSERTS)
+                .collect::<Vec<_>>()
+                .await;
+
+        for response in responses {
+            response?;
+        }
+
+        Ok(())
+    }
+
+    async fn lookup_uids(
+        &self,
+        node_keys: &[&str],
+    ) -> Result<HashMap<String, u64>, GraphStoreError> {
+        let mut resolved_nodes = HashMap::new();
+        if node_keys.is_empty() {
+            return Ok(resolved_nodes);
+        }
+
+        let mut query_blocks = Vec::with_capacity(node_keys.len());
+        for node_key in node_keys.iter() {
+            let query_block = gen_node_key_query(node_key);
+            query_blocks.push(query_block);
+        }
+
+        let query = QueryBuilder::default()
+            .query_blocks(query_blocks)
+            .build()
+            .unwrap();
+
+        let mut txn = self.dgraph_client.new_read_only_txn();
+        let query_responses = txn.query(query.to_query_string()).await?;
+
+        let query_responses: HashMap<String, Vec<HashMap<String, String>>> =
+            serde_json::from_slice(&query_responses.json)
+                .map_err(|e| GraphStoreError::InvalidResponse(e.to_string()))?;
+
+        for (_, query_response) in query_responses.into_iter() {
+            let query_response = match query_response.as_slice() {
+                [query_response] => query_response,
+                [] => {
+                    tracing::error!(message = "Empty response for node_key");
+                    continue;
+                }
+                res => {
+                    tracing::error!(message = "Too many responses for node_key", count=?res.len());
+                    continue;
+                }
+            };
+            let node_key = query_response.get("node_key");
+            let uid = query_response.get("uid");
+            let (node_key, uid) = match (node_key, uid) {
+                (Some(node_key), Some(uid)) => (node_key, uid),
+                (missing_key, missing_uid) => {
+                    tracing::error!(message="Unable to retrieve node_key and uid", node_key=?missing_key, uid=?missing_uid);
+                    continue;
+                }
+            };
+            let uid = u64::from_str_radix(&uid[2..], 16).expect("uid is not valid hex");
+            resolved_nodes.insert(node_key.to_owned(), uid);
+        }
+
+        Ok(resolved_nodes)
+    }
+}
+
+pub struct UpsertErrorHandler {}
+
+impl futures_retry::ErrorHandler<anyhow::Error> for UpsertErrorHandler {
+    type OutError = anyhow::Error;
+
+    fn handle(&mut self, attempt: usize, e: anyhow::Error) -> RetryPolicy<Self::OutError> {
+        let attempt = attempt as u64;
+        let aborted = is_txn_aborted(&e);
+        tracing::warn!(
+            message="Failed to enforce transaction",
+            error=?e,
+            attempt=?attempt,
+            aborted=?aborted,
+        );
+        match (aborted, attempt) {
+            // Another worker committed a conflicting write to the same node. Back off with some
+            // jitter so that workers racing on a hot node don't keep aborting each other.
+            (true, 0..=20) => {
+                let jitter = rand::thread_rng().gen_range(0..=10 * attempt);
+                RetryPolicy::WaitRetry(std::time::Duration::from_millis(10 * attempt + jitter))
+            }
+            (false, 0..=5) => RetryPolicy::Repeat,
+            (false, t @ 6..=20) => {
+                RetryPolicy::WaitRetry(std::time::Duration::from_millis(10 * t as u64))
+            }
+            (_, 21..=u64::MAX) => RetryPolicy::ForwardError(e),
+        }
+    }
+}
+
+/// Dgraph aborts a transaction at commit time if another transaction committed a write to
+/// the same predicate of the same node after this transaction started
+fn is_txn_aborted(e: &anyhow::Error) -> bool {
+    e.chain()
+        .any(|cause| cause.to_string().to_lowercase().contains("aborted"))
+}
+
+fn gen_node_key_query(node_key: &str) -> QueryBlock {
+    QueryBlockBuilder::default()
+        .query_type(QueryBlockType::query())
+        .root_filter(Condition::EQ(
+            "node_key".to_string(),
+            ConditionValue::string(node_key),
+        ))
+        .predicates(vec![
+            Predicate::Field(Field::new("uid")),
+            Predicate::Field(Field::new("node_key")),
+        ])
+        .first(1)
+        .build()
+        .unwrap()
+}
+
+fn uid_from_uids(
+    node_key: &str,
+    key_to_query_name: &HashMap<String, String>,
+    uids: &HashMap<String, String>,
+) -> Option<u64> {
+    let query_name = key_to_query_name.get(node_key)?;
+    let uid = uids.get(query_name)?;
+    Some(u64::from_str_radix(&uid[2..], 16).expect("uid is not valid hex"))
+}
+
+fn extract_node_key_map_uid(
+    dgraph_response: &serde_json::Value,
+    node_key_map_to_uid: &mut HashMap<String, u64>,
+) {
+    let query_responses = dgraph_response.as_object().expect("Invalid response");
+
+    for query_response in query_responses.values() {
+        let query_response = query_response.as_array().expect("Invalid response");
+        for query_response in query_response {
+            let uid = query_response
+                .get("uid")
+                .expect("uid")
+                .as_str()
+                .expect("uid");
+            let node_key = query_response
+                .get("node_key")
+                .expect("node_key")
+                .as_str()
+                .expect("node_key");
+
+            // dgraph uids are hex encoded as '0x1b'
+            let uid = u64::from_str_radix(&uid[2..], 16).expect("uid is not valid hex");
+            node_key_map_to_uid.insert(node_key.to_owned(), uid);
+        }
+    }
+}
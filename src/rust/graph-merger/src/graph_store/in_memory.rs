@@ -0,0 +1,160 @@
+use std::{
+    collections::{
+        BTreeMap,
+        HashMap,
+    },
+    sync::{
+        Arc,
+        Mutex,
+    },
+};
+
+use async_trait::async_trait;
+use rust_proto::graph_descriptions::{
+    IdentifiedNode,
+    MergedEdge,
+    MergedNode,
+    NodeProperty,
+};
+
+use crate::graph_store::{
+    GraphStore,
+    GraphStoreError,
+};
+
+#[derive(Default)]
+struct InMemoryGraph {
+    last_uid: u64,
+    uids: HashMap<String, u64>,
+    nodes: HashMap<u64, IdentifiedNode>,
+    // from_uid -> (edge_name, to_uid) -> edge
+    edges: HashMap<u64, BTreeMap<(String, u64), MergedEdge>>,
+}
+
+impl InMemoryGraph {
+    fn uid_for(&mut self, node_key: &str) -> u64 {
+        match self.uids.get(node_key) {
+            Some(uid) => *uid,
+            None => {
+                self.last_uid += 1;
+                self.uids.insert(node_key.to_owned(), self.last_uid);
+                self.last_uid
+            }
+        }
+    }
+}
+
+/// A `GraphStore` that keeps the entire graph in memory, which is useful for tests and
+/// local development where standing up Dgraph isn't practical.
+#[derive(Clone, Default)]
+pub struct InMemoryGraphStore {
+    graph: Arc<Mutex<InMemoryGraph>>,
+}
+
+impl InMemoryGraphStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the current state of the node with the given node_key, if it exists
+    pub fn get_node(&self, node_key: &str) -> Option<MergedNode> {
+        let graph = self.graph.lock().unwrap();
+        let uid = *graph.uids.get(node_key)?;
+        let node = graph.nodes.get(&uid)?;
+        Some(MergedNode::from(node.clone(), uid))
+    }
+
+    /// Returns the node_keys of every node that `from_node_key` has an `edge_name` edge to
+    pub fn get_edges(&self, from_node_key: &str, edge_name: &str) -> Vec<String> {
+        let graph = self.graph.lock().unwrap();
+        let from_uid = match graph.uids.get(from_node_key) {
+            Some(from_uid) => from_uid,
+            None => return vec![],
+        };
+        graph
+            .edges
+            .get(from_uid)
+            .into_iter()
+            .flatten()
+            .filter(|((name, _), _)| name == edge_name)
+            .filter_map(|((_, to_uid), _)| graph.nodes.get(to_uid))
+            .map(|node| node.clone_node_key())
+            .collect()
+    }
+
+    /// Returns the properties of the `edge_name` edge between the two nodes, if it exists
+    pub fn get_edge_properties(
+        &self,
+        from_node_key: &str,
+        edge_name: &str,
+        to_node_key: &str,
+    ) -> Option<HashMap<String, NodeProperty>> {
+        let graph = self.graph.lock().unwrap();
+        let from_uid = graph.uids.get(from_node_key)?;
+        let to_uid = graph.uids.get(to_node_key)?;
+        let edge = graph
+            .edges
+            .get(from_uid)?
+            .get(&(edge_name.to_owned(), *to_uid))?;
+        Some(edge.properties.clone())
+    }
+}
+
+#[async_trait]
+impl GraphStore for InMemoryGraphStore {
+    async fn upsert_nodes(
+        &self,
+        nodes: &[IdentifiedNode],
+    ) -> Result<HashMap<String, u64>, GraphStoreError> {
+        let mut graph = self.graph.lock().unwrap();
+        let mut node_key_to_uid = HashMap::with_capacity(nodes.len());
+        for node in nodes {
+            let uid = graph.uid_for(&node.node_key);
+            match graph.nodes.get_mut(&uid) {
+                Some(existing) => existing.merge(node),
+                None => {
+                    graph.nodes.insert(uid, node.clone());
+                }
+            }
+            node_key_to_uid.insert(node.clone_node_key(), uid);
+        }
+        Ok(node_key_to_uid)
+    }
+
+    async fn upsert_edges(&self, edges: &[MergedEdge]) -> Result<(), GraphStoreError> {
+        let mut graph = self.graph.lock().unwrap();
+        for edge in edges {
+            let from_uid = parse_uid(&edge.from_uid)?;
+            let to_uid = parse_uid(&edge.to_uid)?;
+            let edges = graph.edges.entry(from_uid).or_default();
+            match edges.get_mut(&(edge.edge_name.clone(), to_uid)) {
+                Some(existing) => existing.merge(edge),
+                None => {
+                    edges.insert((edge.edge_name.clone(), to_uid), edge.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn lookup_uids(
+        &self,
+        node_keys: &[&str],
+    ) -> Result<HashMap<String, u64>, GraphStoreError> {
+        let graph = self.graph.lock().unwrap();
+        Ok(node_keys
+            .iter()
+            .filter_map(|node_key| {
+                graph
+                    .uids
+                    .get(*node_key)
+                    .map(|uid| ((*node_key).to_owned(), *uid))
+            })
+            .collect())
+    }
+}
+
+fn parse_uid(uid: &str) -> Result<u64, GraphStoreError> {
+    uid.parse()
+        .map_err(|_| GraphStoreError::InvalidUid(uid.to_owned()))
+}
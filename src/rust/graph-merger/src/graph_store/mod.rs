@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use rust_proto::graph_descriptions::{
+    IdentifiedNode,
+    MergedEdge,
+};
+
+pub mod dgraph;
+pub mod in_memory;
+
+pub use dgraph::DgraphGraphStore;
+pub use in_memory::InMemoryGraphStore;
+
+#[derive(thiserror::Error, Debug)]
+pub enum GraphStoreError {
+    #[error("Graph store backend error: {0}")]
+    Backend(#[from] anyhow::Error),
+    #[error("Graph store returned an invalid response: {0}")]
+    InvalidResponse(String),
+    #[error("Invalid uid: {0}")]
+    InvalidUid(String),
+}
+
+/// A `GraphStore` is where merged graphs are persisted.
+///
+/// Node upserts must respect the merge semantics of each property kind - immutable properties
+/// keep the first value that was written, increment-only properties keep the largest value, and
+/// decrement-only properties keep the smallest.
+#[async_trait]
+pub trait GraphStore: Clone + Send + Sync + 'static {
+    /// Creates or merges each of the `nodes`, returning the uid of every upserted node keyed
+    /// by its node_key
+    async fn upsert_nodes(
+        &self,
+        nodes: &[IdentifiedNode],
+    ) -> Result<HashMap<String, u64>, GraphStoreError>;
+
+    /// Creates the `edges` between nodes that have already been upserted. Upserting an edge
+    /// that already exists is a no-op.
+    async fn upsert_edges(&self, edges: &[MergedEdge]) -> Result<(), GraphStoreError>;
+
+    /// Looks up the uids of existing nodes. Node keys that don't exist are omitted from the result.
+    async fn lookup_uids(
+        &self,
+        node_keys: &[&str],
+    ) -> Result<HashMap<String, u64>, GraphStoreError>;
+}
@@ -1,3 +1,5 @@
+pub mod graph_store;
+pub mod readiness;
 pub mod reverse_resolver;
 pub mod service;
 pub mod upsert_util;
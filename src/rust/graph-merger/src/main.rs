@@ -1,7 +1,15 @@
+use std::{
+    sync::Arc,
+    time::Duration,
+};
+
+use dgraph_tonic::Client as DgraphClient;
 use grapl_config::{
     env_helpers::{
+        local_fs_emitters_from_env,
+        local_fs_message_source_from_env,
         s3_event_emitters_from_env,
-        FromEnv,
+        sqs_message_source_from_env,
     },
     event_caches,
 };
@@ -10,15 +18,20 @@ use grapl_service::{
     decoder::ProtoDecoder,
     serialization::MergedGraphSerializer,
 };
-use rusoto_dynamodb::DynamoDbClient;
-use rusoto_sqs::SqsClient;
 use sqs_executor::{
-    make_ten,
+    key_creator::zstd_proto_key_fn,
+    local_fs::{
+        LocalFsPayloadRetriever,
+        LocalFsPayloadStore,
+    },
+    make_workers,
     s3_event_retriever::S3PayloadRetriever,
 };
 use tracing::info;
 
 use crate::{
+    graph_store::DgraphGraphStore,
+    readiness::DgraphCheck,
     reverse_resolver::ReverseEdgeResolver,
     service::{
         time_based_key_fn,
@@ -26,6 +39,8 @@ use crate::{
     },
 };
 
+pub mod graph_store;
+pub mod readiness;
 pub mod reverse_resolver;
 pub mod service;
 pub mod upsert_util;
@@ -36,24 +51,27 @@ async fn handler() -> Result<(), Box<dyn std::error::Error>> {
     let (env, _guard) = grapl_config::init_grapl_env!();
     info!("Starting graph-merger");
 
-    let sqs_client = SqsClient::from_env();
-
     let cache = &mut event_caches(&env).await;
 
     let mg_alphas = grapl_config::mg_alphas();
 
+    let schema_dir = grapl_config::model_plugin_schema_dir()
+        .ok_or("GRAPL_MODEL_PLUGIN_SCHEMA_DIR must be set to the deployed schemas' directory")?;
+    let reverse_edge_resolver =
+        ReverseEdgeResolver::from_schema_dir(&schema_dir, MetricReporter::new(&env.service_name))?;
+    reverse_edge_resolver.spawn_schema_reloader(schema_dir, Duration::from_secs(30));
+
     // todo: the intitializer should give a cache to each service
-    let graph_merger = &mut make_ten(async {
+    let graph_merger = &mut make_workers(grapl_config::worker_count(), async {
         let mg_alphas_copy = mg_alphas.clone();
         tracing::debug!(
             mg_alphas=?&mg_alphas_copy,
             "Connecting to mg_alphas"
         );
-        let dynamo = DynamoDbClient::from_env();
-        let reverse_edge_resolver =
-            ReverseEdgeResolver::new(dynamo, MetricReporter::new(&env.service_name), 1000);
+        let reverse_edge_resolver = reverse_edge_resolver.clone();
+        let mg_client = DgraphClient::new(mg_alphas_copy).expect("Failed to create dgraph client.");
         GraphMerger::new(
-            mg_alphas_copy,
+            DgraphGraphStore::new(Arc::new(mg_client)),
             reverse_edge_resolver,
             MetricReporter::new(&env.service_name),
             cache[0].clone(),
@@ -61,32 +79,75 @@ async fn handler() -> Result<(), Box<dyn std::error::Error>> {
     })
     .await;
 
-    let serializer = &mut make_ten(async { MergedGraphSerializer::default() }).await;
+    let serializer = &mut make_workers(grapl_config::worker_count(), async {
+        MergedGraphSerializer::default()
+    })
+    .await;
+
+    let heartbeat = grapl_config::health_server()
+        .with_check(cache[0].clone())
+        .with_check(reverse_edge_resolver.clone())
+        .with_check(DgraphCheck::new(Arc::new(
+            DgraphClient::new(mg_alphas.clone()).expect("Failed to create dgraph client."),
+        )))
+        .spawn();
 
-    let s3_emitter = &mut s3_event_emitters_from_env(&env, time_based_key_fn).await;
+    if let Some((input_dir, output_dir)) = grapl_config::local_fs_dirs() {
+        let payload_retriever = &mut make_workers(grapl_config::worker_count(), async {
+            LocalFsPayloadRetriever::new(
+                LocalFsPayloadStore,
+                ProtoDecoder::default(),
+                MetricReporter::new(&env.service_name),
+            )
+        })
+        .await;
+        let emitter = &mut local_fs_emitters_from_env(&env, output_dir, time_based_key_fn).await;
 
-    let s3_payload_retriever = &mut make_ten(async {
-        S3PayloadRetriever::new(
-            |region_str| grapl_config::env_helpers::init_s3_client(&region_str),
-            ProtoDecoder::default(),
+        info!("Starting process_loop over {:?}", input_dir);
+        sqs_executor::process_loop(
+            local_fs_message_source_from_env(&env, input_dir),
+            cache,
+            graph_merger,
+            payload_retriever,
+            emitter,
+            serializer,
+            grapl_config::retry_policy(),
+            grapl_config::concurrency_policy(),
+            grapl_config::shutdown(),
+            heartbeat,
             MetricReporter::new(&env.service_name),
         )
-    })
-    .await;
+        .await;
+    } else {
+        // Keyed by the zstd dictionary the serializer compressed each payload with
+        let key_fn = zstd_proto_key_fn(env.service_name.clone());
+        let s3_emitter = &mut s3_event_emitters_from_env(&env, key_fn).await;
 
-    info!("Starting process_loop");
-    sqs_executor::process_loop(
-        grapl_config::source_queue_url(),
-        grapl_config::dead_letter_queue_url(),
-        cache,
-        sqs_client.clone(),
-        graph_merger,
-        s3_payload_retriever,
-        s3_emitter,
-        serializer,
-        MetricReporter::new(&env.service_name),
-    )
-    .await;
+        let s3_payload_retriever = &mut make_workers(grapl_config::worker_count(), async {
+            S3PayloadRetriever::new(
+                |region_str| grapl_config::env_helpers::init_s3_client(&region_str),
+                ProtoDecoder::default(),
+                MetricReporter::new(&env.service_name),
+            )
+        })
+        .await;
+
+        info!("Starting process_loop");
+        sqs_executor::process_loop(
+            sqs_message_source_from_env(&env),
+            cache,
+            graph_merger,
+            s3_payload_retriever,
+            s3_emitter,
+            serializer,
+            grapl_config::retry_policy(),
+            grapl_config::concurrency_policy(),
+            grapl_config::shutdown(),
+            heartbeat,
+            MetricReporter::new(&env.service_name),
+        )
+        .await;
+    }
 
     info!("Exiting");
 
@@ -0,0 +1,54 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use dgraph_tonic::{
+    Client as DgraphClient,
+    Query,
+};
+use sqs_executor::health::{
+    CheckError,
+    ReadinessCheck,
+};
+
+use crate::reverse_resolver::ReverseEdgeResolver;
+
+/// Ready while an alpha answers a trivial query
+#[derive(Clone)]
+pub struct DgraphCheck {
+    dgraph_client: Arc<DgraphClient>,
+}
+
+impl DgraphCheck {
+    pub fn new(dgraph_client: Arc<DgraphClient>) -> Self {
+        Self { dgraph_client }
+    }
+}
+
+#[async_trait]
+impl ReadinessCheck for DgraphCheck {
+    fn name(&self) -> &str {
+        "dgraph"
+    }
+
+    async fn check(&self) -> Result<(), CheckError> {
+        let mut txn = self.dgraph_client.new_read_only_txn();
+        txn.query("{ q(func: uid(0x1)) { uid } }").await?;
+        Ok(())
+    }
+}
+
+/// Ready once a model plugin schema declaring at least one edge has been loaded, since
+/// until then no edge can be reversed
+#[async_trait]
+impl ReadinessCheck for ReverseEdgeResolver {
+    fn name(&self) -> &str {
+        "model_plugin_schemas"
+    }
+
+    async fn check(&self) -> Result<(), CheckError> {
+        if self.edge_count() == 0 {
+            return Err("No model plugin schemas have been deployed".into());
+        }
+        Ok(())
+    }
+}
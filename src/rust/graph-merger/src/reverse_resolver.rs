@@ -1,123 +1,407 @@
 use std::{
-    collections::HashMap,
+    collections::{
+        BTreeSet,
+        HashMap,
+    },
+    convert::TryFrom,
     io::Stdout,
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::{
+        Arc,
+        RwLock,
+    },
+    time::{
+        Duration,
+        SystemTime,
+    },
 };
 
-use grapl_observe::metric_reporter::MetricReporter;
-use grapl_utils::{
-    future_ext::GraplFutureExt,
-    rusoto_ext::dynamodb::GraplDynamoDbClientExt,
+use graphql_parser::schema::{
+    parse_schema,
+    Definition,
+    TypeDefinition,
 };
-use lazy_static::lazy_static;
-use rusoto_dynamodb::{
-    AttributeValue,
-    BatchGetItemInput,
-    DynamoDbClient,
-    KeysAndAttributes,
+use grapl_graphql_codegen::node_type::NodeTypeOrExtension;
+use grapl_observe::metric_reporter::{
+    tag,
+    MetricReporter,
 };
-use rust_proto::graph_descriptions::Edge;
-use serde::{
-    Deserialize,
-    Serialize,
+use rust_proto::graph_descriptions::{
+    Edge,
+    EdgeList,
 };
 
-use crate::service::GraphMergerError;
+#[derive(thiserror::Error, Debug)]
+pub enum ReverseEdgeSchemaError {
+    #[error("Failed to read model plugin schema: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Model plugin schema directory {0} doesn't exist")]
+    MissingSchemaDir(String),
+    #[error("Failed to parse model plugin schema {path}: {message}")]
+    Parse { path: String, message: String },
+    #[error("Edge {edge_name} is declared with reverse {reverse_edge_name}, but was already declared with reverse {existing_reverse_edge_name}")]
+    ConflictingReverseEdge {
+        edge_name: String,
+        reverse_edge_name: String,
+        existing_reverse_edge_name: String,
+    },
+    #[error("Invalid edge name {edge_name:?}: {reason}")]
+    InvalidEdgeName {
+        edge_name: String,
+        reason: &'static str,
+    },
+}
+
+/// Predicates that the graph merger writes itself, and so can't be declared as edges
+const RESERVED_PREDICATES: &[&str] = &["uid", "node_key", "dgraph.type"];
+
+/// The reverse of every edge declared by the model plugin schemas, as described by the
+/// `@edge(reverse: "...")` directive
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ReverseEdgeSchema {
+    reverse_edges: HashMap<String, String>,
+    node_types: BTreeSet<String>,
+    property_names: BTreeSet<String>,
+    edge_target_types: HashMap<String, String>,
+}
+
+impl ReverseEdgeSchema {
+    /// Loads every `.graphql` schema in `schema_dir`, which must exist
+    pub fn from_dir(schema_dir: &Path) -> Result<Self, ReverseEdgeSchemaError> {
+        let schema = Self::load_dir(schema_dir, None)?;
+        schema.validate_edge_names()?;
+        Ok(schema)
+    }
+
+    /// Loads the schemas in `schema_dir` as they'd be once `raw_schema` is written to
+    /// `schema_path`, replacing any schema already there, so that a schema can be checked
+    /// against the deployed ones before it's deployed
+    pub fn from_dir_with(
+        schema_dir: &Path,
+        schema_path: &Path,
+        raw_schema: &str,
+    ) -> Result<Self, ReverseEdgeSchemaError> {
+        let mut schema = Self::load_dir(schema_dir, Some(schema_path))?;
+        schema.add_graphql_schema(&schema_path.to_string_lossy(), raw_schema)?;
+        schema.validate_edge_names()?;
+        Ok(schema)
+    }
+
+    fn load_dir(
+        schema_dir: &Path,
+        replaced_path: Option<&Path>,
+    ) -> Result<Self, ReverseEdgeSchemaError> {
+        let schema_paths = schema_paths(schema_dir).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => {
+                ReverseEdgeSchemaError::MissingSchemaDir(schema_dir.to_string_lossy().into_owned())
+            }
+            _ => e.into(),
+        })?;
+
+        let mut schema = Self::default();
+        for schema_path in schema_paths {
+            if Some(schema_path.as_path()) == replaced_path {
+                continue;
+            }
+            let raw_schema = std::fs::read_to_string(&schema_path)?;
+            schema.add_graphql_schema(&schema_path.to_string_lossy(), &raw_schema)?;
+        }
+        Ok(schema)
+    }
+
+    pub fn from_graphql(raw_schema: &str) -> Result<Self, ReverseEdgeSchemaError> {
+        let mut schema = Self::default();
+        schema.add_graphql_schema("<inline>", raw_schema)?;
+        schema.validate_edge_names()?;
+        Ok(schema)
+    }
+
+    fn add_graphql_schema(
+        &mut self,
+        path: &str,
+        raw_schema: &str,
+    ) -> Result<(), ReverseEdgeSchemaError> {
+        let parse_error = |message: String| ReverseEdgeSchemaError::Parse {
+            path: path.to_owned(),
+            message,
+        };
+        let document = parse_schema::<&str>(raw_schema).map_err(|e| parse_error(e.to_string()))?;
+
+        // Extensions are read directly rather than through `parse_into_node_types`, since the
+        // node type they extend may be declared by a different model plugin
+        for definition in document.definitions.iter() {
+            let object = match definition {
+                Definition::TypeDefinition(TypeDefinition::Object(object)) => object,
+                _ => continue,
+            };
+            let (predicates, edges) = match NodeTypeOrExtension::try_from(object)
+                .map_err(|e| parse_error(e.to_string()))?
+            {
+                NodeTypeOrExtension::NodeType(node_type) => {
+                    self.node_types.insert(node_type.type_name);
+                    (node_type.predicates, node_type.edges)
+                }
+                NodeTypeOrExtension::NodeExtension(node_extension) => {
+                    (node_extension.predicates, node_extension.edges)
+                }
+            };
+            self.property_names.extend(
+                predicates
+                    .into_iter()
+                    .map(|predicate| predicate.predicate_name),
+            );
+
+            for edge in edges {
+                self.edge_target_types
+                    .insert(edge.edge_name.clone(), edge.target_type_name.clone());
+                self.add_reverse_edge(&edge.edge_name, &edge.reverse_edge_name)?;
+                let edge = edge.reverse();
+                self.edge_target_types
+                    .insert(edge.edge_name.clone(), edge.target_type_name.clone());
+                self.add_reverse_edge(&edge.edge_name, &edge.reverse_edge_name)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn add_reverse_edge(
+        &mut self,
+        edge_name: &str,
+        reverse_edge_name: &str,
+    ) -> Result<(), ReverseEdgeSchemaError> {
+        match self.reverse_edges.get(edge_name) {
+            Some(existing) if existing != reverse_edge_name => {
+                Err(ReverseEdgeSchemaError::ConflictingReverseEdge {
+                    edge_name: edge_name.to_owned(),
+                    reverse_edge_name: reverse_edge_name.to_owned(),
+                    existing_reverse_edge_name: existing.clone(),
+                })
+            }
+            Some(_) => Ok(()),
+            None => {
+                self.reverse_edges
+                    .insert(edge_name.to_owned(), reverse_edge_name.to_owned());
+                Ok(())
+            }
+        }
+    }
+
+    /// Checks every edge, and every reverse edge, against the names that Dgraph would reject
+    /// or that are already used for something else. Properties are checked across all of the
+    /// schemas, since Dgraph predicates aren't scoped to a node type.
+    fn validate_edge_names(&self) -> Result<(), ReverseEdgeSchemaError> {
+        let mut edge_names: Vec<_> = self.reverse_edges.keys().collect();
+        edge_names.sort_unstable();
+        for edge_name in edge_names {
+            let invalid = |reason| {
+                Err(ReverseEdgeSchemaError::InvalidEdgeName {
+                    edge_name: edge_name.clone(),
+                    reason,
+                })
+            };
+            if !is_identifier(edge_name) {
+                return invalid("edge names must be made of letters, digits and underscores");
+            }
+            if RESERVED_PREDICATES.contains(&edge_name.as_str()) {
+                return invalid("the name is reserved");
+            }
+            if self.property_names.contains(edge_name) {
+                return invalid("the name is already declared as a property");
+            }
+        }
+        Ok(())
+    }
+
+    pub fn reverse_edge_name(&self, edge_name: &str) -> Option<&str> {
+        self.reverse_edges.get(edge_name).map(String::as_str)
+    }
+
+    /// Returns the edges that point to a node type that none of the schemas define. These
+    /// edges can still be reversed, but nothing will be able to query the nodes they point to.
+    pub fn edges_with_unknown_targets(&self) -> Vec<(&str, &str)> {
+        let mut unknown: Vec<_> = self
+            .edge_target_types
+            .iter()
+            .filter(|(_, target_type)| !self.node_types.contains(*target_type))
+            .map(|(edge_name, target_type)| (edge_name.as_str(), target_type.as_str()))
+            .collect();
+        unknown.sort_unstable();
+        unknown
+    }
+
+    pub fn len(&self) -> usize {
+        self.reverse_edges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reverse_edges.is_empty()
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+/// The schemas in `schema_dir`. The directory is created by mounting it, so a missing one is
+/// an error rather than there being no schemas.
+fn schema_paths(schema_dir: &Path) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut schema_paths = vec![];
+    for entry in std::fs::read_dir(schema_dir)? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .map(|ext| ext == "graphql")
+            .unwrap_or(false)
+        {
+            schema_paths.push(path);
+        }
+    }
+    schema_paths.sort_unstable();
+    Ok(schema_paths)
+}
 
-lazy_static! {
-    /// timeout for dynamodb queries
-    static ref DYNAMODB_QUERY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(3);
+/// The most recent modification time of any schema in `schema_dir`, used to detect
+/// newly deployed models
+fn schemas_last_modified(schema_dir: &Path) -> Result<Option<SystemTime>, std::io::Error> {
+    let mut last_modified = None;
+    for schema_path in schema_paths(schema_dir)? {
+        let modified = std::fs::metadata(&schema_path)?.modified()?;
+        last_modified = last_modified.max(Some(modified));
+    }
+    Ok(last_modified)
 }
 
 #[derive(Clone)]
 pub struct ReverseEdgeResolver {
-    dynamo: DynamoDbClient,
-    r_edge_cache: std::sync::Arc<std::sync::Mutex<lru::LruCache<String, String>>>,
+    schema: Arc<RwLock<ReverseEdgeSchema>>,
     metric_reporter: MetricReporter<Stdout>,
 }
 
 impl ReverseEdgeResolver {
-    pub fn new(
-        dynamo: DynamoDbClient,
-        metric_reporter: MetricReporter<Stdout>,
-        cache_size: usize,
-    ) -> Self {
-        let r_edge_cache = lru::LruCache::new(cache_size);
-        let r_edge_cache = std::sync::Arc::new(std::sync::Mutex::new(r_edge_cache));
+    pub fn new(schema: ReverseEdgeSchema, metric_reporter: MetricReporter<Stdout>) -> Self {
         Self {
-            dynamo,
-            r_edge_cache,
+            schema: Arc::new(RwLock::new(schema)),
             metric_reporter,
         }
     }
 
-    pub async fn resolve_reverse_edges(
+    /// Loads the model plugin schemas from `schema_dir`, flagging any edges that
+    /// point to node types the schemas don't define
+    pub fn from_schema_dir(
+        schema_dir: &Path,
+        metric_reporter: MetricReporter<Stdout>,
+    ) -> Result<Self, ReverseEdgeSchemaError> {
+        let schema = ReverseEdgeSchema::from_dir(schema_dir)?;
+        check_schema(&schema);
+        Ok(Self::new(schema, metric_reporter))
+    }
+
+    /// Periodically reloads the schemas in `schema_dir` whenever one of them changes, so that
+    /// newly deployed models are picked up without a restart. If a reload fails the
+    /// previously loaded schema is kept.
+    pub fn spawn_schema_reloader(
         &self,
-        edges: Vec<Edge>,
-    ) -> Result<Vec<Edge>, GraphMergerError> {
+        schema_dir: PathBuf,
+        poll_interval: Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        let schema = self.schema.clone();
+        tokio::spawn(async move {
+            let mut last_modified = schemas_last_modified(&schema_dir).ok().flatten();
+            let mut interval = tokio::time::interval(poll_interval);
+            loop {
+                interval.tick().await;
+                let modified = match schemas_last_modified(&schema_dir) {
+                    Ok(modified) => modified,
+                    Err(e) => {
+                        tracing::warn!(message="Failed to check model plugin schemas", error=?e);
+                        continue;
+                    }
+                };
+                if modified == last_modified {
+                    continue;
+                }
+
+                match ReverseEdgeSchema::from_dir(&schema_dir) {
+                    Ok(reloaded) => {
+                        check_schema(&reloaded);
+                        tracing::info!(
+                            message = "Reloaded model plugin schemas",
+                            edge_count = reloaded.len()
+                        );
+                        *schema.write().unwrap() = reloaded;
+                        last_modified = modified;
+                    }
+                    Err(e) => {
+                        tracing::error!(message="Failed to reload model plugin schemas", error=?e);
+                    }
+                }
+            }
+        })
+    }
+
+    /// The number of edges, including reverse edges, that the loaded schemas declare
+    pub fn edge_count(&self) -> usize {
+        self.schema.read().unwrap().len()
+    }
+
+    pub fn resolve_reverse_edges(&self, edges: Vec<Edge>) -> Vec<Edge> {
         if edges.is_empty() {
-            return Ok(vec![]);
-        }
-        let (mut reversed, remaining) = self.resolve_reverse_edges_from_cache(edges).await;
-        if remaining.is_empty() {
-            return Ok(reversed);
+            return vec![];
         }
-        let mut edge_names: Vec<&String> = remaining.iter().map(|e| &e.edge_name).collect();
-        edge_names.sort_unstable();
-        edge_names.dedup();
-
-        let resolved = get_r_edges_from_dynamodb(&self.dynamo, &edge_names).await?;
 
-        let cache = self.r_edge_cache.clone();
-        let mut cache = cache.lock().unwrap();
-        for f_edge in remaining.into_iter() {
-            if let Some(Some(r_edge_name)) = resolved.get(&f_edge.edge_name) {
-                cache.put(f_edge.edge_name.clone(), r_edge_name.clone());
-                cache.put(r_edge_name.clone(), f_edge.edge_name.clone());
-                reversed.push(reverse_edge(&f_edge, r_edge_name.to_owned()));
+        let mut reversed = Vec::with_capacity(edges.len());
+        let mut unknown = HashMap::new();
+        let schema = self.schema.read().unwrap();
+        for edge in edges.iter() {
+            match schema.reverse_edge_name(&edge.edge_name) {
+                Some(r_edge_name) => reversed.push(reverse_edge(edge, r_edge_name.to_owned())),
+                None => *unknown.entry(edge.edge_name.as_str()).or_insert(0) += 1,
             }
         }
-        reversed.sort_unstable();
-        reversed.dedup();
-        Ok(reversed)
-    }
+        drop(schema);
 
-    pub async fn resolve_reverse_edges_from_cache(
-        &self,
-        edges: Vec<Edge>,
-    ) -> (Vec<Edge>, Vec<Edge>) {
-        let mut reversed = vec![];
-        let mut remaining = vec![];
-        let mut cache_hit = 0;
-        let mut cache_miss = 0;
-        let cache = self.r_edge_cache.clone();
-        let mut cache = cache.lock().unwrap();
-        for edge in edges.into_iter() {
-            match cache.get(&edge.edge_name).map(String::from) {
-                Some(r_edge_name) => {
-                    reversed.push(reverse_edge(&edge, r_edge_name));
-                    cache_hit += 1;
-                }
-                None => {
-                    remaining.push(edge);
-                    cache_miss += 1;
-                }
-            }
+        for (edge_name, count) in unknown {
+            tracing::warn!(
+                message = "No reverse edge is known for edge",
+                edge_name = edge_name,
+                count = count
+            );
+            self.metric_reporter
+                .clone()
+                .counter(
+                    "reverse_resolver.unknown_edge.count",
+                    count as f64,
+                    None,
+                    &[tag("edge_name", edge_name)],
+                )
+                .unwrap_or_else(|e| tracing::warn!(message="Metric failed.", error=?e));
         }
-        drop(cache);
 
-        let _ = self.metric_reporter.clone().counter(
-            "reverse_resolver.cache.hit.count",
-            cache_hit as f64,
-            0.10,
-            &[],
-        );
-        let _ = self.metric_reporter.clone().counter(
-            "reverse_resolver.cache.miss.count",
-            cache_miss as f64,
-            0.10,
-            &[],
+        let mut reversed = EdgeList { edges: reversed };
+        reversed.merge_duplicates();
+        reversed.into_vec()
+    }
+}
+
+fn check_schema(schema: &ReverseEdgeSchema) {
+    if schema.is_empty() {
+        tracing::warn!(message = "No edges are declared by the model plugin schemas");
+    }
+    for (edge_name, target_type) in schema.edges_with_unknown_targets() {
+        tracing::warn!(
+            message = "Edge points to a node type that isn't declared by any schema",
+            edge_name = edge_name,
+            target_type = target_type,
         );
-        (reversed, remaining)
     }
 }
 
@@ -126,109 +410,121 @@ fn reverse_edge(edge: &Edge, reverse_edge_name: String) -> Edge {
         from_node_key: edge.to_node_key.to_owned(),
         to_node_key: edge.from_node_key.to_owned(),
         edge_name: reverse_edge_name,
+        properties: edge.properties.clone(),
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct EdgeMapping {
-    r_edge: String,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-/// Returns a HashMap of f_edge -> Optional r_edge entries from dynamodb
-pub async fn get_r_edges_from_dynamodb(
-    client: &DynamoDbClient,
-    f_edges: &[&String],
-) -> Result<HashMap<String, Option<String>>, GraphMergerError> {
-    let schema_table_name = std::env::var("GRAPL_SCHEMA_TABLE").expect("GRAPL_SCHEMA_TABLE");
-
-    let keys_and_attributes = make_keys(f_edges);
-    tracing::debug!(
-        message="Querying dynamodb for reverse edges",
-        edge_count=?keys_and_attributes.keys.len(),
-    );
-    let mut request_items = HashMap::with_capacity(1);
-    request_items.insert(schema_table_name.clone(), keys_and_attributes);
-
-    let query = BatchGetItemInput {
-        request_items,
-        return_consumed_capacity: None,
-    };
-
-    /*
-       1. Map timeout error
-       2. Map Rusoto error
-       3. Grab responses (HashMap<String, Vec<HashMap<String, AttributeValue>>>) (or return error)
-       4. Pull the entire set of responses out for the schema table (Vec<HashMap<String, AttributeValue>>) (or return error)
-    */
-    let schema_table_response: Vec<HashMap<String, AttributeValue>> = client
-        .batch_get_item_reliably(query)
-        .timeout(*DYNAMODB_QUERY_TIMEOUT)
-        .await
-        .map_err(|e| GraphMergerError::Unexpected(e.to_string()))?
-        .map_err(|e| GraphMergerError::Unexpected(e.to_string()))?
-        .responses
-        .ok_or(GraphMergerError::Unexpected(
-            "Failed to fetch results from dynamodb".to_string(),
-        ))?
-        .remove(&schema_table_name)
-        .ok_or(GraphMergerError::Unexpected(
-            "Missing data from expected table in dynamodb".to_string(),
-        ))?;
-
-    /*
-       1. Remove entries without f_edge and r_edge
-       2. Grab both f_edge and r_edge properties as strings (`.s`)
-       3. If both are present, return; otherwise error and filter entry out
-    */
-    Ok(schema_table_response
-        .into_iter()
-        .filter(|hashmap| hashmap.contains_key("f_edge") && hashmap.contains_key("r_edge"))
-        .filter_map(|hashmap| {
-            match (
-                hashmap.get("f_edge").map(|item| item.s.clone()),
-                hashmap.get("r_edge").map(|item| item.s.clone()),
-            ) {
-                (Some(Some(f_edge)), Some(r_edge)) => Some((f_edge, r_edge)),
-                (Some(Some(f_edge)), _) => {
-                    tracing::error!(
-                        message="Missing r_edge for f_edge in dynamodb schema.",
-                        f_edge=?f_edge,
-                    );
-                    None
-                }
-                (None, Some(Some(r_edge))) => {
-                    tracing::error!(
-                        message="Failed to associate retrieved r_edge with an f_edge",
-                        r_edge=?r_edge,
-                    );
-                    None
-                }
-                _ => None,
+    const SCHEMA: &str = r#"
+        type Process @grapl(identity_algorithm: "session") {
+            process_id: UInt! @pseudo_key,
+            created_at: UInt! @create_time,
+            last_seen_at: UInt! @last_seen_time,
+            terminated_at: UInt! @terminate_time,
+            binary_file: File! @edge(reverse: "executed_as_processes", reverse_relationship: "ToMany"),
+            children: [Process!] @edge(reverse: "parent", reverse_relationship: "ToOne"),
+        }
+    "#;
+
+    #[test]
+    fn test_reverse_edges_from_schema() -> Result<(), ReverseEdgeSchemaError> {
+        let schema = ReverseEdgeSchema::from_graphql(SCHEMA)?;
+        assert_eq!(
+            schema.reverse_edge_name("binary_file"),
+            Some("executed_as_processes")
+        );
+        assert_eq!(
+            schema.reverse_edge_name("executed_as_processes"),
+            Some("binary_file")
+        );
+        assert_eq!(schema.reverse_edge_name("children"), Some("parent"));
+        assert_eq!(schema.reverse_edge_name("parent"), Some("children"));
+        assert_eq!(schema.reverse_edge_name("created_files"), None);
+
+        // `File` isn't declared, so the forward edge targets an unknown type
+        assert_eq!(
+            schema.edges_with_unknown_targets(),
+            vec![("binary_file", "File")]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_conflicting_reverse_edges() {
+        let schema = format!(
+            "{}\n{}",
+            SCHEMA,
+            r#"
+            type File @grapl(identity_algorithm: "session") {
+                file_path: String! @pseudo_key,
+                created_at: UInt! @create_time,
+                last_seen_at: UInt! @last_seen_time,
+                terminated_at: UInt! @terminate_time,
+                executed_as_processes: [Process!] @edge(reverse: "spawned_from", reverse_relationship: "ToOne"),
             }
-        })
-        .collect())
-}
+            "#
+        );
+        assert!(matches!(
+            ReverseEdgeSchema::from_graphql(&schema),
+            Err(ReverseEdgeSchemaError::ConflictingReverseEdge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_invalid_edge_names() {
+        let reverse_edge_named = |reverse_edge_name: &str| {
+            SCHEMA.replace(
+                r#"reverse: "parent""#,
+                &format!("reverse: {:?}", reverse_edge_name),
+            )
+        };
 
-fn make_keys(f_edges: &[&String]) -> KeysAndAttributes {
-    let keys: Vec<HashMap<String, AttributeValue>> = f_edges
-        .iter()
-        .map(|f_edge| {
-            let mut key_map = HashMap::new();
-            key_map.insert(
-                "f_edge".to_string(),
-                AttributeValue {
-                    s: Some(f_edge.to_string()),
-                    ..Default::default()
-                },
+        for reverse_edge_name in ["parent-process", "node_key", "process_id"] {
+            assert!(
+                matches!(
+                    ReverseEdgeSchema::from_graphql(&reverse_edge_named(reverse_edge_name)),
+                    Err(ReverseEdgeSchemaError::InvalidEdgeName { edge_name, .. })
+                        if edge_name == reverse_edge_name
+                ),
+                "{} should be rejected",
+                reverse_edge_name
             );
+        }
+    }
 
-            key_map
-        })
-        .collect();
+    #[test]
+    fn test_schema_checked_against_deployed_schemas() -> Result<(), Box<dyn std::error::Error>> {
+        let schema_dir = std::env::temp_dir().join("grapl-deployed-model-plugin-schemas");
+        std::fs::create_dir_all(&schema_dir)?;
+        let process_path = schema_dir.join("process.graphql");
+        std::fs::write(&process_path, SCHEMA)?;
+        let redeployed = SCHEMA.replace(r#"reverse: "parent""#, r#"reverse: "parent_process""#);
+
+        // Redeploying a model replaces its previous schema
+        let schema = ReverseEdgeSchema::from_dir_with(&schema_dir, &process_path, &redeployed)?;
+        assert_eq!(schema.reverse_edge_name("children"), Some("parent_process"));
+
+        // But a different model can't conflict with it
+        assert!(matches!(
+            ReverseEdgeSchema::from_dir_with(
+                &schema_dir,
+                &schema_dir.join("other.graphql"),
+                &redeployed
+            ),
+            Err(ReverseEdgeSchemaError::ConflictingReverseEdge { .. })
+        ));
+        Ok(())
+    }
 
-    KeysAndAttributes {
-        consistent_read: Some(true),
-        keys,
-        ..Default::default()
+    #[test]
+    fn test_missing_schema_dir() {
+        let schema_dir = std::env::temp_dir().join("grapl-no-such-model-plugin-schemas");
+        assert!(matches!(
+            ReverseEdgeSchema::from_dir(&schema_dir),
+            Err(ReverseEdgeSchemaError::MissingSchemaDir(_))
+        ));
     }
 }
@@ -1,7 +1,6 @@
 use std::{
     fmt::Debug,
     io::Stdout,
-    sync::Arc,
     time::{
         SystemTime,
         UNIX_EPOCH,
@@ -9,9 +8,9 @@ use std::{
 };
 
 use async_trait::async_trait;
-use dgraph_tonic::Client as DgraphClient;
 use grapl_observe::metric_reporter::MetricReporter;
 use rust_proto::graph_descriptions::{
+    Edge,
     IdentifiedGraph,
     MergedGraph,
 };
@@ -33,29 +32,31 @@ use tracing::{
 };
 
 use crate::{
+    graph_store::{
+        GraphStore,
+        GraphStoreError,
+    },
     reverse_resolver::ReverseEdgeResolver,
     upserter,
 };
 
 #[derive(Clone)]
-pub struct GraphMerger<CacheT: Cache> {
-    mg_client: Arc<DgraphClient>,
+pub struct GraphMerger<GraphStoreT: GraphStore, CacheT: Cache> {
+    graph_store: GraphStoreT,
     reverse_edge_resolver: ReverseEdgeResolver,
     metric_reporter: MetricReporter<Stdout>,
     cache: CacheT,
 }
 
-impl<CacheT: Cache> GraphMerger<CacheT> {
+impl<GraphStoreT: GraphStore, CacheT: Cache> GraphMerger<GraphStoreT, CacheT> {
     pub fn new(
-        mg_alphas: Vec<String>,
+        graph_store: GraphStoreT,
         reverse_edge_resolver: ReverseEdgeResolver,
         metric_reporter: MetricReporter<Stdout>,
         cache: CacheT,
     ) -> Self {
-        let mg_client = DgraphClient::new(mg_alphas).expect("Failed to create dgraph client.");
-
         Self {
-            mg_client: Arc::new(mg_client),
+            graph_store,
             reverse_edge_resolver,
             metric_reporter,
             cache,
@@ -67,6 +68,10 @@ impl<CacheT: Cache> GraphMerger<CacheT> {
 pub enum GraphMergerError {
     #[error("UnexpectedError")]
     Unexpected(String),
+    #[error("GraphStoreError")]
+    GraphStore(#[from] GraphStoreError),
+    #[error("Failed to resolve the endpoints of {} edge(s)", .0.len())]
+    UnresolvedEdges(Vec<Edge>),
 }
 
 impl CheckedError for GraphMergerError {
@@ -76,7 +81,7 @@ impl CheckedError for GraphMergerError {
 }
 
 #[async_trait]
-impl<CacheT: Cache> EventHandler for GraphMerger<CacheT> {
+impl<GraphStoreT: GraphStore, CacheT: Cache> EventHandler for GraphMerger<GraphStoreT, CacheT> {
     type InputEvent = IdentifiedGraph;
     type OutputEvent = MergedGraph;
     type Error = GraphMergerError;
@@ -106,9 +111,7 @@ impl<CacheT: Cache> EventHandler for GraphMerger<CacheT> {
             .collect();
         let reverse = self
             .reverse_edge_resolver
-            .resolve_reverse_edges(uncached_edges.clone())
-            .await
-            .map_err(Err)?;
+            .resolve_reverse_edges(uncached_edges.clone());
 
         uncached_edges.extend_from_slice(&reverse[..]);
 
@@ -120,16 +123,41 @@ impl<CacheT: Cache> EventHandler for GraphMerger<CacheT> {
         }
 
         for edge in uncached_edges {
-            uncached_subgraph.add_edge(edge.edge_name, edge.from_node_key, edge.to_node_key);
+            uncached_subgraph.add_edge_with_properties(
+                edge.edge_name,
+                edge.from_node_key,
+                edge.to_node_key,
+                edge.properties,
+            );
+        }
+        // An edge may have been sent along with its reverse, which was resolved again above
+        for edge_list in uncached_subgraph.edges.values_mut() {
+            edge_list.merge_duplicates();
         }
 
-        upserter::GraphMergeHelper {}
-            .upsert_into(
-                self.mg_client.clone(),
-                &uncached_subgraph,
-                &mut merged_graph,
-            )
-            .await;
+        let unresolved_edges = upserter::GraphMergeHelper {}
+            .upsert_into(&self.graph_store, &uncached_subgraph, &mut merged_graph)
+            .await
+            .map_err(|e| Err(e.into()))?;
+
+        if !unresolved_edges.is_empty() {
+            // The endpoints may belong to an event that hasn't been merged yet, so these edges
+            // are retried along with the rest of the event rather than dropped
+            self.metric_reporter
+                .counter(
+                    "graph_merger.unresolved_edges.count",
+                    unresolved_edges.len() as f64,
+                    None,
+                    &[],
+                )
+                .unwrap_or_else(
+                    |e| error!(message="failed to report unresolved_edges.count", error=?e),
+                );
+            return Err(Ok((
+                merged_graph,
+                GraphMergerError::UnresolvedEdges(unresolved_edges),
+            )));
+        }
 
         Ok(merged_graph)
     }
@@ -3,11 +3,16 @@ use std::collections::HashMap;
 use node_property::Property::{
     DecrementOnlyInt as ProtoDecrementOnlyIntProp,
     DecrementOnlyUint as ProtoDecrementOnlyUintProp,
+    ImmutableBool as ProtoImmutableBoolProp,
+    ImmutableBytes as ProtoImmutableBytesProp,
+    ImmutableFloat as ProtoImmutableFloatProp,
     ImmutableInt as ProtoImmutableIntProp,
     ImmutableStr as ProtoImmutableStrProp,
     ImmutableUint as ProtoImmutableUintProp,
     IncrementOnlyInt as ProtoIncrementOnlyIntProp,
     IncrementOnlyUint as ProtoIncrementOnlyUintProp,
+    StrSet as ProtoStrSetProp,
+    Timestamp as ProtoTimestampProp,
 };
 use rust_proto::graph_descriptions::*;
 
@@ -47,6 +52,15 @@ fn escape_quote(s: &str) -> Escaped {
     Escaped(output)
 }
 
+/// Escapes each of the property's values. Every property has exactly one value, except for
+/// sets, which have one per member.
+fn escape_prop_values(node_property: &NodeProperty) -> Vec<Escaped> {
+    match &node_property.property {
+        Some(ProtoStrSetProp(s)) => s.as_inner().iter().map(|s| escape_quote(s)).collect(),
+        _ => vec![escape_prop(node_property)],
+    }
+}
+
 fn escape_prop(node_property: &NodeProperty) -> Escaped {
     match &node_property.property {
         Some(ProtoIncrementOnlyIntProp(i)) => escape_quote(&i.to_string()),
@@ -56,6 +70,12 @@ fn escape_prop(node_property: &NodeProperty) -> Escaped {
         Some(ProtoDecrementOnlyUintProp(i)) => escape_quote(&i.to_string()),
         Some(ProtoImmutableUintProp(i)) => escape_quote(&i.to_string()),
         Some(ProtoImmutableStrProp(s)) => escape_quote(s.as_inner()),
+        Some(ProtoImmutableBoolProp(b)) => escape_quote(&b.to_string()),
+        Some(ProtoImmutableFloatProp(f)) => escape_quote(&f.to_string()),
+        // Dgraph has no bytes type, so they're stored as base64
+        Some(ProtoImmutableBytesProp(b)) => escape_quote(&base64::encode(b.as_inner())),
+        Some(ProtoTimestampProp(t)) => escape_quote(&t.to_string()),
+        Some(ProtoStrSetProp(_)) => panic!("Sets have many values, use escape_prop_values"),
         None => panic!("todo"),
     }
 }
@@ -79,20 +99,31 @@ pub(crate) fn build_upserts(
     mutations.push(creation_quad);
     inner_queries.push_str(&creation_query);
     inner_queries.push('\n');
-    for (prop_name, prop) in properties.iter() {
+    for (prop_index, (prop_name, prop)) in properties.iter().enumerate() {
         if &prop_name == &"node_key" {
             continue;
         }
         if &prop_name == &"dgraph.type" {
             continue;
         }
+        if prop.property.is_none() {
+            tracing::warn!(
+                message="skipping property without a value",
+                predicate_name=?prop_name,
+            );
+            continue;
+        }
+        if let Some(ProtoStrSetProp(s)) = &prop.property {
+            if s.as_inner().is_empty() {
+                continue;
+            }
+        }
         tracing::debug!(
             message="generating upsert quads for predicate",
             predicate_name=?prop_name,
         );
-        let prop_value = escape_prop(prop);
         let (next_query, muts) =
-            gen_node_property_upsert_quads(&creation_var_name, prop_name, &prop_value);
+            gen_node_property_upsert_quads(&creation_var_name, prop_index, prop_name, prop);
         inner_queries.push_str(&next_query);
         inner_queries.push('\n');
         mutations.extend_from_slice(&muts[..]);
@@ -143,44 +174,112 @@ pub(crate) fn node_creation_quads(
     (creation_var_name, inner_query, mu_1)
 }
 
+/// Returns the filter that must hold for `prop` to be written over an existing value, or
+/// `None` if it can always be written.
+///
+/// Because the comparison happens within the upsert's transaction, concurrent writers to the
+/// same predicate will conflict and be retried rather than overwriting each other.
+fn merge_filter(prop_name: &str, prop: &NodeProperty) -> Option<String> {
+    let increment_only = || {
+        format!(
+            "NOT has({prop_name}) OR lt({prop_name}, {prop_value})",
+            prop_name = prop_name,
+            prop_value = escape_prop(prop),
+        )
+    };
+    let decrement_only = || {
+        format!(
+            "NOT has({prop_name}) OR gt({prop_name}, {prop_value})",
+            prop_name = prop_name,
+            prop_value = escape_prop(prop),
+        )
+    };
+    let immutable = || format!("NOT has({prop_name})", prop_name = prop_name);
+
+    match &prop.property {
+        Some(ProtoIncrementOnlyIntProp(_)) | Some(ProtoIncrementOnlyUintProp(_)) => {
+            Some(increment_only())
+        }
+        Some(ProtoDecrementOnlyIntProp(_)) | Some(ProtoDecrementOnlyUintProp(_)) => {
+            Some(decrement_only())
+        }
+        Some(ProtoImmutableIntProp(_))
+        | Some(ProtoImmutableUintProp(_))
+        | Some(ProtoImmutableStrProp(_))
+        | Some(ProtoImmutableBoolProp(_))
+        | Some(ProtoImmutableFloatProp(_))
+        | Some(ProtoImmutableBytesProp(_)) => Some(immutable()),
+        Some(ProtoTimestampProp(t)) => match t.merge() {
+            TimestampMerge::Latest => Some(increment_only()),
+            TimestampMerge::Earliest => Some(decrement_only()),
+            TimestampMerge::Unspecified => Some(immutable()),
+        },
+        // Setting a list predicate adds to it, so sets are unioned by Dgraph itself
+        Some(ProtoStrSetProp(_)) => None,
+        // Properties without a value are skipped by `build_upserts`
+        None => None,
+    }
+}
+
 pub(crate) fn gen_node_property_upsert_quads(
     creation_var_name: &str,
+    prop_index: usize,
     prop_name: &str,
-    prop_value: &Escaped,
+    prop: &NodeProperty,
 ) -> (String, [dgraph_tonic::Mutation; 2]) {
-    // let mut node_query_name = format!("pred_query_{}_{}_{}", prop_name, query_param, predicate_param);
+    let prop_values = escape_prop_values(prop);
+    let prop_var_name = format!("{}_prop_{}", creation_var_name, prop_index);
     let mut mu_0 = dgraph_tonic::Mutation::new();
 
+    // Only matches the node if writing the new value respects the property's merge semantics
+    let filter = match merge_filter(prop_name, prop) {
+        Some(merge_filter) => format!("@filter({})", merge_filter),
+        None => String::new(),
+    };
     let inner_query = format!(
         r#"
-            var(func: uid({creation_var_name}), first: 1)
+            {prop_var_name} as var(func: uid({creation_var_name}), first: 1) {filter}
     "#,
+        prop_var_name = prop_var_name,
         creation_var_name = creation_var_name,
+        filter = filter,
     );
 
-    // If the node exists, set the predicate. Currently 'last write wins'.
-    let mu_0_n_quads = format!(
-        r#"uid({creation_var_name}) <{prop_name}> {prop_value} ."#,
-        creation_var_name = creation_var_name,
-        prop_name = prop_name,
-        prop_value = prop_value,
-    );
+    // If the node exists and the new value wins the merge, set the predicate.
+    let mu_0_n_quads = prop_values
+        .iter()
+        .map(|prop_value| {
+            format!(
+                r#"uid({prop_var_name}) <{prop_name}> {prop_value} ."#,
+                prop_var_name = prop_var_name,
+                prop_name = prop_name,
+                prop_value = prop_value,
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
 
     mu_0.set_set_nquads(mu_0_n_quads);
     mu_0.set_cond(format!(
-        "@if(eq(len({creation_var_name}), 1))",
-        creation_var_name = creation_var_name
+        "@if(eq(len({prop_var_name}), 1))",
+        prop_var_name = prop_var_name
     ));
 
     let mut mu_1 = dgraph_tonic::Mutation::new();
 
     // condition if the node does not exist
-    let mu_1_n_quads = format!(
-        concat!(r#"_:{creation_var_name} <{prop_name}> {prop_value} ."#,),
-        creation_var_name = creation_var_name,
-        prop_name = prop_name,
-        prop_value = prop_value,
-    );
+    let mu_1_n_quads = prop_values
+        .iter()
+        .map(|prop_value| {
+            format!(
+                r#"_:{creation_var_name} <{prop_name}> {prop_value} ."#,
+                creation_var_name = creation_var_name,
+                prop_name = prop_name,
+                prop_value = prop_value,
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
 
     mu_1.set_set_nquads(mu_1_n_quads);
     mu_1.set_cond(format!(
@@ -190,3 +289,367 @@ pub(crate) fn gen_node_property_upsert_quads(
 
     (inner_query, [mu_0, mu_1])
 }
+
+/// The facet `edge_property` is stored as, or `None` if it has no value
+fn facet_value(edge_property: &NodeProperty) -> Option<serde_json::Value> {
+    let facet = match &edge_property.property {
+        Some(ProtoIncrementOnlyIntProp(i)) => i.as_inner().into(),
+        Some(ProtoDecrementOnlyIntProp(i)) => i.as_inner().into(),
+        Some(ProtoImmutableIntProp(i)) => i.as_inner().into(),
+        Some(ProtoIncrementOnlyUintProp(i)) => i.as_inner().into(),
+        Some(ProtoDecrementOnlyUintProp(i)) => i.as_inner().into(),
+        Some(ProtoImmutableUintProp(i)) => i.as_inner().into(),
+        Some(ProtoImmutableStrProp(s)) => s.as_inner().into(),
+        Some(ProtoImmutableBoolProp(b)) => b.as_inner().into(),
+        Some(ProtoImmutableFloatProp(f)) => f.as_inner().into(),
+        Some(ProtoImmutableBytesProp(b)) => base64::encode(b.as_inner()).into(),
+        Some(ProtoTimestampProp(t)) => t.as_inner().into(),
+        // Facets can't hold lists, so sets are stored as their JSON encoding
+        Some(ProtoStrSetProp(s)) => serde_json::to_string(s.as_inner())
+            .expect("strings are always serializable")
+            .into(),
+        None => return None,
+    };
+    Some(facet)
+}
+
+/// Reads a facet back into a property of the same kind as `like`, or `None` if the facet
+/// doesn't hold a value of that kind
+fn facet_property(like: &NodeProperty, facet: &serde_json::Value) -> Option<NodeProperty> {
+    let property: NodeProperty = match like.property.as_ref()? {
+        ProtoIncrementOnlyIntProp(_) => IncrementOnlyIntProp {
+            prop: facet.as_i64()?,
+        }
+        .into(),
+        ProtoDecrementOnlyIntProp(_) => DecrementOnlyIntProp {
+            prop: facet.as_i64()?,
+        }
+        .into(),
+        ProtoImmutableIntProp(_) => ImmutableIntProp {
+            prop: facet.as_i64()?,
+        }
+        .into(),
+        ProtoIncrementOnlyUintProp(_) => IncrementOnlyUintProp {
+            prop: facet.as_u64()?,
+        }
+        .into(),
+        ProtoDecrementOnlyUintProp(_) => DecrementOnlyUintProp {
+            prop: facet.as_u64()?,
+        }
+        .into(),
+        ProtoImmutableUintProp(_) => ImmutableUintProp {
+            prop: facet.as_u64()?,
+        }
+        .into(),
+        ProtoImmutableStrProp(_) => ImmutableStrProp {
+            prop: facet.as_str()?.to_owned(),
+        }
+        .into(),
+        ProtoImmutableBoolProp(_) => ImmutableBoolProp {
+            prop: facet.as_bool()?,
+        }
+        .into(),
+        ProtoImmutableFloatProp(_) => ImmutableFloatProp {
+            prop: facet.as_f64()?,
+        }
+        .into(),
+        ProtoImmutableBytesProp(_) => ImmutableBytesProp {
+            prop: base64::decode(facet.as_str()?).ok()?,
+        }
+        .into(),
+        ProtoTimestampProp(t) => TimestampProp {
+            millis: facet.as_u64()?,
+            merge: t.merge,
+        }
+        .into(),
+        ProtoStrSetProp(_) => {
+            let props: Vec<String> = serde_json::from_str(facet.as_str()?).ok()?;
+            StrSetProp::new(props).into()
+        }
+    };
+    Some(property)
+}
+
+/// The query block, named `query_name`, that reads the facets `edge` already has
+pub(crate) fn edge_facets_query(query_name: &str, edge: &MergedEdge) -> String {
+    format!(
+        r#"
+            {query_name}(func: uid({from_uid})) {{
+                {edge_name} @filter(uid({to_uid})) @facets {{
+                    uid
+                }}
+            }}
+    "#,
+        query_name = query_name,
+        from_uid = edge.from_uid,
+        edge_name = edge.edge_name,
+        to_uid = edge.to_uid,
+    )
+}
+
+/// The facets of the edge returned by the `edge_facets_query` named `query_name`, keyed as
+/// they're written, which is empty if the edge doesn't exist yet
+pub(crate) fn existing_edge_facets(
+    query_response: &serde_json::Value,
+    query_name: &str,
+    edge_name: &str,
+) -> serde_json::Map<String, serde_json::Value> {
+    let to_node = match &query_response[query_name][0][edge_name] {
+        // Edges to a single node are returned as an object, rather than in an array
+        serde_json::Value::Array(to_nodes) => to_nodes.first(),
+        to_node => Some(to_node),
+    };
+    let facet_prefix = format!("{}|", edge_name);
+    to_node
+        .and_then(serde_json::Value::as_object)
+        .map(|to_node| {
+            to_node
+                .iter()
+                .filter(|(facet_name, _)| facet_name.starts_with(&facet_prefix))
+                .map(|(facet_name, facet)| (facet_name.clone(), facet.clone()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Builds the JSON mutation that sets `edge`, storing its properties as facets on the edge.
+///
+/// Dgraph replaces all of an edge's facets whenever the edge is set, so each property is
+/// merged with the edge's `existing_facets`, read in the same transaction, following the
+/// property's merge semantics as `merge_filter` does for node properties. Existing facets
+/// that aren't among the edge's properties are written back as they were.
+pub(crate) fn edge_mutation_json(
+    edge: &MergedEdge,
+    existing_facets: &serde_json::Map<String, serde_json::Value>,
+) -> serde_json::Value {
+    let mut to_node = existing_facets.clone();
+    to_node.insert("uid".to_owned(), edge.to_uid.clone().into());
+    for (prop_name, prop) in edge.properties.iter() {
+        let facet_name = format!("{}|{}", edge.edge_name, prop_name);
+        let existing = to_node
+            .get(&facet_name)
+            .and_then(|facet| facet_property(prop, facet));
+        let merged = match existing {
+            Some(mut existing) => {
+                existing.merge(prop);
+                existing
+            }
+            None => prop.clone(),
+        };
+        match facet_value(&merged) {
+            Some(facet) => {
+                to_node.insert(facet_name, facet);
+            }
+            None => tracing::warn!(
+                message="skipping edge property without a value",
+                edge_name=?edge.edge_name,
+                property_name=?prop_name,
+            ),
+        }
+    }
+
+    let mut from_node = serde_json::Map::with_capacity(2);
+    from_node.insert("uid".to_owned(), edge.from_uid.clone().into());
+    from_node.insert(
+        edge.edge_name.clone(),
+        serde_json::Value::Array(vec![serde_json::Value::Object(to_node)]),
+    );
+    serde_json::Value::Object(from_node)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_filters() {
+        let prop: NodeProperty = IncrementOnlyUintProp { prop: 10 }.into();
+        assert_eq!(
+            merge_filter("last_seen_timestamp", &prop),
+            Some(r#"NOT has(last_seen_timestamp) OR lt(last_seen_timestamp, "10")"#.to_string())
+        );
+
+        let prop: NodeProperty = DecrementOnlyIntProp { prop: -5 }.into();
+        assert_eq!(
+            merge_filter("created_timestamp", &prop),
+            Some(r#"NOT has(created_timestamp) OR gt(created_timestamp, "-5")"#.to_string())
+        );
+
+        let prop: NodeProperty = ImmutableStrProp {
+            prop: "foo".to_string(),
+        }
+        .into();
+        assert_eq!(
+            merge_filter("process_name", &prop),
+            Some("NOT has(process_name)".to_string())
+        );
+
+        let prop: NodeProperty = TimestampProp::earliest(100).into();
+        assert_eq!(
+            merge_filter("first_seen_timestamp", &prop),
+            Some(r#"NOT has(first_seen_timestamp) OR gt(first_seen_timestamp, "100")"#.to_string())
+        );
+
+        let prop: NodeProperty = StrSetProp::new(vec!["a", "b"]).into();
+        assert_eq!(merge_filter("command_line_args", &prop), None);
+    }
+
+    #[test]
+    fn test_properties_without_a_value_are_skipped() {
+        let mut properties = HashMap::new();
+        properties.insert("process_name".to_owned(), NodeProperty { property: None });
+        let (query, mutations) =
+            build_upserts(0, "node-key", "Process", &properties, &mut HashMap::new());
+
+        assert!(!query.contains("_prop_"));
+        // Only the node's creation
+        assert_eq!(mutations.len(), 1);
+    }
+
+    #[test]
+    fn test_str_set_upsert_quads() {
+        let prop: NodeProperty = StrSetProp::new(vec!["--verbose", "-c"]).into();
+        let (query, [mu_0, mu_1]) =
+            gen_node_property_upsert_quads("node_exists_0", 1, "command_line_args", &prop);
+
+        assert!(!query.contains("@filter"));
+        assert_eq!(
+            String::from_utf8(mu_0.set_nquads).unwrap(),
+            concat!(
+                r#"uid(node_exists_0_prop_1) <command_line_args> "--verbose" ."#,
+                "\n",
+                r#"uid(node_exists_0_prop_1) <command_line_args> "-c" ."#,
+            )
+        );
+        assert_eq!(
+            String::from_utf8(mu_1.set_nquads).unwrap(),
+            concat!(
+                r#"_:node_exists_0 <command_line_args> "--verbose" ."#,
+                "\n",
+                r#"_:node_exists_0 <command_line_args> "-c" ."#,
+            )
+        );
+    }
+
+    #[test]
+    fn test_edge_mutation_json() {
+        let mut properties = HashMap::new();
+        properties.insert(
+            "first_seen_timestamp".to_owned(),
+            DecrementOnlyUintProp { prop: 100 }.into(),
+        );
+        properties.insert(
+            "image_name".to_owned(),
+            ImmutableStrProp {
+                prop: "cmd.exe".to_owned(),
+            }
+            .into(),
+        );
+        let edge = MergedEdge {
+            from_uid: "1".to_owned(),
+            from_node_key: "parent".to_owned(),
+            to_uid: "2".to_owned(),
+            to_node_key: "child".to_owned(),
+            edge_name: "children".to_owned(),
+            properties,
+        };
+
+        assert_eq!(
+            edge_mutation_json(&edge, &serde_json::Map::new()),
+            serde_json::json!({
+                "uid": "1",
+                "children": [{
+                    "uid": "2",
+                    "children|first_seen_timestamp": 100,
+                    "children|image_name": "cmd.exe",
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn test_edge_mutation_merges_existing_facets() {
+        let mut properties = HashMap::new();
+        properties.insert(
+            "first_seen_timestamp".to_owned(),
+            DecrementOnlyUintProp { prop: 100 }.into(),
+        );
+        properties.insert(
+            "last_seen_timestamp".to_owned(),
+            IncrementOnlyUintProp { prop: 100 }.into(),
+        );
+        properties.insert(
+            "image_name".to_owned(),
+            ImmutableStrProp {
+                prop: "cmd.exe".to_owned(),
+            }
+            .into(),
+        );
+        let edge = MergedEdge {
+            from_uid: "1".to_owned(),
+            from_node_key: "parent".to_owned(),
+            to_uid: "2".to_owned(),
+            to_node_key: "child".to_owned(),
+            edge_name: "children".to_owned(),
+            properties,
+        };
+
+        let query_response = serde_json::json!({
+            "edge_0": [{
+                "children": [{
+                    "uid": "0x2",
+                    "children|first_seen_timestamp": 50,
+                    "children|last_seen_timestamp": 50,
+                    "children|image_name": "svchost.exe",
+                    "children|pid": 7,
+                }],
+            }],
+        });
+        let existing_facets = existing_edge_facets(&query_response, "edge_0", "children");
+
+        assert_eq!(
+            edge_mutation_json(&edge, &existing_facets),
+            serde_json::json!({
+                "uid": "1",
+                "children": [{
+                    "uid": "2",
+                    "children|first_seen_timestamp": 50,
+                    "children|last_seen_timestamp": 100,
+                    "children|image_name": "svchost.exe",
+                    "children|pid": 7,
+                }],
+            })
+        );
+        assert!(
+            existing_edge_facets(&serde_json::json!({ "edge_0": [] }), "edge_0", "children")
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_edge_properties_without_a_value_are_skipped() {
+        let mut properties = HashMap::new();
+        properties.insert("image_name".to_owned(), NodeProperty { property: None });
+        let edge = MergedEdge {
+            from_uid: "1".to_owned(),
+            from_node_key: "parent".to_owned(),
+            to_uid: "2".to_owned(),
+            to_node_key: "child".to_owned(),
+            edge_name: "children".to_owned(),
+            properties,
+        };
+
+        let mut existing_facets = serde_json::Map::new();
+        existing_facets.insert("children|image_name".to_owned(), "cmd.exe".into());
+        assert_eq!(
+            edge_mutation_json(&edge, &existing_facets),
+            serde_json::json!({
+                "uid": "1",
+                "children": [{
+                    "uid": "2",
+                    "children|image_name": "cmd.exe",
+                }],
+            })
+        );
+    }
+}
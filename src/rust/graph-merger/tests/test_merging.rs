@@ -34,7 +34,10 @@ pub mod test {
         Client as DgraphClient,
         Query,
     };
-    use graph_merger_lib::upserter::GraphMergeHelper;
+    use graph_merger_lib::{
+        graph_store::DgraphGraphStore,
+        upserter::GraphMergeHelper,
+    };
     use rust_proto::graph_descriptions::*;
 
     async fn query_for_uid(dgraph_client: Arc<DgraphClient>, node_key: &str) -> u64 {
@@ -132,6 +135,10 @@ pub mod test {
                             PredicateDefinition::new("example_name", PredicateType::String)
                                 .add_index(Indexing::TRIGRAM),
                         )
+                        .add_predicate(
+                            PredicateDefinition::new("last_seen_timestamp", PredicateType::INT)
+                                .add_index(Indexing::INT),
+                        )
                         .add_predicate(PredicateDefinition::new(
                             "to_many_edge",
                             PredicateType::UIDArray,
@@ -231,8 +238,12 @@ pub mod test {
         );
 
         GraphMergeHelper {}
-            .upsert_into(dgraph_client.clone(), &identified_graph, &mut merged_graph)
-            .await;
+            .upsert_into(
+                &DgraphGraphStore::new(dgraph_client.clone()),
+                &identified_graph,
+                &mut merged_graph,
+            )
+            .await?;
 
         let node_uid_0 = query_for_uid(dgraph_client.clone(), "example-node-key").await;
         let node_uid_1 = query_for_uid(dgraph_client.clone(), "someother-node-key").await;
@@ -308,8 +319,13 @@ pub mod test {
                     let mut merged_graph = MergedGraph::new();
 
                     GraphMergeHelper {}
-                        .upsert_into(dgraph_client.clone(), &identified_graph, &mut merged_graph)
-                        .await;
+                        .upsert_into(
+                            &DgraphGraphStore::new(dgraph_client.clone()),
+                            &identified_graph,
+                            &mut merged_graph,
+                        )
+                        .await
+                        .expect("upsert failed");
                     merged_graph
                 }
             })
@@ -383,8 +399,12 @@ pub mod test {
         let mut merged_graph = MergedGraph::new();
 
         GraphMergeHelper {}
-            .upsert_into(dgraph_client.clone(), &identified_graph, &mut merged_graph)
-            .await;
+            .upsert_into(
+                &DgraphGraphStore::new(dgraph_client.clone()),
+                &identified_graph,
+                &mut merged_graph,
+            )
+            .await?;
 
         // If we query for multiple nodes by node_key we should only ever receive one
         let query_block = QueryBlockBuilder::default()
@@ -423,4 +443,547 @@ pub mod test {
         assert_eq!(example_name, "test_upsert_multifield");
         Ok(())
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_concurrent_increment_only_upserts() -> Result<(), Box<dyn std::error::Error>> {
+        init_test_env();
+        let mg_alpha = grapl_config::mg_alphas()
+            .pop()
+            .expect("Dgraph Alpha not specified.");
+
+        let dgraph_client = DgraphClient::new(mg_alpha).expect("Failed to create dgraph client.");
+        let dgraph_client = std::sync::Arc::new(dgraph_client);
+        let graph_store = DgraphGraphStore::new(dgraph_client.clone());
+
+        let node_key = "test_concurrent_increment_only_upserts-example-node-key";
+        let upserts: Vec<_> = (1..=64u64)
+            .map(|last_seen_timestamp| {
+                let graph_store = graph_store.clone();
+                tokio::spawn(async move {
+                    let mut properties = HashMap::new();
+                    properties.insert(
+                        "last_seen_timestamp".to_string(),
+                        IncrementOnlyUintProp {
+                            prop: last_seen_timestamp,
+                        }
+                        .into(),
+                    );
+                    let mut identified_graph = IdentifiedGraph::new();
+                    identified_graph.add_node(IdentifiedNode {
+                        node_key: node_key.to_string(),
+                        node_type: "ExampleNode".to_string(),
+                        properties,
+                    });
+
+                    GraphMergeHelper {}
+                        .upsert_into(&graph_store, &identified_graph, &mut MergedGraph::new())
+                        .await
+                })
+            })
+            .collect();
+
+        for upsert in futures::future::join_all(upserts).await {
+            upsert??;
+        }
+
+        let query_block = QueryBlockBuilder::default()
+            .query_type(QueryBlockType::query())
+            .root_filter(Condition::EQ(
+                "node_key".to_string(),
+                ConditionValue::string(node_key),
+            ))
+            .predicates(vec![Predicate::Field(Field::new("last_seen_timestamp"))])
+            .first(2)
+            .build()
+            .unwrap();
+
+        let query = QueryBuilder::default()
+            .query_blocks(vec![query_block])
+            .build()
+            .unwrap();
+
+        let mut txn = dgraph_client.new_read_only_txn();
+        let response = txn
+            .query(query.to_query_string())
+            .await
+            .expect("query failed");
+
+        let m: HashMap<String, Vec<HashMap<String, u64>>> =
+            serde_json::from_slice(&response.json).expect("response failed to parse");
+        let m = m.into_iter().next().unwrap().1;
+        assert_eq!(m.len(), 1);
+        assert_eq!(m[0]["last_seen_timestamp"], 64);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_upsert_edge_facets_merge() -> Result<(), Box<dyn std::error::Error>> {
+        init_test_env();
+        let mg_alpha = grapl_config::mg_alphas()
+            .pop()
+            .expect("Dgraph Alpha not specified.");
+        let dgraph_client =
+            Arc::new(DgraphClient::new(mg_alpha).expect("Failed to create dgraph client."));
+        let graph_store = DgraphGraphStore::new(dgraph_client.clone());
+
+        let from_node_key = "test_upsert_edge_facets_merge-parent";
+        let to_node_key = "test_upsert_edge_facets_merge-child";
+        // An older observation, written after a newer one, doesn't overwrite its facets
+        for (first_seen, last_seen, image_name) in
+            [(200, 300, "first.exe"), (100, 250, "second.exe")]
+        {
+            let mut identified_graph = IdentifiedGraph::new();
+            for node_key in [from_node_key, to_node_key] {
+                identified_graph.add_node(IdentifiedNode {
+                    node_key: node_key.to_string(),
+                    node_type: "ExampleNode".to_string(),
+                    properties: HashMap::new(),
+                });
+            }
+
+            let mut edge_properties = HashMap::new();
+            edge_properties.insert(
+                "first_seen_timestamp".to_string(),
+                DecrementOnlyUintProp { prop: first_seen }.into(),
+            );
+            edge_properties.insert(
+                "last_seen_timestamp".to_string(),
+                IncrementOnlyUintProp { prop: last_seen }.into(),
+            );
+            edge_properties.insert(
+                "image_name".to_string(),
+                ImmutableStrProp {
+                    prop: image_name.to_string(),
+                }
+                .into(),
+            );
+            identified_graph.add_edge_with_properties(
+                "to_many_edge",
+                from_node_key,
+                to_node_key,
+                edge_properties,
+            );
+
+            GraphMergeHelper {}
+                .upsert_into(&graph_store, &identified_graph, &mut MergedGraph::new())
+                .await?;
+        }
+
+        let from_uid = query_for_uid(dgraph_client.clone(), from_node_key).await;
+        let query = format!(
+            "{{ q(func: uid({})) {{ to_many_edge @facets {{ uid }} }} }}",
+            from_uid
+        );
+        let mut txn = dgraph_client.new_read_only_txn();
+        let response = txn.query(query).await?;
+        let response: serde_json::Value = serde_json::from_slice(&response.json)?;
+
+        let to_node = &response["q"][0]["to_many_edge"][0];
+        assert_eq!(to_node["to_many_edge|first_seen_timestamp"], 100);
+        assert_eq!(to_node["to_many_edge|last_seen_timestamp"], 300);
+        assert_eq!(to_node["to_many_edge|image_name"], "first.exe");
+        Ok(())
+    }
+}
+
+pub mod in_memory_test {
+    use std::collections::HashMap;
+
+    use graph_merger_lib::{
+        graph_store::InMemoryGraphStore,
+        reverse_resolver::{
+            ReverseEdgeResolver,
+            ReverseEdgeSchema,
+        },
+        service::{
+            GraphMerger,
+            GraphMergerError,
+        },
+        upserter::GraphMergeHelper,
+    };
+    use grapl_observe::{
+        metric_reporter::MetricReporter,
+        prometheus,
+    };
+    use rust_proto::graph_descriptions::*;
+    use sqs_executor::{
+        cache::NopCache,
+        errors::{
+            CheckedError,
+            Recoverable,
+        },
+        event_handler::{
+            CompletedEvents,
+            EventHandler,
+        },
+    };
+
+    fn example_node(node_key: &str, properties: HashMap<String, NodeProperty>) -> IdentifiedNode {
+        IdentifiedNode {
+            node_key: node_key.to_string(),
+            node_type: "ExampleNode".to_string(),
+            properties,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upsert_edge_and_retrieve() -> Result<(), Box<dyn std::error::Error>> {
+        let graph_store = InMemoryGraphStore::new();
+        let mut identified_graph = IdentifiedGraph::new();
+        let mut merged_graph = MergedGraph::new();
+
+        let mut properties = HashMap::new();
+        properties.insert(
+            "example_name".to_string(),
+            ImmutableStrProp {
+                prop: "foobar".to_string(),
+            }
+            .into(),
+        );
+        identified_graph.add_node(example_node("example-node-key", properties));
+
+        let mut properties = HashMap::new();
+        properties.insert(
+            "example_name".to_string(),
+            ImmutableStrProp {
+                prop: "baz".to_string(),
+            }
+            .into(),
+        );
+        identified_graph.add_node(example_node("someother-node-key", properties));
+
+        identified_graph.add_edge(
+            "to_many_edge".to_string(),
+            "example-node-key".to_string(),
+            "someother-node-key".to_string(),
+        );
+        identified_graph.add_edge(
+            "to_single_edge".to_string(),
+            "someother-node-key".to_string(),
+            "example-node-key".to_string(),
+        );
+
+        GraphMergeHelper {}
+            .upsert_into(&graph_store, &identified_graph, &mut merged_graph)
+            .await?;
+
+        let n0 = graph_store.get_node("example-node-key").expect("n0");
+        let n1 = graph_store.get_node("someother-node-key").expect("n1");
+        assert_ne!(n0.uid, n1.uid);
+        assert_eq!(merged_graph.nodes.len(), 2);
+
+        assert_eq!(
+            graph_store.get_edges("example-node-key", "to_many_edge"),
+            vec!["someother-node-key".to_string()]
+        );
+        assert_eq!(
+            graph_store.get_edges("someother-node-key", "to_single_edge"),
+            vec!["example-node-key".to_string()]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upsert_idempotency() -> Result<(), Box<dyn std::error::Error>> {
+        let graph_store = InMemoryGraphStore::new();
+        let node_key = "test_upsert_idempotency-example-node-key";
+        let mut properties = HashMap::new();
+        properties.insert(
+            "example_name".to_string(),
+            ImmutableStrProp {
+                prop: "foobar".to_string(),
+            }
+            .into(),
+        );
+        let n0 = example_node(node_key, properties);
+
+        let mut uids = Vec::with_capacity(10);
+        for _ in 0..10 {
+            let mut identified_graph = IdentifiedGraph::new();
+            identified_graph.add_node(n0.clone());
+            let mut merged_graph = MergedGraph::new();
+
+            GraphMergeHelper {}
+                .upsert_into(&graph_store, &identified_graph, &mut merged_graph)
+                .await?;
+            assert_eq!(merged_graph.nodes.len(), 1);
+            uids.push(merged_graph.nodes[node_key].uid);
+        }
+
+        uids.dedup();
+        assert_eq!(uids.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upsert_multifield() -> Result<(), Box<dyn std::error::Error>> {
+        let graph_store = InMemoryGraphStore::new();
+        let node_key = "test_upsert_multifield-example-node-key";
+        let mut properties = HashMap::new();
+        properties.insert(
+            "example_name".to_string(),
+            ImmutableStrProp {
+                prop: "test_upsert_multifield".to_string(),
+            }
+            .into(),
+        );
+        let mut identified_graph = IdentifiedGraph::new();
+        identified_graph.add_node(example_node(node_key, properties));
+        let mut merged_graph = MergedGraph::new();
+
+        GraphMergeHelper {}
+            .upsert_into(&graph_store, &identified_graph, &mut merged_graph)
+            .await?;
+
+        let node = graph_store.get_node(node_key).expect("node");
+        assert_eq!(node.properties.len(), 1);
+        assert_eq!(
+            node.properties
+                .get("example_name")
+                .and_then(|p| p.as_immutable_str())
+                .map(|p| p.prop.as_str()),
+            Some("test_upsert_multifield")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upsert_merge_semantics() -> Result<(), Box<dyn std::error::Error>> {
+        let graph_store = InMemoryGraphStore::new();
+        let node_key = "test_upsert_merge_semantics-example-node-key";
+
+        for (name, seen, created) in [("first", 10u64, 10u64), ("second", 20, 5), ("third", 15, 7)]
+        {
+            let mut properties = HashMap::new();
+            properties.insert(
+                "example_name".to_string(),
+                ImmutableStrProp {
+                    prop: name.to_string(),
+                }
+                .into(),
+            );
+            properties.insert(
+                "last_seen_timestamp".to_string(),
+                IncrementOnlyUintProp { prop: seen }.into(),
+            );
+            properties.insert(
+                "created_timestamp".to_string(),
+                DecrementOnlyUintProp { prop: created }.into(),
+            );
+            let mut identified_graph = IdentifiedGraph::new();
+            identified_graph.add_node(example_node(node_key, properties));
+
+            GraphMergeHelper {}
+                .upsert_into(&graph_store, &identified_graph, &mut MergedGraph::new())
+                .await?;
+        }
+
+        let node = graph_store.get_node(node_key).expect("node");
+        let prop = |name: &str| node.properties.get(name).expect(name).clone();
+        assert_eq!(
+            prop("example_name")
+                .as_immutable_str()
+                .map(|p| p.prop.clone()),
+            Some("first".to_string())
+        );
+        assert_eq!(
+            prop("last_seen_timestamp")
+                .as_increment_only_uint()
+                .map(|p| p.prop),
+            Some(20)
+        );
+        assert_eq!(
+            prop("created_timestamp")
+                .as_decrement_only_uint()
+                .map(|p| p.prop),
+            Some(5)
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_concurrent_increment_only_upserts() -> Result<(), Box<dyn std::error::Error>> {
+        let graph_store = InMemoryGraphStore::new();
+        let node_key = "test_concurrent_increment_only_upserts-example-node-key";
+
+        let upserts: Vec<_> = (1..=64u64)
+            .map(|last_seen_timestamp| {
+                let graph_store = graph_store.clone();
+                tokio::spawn(async move {
+                    let mut properties = HashMap::new();
+                    properties.insert(
+                        "last_seen_timestamp".to_string(),
+                        IncrementOnlyUintProp {
+                            prop: last_seen_timestamp,
+                        }
+                        .into(),
+                    );
+                    let mut identified_graph = IdentifiedGraph::new();
+                    identified_graph.add_node(example_node(node_key, properties));
+
+                    GraphMergeHelper {}
+                        .upsert_into(&graph_store, &identified_graph, &mut MergedGraph::new())
+                        .await
+                })
+            })
+            .collect();
+
+        for upsert in futures::future::join_all(upserts).await {
+            upsert??;
+        }
+
+        let node = graph_store.get_node(node_key).expect("node");
+        assert_eq!(
+            node.properties
+                .get("last_seen_timestamp")
+                .and_then(|p| p.as_increment_only_uint())
+                .map(|p| p.prop),
+            Some(64)
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unresolved_edges_are_returned() -> Result<(), Box<dyn std::error::Error>> {
+        let graph_store = InMemoryGraphStore::new();
+        let mut identified_graph = IdentifiedGraph::new();
+        identified_graph.add_node(example_node("resolved-node-key", HashMap::new()));
+        identified_graph.add_node(example_node("other-resolved-node-key", HashMap::new()));
+        identified_graph.add_edge(
+            "to_many_edge".to_string(),
+            "resolved-node-key".to_string(),
+            "other-resolved-node-key".to_string(),
+        );
+        // Neither the graph nor the graph store contain this node
+        identified_graph.add_edge(
+            "to_many_edge".to_string(),
+            "resolved-node-key".to_string(),
+            "missing-node-key".to_string(),
+        );
+
+        let unresolved_edges = GraphMergeHelper {}
+            .upsert_into(&graph_store, &identified_graph, &mut MergedGraph::new())
+            .await?;
+
+        assert_eq!(
+            unresolved_edges,
+            vec![Edge {
+                from_node_key: "resolved-node-key".to_string(),
+                to_node_key: "missing-node-key".to_string(),
+                edge_name: "to_many_edge".to_string(),
+                properties: Default::default(),
+            }]
+        );
+        assert_eq!(
+            graph_store.get_edges("resolved-node-key", "to_many_edge"),
+            vec!["other-resolved-node-key".to_string()]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unresolved_edges_are_a_transient_partial_failure(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let graph_store = InMemoryGraphStore::new();
+        let mut graph_merger = GraphMerger::new(
+            graph_store.clone(),
+            ReverseEdgeResolver::new(
+                ReverseEdgeSchema::default(),
+                MetricReporter::new("graph-merger"),
+            ),
+            MetricReporter::new("graph-merger"),
+            NopCache {},
+        );
+
+        let mut identified_graph = IdentifiedGraph::new();
+        identified_graph.add_node(example_node("resolved-node-key", HashMap::new()));
+        identified_graph.add_node(example_node("other-resolved-node-key", HashMap::new()));
+        identified_graph.add_edge(
+            "to_many_edge".to_string(),
+            "resolved-node-key".to_string(),
+            "other-resolved-node-key".to_string(),
+        );
+        // Neither the graph nor the graph store contain this node
+        identified_graph.add_edge(
+            "to_many_edge".to_string(),
+            "resolved-node-key".to_string(),
+            "missing-node-key".to_string(),
+        );
+
+        let (merged_graph, error) = match graph_merger
+            .handle_event(identified_graph, &mut CompletedEvents::default())
+            .await
+        {
+            Err(Ok((merged_graph, error))) => (merged_graph, error),
+            _ => panic!("expected a partial failure"),
+        };
+
+        // The nodes, and the edge between them, are merged anyway
+        assert_eq!(merged_graph.nodes.len(), 2);
+        assert_eq!(
+            graph_store.get_edges("resolved-node-key", "to_many_edge"),
+            vec!["other-resolved-node-key".to_string()]
+        );
+        assert!(matches!(error.error_type(), Recoverable::Transient));
+        match error {
+            GraphMergerError::UnresolvedEdges(unresolved_edges) => assert_eq!(
+                unresolved_edges,
+                vec![Edge {
+                    from_node_key: "resolved-node-key".to_string(),
+                    to_node_key: "missing-node-key".to_string(),
+                    edge_name: "to_many_edge".to_string(),
+                    properties: Default::default(),
+                }]
+            ),
+            error => panic!("unexpected error: {}", error),
+        }
+        assert!(prometheus::registry()
+            .render()
+            .lines()
+            .any(|line| line == "graph_merger_unresolved_edges_count_total 1"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upsert_edge_properties_merge() -> Result<(), Box<dyn std::error::Error>> {
+        let graph_store = InMemoryGraphStore::new();
+        for (first_seen, last_seen) in [(200, 300), (100, 250)] {
+            let mut identified_graph = IdentifiedGraph::new();
+            identified_graph.add_node(example_node("parent-node-key", HashMap::new()));
+            identified_graph.add_node(example_node("child-node-key", HashMap::new()));
+
+            let mut edge_properties = HashMap::new();
+            edge_properties.insert(
+                "first_seen_timestamp".to_string(),
+                DecrementOnlyUintProp { prop: first_seen }.into(),
+            );
+            edge_properties.insert(
+                "last_seen_timestamp".to_string(),
+                IncrementOnlyUintProp { prop: last_seen }.into(),
+            );
+            identified_graph.add_edge_with_properties(
+                "children",
+                "parent-node-key",
+                "child-node-key",
+                edge_properties,
+            );
+
+            GraphMergeHelper {}
+                .upsert_into(&graph_store, &identified_graph, &mut MergedGraph::new())
+                .await?;
+        }
+
+        let edge_properties = graph_store
+            .get_edge_properties("parent-node-key", "children", "child-node-key")
+            .expect("edge");
+        assert_eq!(
+            edge_properties["first_seen_timestamp"].as_decrement_only_uint(),
+            Some(DecrementOnlyUintProp { prop: 100 })
+        );
+        assert_eq!(
+            edge_properties["last_seen_timestamp"].as_increment_only_uint(),
+            Some(IncrementOnlyUintProp { prop: 300 })
+        );
+        Ok(())
+    }
 }
@@ -1,5 +1,6 @@
 use std::{
     io::Stdout,
+    path::PathBuf,
     str::FromStr,
 };
 
@@ -13,8 +14,13 @@ use rusoto_dynamodb::DynamoDbClient;
 use rusoto_s3::S3Client;
 use rusoto_sqs::SqsClient;
 use sqs_executor::{
-    make_ten,
+    local_fs::{
+        LocalFsEmitter,
+        LocalFsMessageSource,
+    },
+    make_workers,
     s3_event_emitter::S3EventEmitter,
+    sqs_message_source::SqsMessageSource,
 };
 
 use crate::ServiceEnv;
@@ -255,9 +261,43 @@ where
 pub async fn s3_event_emitters_from_env<F>(
     env: &ServiceEnv,
     key_fn: F,
-) -> [S3EventEmitter<S3Client, F>; 10]
+) -> Vec<S3EventEmitter<S3Client, F>>
 where
     F: Clone + Fn(&[u8]) -> String + Send + Sync + 'static,
 {
-    make_ten(async { s3_event_emitter_from_env(env, key_fn) }).await
+    make_workers(crate::worker_count(), async {
+        s3_event_emitter_from_env(env, key_fn)
+    })
+    .await
+}
+
+pub fn sqs_message_source_from_env(env: &ServiceEnv) -> SqsMessageSource<SqsClient> {
+    SqsMessageSource::new(
+        SqsClient::from_env(),
+        crate::source_queue_url(),
+        crate::dead_letter_queue_url(),
+        MetricReporter::new(&env.service_name),
+    )
+}
+
+pub fn local_fs_message_source_from_env(
+    env: &ServiceEnv,
+    input_dir: impl Into<PathBuf>,
+) -> LocalFsMessageSource {
+    LocalFsMessageSource::new(input_dir, MetricReporter::new(&env.service_name))
+}
+
+pub async fn local_fs_emitters_from_env<F>(
+    env: &ServiceEnv,
+    output_dir: impl Into<PathBuf>,
+    key_fn: F,
+) -> Vec<LocalFsEmitter<F>>
+where
+    F: Clone + Fn(&[u8]) -> String + Send + Sync + 'static,
+{
+    let output_dir = output_dir.into();
+    make_workers(crate::worker_count(), async {
+        LocalFsEmitter::new(output_dir, key_fn, MetricReporter::new(&env.service_name))
+    })
+    .await
 }
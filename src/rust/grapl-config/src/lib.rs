@@ -15,11 +15,21 @@ use rusoto_sqs::{
     Sqs,
 };
 use sqs_executor::{
-    make_ten,
-    redis_cache::RedisCache,
+    concurrency::ConcurrencyPolicy,
+    health::HealthServer,
+    make_workers,
+    redis_cache::{
+        RedisCache,
+        RedisCacheConfig,
+    },
+    retry_policy::RetryPolicy,
+    shutdown::Shutdown,
 };
 use tracing::debug;
-use tracing_subscriber::EnvFilter;
+use tracing_subscriber::{
+    prelude::*,
+    EnvFilter,
+};
 
 pub mod env_helpers;
 
@@ -44,7 +54,7 @@ pub fn _init_grapl_env(
     let env = ServiceEnv {
         service_name: service_name.to_string(),
     };
-    let tracing_guard = _init_grapl_log();
+    let tracing_guard = _init_grapl_log(service_name);
     tracing::info!(env=?env, "initializing environment");
     (env, tracing_guard)
 }
@@ -61,8 +71,29 @@ pub async fn event_cache(env: &ServiceEnv) -> RedisCache {
         .unwrap_or(String::from("1000000"))
         .parse::<usize>()
         .unwrap_or(1_000_000);
-    RedisCache::with_lru_capacity(
-        lru_cache_size,
+
+    let mut config = RedisCacheConfig::new(&env.service_name);
+    config.lru_capacity = lru_cache_size;
+    if let Some(ttl_secs) = parse_env_var::<u64>("REDIS_CACHE_TTL_SECS") {
+        // A TTL of 0 disables expiry
+        config.ttl = match ttl_secs {
+            0 => None,
+            ttl_secs => Some(Duration::from_secs(ttl_secs)),
+        };
+    }
+    if let Some(failure_ttl_secs) = parse_env_var::<u64>("REDIS_CACHE_FAILURE_TTL_SECS") {
+        config.failure_ttl = Duration::from_secs(failure_ttl_secs);
+    }
+    if let Some(max_failures) = parse_env_var::<u32>("REDIS_CACHE_MAX_FAILURES") {
+        // A limit of 0 means failing events are always retried
+        config.max_failures = match max_failures {
+            0 => None,
+            max_failures => Some(max_failures),
+        };
+    }
+
+    RedisCache::new(
+        config,
         cache_address.to_owned(),
         MetricReporter::<Stdout>::new(&env.service_name),
     )
@@ -70,8 +101,78 @@ pub async fn event_cache(env: &ServiceEnv) -> RedisCache {
     .expect("Could not create redis client")
 }
 
-pub async fn event_caches(env: &ServiceEnv) -> [RedisCache; 10] {
-    make_ten(event_cache(env)).await
+fn parse_env_var<T: FromStr>(name: &str) -> Option<T> {
+    let value = std::env::var(name).ok()?;
+    match value.parse() {
+        Ok(value) => Some(value),
+        Err(_) => panic!("Expected {} to be a valid number, but got {}", name, value),
+    }
+}
+
+/// The default `RetryPolicy`, with any of `MAX_RETRIES`, `RETRY_INITIAL_DELAY_MS` and
+/// `RETRY_MAX_DELAY_MS` overriding it
+pub fn retry_policy() -> RetryPolicy {
+    let mut retry_policy = RetryPolicy::default();
+    if let Some(max_retries) = parse_env_var("MAX_RETRIES") {
+        retry_policy.max_retries = max_retries;
+    }
+    if let Some(initial_delay_ms) = parse_env_var("RETRY_INITIAL_DELAY_MS") {
+        retry_policy.initial_delay = Duration::from_millis(initial_delay_ms);
+    }
+    if let Some(max_delay_ms) = parse_env_var("RETRY_MAX_DELAY_MS") {
+        retry_policy.max_delay = Duration::from_millis(max_delay_ms);
+    }
+    retry_policy
+}
+
+/// How many messages are processed at once, at most. Defaults to 10, which `WORKER_COUNT`
+/// overrides.
+pub fn worker_count() -> usize {
+    parse_env_var("WORKER_COUNT").unwrap_or(10)
+}
+
+/// The default `ConcurrencyPolicy`, with any of `TARGET_HANDLER_LATENCY_MS`,
+/// `MAX_HANDLER_ERROR_RATE` and `MAX_IN_FLIGHT_BYTES` overriding it
+pub fn concurrency_policy() -> ConcurrencyPolicy {
+    let mut concurrency_policy = ConcurrencyPolicy::default();
+    if let Some(target_latency_ms) = parse_env_var("TARGET_HANDLER_LATENCY_MS") {
+        concurrency_policy.target_latency = Duration::from_millis(target_latency_ms);
+    }
+    if let Some(max_error_rate) = parse_env_var("MAX_HANDLER_ERROR_RATE") {
+        concurrency_policy.max_error_rate = max_error_rate;
+    }
+    if let Some(max_in_flight_bytes) = parse_env_var("MAX_IN_FLIGHT_BYTES") {
+        concurrency_policy.max_in_flight_bytes = max_in_flight_bytes;
+    }
+    concurrency_policy
+}
+
+/// How long messages being processed have to finish once the service is told to stop.
+/// Defaults to 25 seconds, inside the 30 seconds the scheduler waits before killing it, which
+/// `SHUTDOWN_DEADLINE_SECS` overrides.
+pub fn shutdown_deadline() -> Duration {
+    Duration::from_secs(parse_env_var("SHUTDOWN_DEADLINE_SECS").unwrap_or(25))
+}
+
+/// Shuts the service down gracefully on SIGTERM or SIGINT. Must be called within a tokio
+/// runtime.
+pub fn shutdown() -> Shutdown {
+    Shutdown::on_signal(shutdown_deadline())
+}
+
+/// Serves `/healthz`, `/readyz` and `/metrics` on `HEALTH_PORT`, 8080 by default. The
+/// process loop is considered stuck after `READINESS_MAX_STALL_SECS` without progress.
+pub fn health_server() -> HealthServer {
+    let port: u16 = parse_env_var("HEALTH_PORT").unwrap_or(8080);
+    let health_server = HealthServer::new(([0, 0, 0, 0], port).into());
+    match parse_env_var("READINESS_MAX_STALL_SECS") {
+        Some(max_stall_secs) => health_server.with_max_stall(Duration::from_secs(max_stall_secs)),
+        None => health_server,
+    }
+}
+
+pub async fn event_caches(env: &ServiceEnv) -> Vec<RedisCache> {
+    make_workers(worker_count(), event_cache(env)).await
 }
 
 pub fn dest_bucket() -> String {
@@ -93,17 +194,64 @@ pub fn region() -> Region {
     }
 }
 
-pub fn _init_grapl_log() -> tracing_appender::non_blocking::WorkerGuard {
+/// Logs JSON to stdout and, with the `otel` feature, exports spans over OTLP to the
+/// collector at `OTEL_EXPORTER_OTLP_ENDPOINT`
+#[cfg_attr(not(feature = "otel"), allow(unused_variables))]
+pub fn _init_grapl_log(service_name: &str) -> tracing_appender::non_blocking::WorkerGuard {
     let filter = EnvFilter::from_default_env();
     let (non_blocking, guard) = tracing_appender::non_blocking(std::io::stdout());
-    tracing_subscriber::fmt()
-        .json()
-        .with_env_filter(filter)
-        .with_writer(non_blocking)
-        .init();
+    let subscriber = tracing_subscriber::registry().with(filter).with(
+        tracing_subscriber::fmt::layer()
+            .json()
+            .with_writer(non_blocking),
+    );
+
+    #[cfg(feature = "otel")]
+    let subscriber = subscriber.with(otlp_layer(service_name));
+
+    subscriber.init();
     guard
 }
 
+#[cfg(feature = "otel")]
+fn otlp_layer<S>(service_name: &str) -> impl tracing_subscriber::Layer<S>
+where
+    S: tracing::Subscriber + for<'span> tracing_subscriber::registry::LookupSpan<'span>,
+{
+    use opentelemetry::{
+        sdk::{
+            trace,
+            Resource,
+        },
+        KeyValue,
+    };
+    use opentelemetry_otlp::WithExportConfig;
+
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(otel_exporter_otlp_endpoint()),
+        )
+        .with_trace_config(
+            trace::config().with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                service_name.to_owned(),
+            )])),
+        )
+        .install_batch(opentelemetry::runtime::Tokio)
+        .expect("Failed to install the OTLP exporter");
+    tracing_opentelemetry::layer().with_tracer(tracer)
+}
+
+/// The OpenTelemetry collector to export spans to, by default one running alongside the
+/// service
+pub fn otel_exporter_otlp_endpoint() -> String {
+    std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+        .unwrap_or_else(|_| "http://localhost:4317".to_owned())
+}
+
 pub fn source_queue_url() -> String {
     std::env::var("SOURCE_QUEUE_URL").expect("SOURCE_QUEUE_URL")
 }
@@ -112,6 +260,13 @@ pub fn dead_letter_queue_url() -> String {
     std::env::var("DEAD_LETTER_QUEUE_URL").expect("DEAD_LETTER_QUEUE_URL")
 }
 
+/// The input and output directories to use instead of SQS and S3, when `LOCAL_INPUT_DIR` is set
+pub fn local_fs_dirs() -> Option<(std::path::PathBuf, std::path::PathBuf)> {
+    let input_dir = std::env::var("LOCAL_INPUT_DIR").ok()?;
+    let output_dir = std::env::var("LOCAL_OUTPUT_DIR").expect("LOCAL_OUTPUT_DIR");
+    Some((input_dir.into(), output_dir.into()))
+}
+
 pub fn mg_alphas() -> Vec<String> {
     return std::env::var("MG_ALPHAS")
         .expect("MG_ALPHAS")
@@ -171,6 +326,17 @@ where
     errs
 }
 
+/// The directory of zstd dictionaries that payloads may be compressed with, named
+/// `<dictionary id>.dict`
+pub fn zstd_dictionary_dir() -> Option<std::path::PathBuf> {
+    std::env::var("ZSTD_DICTIONARY_DIR").ok().map(Into::into)
+}
+
+/// The id of the dictionary, from the `zstd_dictionary_dir`, to compress output with
+pub fn zstd_dictionary_id() -> Option<u32> {
+    parse_env_var("ZSTD_DICTIONARY_ID")
+}
+
 pub fn static_mapping_table_name() -> String {
     std::env::var("GRAPL_STATIC_MAPPING_TABLE").expect("GRAPL_STATIC_MAPPING_TABLE")
 }
@@ -179,6 +345,26 @@ pub fn dynamic_session_table_name() -> String {
     std::env::var("GRAPL_DYNAMIC_SESSION_TABLE").expect("GRAPL_DYNAMIC_SESSION_TABLE")
 }
 
-pub fn source_compression() -> String {
-    std::env::var("SOURCE_COMPRESSION").unwrap_or(String::from("Zstd"))
+/// The directory that deployed model plugin GraphQL schemas are written to
+pub fn model_plugin_schema_dir() -> Option<std::path::PathBuf> {
+    std::env::var("GRAPL_MODEL_PLUGIN_SCHEMA_DIR")
+        .ok()
+        .map(Into::into)
+}
+
+/// Overrides the compression that's otherwise detected from each payload
+pub fn source_compression() -> Option<String> {
+    std::env::var("SOURCE_COMPRESSION").ok()
+}
+
+/// The most a payload may decompress to. It's left unparsed, so that a malformed value can
+/// fall back to the default rather than panicking
+pub fn max_decompressed_bytes() -> Option<String> {
+    std::env::var("MAX_DECOMPRESSED_BYTES").ok()
+}
+
+/// How many events of a payload are handled at a time, by services that handle them in
+/// batches
+pub fn event_batch_size() -> Option<usize> {
+    parse_env_var("EVENT_BATCH_SIZE")
 }
@@ -0,0 +1,102 @@
+use std::str::FromStr;
+
+use color_eyre::eyre::{
+    eyre,
+    Report,
+    Result,
+    WrapErr,
+};
+use grapl_service::decoder::{
+    decompress::maybe_decompress,
+    JsonDecoder,
+    NdjsonDecoder,
+    ProtoDecoder,
+};
+use rust_proto::{
+    graph_descriptions::{
+        GraphDescription,
+        IdentifiedGraph,
+        MergedGraph,
+    },
+    pipeline::Envelope,
+};
+use sqs_executor::event_decoder::PayloadDecoder;
+
+/// How to decode the payload a dead letter refers to, which is whatever the service that
+/// dead lettered it consumes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PayloadKind {
+    GraphDescription,
+    IdentifiedGraph,
+    MergedGraph,
+    Json,
+    Ndjson,
+    /// Decompressed, but otherwise shown as is, such as sysmon's XML
+    Raw,
+}
+
+impl PayloadKind {
+    pub fn for_service(service: &str) -> Self {
+        match service {
+            "node_identifier" => Self::GraphDescription,
+            "graph_merger" => Self::IdentifiedGraph,
+            "analyzer_dispatcher" => Self::MergedGraph,
+            "generic_subgraph_generator" => Self::Json,
+            "osquery_generator" => Self::Ndjson,
+            _ => Self::Raw,
+        }
+    }
+}
+
+impl FromStr for PayloadKind {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "graph-description" => Ok(Self::GraphDescription),
+            "identified-graph" => Ok(Self::IdentifiedGraph),
+            "merged-graph" => Ok(Self::MergedGraph),
+            "json" => Ok(Self::Json),
+            "ndjson" => Ok(Self::Ndjson),
+            "raw" => Ok(Self::Raw),
+            other => Err(eyre!("Unknown decoder: {}", other)),
+        }
+    }
+}
+
+/// Renders an encoded `Envelope` as its metadata followed by its decoded inner message
+pub fn render(kind: PayloadKind, payload: &[u8]) -> Result<String> {
+    let envelope: Envelope =
+        prost::Message::decode(payload).wrap_err("Payload is not an Envelope")?;
+    let body = envelope.inner_message;
+
+    let decoded = match kind {
+        PayloadKind::GraphDescription => {
+            let graph: GraphDescription = ProtoDecoder.decode(body)?;
+            format!("{:#?}", graph)
+        }
+        PayloadKind::IdentifiedGraph => {
+            let graph: IdentifiedGraph = ProtoDecoder.decode(body)?;
+            format!("{:#?}", graph)
+        }
+        PayloadKind::MergedGraph => {
+            let graph: MergedGraph = ProtoDecoder.decode(body)?;
+            format!("{:#?}", graph)
+        }
+        PayloadKind::Json => {
+            let value: serde_json::Value = JsonDecoder.decode(body)?;
+            serde_json::to_string_pretty(&value)?
+        }
+        PayloadKind::Ndjson => {
+            let values: Vec<serde_json::Value> = NdjsonDecoder.decode(body)?;
+            values
+                .iter()
+                .map(serde_json::Value::to_string)
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+        PayloadKind::Raw => String::from_utf8_lossy(&maybe_decompress(&body)?).into_owned(),
+    };
+
+    Ok(format!("{:#?}\n{}", envelope.metadata, decoded))
+}
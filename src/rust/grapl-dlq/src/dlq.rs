@@ -0,0 +1,284 @@
+use std::collections::{
+    BTreeMap,
+    HashSet,
+};
+
+use color_eyre::eyre::{
+    Result,
+    WrapErr,
+};
+use rusoto_sqs::{
+    ChangeMessageVisibilityRequest,
+    DeleteMessageRequest,
+    Message as SqsMessage,
+    ReceiveMessageRequest,
+    SendMessageRequest,
+    Sqs,
+};
+use sqs_executor::{
+    failure_metadata::{
+        FailureMetadata,
+        FAILURE_METADATA_KEY,
+    },
+    sqs_message_source::RETRY_COUNT_ATTRIBUTE,
+};
+
+/// A message on a dead letter queue, along with why it was put there. Messages that were
+/// dead lettered before failure metadata was recorded have no `failure`.
+#[derive(Clone, Debug)]
+pub struct DeadLetter {
+    pub message: SqsMessage,
+    pub failure: Option<FailureMetadata>,
+}
+
+impl DeadLetter {
+    pub fn new(message: SqsMessage) -> Self {
+        let failure = message
+            .message_attributes
+            .as_ref()
+            .and_then(|attributes| attributes.get(FAILURE_METADATA_KEY))
+            .and_then(|attribute| attribute.string_value.as_ref())
+            .and_then(|json| FailureMetadata::from_json(json).ok());
+        Self { message, failure }
+    }
+
+    pub fn message_id(&self) -> &str {
+        self.message.message_id.as_deref().unwrap_or_default()
+    }
+
+    /// The body as it was on the source queue. `move_to_dead_letter` serializes the body
+    /// to JSON, so it's a JSON string on the dead letter queue.
+    pub fn body(&self) -> String {
+        let body = self.message.body.as_deref().unwrap_or_default();
+        serde_json::from_str::<String>(body).unwrap_or_else(|_| body.to_owned())
+    }
+
+    pub fn service(&self) -> &str {
+        self.failure
+            .as_ref()
+            .map(|failure| failure.service.as_str())
+            .unwrap_or("unknown")
+    }
+
+    pub fn error(&self) -> &str {
+        self.failure
+            .as_ref()
+            .map(|failure| failure.error.as_str())
+            .unwrap_or("unknown")
+    }
+}
+
+/// Which dead letters to act on. An empty filter matches every dead letter.
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    pub message_ids: HashSet<String>,
+    pub service: Option<String>,
+    /// Matches dead letters whose error contains this
+    pub error: Option<String>,
+}
+
+impl Filter {
+    pub fn matches(&self, dead_letter: &DeadLetter) -> bool {
+        (self.message_ids.is_empty() || self.message_ids.contains(dead_letter.message_id()))
+            && self
+                .service
+                .as_ref()
+                .map_or(true, |service| service == dead_letter.service())
+            && self
+                .error
+                .as_ref()
+                .map_or(true, |error| dead_letter.error().contains(error.as_str()))
+    }
+}
+
+/// Receives every message currently on the dead letter queue, up to `limit`. Received
+/// messages stay invisible for `visibility_timeout` seconds, which keeps them from being
+/// received twice, so they should be `release`d once they've been looked at.
+pub async fn scan(
+    sqs_client: &impl Sqs,
+    dead_letter_queue_url: &str,
+    visibility_timeout: i64,
+    limit: Option<usize>,
+) -> Result<Vec<DeadLetter>> {
+    let mut seen = HashSet::new();
+    let mut dead_letters = vec![];
+    loop {
+        let messages = sqs_client
+            .receive_message(ReceiveMessageRequest {
+                queue_url: dead_letter_queue_url.to_owned(),
+                max_number_of_messages: Some(10),
+                visibility_timeout: Some(visibility_timeout),
+                wait_time_seconds: Some(1),
+                message_attribute_names: Some(vec!["All".to_owned()]),
+                ..Default::default()
+            })
+            .await
+            .wrap_err("Failed to receive from the dead letter queue")?
+            .messages
+            .unwrap_or_default();
+        if messages.is_empty() {
+            return Ok(dead_letters);
+        }
+        for message in messages {
+            let dead_letter = DeadLetter::new(message);
+            if !seen.insert(dead_letter.message_id().to_owned()) {
+                continue;
+            }
+            dead_letters.push(dead_letter);
+            if limit.map_or(false, |limit| dead_letters.len() >= limit) {
+                return Ok(dead_letters);
+            }
+        }
+    }
+}
+
+/// Groups dead letters by the service that dead lettered them and why
+pub fn group_by_error<'a>(
+    dead_letters: impl IntoIterator<Item = &'a DeadLetter>,
+) -> BTreeMap<(&'a str, &'a str), Vec<&'a DeadLetter>> {
+    let mut groups: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    for dead_letter in dead_letters {
+        groups
+            .entry((dead_letter.service(), dead_letter.error()))
+            .or_default()
+            .push(dead_letter);
+    }
+    groups
+}
+
+/// Sends the dead letter back to the source queue with its retry count reset, and then
+/// deletes it from the dead letter queue
+pub async fn redrive(
+    sqs_client: &impl Sqs,
+    dead_letter_queue_url: &str,
+    source_queue_url: &str,
+    dead_letter: &DeadLetter,
+) -> Result<()> {
+    let mut message_attributes = dead_letter
+        .message
+        .message_attributes
+        .clone()
+        .unwrap_or_default();
+    message_attributes.remove(FAILURE_METADATA_KEY);
+    message_attributes.remove(RETRY_COUNT_ATTRIBUTE);
+
+    sqs_client
+        .send_message(SendMessageRequest {
+            queue_url: source_queue_url.to_owned(),
+            message_body: dead_letter.body(),
+            message_attributes: Some(message_attributes),
+            ..Default::default()
+        })
+        .await
+        .wrap_err_with(|| format!("Failed to redrive {}", dead_letter.message_id()))?;
+    sqs_client
+        .delete_message(DeleteMessageRequest {
+            queue_url: dead_letter_queue_url.to_owned(),
+            receipt_handle: receipt_handle(dead_letter),
+        })
+        .await
+        .wrap_err_with(|| format!("Failed to delete {}", dead_letter.message_id()))?;
+    Ok(())
+}
+
+/// Makes a scanned dead letter visible on the dead letter queue again
+pub async fn release(
+    sqs_client: &impl Sqs,
+    dead_letter_queue_url: &str,
+    dead_letter: &DeadLetter,
+) -> Result<()> {
+    sqs_client
+        .change_message_visibility(ChangeMessageVisibilityRequest {
+            queue_url: dead_letter_queue_url.to_owned(),
+            receipt_handle: receipt_handle(dead_letter),
+            visibility_timeout: 0,
+        })
+        .await
+        .wrap_err_with(|| format!("Failed to release {}", dead_letter.message_id()))?;
+    Ok(())
+}
+
+fn receipt_handle(dead_letter: &DeadLetter) -> String {
+    dead_letter
+        .message
+        .receipt_handle
+        .clone()
+        .expect("missing receipt_handle")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use sqs_executor::{
+        errors::Recoverable,
+        rusoto_helpers::string_attribute,
+    };
+
+    use super::*;
+
+    fn dead_letter(message_id: &str, failure: Option<FailureMetadata>) -> DeadLetter {
+        let mut message_attributes = HashMap::new();
+        if let Some(failure) = failure {
+            message_attributes.insert(
+                FAILURE_METADATA_KEY.to_owned(),
+                string_attribute(failure.to_json()),
+            );
+        }
+        DeadLetter::new(SqsMessage {
+            message_id: Some(message_id.to_owned()),
+            body: Some(serde_json::to_string(r#"{"Records":[]}"#).unwrap()),
+            message_attributes: Some(message_attributes),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_body_is_unwrapped() {
+        let dead_letter = dead_letter("a", None);
+        assert_eq!(dead_letter.body(), r#"{"Records":[]}"#);
+
+        let raw = DeadLetter::new(SqsMessage {
+            body: Some("not json".to_owned()),
+            ..Default::default()
+        });
+        assert_eq!(raw.body(), "not json");
+    }
+
+    #[test]
+    fn test_group_and_filter_by_error() {
+        let timeout = FailureMetadata::new("graph_merger", "Timeout", Recoverable::Transient);
+        let decode = FailureMetadata::new("graph_merger", "DecodeError", Recoverable::Persistent);
+        let dead_letters = vec![
+            dead_letter("a", Some(timeout.clone())),
+            dead_letter("b", Some(decode)),
+            dead_letter("c", Some(timeout)),
+            dead_letter("d", None),
+        ];
+
+        let groups = group_by_error(&dead_letters);
+        let counts: Vec<_> = groups
+            .iter()
+            .map(|(key, group)| (*key, group.len()))
+            .collect();
+        assert_eq!(
+            counts,
+            vec![
+                (("graph_merger", "DecodeError"), 1),
+                (("graph_merger", "Timeout"), 2),
+                (("unknown", "unknown"), 1),
+            ]
+        );
+
+        let filter = Filter {
+            error: Some("Time".to_owned()),
+            ..Default::default()
+        };
+        let matched: Vec<_> = dead_letters
+            .iter()
+            .filter(|dead_letter| filter.matches(dead_letter))
+            .map(DeadLetter::message_id)
+            .collect();
+        assert_eq!(matched, vec!["a", "c"]);
+    }
+}
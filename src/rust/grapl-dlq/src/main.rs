@@ -0,0 +1,220 @@
+use std::collections::HashSet;
+
+use color_eyre::eyre::{
+    eyre,
+    Result,
+    WrapErr,
+};
+use grapl_config::env_helpers::{
+    init_s3_client,
+    FromEnv,
+};
+use grapl_observe::metric_reporter::MetricReporter;
+use grapl_service::decoder::ProtoDecoder;
+use rusoto_sqs::{
+    Message as SqsMessage,
+    SqsClient,
+};
+use rust_proto::graph_descriptions::GraphDescription;
+use sqs_executor::{
+    payload_store::PayloadStore,
+    retriever::S3PayloadRetriever,
+};
+use structopt::StructOpt;
+
+use crate::{
+    decode::PayloadKind,
+    dlq::{
+        DeadLetter,
+        Filter,
+    },
+};
+
+mod decode;
+mod dlq;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "grapl-dlq",
+    about = "Inspect and redrive a service's dead letter queue"
+)]
+struct Opt {
+    #[structopt(long = "dead-letter-queue-url", env = "DEAD_LETTER_QUEUE_URL")]
+    dead_letter_queue_url: String,
+
+    /// How long, in seconds, scanned messages stay hidden from the service and other scans
+    #[structopt(long = "visibility-timeout", default_value = "60")]
+    visibility_timeout: i64,
+
+    /// Stop scanning after this many messages
+    #[structopt(long = "limit")]
+    limit: Option<usize>,
+
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// List every dead lettered message and why it failed
+    List,
+    /// Count dead lettered messages by the service that dead lettered them and why
+    Group,
+    /// Fetch and decode the payload a dead lettered message refers to
+    Show {
+        message_id: String,
+
+        /// One of graph-description, identified-graph, merged-graph, json, ndjson or raw.
+        /// Defaults to what the service that dead lettered the message consumes.
+        #[structopt(long = "decoder")]
+        decoder: Option<PayloadKind>,
+    },
+    /// Send dead lettered messages back to the source queue
+    Redrive {
+        #[structopt(long = "source-queue-url", env = "SOURCE_QUEUE_URL")]
+        source_queue_url: String,
+
+        /// Only redrive these messages. May be repeated.
+        #[structopt(long = "message-id")]
+        message_ids: Vec<String>,
+
+        /// Only redrive messages dead lettered by this service
+        #[structopt(long = "service")]
+        service: Option<String>,
+
+        /// Only redrive messages whose error contains this
+        #[structopt(long = "error")]
+        error: Option<String>,
+
+        /// Print what would be redriven without redriving it
+        #[structopt(long = "dry-run", parse(from_flag))]
+        dry_run: bool,
+    },
+}
+
+fn print_dead_letter(dead_letter: &DeadLetter) {
+    match dead_letter.failure {
+        Some(ref failure) => println!(
+            "{}\t{}\t{:?}\t{}\t{}",
+            dead_letter.message_id(),
+            failure.service,
+            failure.recoverable,
+            failure.failed_at,
+            failure.error,
+        ),
+        None => println!("{}\tunknown", dead_letter.message_id()),
+    }
+}
+
+async fn show(dead_letter: &DeadLetter, decoder: Option<PayloadKind>) -> Result<()> {
+    let kind = decoder.unwrap_or_else(|| PayloadKind::for_service(dead_letter.service()));
+
+    // The decoder is only used by `retrieve_event`, we decode the payload ourselves so
+    // that it can be shown whatever its type
+    let mut payload_store = S3PayloadRetriever::<_, _, ProtoDecoder, GraphDescription, _>::new(
+        |region: String| init_s3_client(&region),
+        ProtoDecoder,
+        MetricReporter::new("grapl_dlq"),
+    );
+    let message = SqsMessage {
+        body: Some(dead_letter.body()),
+        ..Default::default()
+    };
+    let payload = payload_store
+        .load_payload(&message)
+        .await
+        .wrap_err("Failed to fetch the payload")?
+        .ok_or_else(|| eyre!("{} has no payload", dead_letter.message_id()))?;
+
+    print_dead_letter(dead_letter);
+    println!("{}", decode::render(kind, &payload)?);
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt::init();
+    color_eyre::install()?;
+
+    let opt = Opt::from_args();
+    tracing::debug!(message="Executing grapl-dlq", options=?opt);
+
+    let sqs_client = SqsClient::from_env();
+    let dead_letters = dlq::scan(
+        &sqs_client,
+        &opt.dead_letter_queue_url,
+        opt.visibility_timeout,
+        opt.limit,
+    )
+    .await?;
+
+    let mut redriven = HashSet::new();
+    let result = match opt.command {
+        Command::List => {
+            dead_letters.iter().for_each(print_dead_letter);
+            Ok(())
+        }
+        Command::Group => {
+            let mut groups: Vec<_> = dlq::group_by_error(&dead_letters).into_iter().collect();
+            groups.sort_by_key(|(_, group)| std::cmp::Reverse(group.len()));
+            for ((service, error), group) in groups {
+                println!("{}\t{}\t{}", group.len(), service, error);
+            }
+            Ok(())
+        }
+        Command::Show {
+            ref message_id,
+            decoder,
+        } => match dead_letters
+            .iter()
+            .find(|dead_letter| dead_letter.message_id() == message_id)
+        {
+            Some(dead_letter) => show(dead_letter, decoder).await,
+            None => Err(eyre!("{} is not on the dead letter queue", message_id)),
+        },
+        Command::Redrive {
+            ref source_queue_url,
+            ref message_ids,
+            ref service,
+            ref error,
+            dry_run,
+        } => {
+            let filter = Filter {
+                message_ids: message_ids.iter().cloned().collect(),
+                service: service.clone(),
+                error: error.clone(),
+            };
+            let mut result = Ok(());
+            for dead_letter in dead_letters.iter().filter(|d| filter.matches(d)) {
+                print_dead_letter(dead_letter);
+                if dry_run {
+                    continue;
+                }
+                result = dlq::redrive(
+                    &sqs_client,
+                    &opt.dead_letter_queue_url,
+                    source_queue_url,
+                    dead_letter,
+                )
+                .await;
+                if result.is_err() {
+                    break;
+                }
+                redriven.insert(dead_letter.message_id().to_owned());
+            }
+            result
+        }
+    };
+
+    // Whatever wasn't redriven goes back to the dead letter queue as it was
+    for dead_letter in dead_letters
+        .iter()
+        .filter(|dead_letter| !redriven.contains(dead_letter.message_id()))
+    {
+        dlq::release(&sqs_client, &opt.dead_letter_queue_url, dead_letter)
+            .await
+            .unwrap_or_else(|e| tracing::error!(message="release failed", error=?e));
+    }
+
+    result
+}
@@ -10,6 +10,7 @@ use crate::{
         INCREMENT_ONLY,
         LAST_SEEN_TIME,
         PSEUDO_KEY,
+        SET_UNION,
         STATIC_ID,
         TERMINATE_TIME,
     },
@@ -27,6 +28,8 @@ pub enum ConflictResolution {
     IncrementOnly,
     /// Given two values, choose the lesser of thet two
     DecrementOnly,
+    /// Given two sets, take their union
+    SetUnion,
 }
 
 impl ConflictResolution {
@@ -35,6 +38,7 @@ impl ConflictResolution {
             Self::Immutable => true,
             Self::IncrementOnly => false,
             Self::DecrementOnly => false,
+            Self::SetUnion => false,
         }
     }
 
@@ -48,6 +52,7 @@ impl ConflictResolution {
             INCREMENT_ONLY => Some(ConflictResolution::IncrementOnly),
             DECREMENT_ONLY => Some(ConflictResolution::DecrementOnly),
             IMMUTABLE => Some(ConflictResolution::Immutable),
+            SET_UNION => Some(ConflictResolution::SetUnion),
             _ => None,
         }
     }
@@ -62,6 +67,7 @@ mod tests {
         assert!(ConflictResolution::Immutable.implies_cacheable());
         assert!(!ConflictResolution::IncrementOnly.implies_cacheable());
         assert!(!ConflictResolution::DecrementOnly.implies_cacheable());
+        assert!(!ConflictResolution::SetUnion.implies_cacheable());
     }
 }
 
@@ -1,4 +1,7 @@
-use graphql_parser::schema::Directive;
+use graphql_parser::schema::{
+    Directive,
+    Type,
+};
 
 use crate::node_type::MergeFailure;
 
@@ -22,6 +25,8 @@ pub enum CodeGenError<'a> {
     MissingGraplDirectiveArguments {
         directives: Vec<Directive<'a, &'a str>>,
     },
+    #[error("Unsupported Set Type, only sets of strings are supported")]
+    UnsupportedSetType { element_type: Type<'a, &'a str> },
     #[error("Failed to extend node schema")]
     MergeFailure(#[from] MergeFailure),
 }
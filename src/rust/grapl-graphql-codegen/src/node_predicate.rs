@@ -74,13 +74,19 @@ impl NodePredicate {
 
     pub fn generate_python_query_comparisons(&self) -> String {
         match self.predicate_type {
-            PredicateType::String => self.generate_python_str_comparisons(),
+            PredicateType::String | PredicateType::Bytes | PredicateType::StrSet => {
+                self.generate_python_str_comparisons()
+            }
             PredicateType::I64 => self.generate_python_int_comparisons(),
-            PredicateType::U64 => self.generate_python_int_comparisons(),
+            PredicateType::U64 | PredicateType::Timestamp => self.generate_python_int_comparisons(),
+            PredicateType::Bool | PredicateType::F64 => String::new(),
         }
     }
 
     pub fn generate_python_query_def(&self) -> String {
+        if !self.predicate_type.has_python_accessors() {
+            return String::new();
+        }
         let mut query_def = String::with_capacity(256);
         let python_ty = self.predicate_type.into_python_primitive_type();
 
@@ -94,7 +100,7 @@ impl NodePredicate {
         query_def = query_def + "                \"" + &self.predicate_name + "\",\n";
 
         match self.predicate_type {
-            PredicateType::String => {
+            PredicateType::String | PredicateType::Bytes | PredicateType::StrSet => {
                 query_def += "                eq=eq,\n";
                 query_def += "                contains=contains,\n";
                 query_def += "                starts_with=starts_with,\n";
@@ -102,13 +108,14 @@ impl NodePredicate {
                 query_def += "                regexp=regexp,\n";
                 query_def += "                distance_lt=distance_lt\n";
             }
-            PredicateType::U64 | PredicateType::I64 => {
+            PredicateType::U64 | PredicateType::I64 | PredicateType::Timestamp => {
                 query_def += "                eq=eq,\n";
                 query_def += "                gt=gt,\n";
                 query_def += "                ge=ge,\n";
                 query_def += "                lt=lt,\n";
                 query_def += "                le=le,\n";
             }
+            PredicateType::Bool | PredicateType::F64 => unreachable!(),
         }
         query_def += "            )\n";
         query_def += "        )\n";
@@ -117,6 +124,9 @@ impl NodePredicate {
     }
 
     pub fn generate_viewable_get_predicate_method(&self) -> String {
+        if !self.predicate_type.has_python_accessors() {
+            return String::new();
+        }
         let mut get_method = String::with_capacity(512);
 
         let predicate_name = self.predicate_name.as_str();
@@ -172,7 +182,7 @@ pub fn is_nullable<'a>(field_type: &Type<'a, &'a str>) -> bool {
     match field_type {
         Type::NonNullType(_) => false,
         Type::NamedType(_) => true,
-        Type::ListType(_) => panic!("ListType not supported"),
+        Type::ListType(_) => true,
     }
 }
 
@@ -7,8 +7,12 @@ use graphql_parser::schema::Type;
 
 use crate::{
     constants::{
+        BOOLEAN,
+        BYTES,
+        FLOAT,
         INT,
         STRING,
+        TIMESTAMP,
         UINT,
     },
     errors::CodeGenError,
@@ -20,35 +24,59 @@ pub enum PredicateType {
     String,
     I64,
     U64,
+    Bool,
+    F64,
+    /// Stored in the graph as base64
+    Bytes,
+    /// Milliseconds since the unix epoch
+    Timestamp,
+    /// A `[String]` field, which is merged as the union of both sets
+    StrSet,
 }
 
 // Python code generation
 impl PredicateType {
     pub fn into_python_prop_primitive(self) -> String {
         match self {
-            PredicateType::String => "grapl_analyzerlib.node_types.PropType(grapl_analyzerlib.node_types.PropPrimitive.Str, False)",
+            PredicateType::String | PredicateType::Bytes => "grapl_analyzerlib.node_types.PropType(grapl_analyzerlib.node_types.PropPrimitive.Str, False)",
             PredicateType::I64 => "grapl_analyzerlib.node_types.PropType(grapl_analyzerlib.node_types.PropPrimitive.Int, False)",
-            PredicateType::U64 => "grapl_analyzerlib.node_types.PropType(grapl_analyzerlib.node_types.PropPrimitive.Int, False)",
+            PredicateType::U64 | PredicateType::Timestamp => "grapl_analyzerlib.node_types.PropType(grapl_analyzerlib.node_types.PropPrimitive.Int, False)",
+            PredicateType::Bool => "grapl_analyzerlib.node_types.PropType(grapl_analyzerlib.node_types.PropPrimitive.Bool, False)",
+            PredicateType::F64 => "grapl_analyzerlib.node_types.PropType(grapl_analyzerlib.node_types.PropPrimitive.Float, False)",
+            PredicateType::StrSet => "grapl_analyzerlib.node_types.PropType(grapl_analyzerlib.node_types.PropPrimitive.Str, True)",
         }.to_string()
     }
 
     pub fn into_python_primitive_type(self) -> String {
         match self {
-            PredicateType::String => "str",
+            PredicateType::String | PredicateType::Bytes | PredicateType::StrSet => "str",
             PredicateType::I64 => "int",
-            PredicateType::U64 => "int",
+            PredicateType::U64 | PredicateType::Timestamp => "int",
+            PredicateType::Bool => "bool",
+            PredicateType::F64 => "float",
         }
         .to_string()
     }
 
     pub fn into_python_primitive_type_or_not(self) -> String {
         match self {
-            PredicateType::String => "StrOrNot",
+            PredicateType::String | PredicateType::Bytes | PredicateType::StrSet => "StrOrNot",
             PredicateType::I64 => "IntOrNot",
-            PredicateType::U64 => "IntOrNot",
+            PredicateType::U64 | PredicateType::Timestamp => "IntOrNot",
+            PredicateType::Bool => "BoolOrNot",
+            PredicateType::F64 => "FloatOrNot",
         }
         .to_string()
     }
+
+    /// grapl_analyzerlib can only query and fetch string and integer properties, so no
+    /// `with_` or `get_` methods are generated for the other types
+    pub fn has_python_accessors(self) -> bool {
+        match self {
+            PredicateType::Bool | PredicateType::F64 => false,
+            _ => true,
+        }
+    }
 }
 
 impl<'a> TryFrom<&Type<'a, &'a str>> for PredicateType {
@@ -62,12 +90,21 @@ impl<'a> TryFrom<&Type<'a, &'a str>> for PredicateType {
                     STRING => Ok(PredicateType::String),
                     INT => Ok(PredicateType::I64),
                     UINT => Ok(PredicateType::U64),
+                    BOOLEAN => Ok(PredicateType::Bool),
+                    FLOAT => Ok(PredicateType::F64),
+                    BYTES => Ok(PredicateType::Bytes),
+                    TIMESTAMP => Ok(PredicateType::Timestamp),
                     // todo: error
                     unsupported => panic!("Unsupported type: {}", unsupported),
                 }
             }
             Type::NonNullType(ref value) => value.as_ref().try_into(),
-            Type::ListType(_) => todo!("We don't currently support sets of values in Grapl"),
+            Type::ListType(ref value) => match value.as_ref().try_into()? {
+                PredicateType::String => Ok(PredicateType::StrSet),
+                _ => Err(CodeGenError::UnsupportedSetType {
+                    element_type: value.as_ref().clone(),
+                }),
+            },
         }
     }
 }
@@ -90,5 +127,62 @@ mod tests {
             PredicateType::U64.into_python_primitive_type_or_not(),
             "IntOrNot"
         );
+        assert_eq!(
+            PredicateType::Timestamp.into_python_primitive_type_or_not(),
+            "IntOrNot"
+        );
+        assert_eq!(
+            PredicateType::StrSet.into_python_primitive_type_or_not(),
+            "StrOrNot"
+        );
+    }
+
+    fn parse_field_type(schema: &str) -> PredicateType {
+        let document = graphql_parser::parse_schema::<&str>(schema).expect("valid schema");
+        match &document.definitions[0] {
+            graphql_parser::schema::Definition::TypeDefinition(
+                graphql_parser::schema::TypeDefinition::Object(object),
+            ) => PredicateType::try_from(&object.fields[0].field_type).expect("supported type"),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_predicate_type_from_graphql() {
+        assert!(matches!(
+            parse_field_type("type Example { example: Boolean! }"),
+            PredicateType::Bool
+        ));
+        assert!(matches!(
+            parse_field_type("type Example { example: Float }"),
+            PredicateType::F64
+        ));
+        assert!(matches!(
+            parse_field_type("type Example { example: Bytes! }"),
+            PredicateType::Bytes
+        ));
+        assert!(matches!(
+            parse_field_type("type Example { example: Timestamp! }"),
+            PredicateType::Timestamp
+        ));
+        assert!(matches!(
+            parse_field_type("type Example { example: [String!] }"),
+            PredicateType::StrSet
+        ));
+    }
+
+    #[test]
+    fn test_sets_of_other_types_are_unsupported() {
+        let document = graphql_parser::parse_schema::<&str>("type Example { example: [Int] }")
+            .expect("valid schema");
+        match &document.definitions[0] {
+            graphql_parser::schema::Definition::TypeDefinition(
+                graphql_parser::schema::TypeDefinition::Object(object),
+            ) => assert!(matches!(
+                PredicateType::try_from(&object.fields[0].field_type),
+                Err(CodeGenError::UnsupportedSetType { .. })
+            )),
+            _ => unreachable!(),
+        }
     }
 }
@@ -2,6 +2,7 @@ pub mod dgraph_reporter;
 mod macros;
 mod metric_error;
 pub mod metric_reporter;
+pub mod prometheus;
 mod statsd_formatter;
 pub mod timers;
 mod writer_wrapper;
@@ -14,6 +14,7 @@ use chrono::{
 
 use crate::{
     metric_error::MetricError,
+    prometheus,
     statsd_formatter,
     statsd_formatter::{
         statsd_format,
@@ -80,6 +81,7 @@ where
         sample_rate: impl Into<Option<f64>>,
         tags: &[TagPair],
     ) -> Result<(), MetricError> {
+        let sample_rate = sample_rate.into();
         statsd_format(
             &mut self.buffer,
             metric_name,
@@ -88,6 +90,7 @@ where
             sample_rate,
             tags,
         )?;
+        prometheus::registry().record(metric_name, value, metric_type, sample_rate, tags);
         let time = self.format_time_for_cloudwatch((self.utc_now)());
         write!(
             self.out.as_mut(),
@@ -99,6 +102,10 @@ where
         Ok(())
     }
 
+    pub fn service_name(&self) -> &str {
+        &self.service_name
+    }
+
     fn format_time_for_cloudwatch(&self, dt: DateTime<Utc>) -> String {
         // cloudwatch wants ISO8601, but without nanos.
         dt.to_rfc3339_opts(SecondsFormat::Millis, true)
@@ -0,0 +1,249 @@
+//! Every metric a `MetricReporter` writes is also kept in memory, so that it can be scraped
+//! by Prometheus, in its text exposition format, alongside the statsd lines on stdout.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::Mutex,
+};
+
+use lazy_static::lazy_static;
+
+use crate::{
+    metric_reporter::TagPair,
+    statsd_formatter::MetricType,
+};
+
+/// The upper bounds of the histogram buckets. Histograms are durations in milliseconds,
+/// unless tagged with another unit.
+const HISTOGRAM_BUCKETS: [f64; 14] = [
+    1.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0, 30000.0,
+    60000.0,
+];
+
+lazy_static! {
+    static ref REGISTRY: Registry = Registry::default();
+}
+
+/// The registry every `MetricReporter` in the process records to
+pub fn registry() -> &'static Registry {
+    &REGISTRY
+}
+
+type Labels = Vec<(String, String)>;
+
+enum Series {
+    Counter(f64),
+    Gauge(f64),
+    Histogram {
+        buckets: [u64; HISTOGRAM_BUCKETS.len()],
+        sum: f64,
+        count: u64,
+    },
+}
+
+/// The current value of every series, by metric name and labels
+#[derive(Default)]
+pub struct Registry {
+    series: Mutex<BTreeMap<(String, Labels), Series>>,
+}
+
+impl Registry {
+    pub(crate) fn record(
+        &self,
+        metric_name: &str,
+        value: f64,
+        metric_type: MetricType,
+        sample_rate: Option<f64>,
+        tags: &[TagPair],
+    ) {
+        let mut name = sanitize_name(metric_name);
+        if let MetricType::Counter = metric_type {
+            name.push_str("_total");
+        }
+        let labels = tags
+            .iter()
+            .map(|TagPair(key, value)| (sanitize_name(key), (*value).to_owned()))
+            .collect();
+
+        let mut series = self.series.lock().unwrap();
+        let series = series
+            .entry((name, labels))
+            .or_insert_with(|| match metric_type {
+                MetricType::Counter => Series::Counter(0.0),
+                MetricType::Gauge => Series::Gauge(0.0),
+                MetricType::Histogram => Series::Histogram {
+                    buckets: [0; HISTOGRAM_BUCKETS.len()],
+                    sum: 0.0,
+                    count: 0,
+                },
+            });
+        match series {
+            // A sampled counter only saw its share of the increments
+            Series::Counter(total) => *total += value / sample_rate.unwrap_or(1.0),
+            Series::Gauge(current) => *current = value,
+            Series::Histogram {
+                buckets,
+                sum,
+                count,
+            } => {
+                for (bucket, bound) in buckets.iter_mut().zip(HISTOGRAM_BUCKETS) {
+                    if value <= bound {
+                        *bucket += 1;
+                    }
+                }
+                *sum += value;
+                *count += 1;
+            }
+        }
+    }
+
+    /// Every series, in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let series = self.series.lock().unwrap();
+        let mut out = String::new();
+        let mut last_name: Option<&str> = None;
+        // Writing to a `String` can't fail
+        for ((name, labels), series) in series.iter() {
+            if last_name != Some(name.as_str()) {
+                let metric_type = match series {
+                    Series::Counter(_) => "counter",
+                    Series::Gauge(_) => "gauge",
+                    Series::Histogram { .. } => "histogram",
+                };
+                let _ = writeln!(out, "# TYPE {} {}", name, metric_type);
+                last_name = Some(name.as_str());
+            }
+
+            match series {
+                Series::Counter(value) | Series::Gauge(value) => {
+                    let _ = writeln!(out, "{}{} {}", name, format_labels(labels, None), value);
+                }
+                Series::Histogram {
+                    buckets,
+                    sum,
+                    count,
+                } => {
+                    for (bucket, bound) in buckets.iter().zip(HISTOGRAM_BUCKETS) {
+                        let le = bound.to_string();
+                        let _ = writeln!(
+                            out,
+                            "{}_bucket{} {}",
+                            name,
+                            format_labels(labels, Some(&le)),
+                            bucket
+                        );
+                    }
+                    let _ = writeln!(
+                        out,
+                        "{}_bucket{} {}",
+                        name,
+                        format_labels(labels, Some("+Inf")),
+                        count
+                    );
+                    let _ = writeln!(out, "{}_sum{} {}", name, format_labels(labels, None), sum);
+                    let _ = writeln!(
+                        out,
+                        "{}_count{} {}",
+                        name,
+                        format_labels(labels, None),
+                        count
+                    );
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Prometheus names may only contain letters, digits, `_` and `:`, and can't start with a digit
+fn sanitize_name(name: &str) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | ':' => c,
+            _ => '_',
+        })
+        .collect();
+    if sanitized.starts_with(|c: char| c.is_ascii_digit()) {
+        sanitized.insert(0, '_');
+    }
+    sanitized
+}
+
+fn format_labels(labels: &Labels, le: Option<&str>) -> String {
+    let labels: Vec<_> = labels
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .chain(le.map(|le| ("le", le)))
+        .map(|(key, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", key, value)
+        })
+        .collect();
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", labels.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_renders_each_metric_type() {
+        let registry = Registry::default();
+        registry.record(
+            "sqs_executor.messages",
+            1.0,
+            MetricType::Counter,
+            None,
+            &[TagPair("status", "success")],
+        );
+        registry.record(
+            "sqs_executor.messages",
+            1.0,
+            MetricType::Counter,
+            Some(0.5),
+            &[TagPair("status", "success")],
+        );
+        registry.record("concurrency_limit", 4.0, MetricType::Gauge, None, &[]);
+        registry.record("concurrency_limit", 8.0, MetricType::Gauge, None, &[]);
+        registry.record("all_processing.ms", 7.0, MetricType::Histogram, None, &[]);
+        registry.record(
+            "all_processing.ms",
+            70000.0,
+            MetricType::Histogram,
+            None,
+            &[],
+        );
+
+        let rendered = registry.render();
+        let lines: Vec<_> = rendered.lines().collect();
+        assert!(lines.contains(&"# TYPE sqs_executor_messages_total counter"));
+        assert!(lines.contains(&"sqs_executor_messages_total{status=\"success\"} 3"));
+        assert!(lines.contains(&"# TYPE concurrency_limit gauge"));
+        assert!(lines.contains(&"concurrency_limit 8"));
+        assert!(lines.contains(&"# TYPE all_processing_ms histogram"));
+        assert!(lines.contains(&"all_processing_ms_bucket{le=\"5\"} 0"));
+        assert!(lines.contains(&"all_processing_ms_bucket{le=\"10\"} 1"));
+        assert!(lines.contains(&"all_processing_ms_bucket{le=\"60000\"} 1"));
+        assert!(lines.contains(&"all_processing_ms_bucket{le=\"+Inf\"} 2"));
+        assert!(lines.contains(&"all_processing_ms_sum 70007"));
+        assert!(lines.contains(&"all_processing_ms_count 2"));
+    }
+
+    #[test]
+    fn test_sanitizes_names() {
+        assert_eq!(
+            sanitize_name("graph-merger.upsert.ms"),
+            "graph_merger_upsert_ms"
+        );
+        assert_eq!(sanitize_name("5xx"), "_5xx");
+    }
+}
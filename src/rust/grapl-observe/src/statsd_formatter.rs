@@ -27,6 +27,7 @@ pub fn reject_invalid_chars(s: &str) -> Result<(), MetricError> {
     }
 }
 
+#[derive(Clone, Copy)]
 pub enum MetricType {
     Gauge,
     Counter,
@@ -0,0 +1,76 @@
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+use color_eyre::eyre::{
+    Result,
+    WrapErr,
+};
+use grapl_service::zstd_dictionary::{
+    self,
+    DICTIONARY_EXTENSION,
+};
+use rust_proto::pipeline::Envelope;
+use structopt::StructOpt;
+
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "train-zstd-dictionary",
+    about = "Train a zstd dictionary on sample graph payloads"
+)]
+struct Opt {
+    /// Where to write the dictionary, as `<dictionary id>.dict`
+    #[structopt(short = "o", long = "output-dir", parse(from_os_str))]
+    output_dir: PathBuf,
+
+    /// The largest the dictionary may be, in bytes
+    #[structopt(long = "max-size", default_value = "112640")]
+    max_size: usize,
+
+    /// Sample payloads, as the pipeline stores them in S3
+    #[structopt(parse(from_os_str), required = true)]
+    samples: Vec<PathBuf>,
+}
+
+/// The uncompressed message in a stored payload. Payloads that aren't an `Envelope`
+/// are taken to be the message itself.
+fn read_sample(path: &Path) -> Result<Vec<u8>> {
+    let payload = std::fs::read(path).context(format!("Failed to read {:?}", path))?;
+    let message = match prost::Message::decode(payload.as_slice()) {
+        Ok(Envelope { inner_message, .. }) => inner_message,
+        Err(_) => payload,
+    };
+    if message.starts_with(&ZSTD_MAGIC) {
+        Ok(zstd_dictionary::dictionaries()
+            .decompress(&message)
+            .context(format!("Failed to decompress {:?}", path))?)
+    } else {
+        Ok(message)
+    }
+}
+
+fn main() -> Result<()> {
+    tracing_subscriber::fmt::init();
+    color_eyre::install()?;
+
+    let opt = Opt::from_args();
+    tracing::debug!(message="Executing train-zstd-dictionary", options=?opt);
+
+    let samples = opt
+        .samples
+        .iter()
+        .map(|path| read_sample(path))
+        .collect::<Result<Vec<_>>>()?;
+    let dictionary = zstd_dictionary::train(&samples, opt.max_size)?;
+    let id = zstd_dictionary::dictionary_id(&dictionary).expect("trained dictionaries have ids");
+
+    let path = opt
+        .output_dir
+        .join(format!("{}.{}", id, DICTIONARY_EXTENSION));
+    std::fs::write(&path, &dictionary).context(format!("Failed to write {:?}", path))?;
+    println!("{}", id);
+    Ok(())
+}
@@ -0,0 +1,237 @@
+//! ArcSight Common Event Format, with or without a syslog header in front of it.
+
+use std::collections::BTreeMap;
+
+use sqs_executor::event_decoder::{
+    EventStream,
+    PayloadDecoder,
+    StreamingPayloadDecoder,
+};
+
+use crate::decoder::syslog::{
+    decode_frame_stream,
+    parse_error,
+    syslog_header,
+    SyslogDecoderError,
+    SyslogMessage,
+};
+
+const CEF_PREFIX: &str = "CEF:";
+const HEADER_FIELDS: usize = 7;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CefEvent {
+    /// The syslog header the event was sent with, if any
+    pub syslog: Option<SyslogMessage>,
+    pub version: u32,
+    pub device_vendor: String,
+    pub device_product: String,
+    pub device_version: String,
+    pub signature_id: String,
+    pub name: String,
+    /// Either 0 to 10, or one of Unknown, Low, Medium, High or Very-High
+    pub severity: String,
+    pub extensions: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CefDecoder;
+
+impl StreamingPayloadDecoder<CefEvent> for CefDecoder {
+    type DecoderError = SyslogDecoderError;
+
+    fn decode_stream(
+        &mut self,
+        body: Vec<u8>,
+    ) -> Result<EventStream<CefEvent, Self::DecoderError>, Self::DecoderError> {
+        decode_frame_stream(body, "cef", parse_cef)
+    }
+}
+
+impl PayloadDecoder<Vec<CefEvent>> for CefDecoder {
+    type DecoderError = SyslogDecoderError;
+
+    fn decode(&mut self, body: Vec<u8>) -> Result<Vec<CefEvent>, Self::DecoderError> {
+        decode_frame_stream(body, "cef", parse_cef)?.collect()
+    }
+}
+
+pub fn parse_cef(frame: &str) -> Result<CefEvent, SyslogDecoderError> {
+    let start = frame
+        .find(CEF_PREFIX)
+        .ok_or_else(|| parse_error("No CEF header"))?;
+    let syslog = syslog_header(&frame[..start])?;
+
+    let mut rest = &frame[start + CEF_PREFIX.len()..];
+    let mut fields = Vec::with_capacity(HEADER_FIELDS);
+    while fields.len() < HEADER_FIELDS {
+        let (field, after) =
+            header_field(rest).ok_or_else(|| parse_error("Truncated CEF header"))?;
+        fields.push(field);
+        rest = after;
+    }
+    let mut fields = fields.into_iter();
+    let mut next_field = || fields.next().unwrap_or_default();
+
+    Ok(CefEvent {
+        syslog,
+        version: next_field()
+            .trim()
+            .parse()
+            .map_err(|_| parse_error("Invalid CEF version"))?,
+        device_vendor: next_field(),
+        device_product: next_field(),
+        device_version: next_field(),
+        signature_id: next_field(),
+        name: next_field(),
+        severity: next_field(),
+        extensions: extensions(rest),
+    })
+}
+
+/// Unescapes a header field up to the `|` that ends it, returning what's left after it.
+/// Pipes and backslashes are escaped with a backslash in header fields.
+fn header_field(input: &str) -> Option<(String, &str)> {
+    let mut field = String::new();
+    let mut chars = input.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next()? {
+                (_, escaped @ ('|' | '\\')) => field.push(escaped),
+                (_, other) => {
+                    field.push('\\');
+                    field.push(other);
+                }
+            },
+            '|' => return Some((field, &input[i + 1..])),
+            c => field.push(c),
+        }
+    }
+    None
+}
+
+/// Parses space separated `key=value` extensions. Values may contain spaces, so each one
+/// runs up to the space before the next key, and since values may also contain unescaped
+/// `=`, as URLs do, only an `=` after something that could be a key starts one.
+fn extensions(input: &str) -> BTreeMap<String, String> {
+    let input = input.trim();
+
+    // The start of each key, and of the `=` after it
+    let mut keys: Vec<(usize, usize)> = vec![];
+    let mut escaped = false;
+    for (i, c) in input.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' => escaped = true,
+            '=' => {
+                let key_start = input[..i].rfind(' ').map(|space| space + 1).unwrap_or(0);
+                let after_last_key = keys.last().map_or(true, |&(_, last)| key_start > last);
+                if after_last_key && is_key(&input[key_start..i]) {
+                    keys.push((key_start, i));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut extensions = BTreeMap::new();
+    for (n, &(key_start, equals)) in keys.iter().enumerate() {
+        let value_end = keys
+            .get(n + 1)
+            .map_or(input.len(), |&(next_key_start, _)| next_key_start);
+        extensions.insert(
+            input[key_start..equals].to_owned(),
+            unescape_value(input[equals + 1..value_end].trim_end()),
+        );
+    }
+    extensions
+}
+
+fn is_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '[' | ']'))
+}
+
+/// Extension values escape `=` and backslashes, and encode newlines as `\n` or `\r`
+fn unescape_value(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(escaped @ ('=' | '\\')) => unescaped.push(escaped),
+                Some('n') => unescaped.push('\n'),
+                Some('r') => unescaped.push('\r'),
+                Some(other) => {
+                    unescaped.push('\\');
+                    unescaped.push(other);
+                }
+                None => unescaped.push('\\'),
+            },
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_header_and_extensions() {
+        let event = parse_cef(
+            r"<134>Sep 19 08:26:10 host CEF:0|Security|threat\|manager|1.0|100|worm successfully stopped|10|src=10.0.0.1 dst=2.1.2.2 spt=1232 msg=Detected a threat. No action needed.\nSee \\\\share\=path request=https://example.com/?a=b&c=d",
+        )
+        .unwrap();
+
+        let syslog = event.syslog.unwrap();
+        assert_eq!(syslog.facility, Some(16));
+        assert_eq!(syslog.hostname.as_deref(), Some("host"));
+        assert_eq!(event.version, 0);
+        assert_eq!(event.device_vendor, "Security");
+        assert_eq!(event.device_product, "threat|manager");
+        assert_eq!(event.device_version, "1.0");
+        assert_eq!(event.signature_id, "100");
+        assert_eq!(event.name, "worm successfully stopped");
+        assert_eq!(event.severity, "10");
+        assert_eq!(event.extensions.len(), 5);
+        assert_eq!(event.extensions["src"], "10.0.0.1");
+        assert_eq!(event.extensions["spt"], "1232");
+        assert_eq!(
+            event.extensions["msg"],
+            "Detected a threat. No action needed.\nSee \\\\share=path"
+        );
+        assert_eq!(event.extensions["request"], "https://example.com/?a=b&c=d");
+    }
+
+    #[test]
+    fn test_parses_without_syslog_header_or_extensions() {
+        let event = parse_cef("CEF:1|Vendor|Product|2|sig|Name|High|").unwrap();
+        assert_eq!(event.syslog, None);
+        assert_eq!(event.version, 1);
+        assert_eq!(event.severity, "High");
+        assert!(event.extensions.is_empty());
+
+        assert!(parse_cef("CEF:0|Vendor|Product|truncated").is_err());
+        assert!(parse_cef("not CEF at all").is_err());
+    }
+
+    #[test]
+    fn test_decodes_many_events() {
+        let payload = b"CEF:0|V|P|1|a|A|1|src=1.1.1.1\nCEF:0|V|P|1|b|B|2|src=2.2.2.2\n".to_vec();
+        let events = CefDecoder.decode(payload).unwrap();
+        assert_eq!(
+            events
+                .iter()
+                .map(|event| event.signature_id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["a", "b"]
+        );
+    }
+}
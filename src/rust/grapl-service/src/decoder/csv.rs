@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+
+use serde::de::DeserializeOwned;
+use sqs_executor::{
+    errors::{
+        CheckedError,
+        Recoverable,
+    },
+    event_decoder::{
+        EventStream,
+        PayloadDecoder,
+        StreamingPayloadDecoder,
+    },
+};
+
+use crate::decoder::{
+    decompress::PayloadDecompressionError,
+    stream::SkipUndecodable,
+};
+
+/// Decodes CSV with a header row, deserializing each row by the column headers, so columns
+/// may be in any order and extra ones are ignored
+#[derive(Debug, Clone)]
+pub struct CsvDecoder {
+    delimiter: u8,
+    /// Field names by column header
+    field_mapping: HashMap<String, String>,
+}
+
+impl Default for CsvDecoder {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            field_mapping: HashMap::new(),
+        }
+    }
+}
+
+impl CsvDecoder {
+    pub fn with_delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Deserializes the column headed `column` as the field `field`, for exports whose
+    /// headers don't match the event's field names
+    pub fn with_field(mut self, column: impl Into<String>, field: impl Into<String>) -> Self {
+        self.field_mapping.insert(column.into(), field.into());
+        self
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CsvDecoderError {
+    #[error("DecompressionError")]
+    Decompression(#[from] PayloadDecompressionError),
+    #[error("CsvError: {0}")]
+    Csv(#[from] csv::Error),
+}
+
+impl CheckedError for CsvDecoderError {
+    fn error_type(&self) -> Recoverable {
+        match self {
+            Self::Decompression(e) => e.error_type(),
+            Self::Csv(_) => Recoverable::Persistent,
+        }
+    }
+}
+
+impl CsvDecoderError {
+    /// Failing to read the payload, as opposed to failing to decode a row, means that it
+    /// failed to decompress
+    fn from_csv(error: csv::Error) -> Self {
+        if !error.is_io_error() {
+            return Self::Csv(error);
+        }
+        match error.into_kind() {
+            csv::ErrorKind::Io(error) => PayloadDecompressionError::from(error).into(),
+            _ => unreachable!("is_io_error"),
+        }
+    }
+}
+
+impl<D> StreamingPayloadDecoder<D> for CsvDecoder
+where
+    D: DeserializeOwned + Send + 'static,
+{
+    type DecoderError = CsvDecoderError;
+
+    fn decode_stream(
+        &mut self,
+        body: Vec<u8>,
+    ) -> Result<EventStream<D, Self::DecoderError>, Self::DecoderError> {
+        let decompressed = super::decompress::maybe_decompress_reader(body)?;
+
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .from_reader(decompressed);
+        let headers: csv::StringRecord = reader
+            .headers()
+            .map_err(CsvDecoderError::from_csv)?
+            .iter()
+            .map(|column| {
+                self.field_mapping
+                    .get(column)
+                    .map(String::as_str)
+                    .unwrap_or(column)
+            })
+            .collect();
+        reader.set_headers(headers);
+
+        // As with the `NdjsonDecoder`, bad rows are skipped, and it's only an error if every
+        // row is bad
+        let rows = reader.into_deserialize::<D>().map(|result| match result {
+            Ok(event) => Ok(Ok(event)),
+            Err(error) if error.is_io_error() => Err(CsvDecoderError::from_csv(error)),
+            Err(error) => {
+                tracing::error!(message="Unable to deserialize CSV row.", error=?error);
+                Ok(Err(CsvDecoderError::Csv(error)))
+            }
+        });
+        Ok(Box::new(SkipUndecodable::new(rows)))
+    }
+}
+
+impl<D> PayloadDecoder<Vec<D>> for CsvDecoder
+where
+    D: DeserializeOwned + Send + 'static,
+{
+    type DecoderError = CsvDecoderError;
+
+    fn decode(&mut self, body: Vec<u8>) -> Result<Vec<D>, Self::DecoderError> {
+        self.decode_stream(body)?.collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct ProcessStart {
+        pid: u64,
+        image_name: String,
+    }
+
+    #[test]
+    fn test_decodes_by_header() {
+        let payload =
+            b"image_name,pid,user\nsvchost.exe,1,SYSTEM\nnot-a-pid,nope,SYSTEM\ncmd.exe,2,alice\n";
+        let events: Vec<ProcessStart> = CsvDecoder::default().decode(payload.to_vec()).unwrap();
+        assert_eq!(
+            events,
+            vec![
+                ProcessStart {
+                    pid: 1,
+                    image_name: "svchost.exe".to_owned()
+                },
+                ProcessStart {
+                    pid: 2,
+                    image_name: "cmd.exe".to_owned()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_maps_fields_and_delimiters() {
+        let payload = b"ProcessId\tImage\n3\tpowershell.exe\n";
+        let mut decoder = CsvDecoder::default()
+            .with_delimiter(b'\t')
+            .with_field("ProcessId", "pid")
+            .with_field("Image", "image_name");
+        let events: Vec<ProcessStart> = decoder.decode(payload.to_vec()).unwrap();
+        assert_eq!(
+            events,
+            vec![ProcessStart {
+                pid: 3,
+                image_name: "powershell.exe".to_owned()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_errors_when_every_row_is_bad() {
+        let payload = b"pid,image_name\nnope,a\nnope,b\n";
+        let result: Result<Vec<ProcessStart>, _> = CsvDecoder::default().decode(payload.to_vec());
+        assert!(matches!(result, Err(CsvDecoderError::Csv(_))));
+    }
+}
@@ -2,22 +2,103 @@ use std::{
     io::{
         Cursor,
         Read,
+        Take,
     },
     str::FromStr,
     sync::Arc,
 };
 
+use bzip2::read::BzDecoder;
+use lazy_static::lazy_static;
 use libflate::gzip::Decoder as GzDecoder;
+use lz4_flex::frame::FrameDecoder as Lz4Decoder;
 use sqs_executor::errors::{
     CheckedError,
     Recoverable,
 };
+use tracing::warn;
+use xz2::read::XzDecoder;
 
-#[derive(Debug, Clone, PartialEq)]
+use crate::zstd_dictionary::{
+    self,
+    ZstdDictionaryError,
+};
+
+/// Payloads that decompress to more than this are rejected, unless `MAX_DECOMPRESSED_BYTES`
+/// says otherwise
+pub const DEFAULT_MAX_DECOMPRESSED_BYTES: usize = 512 * 1024 * 1024;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const LZ4_MAGIC: &[u8] = &[0x04, 0x22, 0x4d, 0x18];
+/// Followed by the block size, `1` to `9`
+const BZIP2_MAGIC: &[u8] = b"BZh";
+const XZ_MAGIC: &[u8] = &[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00];
+
+lazy_static! {
+    /// `SOURCE_COMPRESSION`, if it's set to something we recognize
+    static ref OVERRIDE: Option<PayloadDecompression> = grapl_config::source_compression()
+        .and_then(|value| match PayloadDecompression::from_str(&value) {
+            Ok(decompression) => Some(decompression),
+            Err(()) => {
+                warn!(
+                    value = value.as_str(),
+                    "Unrecognized SOURCE_COMPRESSION, detecting compression instead"
+                );
+                None
+            }
+        });
+    /// `MAX_DECOMPRESSED_BYTES`, if it's set to a valid number
+    static ref MAX_DECOMPRESSED_BYTES: usize = grapl_config::max_decompressed_bytes()
+        .and_then(|value| match value.parse() {
+            Ok(max_decompressed_bytes) => Some(max_decompressed_bytes),
+            Err(_) => {
+                warn!(
+                    value = value.as_str(),
+                    default = DEFAULT_MAX_DECOMPRESSED_BYTES,
+                    "Invalid MAX_DECOMPRESSED_BYTES, using the default instead"
+                );
+                None
+            }
+        })
+        .unwrap_or(DEFAULT_MAX_DECOMPRESSED_BYTES);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PayloadDecompression {
     Gzip,
     None,
     Zstd,
+    Lz4,
+    Bzip2,
+    Xz,
+}
+
+impl PayloadDecompression {
+    /// Detects the compression from the payload's magic bytes. Anything unrecognized is
+    /// taken to be uncompressed.
+    pub fn detect(input: &[u8]) -> Self {
+        if input.starts_with(GZIP_MAGIC) {
+            Self::Gzip
+        } else if input.starts_with(ZSTD_MAGIC) {
+            Self::Zstd
+        } else if input.starts_with(LZ4_MAGIC) {
+            Self::Lz4
+        } else if is_bzip2(input) {
+            Self::Bzip2
+        } else if input.starts_with(XZ_MAGIC) {
+            Self::Xz
+        } else {
+            Self::None
+        }
+    }
+}
+
+fn is_bzip2(input: &[u8]) -> bool {
+    match input.strip_prefix(BZIP2_MAGIC) {
+        Some([block_size, ..]) => (b'1'..=b'9').contains(block_size),
+        _ => false,
+    }
 }
 
 impl FromStr for PayloadDecompression {
@@ -28,6 +109,9 @@ impl FromStr for PayloadDecompression {
             "gzip" => Ok(PayloadDecompression::Gzip),
             "none" => Ok(PayloadDecompression::None),
             "zstd" => Ok(PayloadDecompression::Zstd),
+            "lz4" => Ok(PayloadDecompression::Lz4),
+            "bzip2" => Ok(PayloadDecompression::Bzip2),
+            "xz" => Ok(PayloadDecompression::Xz),
             _ => Err(()),
         }
     }
@@ -37,11 +121,28 @@ impl FromStr for PayloadDecompression {
 pub enum PayloadDecompressionError {
     #[error("DecompressionError")]
     DecompressionError(Arc<std::io::Error>),
+    #[error("ZstdDictionaryError: {0}")]
+    ZstdDictionary(Arc<ZstdDictionaryError>),
+    #[error("Payload decompresses to more than {0} bytes")]
+    TooLarge(usize),
 }
 
 impl From<std::io::Error> for PayloadDecompressionError {
     fn from(err: std::io::Error) -> Self {
-        PayloadDecompressionError::DecompressionError(Arc::new(err))
+        // A `DecompressingReader` reports its errors through `Read`
+        match err
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<PayloadDecompressionError>())
+        {
+            Some(inner) => inner.clone(),
+            None => PayloadDecompressionError::DecompressionError(Arc::new(err)),
+        }
+    }
+}
+
+impl From<ZstdDictionaryError> for PayloadDecompressionError {
+    fn from(err: ZstdDictionaryError) -> Self {
+        PayloadDecompressionError::ZstdDictionary(Arc::new(err))
     }
 }
 
@@ -49,26 +150,185 @@ impl CheckedError for PayloadDecompressionError {
     fn error_type(&self) -> Recoverable {
         match self {
             Self::DecompressionError(_) => Recoverable::Persistent,
+            Self::ZstdDictionary(e) => e.error_type(),
+            Self::TooLarge(_) => Recoverable::Persistent,
         }
     }
 }
 
+/// Decompresses the payload, detecting its compression unless `SOURCE_COMPRESSION`
+/// overrides it
 pub fn maybe_decompress(input: &[u8]) -> Result<Vec<u8>, PayloadDecompressionError> {
-    let value = grapl_config::source_compression();
-    match PayloadDecompression::from_str(value.as_str()).expect("PayloadDecompression") {
-        PayloadDecompression::Gzip => {
-            let mut decoder = GzDecoder::new(input)?;
-            let mut decoded_data = Vec::with_capacity(input.len());
+    let decompression = OVERRIDE.unwrap_or_else(|| PayloadDecompression::detect(input));
+    decompress(input, decompression, *MAX_DECOMPRESSED_BYTES)
+}
+
+pub fn decompress(
+    input: &[u8],
+    decompression: PayloadDecompression,
+    max_decompressed_bytes: usize,
+) -> Result<Vec<u8>, PayloadDecompressionError> {
+    let decoder: Box<dyn Read + '_> = match decompression {
+        PayloadDecompression::Gzip => Box::new(GzDecoder::new(input)?),
+        PayloadDecompression::None => Box::new(input),
+        PayloadDecompression::Zstd => zstd_dictionary::dictionaries().decoder(input)?,
+        PayloadDecompression::Lz4 => Box::new(Lz4Decoder::new(input)),
+        PayloadDecompression::Bzip2 => Box::new(BzDecoder::new(input)),
+        PayloadDecompression::Xz => Box::new(XzDecoder::new(input)),
+    };
+
+    // Read one byte past the limit, to tell a payload that's exactly at it from a bomb
+    let mut decoded_data = Vec::with_capacity(input.len().min(max_decompressed_bytes));
+    decoder
+        .take((max_decompressed_bytes as u64).saturating_add(1))
+        .read_to_end(&mut decoded_data)?;
+    if decoded_data.len() > max_decompressed_bytes {
+        return Err(PayloadDecompressionError::TooLarge(max_decompressed_bytes));
+    }
+
+    Ok(decoded_data)
+}
+
+/// Decompresses the payload as it's read, so it's never entirely in memory decompressed.
+/// Like `maybe_decompress`, its compression is detected unless `SOURCE_COMPRESSION`
+/// overrides it.
+pub fn maybe_decompress_reader(
+    input: Vec<u8>,
+) -> Result<DecompressingReader, PayloadDecompressionError> {
+    let decompression = OVERRIDE.unwrap_or_else(|| PayloadDecompression::detect(&input));
+    decompress_reader(input, decompression, *MAX_DECOMPRESSED_BYTES)
+}
+
+pub fn decompress_reader(
+    input: Vec<u8>,
+    decompression: PayloadDecompression,
+    max_decompressed_bytes: usize,
+) -> Result<DecompressingReader, PayloadDecompressionError> {
+    let decoder: Box<dyn Read + Send> = match decompression {
+        PayloadDecompression::Gzip => Box::new(GzDecoder::new(Cursor::new(input))?),
+        PayloadDecompression::None => Box::new(Cursor::new(input)),
+        PayloadDecompression::Zstd => zstd_dictionary::dictionaries().owned_decoder(input)?,
+        PayloadDecompression::Lz4 => Box::new(Lz4Decoder::new(Cursor::new(input))),
+        PayloadDecompression::Bzip2 => Box::new(BzDecoder::new(Cursor::new(input))),
+        PayloadDecompression::Xz => Box::new(XzDecoder::new(Cursor::new(input))),
+    };
 
-            decoder.read_to_end(&mut decoded_data)?;
+    Ok(DecompressingReader {
+        decoder: decoder.take((max_decompressed_bytes as u64).saturating_add(1)),
+        read: 0,
+        max_decompressed_bytes,
+    })
+}
+
+/// A decompressed payload, as from `maybe_decompress_reader`.
+///
+/// Reading past `max_decompressed_bytes` fails with an `io::Error` that converts back into
+/// `PayloadDecompressionError::TooLarge`.
+pub struct DecompressingReader {
+    decoder: Take<Box<dyn Read + Send>>,
+    read: usize,
+    max_decompressed_bytes: usize,
+}
+
+impl Read for DecompressingReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.decoder.read(buf)?;
+        self.read += read;
+        if self.read > self.max_decompressed_bytes {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                PayloadDecompressionError::TooLarge(self.max_decompressed_bytes),
+            ));
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    const PAYLOAD: &[u8] = b"{\"event\": \"process_start\"}\n{\"event\": \"process_stop\"}\n";
+
+    fn compressed() -> Vec<(PayloadDecompression, Vec<u8>)> {
+        let mut gzip = libflate::gzip::Encoder::new(vec![]).unwrap();
+        gzip.write_all(PAYLOAD).unwrap();
+
+        let mut lz4 = lz4_flex::frame::FrameEncoder::new(vec![]);
+        lz4.write_all(PAYLOAD).unwrap();
+
+        let mut bzip2 = bzip2::write::BzEncoder::new(vec![], bzip2::Compression::default());
+        bzip2.write_all(PAYLOAD).unwrap();
 
-            Ok(decoded_data)
+        let mut xz = xz2::write::XzEncoder::new(vec![], 6);
+        xz.write_all(PAYLOAD).unwrap();
+
+        vec![
+            (PayloadDecompression::None, PAYLOAD.to_vec()),
+            (
+                PayloadDecompression::Gzip,
+                gzip.finish().into_result().unwrap(),
+            ),
+            (
+                PayloadDecompression::Zstd,
+                zstd::stream::encode_all(PAYLOAD, 4).unwrap(),
+            ),
+            (PayloadDecompression::Lz4, lz4.finish().unwrap()),
+            (PayloadDecompression::Bzip2, bzip2.finish().unwrap()),
+            (PayloadDecompression::Xz, xz.finish().unwrap()),
+        ]
+    }
+
+    #[test]
+    fn test_detects_and_decompresses_each_format() {
+        for (expected, input) in compressed() {
+            let detected = PayloadDecompression::detect(&input);
+            assert_eq!(detected, expected);
+            assert_eq!(decompress(&input, detected, 1024).unwrap(), PAYLOAD);
         }
-        PayloadDecompression::None => Ok(input.to_vec()),
-        PayloadDecompression::Zstd => {
-            let body = Cursor::new(input);
+    }
 
-            zstd::stream::decode_all(body).map_err(|e| e.into())
+    #[test]
+    fn test_text_starting_like_bzip2_is_uncompressed() {
+        for input in [&b"BZh"[..], b"BZh0\n", b"BZh 9\n", b"BZhello\n"] {
+            let detected = PayloadDecompression::detect(input);
+            assert_eq!(detected, PayloadDecompression::None);
+            assert_eq!(decompress(input, detected, 1024).unwrap(), input);
         }
     }
+
+    #[test]
+    fn test_rejects_payloads_over_the_limit() {
+        let bomb = zstd::stream::encode_all(&vec![0u8; 1024 * 1024][..], 4).unwrap();
+        let err = decompress(&bomb, PayloadDecompression::Zstd, 1024).unwrap_err();
+        assert!(matches!(err, PayloadDecompressionError::TooLarge(1024)));
+        assert_eq!(err.error_type(), Recoverable::Persistent);
+
+        let exact = decompress(PAYLOAD, PayloadDecompression::None, PAYLOAD.len()).unwrap();
+        assert_eq!(exact, PAYLOAD);
+    }
+
+    #[test]
+    fn test_decompresses_each_format_as_its_read() {
+        for (decompression, input) in compressed() {
+            let mut decompressed = vec![];
+            decompress_reader(input, decompression, 1024)
+                .unwrap()
+                .read_to_end(&mut decompressed)
+                .unwrap();
+            assert_eq!(decompressed, PAYLOAD);
+        }
+    }
+
+    #[test]
+    fn test_reader_rejects_payloads_over_the_limit() {
+        let bomb = zstd::stream::encode_all(&vec![0u8; 1024 * 1024][..], 4).unwrap();
+        let mut reader = decompress_reader(bomb, PayloadDecompression::Zstd, 1024).unwrap();
+
+        let err = reader.read_to_end(&mut vec![]).unwrap_err();
+        let err = PayloadDecompressionError::from(err);
+        assert!(matches!(err, PayloadDecompressionError::TooLarge(1024)));
+    }
 }
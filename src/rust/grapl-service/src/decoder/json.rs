@@ -20,7 +20,7 @@ pub enum JsonDecoderError {
 impl CheckedError for JsonDecoderError {
     fn error_type(&self) -> Recoverable {
         match self {
-            Self::DecompressionError(_) => Recoverable::Persistent,
+            Self::DecompressionError(e) => e.error_type(),
             Self::JsonError(_) => Recoverable::Persistent,
         }
     }
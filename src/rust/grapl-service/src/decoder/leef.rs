@@ -0,0 +1,167 @@
+//! IBM QRadar's Log Event Extended Format, versions 1.0 and 2.0, with or without a syslog
+//! header in front of it.
+
+use std::collections::BTreeMap;
+
+use sqs_executor::event_decoder::{
+    EventStream,
+    PayloadDecoder,
+    StreamingPayloadDecoder,
+};
+
+use crate::decoder::syslog::{
+    decode_frame_stream,
+    parse_error,
+    syslog_header,
+    SyslogDecoderError,
+    SyslogMessage,
+};
+
+const LEEF_PREFIX: &str = "LEEF:";
+/// LEEF 1.0 attributes are always tab delimited, and LEEF 2.0 ones are by default
+const DEFAULT_DELIMITER: char = '\t';
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LeefEvent {
+    /// The syslog header the event was sent with, if any
+    pub syslog: Option<SyslogMessage>,
+    pub version: String,
+    pub vendor: String,
+    pub product: String,
+    pub product_version: String,
+    pub event_id: String,
+    pub attributes: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LeefDecoder;
+
+impl StreamingPayloadDecoder<LeefEvent> for LeefDecoder {
+    type DecoderError = SyslogDecoderError;
+
+    fn decode_stream(
+        &mut self,
+        body: Vec<u8>,
+    ) -> Result<EventStream<LeefEvent, Self::DecoderError>, Self::DecoderError> {
+        decode_frame_stream(body, "leef", parse_leef)
+    }
+}
+
+impl PayloadDecoder<Vec<LeefEvent>> for LeefDecoder {
+    type DecoderError = SyslogDecoderError;
+
+    fn decode(&mut self, body: Vec<u8>) -> Result<Vec<LeefEvent>, Self::DecoderError> {
+        decode_frame_stream(body, "leef", parse_leef)?.collect()
+    }
+}
+
+pub fn parse_leef(frame: &str) -> Result<LeefEvent, SyslogDecoderError> {
+    let start = frame
+        .find(LEEF_PREFIX)
+        .ok_or_else(|| parse_error("No LEEF header"))?;
+    let syslog = syslog_header(&frame[..start])?;
+
+    let mut fields = frame[start + LEEF_PREFIX.len()..].splitn(6, '|');
+    let mut next_field = || {
+        fields
+            .next()
+            .map(str::to_owned)
+            .ok_or_else(|| parse_error("Truncated LEEF header"))
+    };
+    let version = next_field()?;
+    let vendor = next_field()?;
+    let product = next_field()?;
+    let product_version = next_field()?;
+    let event_id = next_field()?;
+    // Some senders leave off the `|` after the event id when there are no attributes
+    let rest = fields.next().unwrap_or_default();
+
+    let (delimiter, attributes) = match version.trim() {
+        "1.0" => (DEFAULT_DELIMITER, rest),
+        "2.0" => match rest.split_once('|') {
+            Some((delimiter, attributes)) => (parse_delimiter(delimiter)?, attributes),
+            None => (DEFAULT_DELIMITER, rest),
+        },
+        _ => return Err(parse_error("Unsupported LEEF version")),
+    };
+
+    Ok(LeefEvent {
+        syslog,
+        version,
+        vendor,
+        product,
+        product_version,
+        event_id,
+        attributes: parse_attributes(attributes, delimiter),
+    })
+}
+
+/// The delimiter is given as the character itself, or as its code in hex, as in `x09` or
+/// `0x09`. Left empty, it's a tab.
+fn parse_delimiter(delimiter: &str) -> Result<char, SyslogDecoderError> {
+    let mut chars = delimiter.chars();
+    match (chars.next(), chars.next()) {
+        (None, _) => Ok(DEFAULT_DELIMITER),
+        (Some(c), None) => Ok(c),
+        _ => delimiter
+            .strip_prefix("0x")
+            .or_else(|| delimiter.strip_prefix('x'))
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .and_then(char::from_u32)
+            .ok_or_else(|| parse_error("Invalid LEEF delimiter")),
+    }
+}
+
+fn parse_attributes(attributes: &str, delimiter: char) -> BTreeMap<String, String> {
+    attributes
+        .split(delimiter)
+        .filter_map(|attribute| attribute.split_once('='))
+        .filter(|(key, _)| !key.trim().is_empty())
+        .map(|(key, value)| (key.trim().to_owned(), value.to_owned()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_leef_1() {
+        let event = parse_leef(
+            "<13>Jan 18 11:07:53 192.168.1.1 LEEF:1.0|Microsoft|MSExchange|4.0 SP1|15345|src=10.50.1.1\tdst=2.10.20.20\tmsg=a=b c",
+        )
+        .unwrap();
+
+        let syslog = event.syslog.unwrap();
+        assert_eq!(syslog.hostname.as_deref(), Some("192.168.1.1"));
+        assert_eq!(event.version, "1.0");
+        assert_eq!(event.vendor, "Microsoft");
+        assert_eq!(event.product, "MSExchange");
+        assert_eq!(event.product_version, "4.0 SP1");
+        assert_eq!(event.event_id, "15345");
+        assert_eq!(event.attributes.len(), 3);
+        assert_eq!(event.attributes["dst"], "2.10.20.20");
+        assert_eq!(event.attributes["msg"], "a=b c");
+    }
+
+    #[test]
+    fn test_parses_leef_2_delimiters() {
+        let event =
+            parse_leef("LEEF:2.0|Lancope|StealthWatch|1.0|41|^|src=10.0.1.8^dst=10.0.0.5").unwrap();
+        assert_eq!(event.syslog, None);
+        assert_eq!(event.attributes["src"], "10.0.1.8");
+        assert_eq!(event.attributes["dst"], "10.0.0.5");
+
+        let event =
+            parse_leef("LEEF:2.0|Vendor|Product|1.0|41|0x7c|src=10.0.1.8|dst=10.0.0.5").unwrap();
+        assert_eq!(event.attributes.len(), 2);
+
+        let event = parse_leef("LEEF:2.0|Vendor|Product|1.0|41").unwrap();
+        assert_eq!(event.event_id, "41");
+        assert!(event.attributes.is_empty());
+
+        assert!(parse_leef("LEEF:2.0|Vendor|Product|1.0|41|xzz|src=1").is_err());
+        assert!(parse_leef("LEEF:3.0|Vendor|Product|1.0|41|").is_err());
+        assert!(parse_leef("LEEF:1.0|Vendor|Product").is_err());
+    }
+}
@@ -1,14 +1,44 @@
+pub mod cef;
+pub mod csv;
 pub mod decompress;
 pub mod json;
+pub mod leef;
 pub mod ndjson;
+pub mod parquet;
 pub mod proto;
+pub mod stream;
+pub mod syslog;
 
+pub use cef::{
+    CefDecoder,
+    CefEvent,
+};
 pub use json::{
     JsonDecoder,
     JsonDecoderError,
 };
+pub use leef::{
+    LeefDecoder,
+    LeefEvent,
+};
 pub use ndjson::NdjsonDecoder;
 pub use proto::{
     ProtoDecoder,
     ProtoDecoderError,
 };
+pub use syslog::{
+    SyslogDecoder,
+    SyslogDecoderError,
+    SyslogMessage,
+};
+
+pub use self::{
+    csv::{
+        CsvDecoder,
+        CsvDecoderError,
+    },
+    parquet::{
+        ParquetDecoder,
+        ParquetDecoderError,
+    },
+};
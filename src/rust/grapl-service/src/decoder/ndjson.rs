@@ -1,13 +1,20 @@
-use serde::Deserialize;
+use serde::de::DeserializeOwned;
 use sqs_executor::{
     errors::{
         CheckedError,
         Recoverable,
     },
-    event_decoder::PayloadDecoder,
+    event_decoder::{
+        EventStream,
+        PayloadDecoder,
+        StreamingPayloadDecoder,
+    },
 };
 
-use crate::decoder::decompress::PayloadDecompressionError;
+use crate::decoder::{
+    decompress::PayloadDecompressionError,
+    stream::decode_lines,
+};
 
 #[derive(Debug, Clone, Default)]
 pub struct NdjsonDecoder;
@@ -23,49 +30,48 @@ pub enum NdjsonDecoderError {
 impl CheckedError for NdjsonDecoderError {
     fn error_type(&self) -> Recoverable {
         match self {
-            Self::Decompression(_) => Recoverable::Persistent,
+            Self::Decompression(e) => e.error_type(),
             Self::Deserialization(_) => Recoverable::Persistent,
         }
     }
 }
 
-impl<D> PayloadDecoder<Vec<D>> for NdjsonDecoder
+impl<D> StreamingPayloadDecoder<D> for NdjsonDecoder
 where
-    for<'a> D: Deserialize<'a>,
+    D: DeserializeOwned + Send + 'static,
 {
     type DecoderError = NdjsonDecoderError;
 
-    fn decode(&mut self, body: Vec<u8>) -> Result<Vec<D>, Self::DecoderError> {
-        let decompressed = super::decompress::maybe_decompress(body.as_slice())?;
+    fn decode_stream(
+        &mut self,
+        body: Vec<u8>,
+    ) -> Result<EventStream<D, Self::DecoderError>, Self::DecoderError> {
+        let reader = super::decompress::maybe_decompress_reader(body)?;
 
-        let mut first_deserialization_error: Option<serde_json::Error> = None;
-
-        let events: Vec<_> = decompressed
-            .split(|byte| *byte == b'\n')
-            .filter(|line| !line.is_empty())
-            .filter_map(|line| {
-                let result = serde_json::from_slice(line);
-                match result {
-                    Ok(result) => Some(result),
-                    Err(error) => {
-                        tracing::error!(message="Unable to deserialize OSQuery event.", error=?error);
+        // If some of our events successfully parse then we want to continue and send those to
+        // the event handler. Only if all parsing fails do we want to return an error. Note the
+        // other error condition is a failure to decompress.
+        Ok(decode_lines(reader, |line| {
+            if line.is_empty() {
+                return None;
+            }
+            let result: Result<D, NdjsonDecoderError> =
+                serde_json::from_slice(line).map_err(|error| {
+                    tracing::error!(message="Unable to deserialize OSQuery event.", error=?error);
+                    error.into()
+                });
+            Some(result)
+        }))
+    }
+}
 
-                        if first_deserialization_error.is_none() {
-                            first_deserialization_error = Some(error);
-                        }
-                        None
-                    }
-                }
-            })
-            .collect();
+impl<D> PayloadDecoder<Vec<D>> for NdjsonDecoder
+where
+    D: DeserializeOwned + Send + 'static,
+{
+    type DecoderError = NdjsonDecoderError;
 
-        // This is a bit awkward at the moment, due to interfaces to the sqs-executor.  If some of
-        // our events successfully parsed then we want to continue and send those to the event
-        // handler. Only if all parsing has failed and we have no events do we want to return an
-        // error here. Note the other error condition is a failure to decompress above.
-        match first_deserialization_error {
-            Some(error) if events.is_empty() => Err(error.into()),
-            _ => Ok(events),
-        }
+    fn decode(&mut self, body: Vec<u8>) -> Result<Vec<D>, Self::DecoderError> {
+        self.decode_stream(body)?.collect()
     }
 }
@@ -0,0 +1,323 @@
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+};
+
+use parquet::{
+    errors::ParquetError,
+    file::reader::{
+        FileReader,
+        SerializedFileReader,
+    },
+    record::{
+        Field,
+        Row,
+    },
+    util::cursor::SliceableCursor,
+};
+use serde::de::DeserializeOwned;
+use serde_json::{
+    Map,
+    Value,
+};
+use sqs_executor::{
+    errors::{
+        CheckedError,
+        Recoverable,
+    },
+    event_decoder::{
+        EventStream,
+        PayloadDecoder,
+        StreamingPayloadDecoder,
+    },
+};
+
+use crate::decoder::{
+    decompress::PayloadDecompressionError,
+    stream::SkipUndecodable,
+};
+
+/// Decodes Parquet, deserializing each row by its column names, one row group at a time.
+///
+/// Rows are deserialized as if they were JSON objects. Dates are days since the epoch and
+/// timestamps are in whichever unit the column is in, as numbers, and decimals are strings.
+#[derive(Debug, Clone, Default)]
+pub struct ParquetDecoder {
+    /// Field names by column name
+    field_mapping: HashMap<String, String>,
+}
+
+impl ParquetDecoder {
+    /// Deserializes the column `column` as the field `field`, for exports whose column
+    /// names don't match the event's field names
+    pub fn with_field(mut self, column: impl Into<String>, field: impl Into<String>) -> Self {
+        self.field_mapping.insert(column.into(), field.into());
+        self
+    }
+
+    fn row_to_json(&self, row: &Row) -> Value {
+        Value::Object(
+            row.get_column_iter()
+                .map(|(column, field)| {
+                    let name = self.field_mapping.get(column).unwrap_or(column);
+                    (name.clone(), field_to_json(field))
+                })
+                .collect(),
+        )
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ParquetDecoderError {
+    #[error("DecompressionError")]
+    Decompression(#[from] PayloadDecompressionError),
+    #[error("ParquetError: {0}")]
+    Parquet(#[from] ParquetError),
+    #[error("DeserializeError")]
+    Deserialization(#[from] serde_json::Error),
+}
+
+impl CheckedError for ParquetDecoderError {
+    fn error_type(&self) -> Recoverable {
+        match self {
+            Self::Decompression(e) => e.error_type(),
+            Self::Parquet(_) => Recoverable::Persistent,
+            Self::Deserialization(_) => Recoverable::Persistent,
+        }
+    }
+}
+
+/// The rows of a Parquet file, read one row group at a time
+struct ParquetRows<D> {
+    decoder: ParquetDecoder,
+    reader: SerializedFileReader<SliceableCursor>,
+    next_row_group: usize,
+    /// What's left of the row group being read
+    rows: std::vec::IntoIter<Row>,
+    phantom: PhantomData<D>,
+}
+
+impl<D> ParquetRows<D> {
+    fn next_row(&mut self) -> Result<Option<Row>, ParquetError> {
+        loop {
+            if let Some(row) = self.rows.next() {
+                return Ok(Some(row));
+            }
+            if self.next_row_group >= self.reader.num_row_groups() {
+                return Ok(None);
+            }
+            let row_group = self.reader.get_row_group(self.next_row_group)?;
+            self.rows = row_group
+                .get_row_iter(None)?
+                .collect::<Vec<_>>()
+                .into_iter();
+            self.next_row_group += 1;
+        }
+    }
+}
+
+impl<D> Iterator for ParquetRows<D>
+where
+    D: DeserializeOwned,
+{
+    type Item = Result<Result<D, ParquetDecoderError>, ParquetDecoderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = match self.next_row() {
+            Ok(row) => row?,
+            Err(error) => return Some(Err(error.into())),
+        };
+        let event: Result<D, ParquetDecoderError> =
+            serde_json::from_value(self.decoder.row_to_json(&row)).map_err(|error| {
+                tracing::error!(message="Unable to deserialize Parquet row.", error=?error);
+                error.into()
+            });
+        Some(Ok(event))
+    }
+}
+
+impl<D> StreamingPayloadDecoder<D> for ParquetDecoder
+where
+    D: DeserializeOwned + Send + 'static,
+{
+    type DecoderError = ParquetDecoderError;
+
+    fn decode_stream(
+        &mut self,
+        body: Vec<u8>,
+    ) -> Result<EventStream<D, Self::DecoderError>, Self::DecoderError> {
+        // Parquet is read from wherever its footer points to, so the file itself has to be
+        // decompressed up front. Only its rows are streamed.
+        let decompressed = super::decompress::maybe_decompress(body.as_slice())?;
+        drop(body);
+        let reader = SerializedFileReader::new(SliceableCursor::new(decompressed))?;
+
+        // As with the `NdjsonDecoder`, bad rows are skipped, and it's only an error if every
+        // row is bad
+        let rows: ParquetRows<D> = ParquetRows {
+            decoder: self.clone(),
+            reader,
+            next_row_group: 0,
+            rows: vec![].into_iter(),
+            phantom: PhantomData,
+        };
+        Ok(Box::new(SkipUndecodable::new(rows)))
+    }
+}
+
+impl<D> PayloadDecoder<Vec<D>> for ParquetDecoder
+where
+    D: DeserializeOwned + Send + 'static,
+{
+    type DecoderError = ParquetDecoderError;
+
+    fn decode(&mut self, body: Vec<u8>) -> Result<Vec<D>, Self::DecoderError> {
+        self.decode_stream(body)?.collect()
+    }
+}
+
+fn field_to_json(field: &Field) -> Value {
+    match field {
+        Field::Null => Value::Null,
+        Field::Bool(value) => Value::from(*value),
+        Field::Byte(value) => Value::from(*value),
+        Field::Short(value) => Value::from(*value),
+        Field::Int(value) => Value::from(*value),
+        Field::Long(value) => Value::from(*value),
+        Field::UByte(value) => Value::from(*value),
+        Field::UShort(value) => Value::from(*value),
+        Field::UInt(value) => Value::from(*value),
+        Field::ULong(value) => Value::from(*value),
+        Field::Float(value) => Value::from(f64::from(*value)),
+        Field::Double(value) => Value::from(*value),
+        Field::Str(value) => Value::from(value.as_str()),
+        Field::Bytes(value) => match value.as_utf8() {
+            Ok(value) => Value::from(value),
+            Err(_) => Value::from(value.data()),
+        },
+        Field::Date(days) => Value::from(*days),
+        Field::TimestampMillis(millis) => Value::from(*millis),
+        Field::TimestampMicros(micros) => Value::from(*micros),
+        Field::Group(row) => Value::Object(
+            row.get_column_iter()
+                .map(|(column, field)| (column.clone(), field_to_json(field)))
+                .collect(),
+        ),
+        Field::ListInternal(list) => {
+            Value::Array(list.elements().iter().map(field_to_json).collect())
+        }
+        Field::MapInternal(map) => Value::Object(
+            map.entries()
+                .iter()
+                .map(|(key, value)| {
+                    let key = match key {
+                        Field::Str(key) => key.clone(),
+                        key => key.to_string(),
+                    };
+                    (key, field_to_json(value))
+                })
+                .collect::<Map<_, _>>(),
+        ),
+        Field::Decimal(_) => Value::from(field.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use parquet::{
+        column::writer::ColumnWriter,
+        data_type::ByteArray,
+        file::{
+            properties::WriterProperties,
+            writer::{
+                FileWriter,
+                InMemoryWriteableCursor,
+                RowGroupWriter,
+                SerializedFileWriter,
+            },
+        },
+        schema::parser::parse_message_type,
+    };
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct ProcessStart {
+        pid: i64,
+        image_name: String,
+    }
+
+    /// A file with a row group for each batch of rows
+    fn parquet_file(row_groups: &[&[(i64, &str)]]) -> Vec<u8> {
+        let schema = Arc::new(
+            parse_message_type(
+                "message process_start { REQUIRED INT64 ProcessId; REQUIRED BINARY image_name (UTF8); }",
+            )
+            .unwrap(),
+        );
+        let cursor = InMemoryWriteableCursor::default();
+        let mut writer = SerializedFileWriter::new(
+            cursor.clone(),
+            schema,
+            Arc::new(WriterProperties::builder().build()),
+        )
+        .unwrap();
+
+        for rows in row_groups {
+            let mut row_group = writer.next_row_group().unwrap();
+            while let Some(mut column) = row_group.next_column().unwrap() {
+                match column {
+                    ColumnWriter::Int64ColumnWriter(ref mut column) => {
+                        let pids: Vec<_> = rows.iter().map(|(pid, _)| *pid).collect();
+                        column.write_batch(&pids, None, None).unwrap();
+                    }
+                    ColumnWriter::ByteArrayColumnWriter(ref mut column) => {
+                        let names: Vec<_> = rows
+                            .iter()
+                            .map(|(_, name)| ByteArray::from(*name))
+                            .collect();
+                        column.write_batch(&names, None, None).unwrap();
+                    }
+                    _ => unreachable!(),
+                }
+                row_group.close_column(column).unwrap();
+            }
+            writer.close_row_group(row_group).unwrap();
+        }
+        writer.close().unwrap();
+        cursor.data()
+    }
+
+    #[test]
+    fn test_decodes_every_row_group_with_field_mapping() {
+        let payload = parquet_file(&[
+            &[(1, "svchost.exe"), (2, "cmd.exe")],
+            &[(3, "explorer.exe")],
+        ]);
+        let mut decoder = ParquetDecoder::default().with_field("ProcessId", "pid");
+        let events: Vec<ProcessStart> = decoder.decode(payload).unwrap();
+        assert_eq!(
+            events.iter().map(|event| event.pid).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(events[2].image_name, "explorer.exe");
+    }
+
+    #[test]
+    fn test_errors_when_every_row_is_bad() {
+        let payload = parquet_file(&[&[(1, "svchost.exe")]]);
+        // Without the mapping there's no `pid`
+        let result: Result<Vec<ProcessStart>, _> = ParquetDecoder::default().decode(payload);
+        assert!(matches!(
+            result,
+            Err(ParquetDecoderError::Deserialization(_))
+        ));
+
+        let result: Result<Vec<ProcessStart>, _> =
+            ParquetDecoder::default().decode(b"not parquet".to_vec());
+        assert!(matches!(result, Err(ParquetDecoderError::Parquet(_))));
+    }
+}
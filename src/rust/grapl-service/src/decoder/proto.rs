@@ -23,7 +23,7 @@ pub enum ProtoDecoderError {
 impl CheckedError for ProtoDecoderError {
     fn error_type(&self) -> Recoverable {
         match self {
-            Self::DecompressionError(_) => Recoverable::Persistent,
+            Self::DecompressionError(e) => e.error_type(),
             Self::ProtoError(_) => Recoverable::Persistent,
         }
     }
@@ -0,0 +1,120 @@
+//! Helpers for implementing `StreamingPayloadDecoder`s.
+
+use std::io::{
+    BufRead,
+    BufReader,
+};
+
+use sqs_executor::event_decoder::EventStream;
+
+use crate::decoder::decompress::{
+    DecompressingReader,
+    PayloadDecompressionError,
+};
+
+/// Skips the events that fail to decode, as our decoders do when decoding a whole payload.
+///
+/// `events` yields an `Err` for a payload that can't be read any further, which ends the
+/// stream, and an `Ok(Err)` for an event that can't be decoded. Only if none of the events
+/// decode is the first of those yielded, once the payload has been read.
+pub struct SkipUndecodable<I, DecoderErrorT> {
+    events: I,
+    decoded_any: bool,
+    first_error: Option<DecoderErrorT>,
+    done: bool,
+}
+
+impl<I, DecoderErrorT> SkipUndecodable<I, DecoderErrorT> {
+    pub fn new(events: I) -> Self {
+        Self {
+            events,
+            decoded_any: false,
+            first_error: None,
+            done: false,
+        }
+    }
+}
+
+impl<I, E, DecoderErrorT> Iterator for SkipUndecodable<I, DecoderErrorT>
+where
+    I: Iterator<Item = Result<Result<E, DecoderErrorT>, DecoderErrorT>>,
+{
+    type Item = Result<E, DecoderErrorT>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        loop {
+            match self.events.next() {
+                Some(Ok(Ok(event))) => {
+                    self.decoded_any = true;
+                    return Some(Ok(event));
+                }
+                Some(Ok(Err(error))) => {
+                    self.first_error.get_or_insert(error);
+                }
+                Some(Err(error)) => {
+                    self.done = true;
+                    return Some(Err(error));
+                }
+                None => {
+                    self.done = true;
+                    return match self.first_error.take() {
+                        Some(error) if !self.decoded_any => Some(Err(error)),
+                        _ => None,
+                    };
+                }
+            }
+        }
+    }
+}
+
+/// Decodes each line of the payload with `decode`, skipping the lines it returns `None` for,
+/// and the ones it fails to decode as `SkipUndecodable` does
+pub fn decode_lines<E, DecoderErrorT, F>(
+    reader: DecompressingReader,
+    mut decode: F,
+) -> EventStream<E, DecoderErrorT>
+where
+    F: FnMut(&[u8]) -> Option<Result<E, DecoderErrorT>> + Send + 'static,
+    E: Send + 'static,
+    DecoderErrorT: From<PayloadDecompressionError> + Send + 'static,
+{
+    let lines = BufReader::new(reader)
+        .split(b'\n')
+        .filter_map(move |line| match line {
+            Ok(line) => decode(&line).map(Ok),
+            Err(error) => Some(Err(PayloadDecompressionError::from(error).into())),
+        });
+    Box::new(SkipUndecodable::new(lines))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skips_undecodable_events() {
+        let events: Vec<Result<Result<u32, &str>, &str>> =
+            vec![Ok(Err("bad")), Ok(Ok(1)), Ok(Err("worse")), Ok(Ok(2))];
+        let decoded: Vec<_> = SkipUndecodable::new(events.into_iter()).collect();
+        assert_eq!(decoded, vec![Ok(1), Ok(2)]);
+    }
+
+    #[test]
+    fn test_errors_when_no_event_decodes() {
+        let events: Vec<Result<Result<u32, &str>, &str>> = vec![Ok(Err("bad")), Ok(Err("worse"))];
+        let decoded: Vec<_> = SkipUndecodable::new(events.into_iter()).collect();
+        assert_eq!(decoded, vec![Err("bad")]);
+    }
+
+    #[test]
+    fn test_ends_when_the_payload_cant_be_read() {
+        let events: Vec<Result<Result<u32, &str>, &str>> =
+            vec![Ok(Ok(1)), Err("truncated"), Ok(Ok(2))];
+        let decoded: Vec<_> = SkipUndecodable::new(events.into_iter()).collect();
+        assert_eq!(decoded, vec![Ok(1), Err("truncated")]);
+    }
+}
@@ -0,0 +1,625 @@
+//! Syslog, as in RFC 5424 and the older BSD format of RFC 3164, many messages to a payload.
+//!
+//! Messages are framed as in RFC 6587. A payload whose first message is octet counted
+//! (`<length> <message>`) is taken to be octet counted throughout, so messages may contain
+//! newlines. Otherwise messages are newline delimited, and if the first one starts with a
+//! `<PRI>` then so must every other one, with the lines that don't, such as stack traces,
+//! taken to continue the message before them.
+
+use std::{
+    collections::BTreeMap,
+    io::{
+        BufRead,
+        BufReader,
+        Chain,
+        Cursor,
+        Read,
+    },
+};
+
+use sqs_executor::{
+    errors::{
+        CheckedError,
+        Recoverable,
+    },
+    event_decoder::{
+        EventStream,
+        PayloadDecoder,
+        StreamingPayloadDecoder,
+    },
+};
+
+use crate::decoder::{
+    decompress::PayloadDecompressionError,
+    stream::SkipUndecodable,
+};
+
+const BSD_TIMESTAMP_LEN: usize = "Jan  1 00:00:00".len();
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SyslogMessage {
+    pub facility: Option<u8>,
+    pub severity: Option<u8>,
+    /// Only RFC 5424 messages have a version
+    pub version: Option<u32>,
+    /// As it was sent, since RFC 3164 timestamps have neither a year nor a timezone
+    pub timestamp: Option<String>,
+    pub hostname: Option<String>,
+    pub app_name: Option<String>,
+    pub proc_id: Option<String>,
+    pub msg_id: Option<String>,
+    /// Parameters by SD-ID
+    pub structured_data: BTreeMap<String, BTreeMap<String, String>>,
+    pub message: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SyslogDecoderError {
+    #[error("DecompressionError")]
+    Decompression(#[from] PayloadDecompressionError),
+    #[error("FramingError: {0}")]
+    Framing(String),
+    #[error("ParseError: {0}")]
+    Parse(String),
+}
+
+impl CheckedError for SyslogDecoderError {
+    fn error_type(&self) -> Recoverable {
+        match self {
+            Self::Decompression(e) => e.error_type(),
+            Self::Framing(_) => Recoverable::Persistent,
+            Self::Parse(_) => Recoverable::Persistent,
+        }
+    }
+}
+
+pub(crate) fn parse_error(message: &str) -> SyslogDecoderError {
+    SyslogDecoderError::Parse(message.to_owned())
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SyslogDecoder;
+
+impl StreamingPayloadDecoder<SyslogMessage> for SyslogDecoder {
+    type DecoderError = SyslogDecoderError;
+
+    fn decode_stream(
+        &mut self,
+        body: Vec<u8>,
+    ) -> Result<EventStream<SyslogMessage, Self::DecoderError>, Self::DecoderError> {
+        decode_frame_stream(body, "syslog", parse_syslog)
+    }
+}
+
+impl PayloadDecoder<Vec<SyslogMessage>> for SyslogDecoder {
+    type DecoderError = SyslogDecoderError;
+
+    fn decode(&mut self, body: Vec<u8>) -> Result<Vec<SyslogMessage>, Self::DecoderError> {
+        decode_frame_stream(body, "syslog", parse_syslog)?.collect()
+    }
+}
+
+/// Parses each message in the payload as it's read. As with the `NdjsonDecoder`, messages
+/// that fail to parse are logged and skipped, and it's only an error if none of them parse.
+pub(crate) fn decode_frame_stream<T: Send + 'static>(
+    body: Vec<u8>,
+    format: &'static str,
+    parse: fn(&str) -> Result<T, SyslogDecoderError>,
+) -> Result<EventStream<T, SyslogDecoderError>, SyslogDecoderError> {
+    let reader = super::decompress::maybe_decompress_reader(body)?;
+
+    let events = Frames::new(BufReader::new(reader))?.map(move |frame| {
+        frame.map(|frame| {
+            parse(&frame).map_err(|error| {
+                tracing::error!(message="Unable to parse event.", format=format, error=?error);
+                error
+            })
+        })
+    });
+    Ok(Box::new(SkipUndecodable::new(events)))
+}
+
+/// Splits a payload into messages
+pub fn frames(payload: &str) -> Result<Vec<String>, SyslogDecoderError> {
+    Frames::new(payload.as_bytes())?.collect()
+}
+
+/// The most digits an octet count may have
+const MAX_OCTET_COUNT_DIGITS: u64 = 20;
+
+/// Reads the messages from a payload, one at a time. The framing is decided by the first
+/// line, which is read when it's created.
+pub struct Frames<R> {
+    reader: Chain<Cursor<Vec<u8>>, R>,
+    octet_counted: bool,
+    /// Whether lines that don't start with a `<PRI>` continue the message before them
+    continuations: bool,
+    /// The line that starts the next newline delimited message, which was read to find the
+    /// end of the one before it
+    next_line: Option<String>,
+    done: bool,
+}
+
+impl<R: BufRead> Frames<R> {
+    pub fn new(mut reader: R) -> Result<Self, SyslogDecoderError> {
+        let mut first_line = vec![];
+        loop {
+            first_line.clear();
+            if reader
+                .read_until(b'\n', &mut first_line)
+                .map_err(read_error)?
+                == 0
+                || !first_line.iter().all(u8::is_ascii_whitespace)
+            {
+                break;
+            }
+        }
+        let leading_whitespace = first_line
+            .iter()
+            .take_while(|byte| byte.is_ascii_whitespace())
+            .count();
+        first_line.drain(..leading_whitespace);
+
+        let first = String::from_utf8_lossy(&first_line);
+        let octet_counted = octet_count(&first).is_some();
+        let continuations = !octet_counted && has_priority(&first);
+        Ok(Self {
+            reader: Cursor::new(first_line).chain(reader),
+            octet_counted,
+            continuations,
+            next_line: None,
+            done: false,
+        })
+    }
+
+    fn next_octet_counted(&mut self) -> Result<Option<String>, SyslogDecoderError> {
+        loop {
+            let buf = self.reader.fill_buf().map_err(read_error)?;
+            if buf.is_empty() {
+                return Ok(None);
+            }
+            let whitespace = buf
+                .iter()
+                .take_while(|byte| byte.is_ascii_whitespace())
+                .count();
+            let more = whitespace == buf.len();
+            self.reader.consume(whitespace);
+            if !more {
+                break;
+            }
+        }
+
+        let mut count = vec![];
+        (&mut self.reader)
+            .take(MAX_OCTET_COUNT_DIGITS + 1)
+            .read_until(b' ', &mut count)
+            .map_err(read_error)?;
+        let count = String::from_utf8_lossy(&count);
+        let (len, _) = octet_count(&count)
+            .ok_or_else(|| SyslogDecoderError::Framing("Expected an octet count".to_owned()))?;
+
+        let mut frame = vec![];
+        (&mut self.reader)
+            .take(len as u64)
+            .read_to_end(&mut frame)
+            .map_err(read_error)?;
+        if frame.len() < len {
+            return Err(SyslogDecoderError::Framing(format!(
+                "Octet count {} doesn't fit the payload",
+                len
+            )));
+        }
+        Ok(Some(
+            String::from_utf8_lossy(&frame)
+                .trim_end_matches(&['\r', '\n'][..])
+                .to_owned(),
+        ))
+    }
+
+    fn next_newline_delimited(&mut self) -> Result<Option<String>, SyslogDecoderError> {
+        loop {
+            let mut frame = match self.next_line.take() {
+                Some(line) => line,
+                None => match self.read_line()? {
+                    Some(line) => line,
+                    None => return Ok(None),
+                },
+            };
+            if self.continuations {
+                while let Some(line) = self.read_line()? {
+                    if has_priority(&line) {
+                        self.next_line = Some(line);
+                        break;
+                    }
+                    frame.push_str(&line);
+                }
+            }
+
+            let frame = frame.trim_end_matches(&['\r', '\n'][..]);
+            if !frame.trim().is_empty() {
+                return Ok(Some(frame.to_owned()));
+            }
+        }
+    }
+
+    fn read_line(&mut self) -> Result<Option<String>, SyslogDecoderError> {
+        let mut line = vec![];
+        match self
+            .reader
+            .read_until(b'\n', &mut line)
+            .map_err(read_error)?
+        {
+            0 => Ok(None),
+            _ => Ok(Some(String::from_utf8_lossy(&line).into_owned())),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Frames<R> {
+    type Item = Result<String, SyslogDecoderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let frame = if self.octet_counted {
+            self.next_octet_counted()
+        } else {
+            self.next_newline_delimited()
+        };
+        match frame {
+            Ok(Some(frame)) => Some(Ok(frame)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(error) => {
+                // There's no telling where the next message starts
+                self.done = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+fn read_error(error: std::io::Error) -> SyslogDecoderError {
+    PayloadDecompressionError::from(error).into()
+}
+
+/// The length of the message that starts the payload, and the rest of the payload from the
+/// message on, if it's octet counted
+fn octet_count(payload: &str) -> Option<(usize, &str)> {
+    let digits = payload.find(|c: char| !c.is_ascii_digit())?;
+    if digits == 0 || !payload[digits..].starts_with(' ') {
+        return None;
+    }
+    let len = payload[..digits].parse().ok()?;
+    Some((len, &payload[digits + 1..]))
+}
+
+/// Whether the line starts with something shaped like a `<PRI>`, valid or not
+fn has_priority(line: &str) -> bool {
+    match line.strip_prefix('<').and_then(|rest| rest.split_once('>')) {
+        Some((digits, _)) => {
+            (1..=3).contains(&digits.len()) && digits.chars().all(|c| c.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+/// The `<PRI>` the message starts with, if it has one, and the rest of the message after it
+fn priority(message: &str) -> Result<Option<(u8, &str)>, SyslogDecoderError> {
+    let rest = match message.strip_prefix('<') {
+        Some(rest) => rest,
+        None => return Ok(None),
+    };
+    let (digits, rest) = rest
+        .split_once('>')
+        .ok_or_else(|| parse_error("Unterminated PRI"))?;
+    match digits.parse::<u8>() {
+        Ok(priority) if digits.len() <= 3 && priority <= 191 => Ok(Some((priority, rest))),
+        _ => Err(parse_error("Invalid PRI")),
+    }
+}
+
+pub fn parse_syslog(frame: &str) -> Result<SyslogMessage, SyslogDecoderError> {
+    let mut message = SyslogMessage::default();
+    let mut rest = frame;
+    if let Some((priority, after)) = priority(frame)? {
+        message.facility = Some(priority / 8);
+        message.severity = Some(priority % 8);
+        rest = after;
+    }
+
+    match version(rest) {
+        Some((version, after)) => {
+            message.version = Some(version);
+            parse_rfc5424(after, &mut message)?;
+        }
+        None => parse_rfc3164(rest, &mut message),
+    }
+    Ok(message)
+}
+
+/// The syslog header in front of a CEF or LEEF event, if there is one
+pub(crate) fn syslog_header(prefix: &str) -> Result<Option<SyslogMessage>, SyslogDecoderError> {
+    if prefix.trim().is_empty() {
+        return Ok(None);
+    }
+    parse_syslog(prefix).map(Some)
+}
+
+fn version(rest: &str) -> Option<(u32, &str)> {
+    let (digits, after) = rest.split_once(' ')?;
+    if digits.is_empty() || digits.len() > 2 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    match digits.parse() {
+        Ok(0) | Err(_) => None,
+        Ok(version) => Some((version, after)),
+    }
+}
+
+fn parse_rfc5424(mut rest: &str, message: &mut SyslogMessage) -> Result<(), SyslogDecoderError> {
+    let mut next_field = || -> Result<Option<String>, SyslogDecoderError> {
+        let (field, after) = rest
+            .split_once(' ')
+            .ok_or_else(|| parse_error("Truncated RFC 5424 header"))?;
+        rest = after;
+        Ok(match field {
+            "-" => None,
+            field => Some(field.to_owned()),
+        })
+    };
+    message.timestamp = next_field()?;
+    message.hostname = next_field()?;
+    message.app_name = next_field()?;
+    message.proc_id = next_field()?;
+    message.msg_id = next_field()?;
+
+    let rest = match rest.strip_prefix('-') {
+        Some(after) => after,
+        None => structured_data(rest, &mut message.structured_data)?,
+    };
+    let msg = match rest.strip_prefix(' ') {
+        Some(msg) => msg,
+        None if rest.is_empty() => rest,
+        None => return Err(parse_error("Expected a space after structured data")),
+    };
+    message.message = msg.trim_start_matches('\u{feff}').to_owned();
+    Ok(())
+}
+
+/// Parses `[SD-ID name="value" ...]` elements, returning what's left after them
+fn structured_data<'a>(
+    mut rest: &'a str,
+    structured_data: &mut BTreeMap<String, BTreeMap<String, String>>,
+) -> Result<&'a str, SyslogDecoderError> {
+    if !rest.starts_with('[') {
+        return Err(parse_error("Expected structured data"));
+    }
+    while let Some(element) = rest.strip_prefix('[') {
+        let id_end = element
+            .find(&[' ', ']'][..])
+            .ok_or_else(|| parse_error("Unterminated structured data element"))?;
+        let mut params = BTreeMap::new();
+        rest = &element[id_end..];
+        loop {
+            if let Some(after) = rest.strip_prefix(']') {
+                rest = after;
+                break;
+            }
+            let param = rest
+                .strip_prefix(' ')
+                .ok_or_else(|| parse_error("Invalid structured data element"))?;
+            let (name, value) = param
+                .split_once('=')
+                .ok_or_else(|| parse_error("Invalid structured data parameter"))?;
+            let value = value
+                .strip_prefix('"')
+                .ok_or_else(|| parse_error("Unquoted structured data parameter value"))?;
+            let (value, after) = quoted(value)?;
+            params.insert(name.to_owned(), value);
+            rest = after;
+        }
+        structured_data.insert(element[..id_end].to_owned(), params);
+    }
+    Ok(rest)
+}
+
+/// Unescapes a parameter value up to its closing quote, returning what's left after it
+fn quoted(input: &str) -> Result<(String, &str), SyslogDecoderError> {
+    let mut value = String::new();
+    let mut chars = input.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some((_, escaped @ ('"' | '\\' | ']'))) => value.push(escaped),
+                // Backslashes before anything else are kept as they are
+                Some((_, other)) => {
+                    value.push('\\');
+                    value.push(other);
+                }
+                None => break,
+            },
+            '"' => return Ok((value, &input[i + 1..])),
+            c => value.push(c),
+        }
+    }
+    Err(parse_error("Unterminated structured data parameter value"))
+}
+
+/// RFC 3164 only describes what senders tend to do, so whatever doesn't look like a
+/// timestamp, hostname or tag is left in the message rather than rejected
+fn parse_rfc3164(mut rest: &str, message: &mut SyslogMessage) {
+    if let Some(timestamp) = bsd_timestamp(rest) {
+        message.timestamp = Some(timestamp.to_owned());
+        rest = rest[timestamp.len()..].trim_start_matches(' ');
+
+        let (hostname, after) = rest.split_once(' ').unwrap_or((rest, ""));
+        if !hostname.is_empty() && !hostname.ends_with(':') && !hostname.contains('[') {
+            message.hostname = Some(hostname.to_owned());
+            rest = after;
+        }
+    }
+
+    if let Some((app_name, proc_id, after)) = tag(rest) {
+        message.app_name = Some(app_name.to_owned());
+        message.proc_id = proc_id.map(str::to_owned);
+        rest = after;
+    }
+    message.message = rest.to_owned();
+}
+
+/// A `Mmm dd hh:mm:ss` timestamp, or the RFC 3339 timestamps many senders use instead
+fn bsd_timestamp(rest: &str) -> Option<&str> {
+    if let Some(timestamp) = rest.get(..BSD_TIMESTAMP_LEN) {
+        let bytes = timestamp.as_bytes();
+        let is_digit = |i: usize| bytes[i].is_ascii_digit();
+        if timestamp
+            .get(..3)
+            .map_or(false, |month| MONTHS.contains(&month))
+            && bytes[3] == b' '
+            && (bytes[4] == b' ' || is_digit(4))
+            && is_digit(5)
+            && bytes[6] == b' '
+            && [7, 8, 10, 11, 13, 14].into_iter().all(is_digit)
+            && bytes[9] == b':'
+            && bytes[12] == b':'
+        {
+            return Some(timestamp);
+        }
+    }
+
+    let token = rest.split(' ').next()?;
+    let bytes = token.as_bytes();
+    if bytes.len() > 10 && bytes[..4].iter().all(u8::is_ascii_digit) && bytes[4] == b'-' {
+        if token.contains('T') {
+            return Some(token);
+        }
+    }
+    None
+}
+
+/// An `app[pid]: ` or `app: ` tag, and what's left after it
+fn tag(rest: &str) -> Option<(&str, Option<&str>, &str)> {
+    let (tag, after) = rest.split_once(':')?;
+    if tag.is_empty() || tag.contains(' ') {
+        return None;
+    }
+    let after = after.strip_prefix(' ').unwrap_or(after);
+    match tag.split_once('[') {
+        Some((app_name, proc_id)) => Some((app_name, Some(proc_id.strip_suffix(']')?), after)),
+        None => Some((tag, None, after)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_rfc5424() {
+        let message = parse_syslog(
+            r#"<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 [exampleSDID@32473 iut="3" eventSource="App\"lic\]ation"][examplePriority@32473 class="high"] An application event"#,
+        )
+        .unwrap();
+
+        assert_eq!(message.facility, Some(20));
+        assert_eq!(message.severity, Some(5));
+        assert_eq!(message.version, Some(1));
+        assert_eq!(
+            message.timestamp.as_deref(),
+            Some("2003-10-11T22:14:15.003Z")
+        );
+        assert_eq!(message.hostname.as_deref(), Some("mymachine.example.com"));
+        assert_eq!(message.app_name.as_deref(), Some("evntslog"));
+        assert_eq!(message.proc_id, None);
+        assert_eq!(message.msg_id.as_deref(), Some("ID47"));
+        assert_eq!(
+            message.structured_data["exampleSDID@32473"]["eventSource"],
+            "App\"lic]ation"
+        );
+        assert_eq!(
+            message.structured_data["examplePriority@32473"]["class"],
+            "high"
+        );
+        assert_eq!(message.message, "An application event");
+    }
+
+    #[test]
+    fn test_parses_rfc3164() {
+        let message =
+            parse_syslog("<34>Oct  1 22:14:15 mymachine su[230]: 'su root' failed").unwrap();
+
+        assert_eq!(message.facility, Some(4));
+        assert_eq!(message.severity, Some(2));
+        assert_eq!(message.version, None);
+        assert_eq!(message.timestamp.as_deref(), Some("Oct  1 22:14:15"));
+        assert_eq!(message.hostname.as_deref(), Some("mymachine"));
+        assert_eq!(message.app_name.as_deref(), Some("su"));
+        assert_eq!(message.proc_id.as_deref(), Some("230"));
+        assert_eq!(message.message, "'su root' failed");
+
+        // Without a recognizable header, it's all message
+        let message = parse_syslog("<13>just a message").unwrap();
+        assert_eq!(message.timestamp, None);
+        assert_eq!(message.message, "just a message");
+
+        assert!(parse_syslog("<192>Oct  1 22:14:15 host out of range").is_err());
+    }
+
+    #[test]
+    fn test_frames_octet_counted_messages() {
+        let payload = "29 <13>1 - - - - - - first\nline\n24 <13>1 - - - - - - second";
+        assert_eq!(
+            frames(payload).unwrap(),
+            vec!["<13>1 - - - - - - first\nline", "<13>1 - - - - - - second"]
+        );
+
+        assert!(matches!(
+            frames("100 <13>1 - - - - - - truncated"),
+            Err(SyslogDecoderError::Framing(_))
+        ));
+    }
+
+    #[test]
+    fn test_frames_multi_line_messages() {
+        let payload = "<13>Oct  1 22:14:15 host app: Exception\n\tat Main.main\n\n<13>Oct  1 22:14:16 host app: next\r\n";
+        assert_eq!(
+            frames(payload).unwrap(),
+            vec![
+                "<13>Oct  1 22:14:15 host app: Exception\n\tat Main.main",
+                "<13>Oct  1 22:14:16 host app: next",
+            ]
+        );
+
+        // Without PRIs every line is a message
+        assert_eq!(
+            frames("CEF:0|a\n\nCEF:0|b\n").unwrap(),
+            vec!["CEF:0|a", "CEF:0|b"]
+        );
+    }
+
+    #[test]
+    fn test_decoder_skips_unparseable_messages() {
+        let payload = b"<13>1 - - - - - - good\n<999>bad\n".to_vec();
+        let messages = SyslogDecoder.decode(payload).unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].message, "good");
+
+        assert!(SyslogDecoder.decode(b"<999>bad\n".to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_stream_yields_messages_before_a_framing_error() {
+        let payload = b"23 <13>1 - - - - - - first\n100 <13>1 - - - - - - truncated".to_vec();
+        let messages: Vec<_> = SyslogDecoder.decode_stream(payload).unwrap().collect();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].as_ref().unwrap().message, "first");
+        assert!(matches!(messages[1], Err(SyslogDecoderError::Framing(_))));
+    }
+}
@@ -1,5 +1,6 @@
 pub mod decoder;
 pub mod serialization;
+pub mod zstd_dictionary;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,333 @@
+use std::collections::{
+    HashMap,
+    HashSet,
+};
+
+use prost::Message;
+use rust_proto::graph_descriptions::*;
+
+/// Bounds on each payload a graph serializer emits. Payloads are split by source node, so
+/// each edge is kept in the same payload as its source node, and its target node is copied
+/// into that payload too, since an edge can't be identified without both of its ends. A node
+/// with more edges than `max_edges` is repeated in as many payloads as it takes to hold them,
+/// which is fine since nodes are merged downstream. A single node that's over the limits on
+/// its own, along with the targets of its edges, is still emitted, alone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkLimits {
+    pub max_nodes: usize,
+    pub max_edges: usize,
+    /// The most encoded, uncompressed bytes per payload, approximately
+    pub max_bytes: usize,
+}
+
+impl Default for ChunkLimits {
+    fn default() -> Self {
+        Self {
+            max_nodes: 10_000,
+            max_edges: 50_000,
+            max_bytes: 4 * 1024 * 1024,
+        }
+    }
+}
+
+/// A graph made of nodes and the lists of edges from them, both by node key
+pub trait ChunkableGraph: Default {
+    type Node: Message + Clone;
+    type EdgeList: Message;
+
+    fn into_parts(self) -> (HashMap<String, Self::Node>, HashMap<String, Self::EdgeList>);
+    fn insert(&mut self, node_key: String, node: Option<Self::Node>, edges: Option<Self::EdgeList>);
+    fn edge_count(edges: &Self::EdgeList) -> usize;
+    fn target_keys(edges: &Self::EdgeList) -> Vec<&str>;
+    fn split_edges(edges: Self::EdgeList, max_edges: usize) -> Vec<Self::EdgeList>;
+}
+
+macro_rules! impl_chunkable_graph {
+    ($graph:ident, $node:ident, $edge_list:ident) => {
+        impl ChunkableGraph for $graph {
+            type Node = $node;
+            type EdgeList = $edge_list;
+
+            fn into_parts(self) -> (HashMap<String, Self::Node>, HashMap<String, Self::EdgeList>) {
+                (self.nodes, self.edges)
+            }
+
+            fn insert(
+                &mut self,
+                node_key: String,
+                node: Option<Self::Node>,
+                edges: Option<Self::EdgeList>,
+            ) {
+                if let Some(edges) = edges {
+                    self.edges.insert(node_key.clone(), edges);
+                }
+                if let Some(node) = node {
+                    self.nodes.insert(node_key, node);
+                }
+            }
+
+            fn edge_count(edges: &Self::EdgeList) -> usize {
+                edges.edges.len()
+            }
+
+            fn target_keys(edges: &Self::EdgeList) -> Vec<&str> {
+                edges
+                    .edges
+                    .iter()
+                    .map(|edge| edge.to_node_key.as_str())
+                    .collect()
+            }
+
+            fn split_edges(edges: Self::EdgeList, max_edges: usize) -> Vec<Self::EdgeList> {
+                edges
+                    .edges
+                    .chunks(max_edges.max(1))
+                    .map(|edges| $edge_list {
+                        edges: edges.to_vec(),
+                    })
+                    .collect()
+            }
+        }
+    };
+}
+
+impl_chunkable_graph!(GraphDescription, NodeDescription, EdgeList);
+impl_chunkable_graph!(IdentifiedGraph, IdentifiedNode, EdgeList);
+impl_chunkable_graph!(MergedGraph, MergedNode, MergedEdgeList);
+
+/// A source node, some or all of its edges, and the nodes those edges point to
+struct Unit<G: ChunkableGraph> {
+    node_key: String,
+    node: Option<G::Node>,
+    edges: Option<G::EdgeList>,
+    targets: Vec<(String, G::Node)>,
+}
+
+impl<G: ChunkableGraph> Unit<G> {
+    fn new(
+        node_key: String,
+        node: Option<G::Node>,
+        edges: Option<G::EdgeList>,
+        nodes: &HashMap<String, G::Node>,
+    ) -> Self {
+        let mut target_keys: Vec<&str> = edges.as_ref().map(G::target_keys).unwrap_or_default();
+        target_keys.sort_unstable();
+        target_keys.dedup();
+        let targets = target_keys
+            .into_iter()
+            .filter(|target_key| *target_key != node_key)
+            .filter_map(|target_key| {
+                nodes
+                    .get(target_key)
+                    .map(|target| (target_key.to_owned(), target.clone()))
+            })
+            .collect();
+
+        Self {
+            node_key,
+            node,
+            edges,
+            targets,
+        }
+    }
+
+    fn edges(&self) -> usize {
+        self.edges.as_ref().map(G::edge_count).unwrap_or_default()
+    }
+
+    /// The nodes this adds to a graph that already has `node_keys`, along with the encoded
+    /// size of the map entries it adds, roughly
+    fn added(&self, node_keys: &HashSet<String>) -> (usize, usize) {
+        let entry = |node_key: &str, len: usize| node_key.len() + len + 8;
+        let (mut nodes, mut bytes) = (0, 0);
+
+        let new_nodes = self
+            .node
+            .iter()
+            .map(|node| (self.node_key.as_str(), node))
+            .chain(self.targets.iter().map(|(key, node)| (key.as_str(), node)))
+            .filter(|(node_key, _)| !node_keys.contains(*node_key));
+        for (node_key, node) in new_nodes {
+            nodes += 1;
+            bytes += entry(node_key, node.encoded_len());
+        }
+        if let Some(edges) = self.edges.as_ref() {
+            bytes += entry(&self.node_key, edges.encoded_len());
+        }
+        (nodes, bytes)
+    }
+}
+
+/// Splits the graph into graphs within `limits`
+pub fn chunk<G: ChunkableGraph>(graph: G, limits: &ChunkLimits) -> Vec<G> {
+    let (nodes, mut edges) = graph.into_parts();
+
+    let mut node_keys: Vec<String> = nodes.keys().chain(edges.keys()).cloned().collect();
+    node_keys.sort_unstable();
+    node_keys.dedup();
+
+    // Nodes are looked up as edge targets until every unit has been made, so they're cloned
+    // rather than moved into their units
+    let mut units = Vec::with_capacity(node_keys.len());
+    for node_key in node_keys {
+        let node = nodes.get(&node_key).cloned();
+        match edges.remove(&node_key) {
+            Some(edge_list) if G::edge_count(&edge_list) > limits.max_edges => {
+                for edge_list in G::split_edges(edge_list, limits.max_edges) {
+                    units.push(Unit::<G>::new(
+                        node_key.clone(),
+                        node.clone(),
+                        Some(edge_list),
+                        &nodes,
+                    ));
+                }
+            }
+            edge_list => units.push(Unit::new(node_key, node, edge_list, &nodes)),
+        }
+    }
+    drop(nodes);
+
+    let mut chunks = vec![];
+    let mut current = G::default();
+    let mut current_keys = HashSet::new();
+    let (mut current_edges, mut current_bytes) = (0, 0);
+    for unit in units {
+        let unit_edges = unit.edges();
+        let (unit_nodes, mut unit_bytes) = unit.added(&current_keys);
+        let is_empty = current_keys.is_empty() && current_edges == 0;
+        if !is_empty
+            && (current_keys.len() + unit_nodes > limits.max_nodes
+                || current_edges + unit_edges > limits.max_edges
+                || current_bytes + unit_bytes > limits.max_bytes)
+        {
+            chunks.push(std::mem::take(&mut current));
+            current_keys.clear();
+            current_edges = 0;
+            current_bytes = 0;
+            // Nodes shared with the last chunk are copied into this one too
+            unit_bytes = unit.added(&current_keys).1;
+        }
+
+        for (target_key, target) in unit.targets {
+            if current_keys.insert(target_key.clone()) {
+                current.insert(target_key, Some(target), None);
+            }
+        }
+        if unit.node.is_some() {
+            current_keys.insert(unit.node_key.clone());
+        }
+        current.insert(unit.node_key, unit.node, unit.edges);
+        current_edges += unit_edges;
+        current_bytes += unit_bytes;
+    }
+    if !current_keys.is_empty() || current_edges > 0 {
+        chunks.push(current);
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(node_count: usize, edges_per_node: usize) -> GraphDescription {
+        let mut graph = GraphDescription::default();
+        for i in 0..node_count {
+            let node_key = format!("node-{}", i);
+            graph.nodes.insert(
+                node_key.clone(),
+                NodeDescription {
+                    node_key: node_key.clone(),
+                    ..Default::default()
+                },
+            );
+            let edges: Vec<_> = (0..edges_per_node)
+                .map(|j| Edge {
+                    from_node_key: node_key.clone(),
+                    to_node_key: format!("node-{}", (i + j + 1) % node_count),
+                    edge_name: "children".to_owned(),
+                    ..Default::default()
+                })
+                .collect();
+            if !edges.is_empty() {
+                graph.edges.insert(node_key, EdgeList { edges });
+            }
+        }
+        graph
+    }
+
+    fn assert_edges_with_both_ends(chunks: &[GraphDescription]) {
+        for chunk in chunks {
+            for (node_key, edge_list) in chunk.edges.iter() {
+                assert!(chunk.nodes.contains_key(node_key));
+                assert!(edge_list
+                    .edges
+                    .iter()
+                    .all(|edge| &edge.from_node_key == node_key));
+                assert!(edge_list
+                    .edges
+                    .iter()
+                    .all(|edge| chunk.nodes.contains_key(&edge.to_node_key)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_small_graphs_are_one_chunk() {
+        let chunks = chunk(graph(10, 2), &ChunkLimits::default());
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].nodes.len(), 10);
+        assert_eq!(chunks[0].edges.len(), 10);
+    }
+
+    #[test]
+    fn test_chunks_by_node_and_edge_count() {
+        let limits = ChunkLimits {
+            max_nodes: 4,
+            max_edges: 6,
+            ..Default::default()
+        };
+        let chunks = chunk(graph(10, 2), &limits);
+        // 2 nodes, their 4 edges and the 2 other nodes those point to fill each chunk
+        assert_eq!(chunks.len(), 5);
+        assert!(chunks
+            .iter()
+            .all(|chunk| chunk.nodes.len() <= 4 && chunk.edges.len() == 2));
+        let sources: HashSet<_> = chunks.iter().flat_map(|c| c.edges.keys()).collect();
+        assert_eq!(sources.len(), 10);
+        assert_edges_with_both_ends(&chunks);
+    }
+
+    #[test]
+    fn test_chunks_by_encoded_size() {
+        let graph = graph(100, 1);
+        let limits = ChunkLimits {
+            max_bytes: graph.encoded_len() / 4,
+            ..Default::default()
+        };
+        let chunks = chunk(graph, &limits);
+        assert!(chunks.len() >= 4);
+        assert!(chunks
+            .iter()
+            .all(|chunk| chunk.encoded_len() <= limits.max_bytes));
+        assert_edges_with_both_ends(&chunks);
+    }
+
+    #[test]
+    fn test_splits_a_node_with_too_many_edges() {
+        let limits = ChunkLimits {
+            max_edges: 4,
+            ..Default::default()
+        };
+        let chunks = chunk(graph(1, 10), &limits);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|chunk| chunk.edges["node-0"].edges.len())
+                .collect::<Vec<_>>(),
+            vec![4, 4, 2]
+        );
+        assert_edges_with_both_ends(&chunks);
+    }
+}
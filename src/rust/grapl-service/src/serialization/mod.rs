@@ -1,5 +1,7 @@
+pub mod chunking;
 pub mod zstd_proto_graph;
 
+pub use chunking::ChunkLimits;
 pub use zstd_proto_graph::{
     GraphDescriptionSerializer,
     GraphDescriptionSerializerError,
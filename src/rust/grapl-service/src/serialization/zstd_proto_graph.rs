@@ -6,17 +6,35 @@ use tracing::{
     error,
 };
 
+use crate::{
+    serialization::chunking::{
+        chunk,
+        ChunkLimits,
+    },
+    zstd_dictionary,
+};
+
 /// Takes a subgraph generated by a Generator and serializes it so it can be returned by a
 /// lambda as an execution result.
 #[derive(Clone, Debug, Default)]
 pub struct GraphDescriptionSerializer {
     proto: Vec<u8>,
     compressed: Vec<u8>,
+    chunk_limits: ChunkLimits,
 }
 
 impl GraphDescriptionSerializer {
     pub fn new(proto: Vec<u8>, compressed: Vec<u8>) -> Self {
-        Self { proto, compressed }
+        Self {
+            proto,
+            compressed,
+            chunk_limits: ChunkLimits::default(),
+        }
+    }
+
+    pub fn with_chunk_limits(mut self, chunk_limits: ChunkLimits) -> Self {
+        self.chunk_limits = chunk_limits;
+        self
     }
 }
 
@@ -61,8 +79,7 @@ impl CompletionEventSerializer for GraphDescriptionSerializer {
         }
 
         for edge_list in subgraph.edges.values_mut() {
-            edge_list.edges.sort_unstable();
-            edge_list.edges.dedup();
+            edge_list.merge_duplicates();
         }
 
         // TODO: This should be a metric
@@ -74,19 +91,27 @@ impl CompletionEventSerializer for GraphDescriptionSerializer {
             pre_edges,
         );
 
-        self.proto.clear();
-        self.compressed.clear();
+        let chunks = chunk(subgraph, &self.chunk_limits);
+        let mut serialized = Vec::with_capacity(chunks.len());
+        for chunk in chunks {
+            self.proto.clear();
 
-        // encode generated subgraphs into protocol buffer
-        prost::Message::encode(&subgraph, &mut self.proto)?;
+            // encode each chunk of the subgraph into protocol buffer
+            prost::Message::encode(&chunk, &mut self.proto)?;
 
-        let mut compressed = &mut self.compressed;
-        let mut proto = std::io::Cursor::new(&self.proto);
+            // compress encoded chunk into `compressed` vector, with the current dictionary
+            // if there is one
+            self.compressed = zstd_dictionary::dictionaries().compress(&self.proto)?;
+            serialized.push(self.compressed.clone());
+        }
 
-        // compress encoded subgraph into `compressed` vector
-        zstd::stream::copy_encode(&mut proto, &mut compressed, 4)?;
+        Ok(serialized)
+    }
 
-        Ok(vec![compressed.clone()])
+    fn compression_dictionary_id(&self) -> u32 {
+        zstd_dictionary::dictionaries()
+            .current()
+            .unwrap_or_default()
     }
 }
 
@@ -102,11 +127,21 @@ pub enum IdentifiedGraphSerializerError {
 pub struct IdentifiedGraphSerializer {
     proto: Vec<u8>,
     compressed: Vec<u8>,
+    chunk_limits: ChunkLimits,
 }
 
 impl IdentifiedGraphSerializer {
     pub fn new(proto: Vec<u8>, compressed: Vec<u8>) -> Self {
-        Self { proto, compressed }
+        Self {
+            proto,
+            compressed,
+            chunk_limits: ChunkLimits::default(),
+        }
+    }
+
+    pub fn with_chunk_limits(mut self, chunk_limits: ChunkLimits) -> Self {
+        self.chunk_limits = chunk_limits;
+        self
     }
 }
 
@@ -143,8 +178,7 @@ impl CompletionEventSerializer for IdentifiedGraphSerializer {
         }
 
         for edge_list in subgraph.edges.values_mut() {
-            edge_list.edges.sort_unstable();
-            edge_list.edges.dedup();
+            edge_list.merge_duplicates();
         }
         // TODO: This should be a metric
         debug!(
@@ -155,19 +189,27 @@ impl CompletionEventSerializer for IdentifiedGraphSerializer {
             pre_edges,
         );
 
-        self.proto.clear();
-        self.compressed.clear();
+        let chunks = chunk(subgraph, &self.chunk_limits);
+        let mut serialized = Vec::with_capacity(chunks.len());
+        for chunk in chunks {
+            self.proto.clear();
 
-        // encode generated subgraphs into protocol buffer
-        prost::Message::encode(&subgraph, &mut self.proto)?;
+            // encode each chunk of the subgraph into protocol buffer
+            prost::Message::encode(&chunk, &mut self.proto)?;
 
-        let mut compressed = &mut self.compressed;
-        let mut proto = std::io::Cursor::new(&self.proto);
+            // compress encoded chunk into `compressed` vector, with the current dictionary
+            // if there is one
+            self.compressed = zstd_dictionary::dictionaries().compress(&self.proto)?;
+            serialized.push(self.compressed.clone());
+        }
 
-        // compress encoded subgraph into `compressed` vector
-        zstd::stream::copy_encode(&mut proto, &mut compressed, 4)?;
+        Ok(serialized)
+    }
 
-        Ok(vec![compressed.clone()])
+    fn compression_dictionary_id(&self) -> u32 {
+        zstd_dictionary::dictionaries()
+            .current()
+            .unwrap_or_default()
     }
 }
 
@@ -183,11 +225,21 @@ pub enum MergedGraphSerializerError {
 pub struct MergedGraphSerializer {
     proto: Vec<u8>,
     compressed: Vec<u8>,
+    chunk_limits: ChunkLimits,
 }
 
 impl MergedGraphSerializer {
     pub fn new(proto: Vec<u8>, compressed: Vec<u8>) -> Self {
-        Self { proto, compressed }
+        Self {
+            proto,
+            compressed,
+            chunk_limits: ChunkLimits::default(),
+        }
+    }
+
+    pub fn with_chunk_limits(mut self, chunk_limits: ChunkLimits) -> Self {
+        self.chunk_limits = chunk_limits;
+        self
     }
 }
 
@@ -223,8 +275,7 @@ impl CompletionEventSerializer for MergedGraphSerializer {
             return Ok(vec![]);
         }
         for edge_list in subgraph.edges.values_mut() {
-            edge_list.edges.sort_unstable();
-            edge_list.edges.dedup();
+            edge_list.merge_duplicates();
         }
         // TODO: This should be a metric
         debug!(
@@ -235,18 +286,26 @@ impl CompletionEventSerializer for MergedGraphSerializer {
             pre_edges,
         );
 
-        self.proto.clear();
-        self.compressed.clear();
+        let chunks = chunk(subgraph, &self.chunk_limits);
+        let mut serialized = Vec::with_capacity(chunks.len());
+        for chunk in chunks {
+            self.proto.clear();
 
-        // encode generated subgraphs into protocol buffer
-        prost::Message::encode(&subgraph, &mut self.proto)?;
+            // encode each chunk of the subgraph into protocol buffer
+            prost::Message::encode(&chunk, &mut self.proto)?;
 
-        let mut compressed = &mut self.compressed;
-        let mut proto = std::io::Cursor::new(&self.proto);
+            // compress encoded chunk into `compressed` vector, with the current dictionary
+            // if there is one
+            self.compressed = zstd_dictionary::dictionaries().compress(&self.proto)?;
+            serialized.push(self.compressed.clone());
+        }
 
-        // compress encoded subgraph into `compressed` vector
-        zstd::stream::copy_encode(&mut proto, &mut compressed, 4)?;
+        Ok(serialized)
+    }
 
-        Ok(vec![compressed.clone()])
+    fn compression_dictionary_id(&self) -> u32 {
+        zstd_dictionary::dictionaries()
+            .current()
+            .unwrap_or_default()
     }
 }
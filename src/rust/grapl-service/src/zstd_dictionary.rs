@@ -0,0 +1,253 @@
+//! Zstd dictionaries for graph payloads.
+//!
+//! Our subgraphs are small, and repeat the same node types, property names and edge names,
+//! so compressing them with a dictionary trained on earlier payloads shrinks them a lot.
+//!
+//! Every dictionary has an id, which zstd records in the header of each frame compressed
+//! with it, so a frame can always be decompressed with the right dictionary. Each
+//! dictionary is deployed as `<id>.dict` in the `ZSTD_DICTIONARY_DIR`, and every one of
+//! them is loaded for decompression. Only the one named by `ZSTD_DICTIONARY_ID` is used for
+//! compression, so to roll out a new dictionary, add it to every service's directory first
+//! and only then switch the id. A frame whose dictionary hasn't been deployed yet fails
+//! transiently, so it's retried rather than dead lettered.
+
+use std::{
+    collections::HashMap,
+    io::{
+        Cursor,
+        Read,
+        Write,
+    },
+    path::Path,
+    sync::Arc,
+};
+
+use lazy_static::lazy_static;
+use sqs_executor::errors::{
+    CheckedError,
+    Recoverable,
+};
+
+pub const COMPRESSION_LEVEL: i32 = 4;
+pub const DICTIONARY_EXTENSION: &str = "dict";
+
+lazy_static! {
+    static ref DICTIONARIES: ZstdDictionaries = ZstdDictionaries::from_env();
+}
+
+/// The dictionaries configured by `ZSTD_DICTIONARY_DIR` and `ZSTD_DICTIONARY_ID`
+pub fn dictionaries() -> &'static ZstdDictionaries {
+    &DICTIONARIES
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ZstdDictionaryError {
+    #[error("IO: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("No dictionary with id {0}")]
+    MissingDictionary(u32),
+    #[error("Dictionary has no id")]
+    NoDictionaryId,
+}
+
+impl CheckedError for ZstdDictionaryError {
+    fn error_type(&self) -> Recoverable {
+        match self {
+            Self::Io(_) => Recoverable::Persistent,
+            // It may just not have been deployed here yet
+            Self::MissingDictionary(_) => Recoverable::Transient,
+            Self::NoDictionaryId => Recoverable::Persistent,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ZstdDictionaries {
+    dictionaries: HashMap<u32, Arc<Vec<u8>>>,
+    /// The dictionary to compress with
+    current: Option<u32>,
+}
+
+impl ZstdDictionaries {
+    fn from_env() -> Self {
+        let mut dictionaries = match grapl_config::zstd_dictionary_dir() {
+            Some(dir) => Self::load_dir(&dir).expect("Failed to load zstd dictionaries"),
+            None => Self::default(),
+        };
+        if let Some(id) = grapl_config::zstd_dictionary_id() {
+            dictionaries
+                .set_current(id)
+                .expect("ZSTD_DICTIONARY_ID isn't in ZSTD_DICTIONARY_DIR");
+        }
+        dictionaries
+    }
+
+    /// Loads every `<id>.dict` file in `dir`
+    pub fn load_dir(dir: &Path) -> Result<Self, ZstdDictionaryError> {
+        let mut dictionaries = Self::default();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str())
+                == Some(DICTIONARY_EXTENSION)
+            {
+                dictionaries.insert(std::fs::read(&path)?)?;
+            }
+        }
+        Ok(dictionaries)
+    }
+
+    /// Adds a dictionary that frames may be decompressed with, returning its id
+    pub fn insert(&mut self, dictionary: Vec<u8>) -> Result<u32, ZstdDictionaryError> {
+        let id = dictionary_id(&dictionary).ok_or(ZstdDictionaryError::NoDictionaryId)?;
+        self.dictionaries.insert(id, Arc::new(dictionary));
+        Ok(id)
+    }
+
+    pub fn set_current(&mut self, id: u32) -> Result<(), ZstdDictionaryError> {
+        if !self.dictionaries.contains_key(&id) {
+            return Err(ZstdDictionaryError::MissingDictionary(id));
+        }
+        self.current = Some(id);
+        Ok(())
+    }
+
+    /// The id of the dictionary that `compress` uses
+    pub fn current(&self) -> Option<u32> {
+        self.current
+    }
+
+    /// Compresses with the current dictionary, or without one if there isn't one
+    pub fn compress(&self, input: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self.current.and_then(|id| self.dictionaries.get(&id)) {
+            Some(dictionary) => {
+                let mut encoder = zstd::stream::write::Encoder::with_dictionary(
+                    Vec::with_capacity(input.len()),
+                    COMPRESSION_LEVEL,
+                    dictionary,
+                )?;
+                encoder.write_all(input)?;
+                encoder.finish()
+            }
+            None => zstd::stream::encode_all(input, COMPRESSION_LEVEL),
+        }
+    }
+
+    /// Decompresses with whichever dictionary the frame was compressed with
+    pub fn decompress(&self, input: &[u8]) -> Result<Vec<u8>, ZstdDictionaryError> {
+        let mut decompressed = Vec::with_capacity(input.len() * 4);
+        self.decoder(input)?.read_to_end(&mut decompressed)?;
+        Ok(decompressed)
+    }
+
+    /// A reader of the decompressed frame, with whichever dictionary it was compressed with
+    pub fn decoder<'a>(&self, input: &'a [u8]) -> Result<Box<dyn Read + 'a>, ZstdDictionaryError> {
+        match zstd_safe::get_dict_id_from_frame(input) {
+            0 => Ok(Box::new(zstd::stream::read::Decoder::new(input)?)),
+            id => {
+                let dictionary = self
+                    .dictionaries
+                    .get(&id)
+                    .ok_or(ZstdDictionaryError::MissingDictionary(id))?;
+                Ok(Box::new(zstd::stream::read::Decoder::with_dictionary(
+                    input, dictionary,
+                )?))
+            }
+        }
+    }
+
+    /// Like `decoder`, but taking ownership of the frame, so the reader isn't tied to a borrow
+    /// of it
+    pub fn owned_decoder(
+        &self,
+        input: Vec<u8>,
+    ) -> Result<Box<dyn Read + Send>, ZstdDictionaryError> {
+        match zstd_safe::get_dict_id_from_frame(&input) {
+            0 => Ok(Box::new(zstd::stream::read::Decoder::new(Cursor::new(
+                input,
+            ))?)),
+            id => {
+                let dictionary = self
+                    .dictionaries
+                    .get(&id)
+                    .ok_or(ZstdDictionaryError::MissingDictionary(id))?;
+                Ok(Box::new(zstd::stream::read::Decoder::with_dictionary(
+                    Cursor::new(input),
+                    dictionary,
+                )?))
+            }
+        }
+    }
+}
+
+/// The id a dictionary records in the frames compressed with it, if it has one
+pub fn dictionary_id(dictionary: &[u8]) -> Option<u32> {
+    match zstd_safe::get_dict_id_from_dict(dictionary) {
+        0 => None,
+        id => Some(id),
+    }
+}
+
+/// Trains a dictionary of up to `max_size` bytes on uncompressed sample payloads
+pub fn train<S: AsRef<[u8]>>(
+    samples: &[S],
+    max_size: usize,
+) -> Result<Vec<u8>, ZstdDictionaryError> {
+    let dictionary = zstd::dict::from_samples(samples, max_size)?;
+    if dictionary_id(&dictionary).is_none() {
+        return Err(ZstdDictionaryError::NoDictionaryId);
+    }
+    Ok(dictionary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples() -> Vec<Vec<u8>> {
+        (0..1000)
+            .map(|i| {
+                format!(
+                    r#"{{"node_type":"Process","process_id":{},"process_name":"proc-{}.exe","edges":["children","bin_file"]}}"#,
+                    i,
+                    i % 37
+                )
+                .into_bytes()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_dictionary_round_trip() {
+        let samples = samples();
+        let dictionary = train(&samples, 4096).expect("train");
+
+        let mut with = ZstdDictionaries::default();
+        let id = with.insert(dictionary).expect("insert");
+        with.set_current(id).expect("set_current");
+        let compressed = with.compress(&samples[0]).expect("compress");
+        assert_eq!(zstd_safe::get_dict_id_from_frame(&compressed), id);
+
+        // Services without the dictionary can't decompress it yet, but can retry
+        let without = ZstdDictionaries::default();
+        let err = without.decompress(&compressed).unwrap_err();
+        assert_eq!(err.error_type(), Recoverable::Transient);
+
+        // Frames compressed without a dictionary still decompress alongside it
+        let plain = without.compress(&samples[1]).expect("compress");
+        assert_eq!(with.decompress(&plain).expect("decompress"), samples[1]);
+        assert_eq!(
+            with.decompress(&compressed).expect("decompress"),
+            samples[0]
+        );
+    }
+
+    #[test]
+    fn test_set_current_requires_dictionary() {
+        let mut dictionaries = ZstdDictionaries::default();
+        assert!(matches!(
+            dictionaries.set_current(7),
+            Err(ZstdDictionaryError::MissingDictionary(7))
+        ));
+        assert_eq!(dictionaries.current(), None);
+    }
+}
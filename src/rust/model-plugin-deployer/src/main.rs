@@ -5,7 +5,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let (_env, _guard) = grapl_config::init_grapl_env!();
 
     let socket_addr = get_socket_addr()?;
+    let schema_dir = grapl_config::model_plugin_schema_dir()
+        .ok_or("GRAPL_MODEL_PLUGIN_SCHEMA_DIR must be set to the deployed schemas' directory")?;
 
-    model_plugin_deployer::server::exec_service(socket_addr).await?;
+    model_plugin_deployer::server::exec_service(socket_addr, schema_dir).await?;
     Ok(())
 }
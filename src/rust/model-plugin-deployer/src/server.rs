@@ -1,5 +1,20 @@
-use std::net::SocketAddr;
+use std::{
+    net::SocketAddr,
+    path::{
+        Path,
+        PathBuf,
+    },
+};
 
+use graph_merger_lib::reverse_resolver::{
+    ReverseEdgeSchema,
+    ReverseEdgeSchemaError,
+};
+use graphql_parser::schema::{
+    parse_schema,
+    Definition,
+    TypeDefinition,
+};
 use tonic::{
     transport::Server,
     Code,
@@ -20,11 +35,14 @@ use crate::model_plugin_deployer::{
     SchemaType,
 };
 
-pub struct ModelPluginDeployer {}
+pub struct ModelPluginDeployer {
+    /// Where deployed schemas are written for the graph merger to read
+    schema_dir: PathBuf,
+}
 
 impl ModelPluginDeployer {
-    fn new() -> ModelPluginDeployer {
-        ModelPluginDeployer {}
+    fn new(schema_dir: PathBuf) -> ModelPluginDeployer {
+        ModelPluginDeployer { schema_dir }
     }
 
     /// The actual business logic for `deploy_model`
@@ -34,7 +52,11 @@ impl ModelPluginDeployer {
     ) -> Result<DeployModelResponse, Status> {
         match SchemaType::from_i32(inner_request.schema_type) {
             Some(SchemaType::Graphql) => {
-                // TODO: Read the schema as graphql
+                write_graphql_schema(
+                    &self.schema_dir,
+                    &inner_request.model_name,
+                    &inner_request.schema,
+                )?;
                 Ok(DeployModelResponse {})
             }
             _ => Err(Status::new(Code::InvalidArgument, "Unhandled schema type")),
@@ -65,13 +87,83 @@ impl ModelPluginDeployerRpcService for ModelPluginDeployer {
     }
 }
 
-pub async fn exec_service(socket_addr: SocketAddr) -> Result<(), Box<dyn std::error::Error>> {
+/// Model names name their schema's file, so they're limited to characters that are safe in one
+fn is_valid_model_name(model_name: &str) -> bool {
+    !model_name.is_empty()
+        && model_name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Writes the schema to `schema_dir`, named after its model so that redeploying a model
+/// replaces its previous schema. The schema is only written if the graph merger could load it
+/// alongside every other deployed schema, and it's written to a temporary file first, so that
+/// a partially written schema is never read.
+fn write_graphql_schema(
+    schema_dir: &Path,
+    model_name: &str,
+    raw_schema: &[u8],
+) -> Result<(), Status> {
+    if !is_valid_model_name(model_name) {
+        return Err(Status::new(
+            Code::InvalidArgument,
+            "Model name must be made of letters, digits, '-' and '_'",
+        ));
+    }
+    let raw_schema = std::str::from_utf8(raw_schema)
+        .map_err(|_| Status::new(Code::InvalidArgument, "Schema is not UTF-8"))?;
+    let document = parse_schema::<&str>(raw_schema).map_err(|e| {
+        Status::new(
+            Code::InvalidArgument,
+            format!("Schema is not valid GraphQL: {}", e),
+        )
+    })?;
+    let declares_type = document.definitions.iter().any(|definition| {
+        matches!(
+            definition,
+            Definition::TypeDefinition(TypeDefinition::Object(_))
+        )
+    });
+    if !declares_type {
+        return Err(Status::new(
+            Code::InvalidArgument,
+            "Schema does not declare a type",
+        ));
+    }
+
+    let schema_path = schema_dir.join(format!("{}.graphql", model_name));
+    ReverseEdgeSchema::from_dir_with(schema_dir, &schema_path, raw_schema).map_err(
+        |e| match e {
+            ReverseEdgeSchemaError::Io(_) | ReverseEdgeSchemaError::MissingSchemaDir(_) => {
+                tracing::error!(message="Failed to read deployed schemas", error=?e);
+                Status::new(Code::Internal, "Failed to read deployed schemas")
+            }
+            _ => Status::new(
+                Code::InvalidArgument,
+                format!("Schema conflicts with the deployed schemas: {}", e),
+            ),
+        },
+    )?;
+
+    let partial_path = schema_dir.join(format!("{}.graphql.partial", model_name));
+    std::fs::write(&partial_path, raw_schema)
+        .and_then(|_| std::fs::rename(&partial_path, &schema_path))
+        .map_err(|e| {
+            tracing::error!(message="Failed to write schema", schema_path=?schema_path, error=?e);
+            Status::new(Code::Internal, "Failed to write schema")
+        })
+}
+
+pub async fn exec_service(
+    socket_addr: SocketAddr,
+    schema_dir: PathBuf,
+) -> Result<(), Box<dyn std::error::Error>> {
     let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
     health_reporter
         .set_serving::<ModelPluginDeployerRpcServiceServer<ModelPluginDeployer>>()
         .await;
 
-    let model_plugin_deployer_instance = ModelPluginDeployer::new();
+    let model_plugin_deployer_instance = ModelPluginDeployer::new(schema_dir);
 
     metrics::register_counter!("request_count", "count of requests made to endpoint");
     metrics::register_histogram!("request_ns", "nanoseconds for request execution");
@@ -98,7 +190,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_error_if_schema_type_not_defined() -> Result<(), String> {
-        let service_instance = ModelPluginDeployer::new();
+        let service_instance = ModelPluginDeployer::new(std::env::temp_dir());
         let inner_request = DeployModelRequest::default();
         let response = service_instance.handle_deploy_model(inner_request);
         match response {
@@ -110,4 +202,88 @@ mod tests {
             }
         }
     }
+
+    fn graphql_request(model_name: &str, schema: &str) -> DeployModelRequest {
+        DeployModelRequest {
+            schema: schema.as_bytes().to_vec(),
+            schema_type: SchemaType::Graphql.into(),
+            model_name: model_name.to_owned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_graphql_schema_is_written() -> Result<(), Box<dyn std::error::Error>> {
+        let schema_dir = std::env::temp_dir().join("model-plugin-deployer-test-schemas");
+        std::fs::create_dir_all(&schema_dir)?;
+        let schema = r#"
+            type Process @grapl(identity_algorithm: "session") {
+                process_id: UInt! @pseudo_key,
+            }
+        "#;
+
+        let service_instance = ModelPluginDeployer::new(schema_dir.clone());
+        service_instance.handle_deploy_model(graphql_request("process-model", schema))?;
+
+        assert_eq!(
+            std::fs::read_to_string(schema_dir.join("process-model.graphql"))?,
+            schema
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_error_if_schema_conflicts_with_deployed_schemas(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let schema_dir = std::env::temp_dir().join("model-plugin-deployer-conflicting-schemas");
+        std::fs::create_dir_all(&schema_dir)?;
+        let schema_with_reverse = |reverse_edge_name: &str| {
+            format!(
+                r#"
+                type Process @grapl(identity_algorithm: "session") {{
+                    process_id: UInt! @pseudo_key,
+                    children: [Process!] @edge(reverse: "{}", reverse_relationship: "ToOne"),
+                }}
+                "#,
+                reverse_edge_name
+            )
+        };
+
+        let service_instance = ModelPluginDeployer::new(schema_dir.clone());
+        service_instance.handle_deploy_model(graphql_request(
+            "process-model",
+            &schema_with_reverse("parent"),
+        ))?;
+        // Redeploying the same model replaces its schema
+        service_instance.handle_deploy_model(graphql_request(
+            "process-model",
+            &schema_with_reverse("parent_process"),
+        ))?;
+
+        let response = service_instance.handle_deploy_model(graphql_request(
+            "other-model",
+            &schema_with_reverse("parent"),
+        ));
+        assert_eq!(response.unwrap_err().code(), Code::InvalidArgument);
+        assert!(!schema_dir.join("other-model.graphql").exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_error_if_schema_is_not_graphql() {
+        let service_instance = ModelPluginDeployer::new(std::env::temp_dir());
+        let response = service_instance.handle_deploy_model(graphql_request("model", "Hello"));
+        assert_eq!(response.unwrap_err().code(), Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_error_if_model_name_is_not_a_file_name() {
+        let service_instance = ModelPluginDeployer::new(std::env::temp_dir());
+        for model_name in ["", "../process", "process.graphql"] {
+            let response = service_instance.handle_deploy_model(graphql_request(
+                model_name,
+                r#"type Process @grapl(identity_algorithm: "session") { process_id: UInt! @pseudo_key }"#,
+            ));
+            assert_eq!(response.unwrap_err().code(), Code::InvalidArgument);
+        }
+    }
 }
@@ -14,7 +14,13 @@ async fn test_deploy_model() -> Result<(), Box<dyn std::error::Error>> {
     let mut client = RpcClient::from_env().await?;
     let request = tonic::Request::new(DeployModelRequest {
         schema_type: SchemaType::Graphql.into(),
-        schema: b"Hello".to_vec(),
+        model_name: "test-deploy-model".to_owned(),
+        schema: br#"
+            type Process @grapl(identity_algorithm: "session") {
+                process_id: UInt! @pseudo_key,
+            }
+        "#
+        .to_vec(),
     });
     client.deploy_model(request).await?;
     Ok(())
@@ -25,6 +31,7 @@ async fn test_unsupported_schema_type() -> Result<(), Box<dyn std::error::Error>
     let mut client = RpcClient::from_env().await?;
     let request = tonic::Request::new(DeployModelRequest {
         schema_type: SchemaType::Unspecified.into(),
+        model_name: "test-unsupported-schema-type".to_owned(),
         schema: b"Hello".to_vec(),
     });
     let result = client.deploy_model(request).await;
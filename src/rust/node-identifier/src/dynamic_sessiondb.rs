@@ -1,11 +1,14 @@
-use std::collections::{
-    HashMap,
-    HashSet,
+use std::{
+    collections::{
+        HashMap,
+        HashSet,
+    },
+    io::Stdout,
 };
 
-use failure::{
-    bail,
-    Error,
+use grapl_observe::metric_reporter::{
+    tag,
+    MetricReporter,
 };
 use rusoto_dynamodb::{
     AttributeValue,
@@ -33,8 +36,13 @@ use tracing::{
 };
 
 use crate::{
-    sessiondb::SessionDb,
-    sessions::UnidSession,
+    error::NodeIdentifierError,
+    sessiondb::SessionStore,
+    sessions::{
+        is_plausible_timestamp,
+        SkewTolerances,
+        UnidSession,
+    },
 };
 
 #[derive(Debug, Clone)]
@@ -65,7 +73,7 @@ where
     }
 
     #[tracing::instrument(skip(self))]
-    pub async fn direct_map(&self, input: &str) -> Result<Option<String>, Error> {
+    pub async fn direct_map(&self, input: &str) -> Result<Option<String>, NodeIdentifierError> {
         let mut key: HashMap<String, AttributeValue> = HashMap::new();
 
         key.insert(
@@ -95,7 +103,11 @@ where
     }
 
     #[tracing::instrument(skip(self), err)]
-    pub async fn create_mapping(&self, input: String, maps_to: String) -> Result<(), Error> {
+    pub async fn create_mapping(
+        &self,
+        input: String,
+        maps_to: String,
+    ) -> Result<(), NodeIdentifierError> {
         let mapping = DirectMapping {
             pseudo_key: input,
             mapping: maps_to,
@@ -113,29 +125,33 @@ where
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct NodeDescriptionIdentifier<D>
+/// Attributes node keys, using `S` to track the sessions of session-identified nodes
+#[derive(Clone)]
+pub struct NodeDescriptionIdentifier<S>
 where
-    D: DynamoDb,
+    S: SessionStore,
 {
-    dyn_session_db: SessionDb<D>,
-    dyn_mapping_db: DynamicMappingDb<D>,
+    dyn_session_db: S,
     should_guess: bool,
+    skew_tolerances: SkewTolerances,
+    metric_reporter: MetricReporter<Stdout>,
 }
 
-impl<D> NodeDescriptionIdentifier<D>
+impl<S> NodeDescriptionIdentifier<S>
 where
-    D: DynamoDb,
+    S: SessionStore,
 {
     pub fn new(
-        dyn_session_db: SessionDb<D>,
-        dyn_mapping_db: DynamicMappingDb<D>,
+        dyn_session_db: S,
         should_guess: bool,
+        skew_tolerances: SkewTolerances,
+        metric_reporter: MetricReporter<Stdout>,
     ) -> Self {
         Self {
             dyn_session_db,
-            dyn_mapping_db,
             should_guess,
+            skew_tolerances,
+            metric_reporter,
         }
     }
 
@@ -144,11 +160,13 @@ where
         &self,
         node: &mut NodeDescription,
         strategy: &SessionStrategy,
-    ) -> Result<String, Error> {
+    ) -> Result<String, NodeIdentifierError> {
         let mut primary_key = String::with_capacity(32);
 
         if strategy.primary_key_requires_asset_id {
-            panic!("asset_id resolution is currently not supported")
+            return Err(NodeIdentifierError::InvalidStrategy(
+                "asset_id resolution is currently not supported".to_owned(),
+            ));
         }
 
         for prop_name in &strategy.primary_key_properties {
@@ -156,10 +174,12 @@ where
 
             match prop_val {
                 Some(val) => primary_key.push_str(&val.to_string()),
-                None => bail!(format!(
-                    "Node is missing required property {} for identity",
-                    prop_name
-                )),
+                None => {
+                    return Err(NodeIdentifierError::MissingIdentityProperty {
+                        node_key: node.node_key.clone(),
+                        property: prop_name.clone(),
+                    })
+                }
             }
         }
 
@@ -176,7 +196,7 @@ where
         &self,
         node: &NodeDescription,
         strategy: &Static,
-    ) -> Result<String, Error> {
+    ) -> Result<String, NodeIdentifierError> {
         let mut hasher = Sha256::new();
 
         // first, let's sort the properties, so we get a consistent ordering for hashing
@@ -186,10 +206,12 @@ where
         for prop_name in sorted_key_properties {
             match node.properties.get(&prop_name) {
                 Some(prop_val) => hasher.update(prop_val.to_string().as_bytes()),
-                None => bail!(format!(
-                    "Node is missing required property {} for identity",
-                    prop_name
-                )),
+                None => {
+                    return Err(NodeIdentifierError::MissingIdentityProperty {
+                        node_key: node.node_key.clone(),
+                        property: prop_name,
+                    })
+                }
             }
         }
 
@@ -203,34 +225,43 @@ where
         &self,
         node: NodeDescription,
         strategy: &SessionStrategy,
-    ) -> Result<NodeDescription, Error> {
+    ) -> Result<NodeDescription, NodeIdentifierError> {
         let mut attributed_node = node.clone();
 
         let primary_key = self
             .primary_session_key(&mut attributed_node, strategy)
             .await?;
 
-        let created_time = strategy.create_time;
-        let last_seen_time = strategy.last_seen_time;
+        let time_unit = strategy.time_unit();
+        let created_time = time_unit.to_millis(strategy.create_time);
+        let last_seen_time = time_unit.to_millis(strategy.last_seen_time);
+        let skew_tolerance = self.skew_tolerances.for_node_type(&node.node_type);
 
         let unid = match (created_time != 0, last_seen_time != 0) {
             (true, _) => UnidSession {
                 pseudo_key: primary_key,
                 timestamp: created_time,
                 is_creation: true,
+                skew_tolerance,
             },
             (_, true) => UnidSession {
                 pseudo_key: primary_key,
                 timestamp: last_seen_time,
                 is_creation: false,
+                skew_tolerance,
             },
-            _ => bail!(
-                "Terminating sessions not yet supported: {:?} {:?}",
-                node.properties,
-                &strategy,
-            ),
+            _ => {
+                return Err(NodeIdentifierError::InvalidStrategy(format!(
+                    "Terminating sessions not yet supported: {:?} {:?}",
+                    node.properties, &strategy,
+                )))
+            }
         };
 
+        if !is_plausible_timestamp(unid.timestamp) {
+            self.report_implausible_timestamp(&node.node_type, time_unit, unid.timestamp);
+        }
+
         let session_id = self
             .dyn_session_db
             .handle_unid_session(unid, self.should_guess)
@@ -241,12 +272,35 @@ where
         Ok(attributed_node)
     }
 
+    /// A timestamp that lands far outside of the present day was most likely emitted in a
+    /// different unit than its strategy declares, so we make that visible.
+    fn report_implausible_timestamp(&self, node_type: &str, time_unit: TimeUnit, timestamp: u64) {
+        warn!(
+            message="Session timestamp is implausible, it may be in the wrong unit",
+            node_type=?node_type,
+            time_unit=?time_unit,
+            timestamp=?timestamp,
+        );
+        self.metric_reporter
+            .clone()
+            .counter(
+                "node_identifier.implausible_timestamp.count",
+                1.0,
+                None,
+                &[
+                    tag("node_type", node_type),
+                    tag("time_unit", time_unit_tag(time_unit)),
+                ],
+            )
+            .unwrap_or_else(|e| warn!(message="Metric failed.", error=?e));
+    }
+
     #[tracing::instrument(skip(self, node, strategy), err)]
     pub async fn attribute_static_mapping(
         &self,
         mut node: NodeDescription,
         strategy: &Static,
-    ) -> Result<NodeDescription, Error> {
+    ) -> Result<NodeDescription, NodeIdentifierError> {
         let static_node_key = self.get_static_node_key(&node, strategy)?;
         node.set_key(static_node_key);
 
@@ -257,11 +311,20 @@ where
     pub async fn attribute_dynamic_node(
         &self,
         node: &NodeDescription,
-    ) -> Result<NodeDescription, Error> {
+    ) -> Result<NodeDescription, NodeIdentifierError> {
         let mut attributed_node = node.clone();
-        let strategy = &node.id_strategy[0];
-
-        match strategy.strategy.as_ref().unwrap() {
+        let strategy = node
+            .id_strategy
+            .first()
+            .and_then(|strategy| strategy.strategy.as_ref())
+            .ok_or_else(|| {
+                NodeIdentifierError::InvalidStrategy(format!(
+                    "Node {} has no identity strategy",
+                    node.node_key
+                ))
+            })?;
+
+        match strategy {
             id_strategy::Strategy::Session(ref strategy) => {
                 info!("Attributing dynamic node via session");
                 attributed_node = self
@@ -317,3 +380,12 @@ where
         }
     }
 }
+
+fn time_unit_tag(time_unit: TimeUnit) -> &'static str {
+    match time_unit {
+        TimeUnit::Unspecified => "unspecified",
+        TimeUnit::Seconds => "seconds",
+        TimeUnit::Milliseconds => "milliseconds",
+        TimeUnit::WindowsFiletime => "windows_filetime",
+    }
+}
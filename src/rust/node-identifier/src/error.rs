@@ -1,3 +1,12 @@
+use rusoto_core::RusotoError;
+use rusoto_dynamodb::{
+    DeleteItemError,
+    GetItemError,
+    PutItemError,
+    QueryError,
+    TransactWriteItemsError,
+    UpdateItemError,
+};
 use sqs_executor::errors::{
     CheckedError,
     Recoverable,
@@ -5,12 +14,208 @@ use sqs_executor::errors::{
 
 #[derive(thiserror::Error, Debug)]
 pub enum NodeIdentifierError {
-    #[error("Unexpected error")]
-    Unexpected,
+    #[error("Node {node_key} is missing required property {property} for identity")]
+    MissingIdentityProperty { node_key: String, property: String },
+    #[error("DynamoDB request was throttled: {0}")]
+    DynamoDbThrottled(String),
+    #[error("DynamoDB conditional check conflict: {0}")]
+    ConditionalCheckConflict(String),
+    #[error("DynamoDB request failed: {0}")]
+    DynamoDb(String),
+    #[error("Unexpected number of items returned: {0}")]
+    UnexpectedItemCount(usize),
+    #[error("Failed to deserialize session: {0}")]
+    Deserialization(#[from] serde_dynamodb::Error),
+    #[error("Clock skew violation: unid timestamp {unid_timestamp} > session create_time {session_create_time}")]
+    ClockSkewViolation {
+        unid_timestamp: u64,
+        session_create_time: u64,
+    },
+    #[error("Invalid identity strategy: {0}")]
+    InvalidStrategy(String),
+    #[error("Could not attribute session for {pseudo_key}. should_default {should_default}")]
+    UnattributedSession {
+        pseudo_key: String,
+        should_default: bool,
+    },
+    #[error("Failed to identify {} node(s)", .0.len())]
+    NodeFailures(Vec<(String, NodeIdentifierError)>),
 }
 
 impl CheckedError for NodeIdentifierError {
     fn error_type(&self) -> Recoverable {
-        Recoverable::Transient
+        match self {
+            Self::MissingIdentityProperty { .. } => Recoverable::Persistent,
+            Self::DynamoDbThrottled(_) => Recoverable::Transient,
+            // The session was modified underneath us, a retry will read the new version
+            Self::ConditionalCheckConflict(_) => Recoverable::Transient,
+            Self::DynamoDb(_) => Recoverable::Transient,
+            Self::UnexpectedItemCount(_) => Recoverable::Transient,
+            Self::Deserialization(_) => Recoverable::Persistent,
+            Self::ClockSkewViolation { .. } => Recoverable::Persistent,
+            Self::InvalidStrategy(_) => Recoverable::Persistent,
+            // The retry service identifies with `should_default`, so these can succeed later
+            Self::UnattributedSession { .. } => Recoverable::Transient,
+            // Only worth retrying if at least one of the nodes could succeed on retry
+            Self::NodeFailures(failures) => {
+                if failures.iter().any(|(_, e)| e.is_transient()) {
+                    Recoverable::Transient
+                } else {
+                    Recoverable::Persistent
+                }
+            }
+        }
+    }
+}
+
+/// Errors that don't come from the DynamoDB service itself. Throttling is sometimes
+/// only reported through the raw response body, so we check for it here as well.
+fn from_rusoto_error<E>(e: RusotoError<E>) -> NodeIdentifierError
+where
+    E: std::error::Error + 'static,
+{
+    match e {
+        RusotoError::Unknown(ref response)
+            if response.body_as_str().contains("ThrottlingException") =>
+        {
+            NodeIdentifierError::DynamoDbThrottled(response.body_as_str().to_owned())
+        }
+        e => NodeIdentifierError::DynamoDb(e.to_string()),
+    }
+}
+
+impl From<RusotoError<QueryError>> for NodeIdentifierError {
+    fn from(e: RusotoError<QueryError>) -> Self {
+        match e {
+            RusotoError::Service(QueryError::ProvisionedThroughputExceeded(msg))
+            | RusotoError::Service(QueryError::RequestLimitExceeded(msg)) => {
+                Self::DynamoDbThrottled(msg)
+            }
+            e => from_rusoto_error(e),
+        }
+    }
+}
+
+impl From<RusotoError<GetItemError>> for NodeIdentifierError {
+    fn from(e: RusotoError<GetItemError>) -> Self {
+        match e {
+            RusotoError::Service(GetItemError::ProvisionedThroughputExceeded(msg))
+            | RusotoError::Service(GetItemError::RequestLimitExceeded(msg)) => {
+                Self::DynamoDbThrottled(msg)
+            }
+            e => from_rusoto_error(e),
+        }
+    }
+}
+
+impl From<RusotoError<PutItemError>> for NodeIdentifierError {
+    fn from(e: RusotoError<PutItemError>) -> Self {
+        match e {
+            RusotoError::Service(PutItemError::ProvisionedThroughputExceeded(msg))
+            | RusotoError::Service(PutItemError::RequestLimitExceeded(msg)) => {
+                Self::DynamoDbThrottled(msg)
+            }
+            RusotoError::Service(PutItemError::ConditionalCheckFailed(msg))
+            | RusotoError::Service(PutItemError::TransactionConflict(msg)) => {
+                Self::ConditionalCheckConflict(msg)
+            }
+            e => from_rusoto_error(e),
+        }
+    }
+}
+
+impl From<RusotoError<UpdateItemError>> for NodeIdentifierError {
+    fn from(e: RusotoError<UpdateItemError>) -> Self {
+        match e {
+            RusotoError::Service(UpdateItemError::ProvisionedThroughputExceeded(msg))
+            | RusotoError::Service(UpdateItemError::RequestLimitExceeded(msg)) => {
+                Self::DynamoDbThrottled(msg)
+            }
+            RusotoError::Service(UpdateItemError::ConditionalCheckFailed(msg))
+            | RusotoError::Service(UpdateItemError::TransactionConflict(msg)) => {
+                Self::ConditionalCheckConflict(msg)
+            }
+            e => from_rusoto_error(e),
+        }
+    }
+}
+
+impl From<RusotoError<DeleteItemError>> for NodeIdentifierError {
+    fn from(e: RusotoError<DeleteItemError>) -> Self {
+        match e {
+            RusotoError::Service(DeleteItemError::ProvisionedThroughputExceeded(msg))
+            | RusotoError::Service(DeleteItemError::RequestLimitExceeded(msg)) => {
+                Self::DynamoDbThrottled(msg)
+            }
+            RusotoError::Service(DeleteItemError::ConditionalCheckFailed(msg))
+            | RusotoError::Service(DeleteItemError::TransactionConflict(msg)) => {
+                Self::ConditionalCheckConflict(msg)
+            }
+            e => from_rusoto_error(e),
+        }
+    }
+}
+
+impl From<RusotoError<TransactWriteItemsError>> for NodeIdentifierError {
+    fn from(e: RusotoError<TransactWriteItemsError>) -> Self {
+        match e {
+            RusotoError::Service(TransactWriteItemsError::ProvisionedThroughputExceeded(msg))
+            | RusotoError::Service(TransactWriteItemsError::RequestLimitExceeded(msg)) => {
+                Self::DynamoDbThrottled(msg)
+            }
+            RusotoError::Service(TransactWriteItemsError::TransactionCanceled(msg))
+            | RusotoError::Service(TransactWriteItemsError::TransactionInProgress(msg)) => {
+                Self::ConditionalCheckConflict(msg)
+            }
+            e => from_rusoto_error(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn node_failures_are_persistent_only_if_every_failure_is() {
+        let persistent = NodeIdentifierError::NodeFailures(vec![
+            (
+                "a".to_owned(),
+                NodeIdentifierError::InvalidStrategy("missing strategy".to_owned()),
+            ),
+            (
+                "b".to_owned(),
+                NodeIdentifierError::MissingIdentityProperty {
+                    node_key: "b".to_owned(),
+                    property: "pid".to_owned(),
+                },
+            ),
+        ]);
+        assert!(persistent.is_persistent());
+
+        let transient = NodeIdentifierError::NodeFailures(vec![
+            (
+                "a".to_owned(),
+                NodeIdentifierError::InvalidStrategy("missing strategy".to_owned()),
+            ),
+            (
+                "b".to_owned(),
+                NodeIdentifierError::DynamoDbThrottled("slow down".to_owned()),
+            ),
+        ]);
+        assert!(transient.is_transient());
+    }
+
+    #[test]
+    fn conditional_check_failures_are_conflicts() {
+        let e: NodeIdentifierError = RusotoError::Service(UpdateItemError::ConditionalCheckFailed(
+            "version mismatch".to_owned(),
+        ))
+        .into();
+        assert!(matches!(
+            e,
+            NodeIdentifierError::ConditionalCheckConflict(_)
+        ));
+        assert!(e.is_transient());
     }
 }
@@ -0,0 +1,238 @@
+use std::{
+    collections::{
+        BTreeMap,
+        HashMap,
+    },
+    sync::{
+        Arc,
+        Mutex,
+    },
+};
+
+use async_trait::async_trait;
+
+use crate::{
+    error::NodeIdentifierError,
+    sessiondb::SessionStore,
+    sessions::{
+        Session,
+        UnidSession,
+    },
+};
+
+/// Sessions by pseudo key, ordered by create time, the same way the DynamoDB table is keyed
+type Sessions = HashMap<String, BTreeMap<u64, Session>>;
+
+/// A `SessionStore` that keeps sessions in memory, for running the node identifier without
+/// DynamoDB. Clones share the same sessions.
+#[derive(Debug, Clone, Default)]
+pub struct InMemorySessionDb {
+    sessions: Arc<Mutex<Sessions>>,
+}
+
+impl InMemorySessionDb {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every session for the pseudo key, oldest first
+    pub fn sessions_for(&self, pseudo_key: &str) -> Vec<Session> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(pseudo_key)
+            .map(|sessions| sessions.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Applies `update` to the stored copy of `session`, if it's still at the same version
+    fn update_versioned(
+        &self,
+        session: &Session,
+        update: impl FnOnce(&mut Session),
+    ) -> Result<(), NodeIdentifierError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let stored = sessions
+            .get_mut(&session.pseudo_key)
+            .and_then(|sessions| sessions.get_mut(&session.create_time))
+            .filter(|stored| stored.version == session.version)
+            .ok_or_else(|| {
+                NodeIdentifierError::ConditionalCheckConflict(format!(
+                    "session {} is not at version {}",
+                    session.session_id, session.version
+                ))
+            })?;
+        update(stored);
+        stored.version += 1;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SessionStore for InMemorySessionDb {
+    async fn find_first_session_after(
+        &self,
+        unid: &UnidSession,
+    ) -> Result<Option<Session>, NodeIdentifierError> {
+        let sessions = self.sessions.lock().unwrap();
+        Ok(sessions.get(&unid.pseudo_key).and_then(|sessions| {
+            sessions
+                .range(unid.timestamp..)
+                .next()
+                .map(|(_, session)| session.clone())
+        }))
+    }
+
+    async fn find_last_session_before(
+        &self,
+        unid: &UnidSession,
+    ) -> Result<Option<Session>, NodeIdentifierError> {
+        let sessions = self.sessions.lock().unwrap();
+        Ok(sessions.get(&unid.pseudo_key).and_then(|sessions| {
+            sessions
+                .range(..=unid.timestamp)
+                .next_back()
+                .map(|(_, session)| session.clone())
+        }))
+    }
+
+    async fn update_session_create_time(
+        &self,
+        session: &Session,
+        new_time: u64,
+        is_canon: bool,
+    ) -> Result<(), NodeIdentifierError> {
+        let mut new_session = session.to_owned();
+        new_session.create_time = new_time;
+        new_session.is_create_canon = is_canon;
+        new_session.version += 1;
+
+        let mut sessions = self.sessions.lock().unwrap();
+        let sessions = sessions.entry(session.pseudo_key.clone()).or_default();
+        sessions.remove(&session.create_time);
+        sessions.insert(new_time, new_session);
+        Ok(())
+    }
+
+    async fn make_create_time_canonical(
+        &self,
+        session: &Session,
+    ) -> Result<(), NodeIdentifierError> {
+        self.update_versioned(session, |stored| stored.is_create_canon = true)
+    }
+
+    async fn update_session_end_time(
+        &self,
+        session: &Session,
+        new_time: u64,
+        is_canon: bool,
+    ) -> Result<(), NodeIdentifierError> {
+        self.update_versioned(session, |stored| {
+            stored.end_time = new_time;
+            stored.is_end_canon = is_canon;
+        })
+    }
+
+    async fn create_session(&self, session: &Session) -> Result<(), NodeIdentifierError> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .entry(session.pseudo_key.clone())
+            .or_default()
+            .insert(session.create_time, session.clone());
+        Ok(())
+    }
+
+    async fn delete_session(&self, session: &Session) -> Result<(), NodeIdentifierError> {
+        if let Some(sessions) = self.sessions.lock().unwrap().get_mut(&session.pseudo_key) {
+            sessions.remove(&session.create_time);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unid(timestamp: u64, is_creation: bool) -> UnidSession {
+        UnidSession {
+            pseudo_key: "DESKTOP-FVSHABR5752".to_owned(),
+            timestamp,
+            is_creation,
+            skew_tolerance: 10,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_creation_then_last_seen_share_a_session() {
+        let session_db = InMemorySessionDb::new();
+
+        let created = session_db
+            .handle_unid_session(unid(1_544_301_205_000, true), false)
+            .await
+            .expect("creation");
+        let recreated = session_db
+            .handle_unid_session(unid(1_544_301_205_000, true), false)
+            .await
+            .expect("repeated creation");
+        let seen = session_db
+            .handle_unid_session(unid(1_544_301_205_050, false), false)
+            .await
+            .expect("last seen");
+
+        assert_eq!(created, recreated);
+        assert_eq!(created, seen);
+        assert_eq!(session_db.sessions_for("DESKTOP-FVSHABR5752").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_last_seen_only_creates_a_session_when_defaulting() {
+        let session_db = InMemorySessionDb::new();
+
+        let unattributed = session_db
+            .handle_unid_session(unid(1_544_301_205_000, false), false)
+            .await;
+        assert!(matches!(
+            unattributed,
+            Err(NodeIdentifierError::UnattributedSession { .. })
+        ));
+
+        let guessed = session_db
+            .handle_unid_session(unid(1_544_301_205_000, false), true)
+            .await
+            .expect("defaulted session");
+
+        // The guessed session's create time is extended back by an earlier creation event
+        let created = session_db
+            .handle_unid_session(unid(1_544_301_204_000, true), false)
+            .await
+            .expect("creation");
+        assert_eq!(guessed, created);
+
+        let sessions = session_db.sessions_for("DESKTOP-FVSHABR5752");
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].create_time, 1_544_301_204_000);
+        assert!(sessions[0].is_create_canon);
+    }
+
+    #[tokio::test]
+    async fn test_versioned_updates_conflict_on_stale_sessions() {
+        let session_db = InMemorySessionDb::new();
+        session_db
+            .handle_unid_session(unid(1_544_301_205_000, true), false)
+            .await
+            .expect("creation");
+        let stale = session_db.sessions_for("DESKTOP-FVSHABR5752").remove(0);
+
+        session_db
+            .update_session_end_time(&stale, 1_544_301_206_000, true)
+            .await
+            .expect("first update");
+        let conflict = session_db.make_create_time_canonical(&stale).await;
+        assert!(matches!(
+            conflict,
+            Err(NodeIdentifierError::ConditionalCheckConflict(_))
+        ));
+    }
+}
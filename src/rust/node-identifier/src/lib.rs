@@ -1,14 +1,13 @@
 use std::collections::HashMap;
 
 use async_trait::async_trait;
-use dynamic_sessiondb::{
-    DynamicMappingDb,
-    NodeDescriptionIdentifier,
-};
-use failure::Error;
+use dynamic_sessiondb::NodeDescriptionIdentifier;
 use grapl_config::{
     env_helpers::{
+        local_fs_emitters_from_env,
+        local_fs_message_source_from_env,
         s3_event_emitters_from_env,
+        sqs_message_source_from_env,
         FromEnv,
     },
     event_caches,
@@ -18,23 +17,31 @@ use grapl_service::{
     decoder::ProtoDecoder,
     serialization::IdentifiedGraphSerializer,
 };
-use grapl_utils::rusoto_ext::dynamodb::GraplDynamoDbClientExt;
 use rusoto_dynamodb::DynamoDbClient;
-use rusoto_sqs::SqsClient;
 use rust_proto::graph_descriptions::{
     GraphDescription,
     IdentifiedGraph,
     IdentifiedNode,
     NodeDescription,
 };
-use sessiondb::SessionDb;
+use sessiondb::{
+    SessionDb,
+    SessionStore,
+};
+use sessions::SkewTolerances;
 use sqs_executor::{
     cache::Cache,
+    errors::CheckedError,
     event_handler::{
         CompletedEvents,
         EventHandler,
     },
-    make_ten,
+    key_creator::zstd_proto_key_fn,
+    local_fs::{
+        LocalFsPayloadRetriever,
+        LocalFsPayloadStore,
+    },
+    make_workers,
     s3_event_retriever::S3PayloadRetriever,
     time_based_key_fn,
 };
@@ -44,10 +51,15 @@ use tracing::{
     warn,
 };
 
-use crate::error::NodeIdentifierError;
+use crate::{
+    error::NodeIdentifierError,
+    readiness::DynamoDbTableCheck,
+};
 
 pub mod dynamic_sessiondb;
-mod error;
+pub mod error;
+pub mod in_memory_sessiondb;
+pub mod readiness;
 pub mod sessiondb;
 pub mod sessions;
 
@@ -61,31 +73,28 @@ pub mod sessions;
     * [Static](`rust_proto::graph_descriptions::Static`) - strategy used for nodes with canonical and unique identifiers (e.g. aws events)
 */
 #[derive(Clone)]
-pub struct NodeIdentifier<D, CacheT>
+pub struct NodeIdentifier<S, CacheT>
 where
-    D: GraplDynamoDbClientExt,
+    S: SessionStore,
     CacheT: Cache,
 {
-    dynamic_identifier: NodeDescriptionIdentifier<D>,
-    node_id_db: D,
+    dynamic_identifier: NodeDescriptionIdentifier<S>,
     should_default: bool,
     cache: CacheT,
 }
 
-impl<D, CacheT> NodeIdentifier<D, CacheT>
+impl<S, CacheT> NodeIdentifier<S, CacheT>
 where
-    D: GraplDynamoDbClientExt,
+    S: SessionStore,
     CacheT: Cache,
 {
     pub fn new(
-        dynamic_identifier: NodeDescriptionIdentifier<D>,
-        node_id_db: D,
+        dynamic_identifier: NodeDescriptionIdentifier<S>,
         should_default: bool,
         cache: CacheT,
     ) -> Self {
         Self {
             dynamic_identifier,
-            node_id_db,
             should_default,
             cache,
         }
@@ -93,7 +102,10 @@ where
 
     // todo: We should be yielding IdentifiedNode's here
     #[tracing::instrument(fields(node_key=?node.node_key), skip(self, node))]
-    async fn attribute_node_key(&self, node: &NodeDescription) -> Result<IdentifiedNode, Error> {
+    async fn attribute_node_key(
+        &self,
+        node: &NodeDescription,
+    ) -> Result<IdentifiedNode, NodeIdentifierError> {
         let new_node = self.dynamic_identifier.attribute_dynamic_node(node).await?;
         Ok(new_node.into())
     }
@@ -101,15 +113,15 @@ where
     /// Performs batch identification of unidentified nodes into identified nodes.
     ///
     /// A map of unidentified node keys to identified node keys will be returned in addition to the
-    /// last error, if any, that occurred while identifying nodes.
+    /// errors, keyed by unidentified node key, for every node that failed to identify.
     #[tracing::instrument(skip(self, unidentified_subgraph, identified_graph))]
     pub async fn identify_nodes(
         &self,
         unidentified_subgraph: &GraphDescription,
         identified_graph: &mut IdentifiedGraph,
-    ) -> (HashMap<String, String>, Option<failure::Error>) {
+    ) -> (HashMap<String, String>, Vec<(String, NodeIdentifierError)>) {
         let mut identified_nodekey_map = HashMap::new();
-        let mut attribution_failure = None;
+        let mut attribution_failures = vec![];
 
         // new method
         for (unidentified_node_key, unidentified_node) in unidentified_subgraph.nodes.iter() {
@@ -119,9 +131,10 @@ where
                     warn!(
                         message="Failed to attribute node_key",
                         node_key=?unidentified_node_key,
-                        error=?e
+                        error=?e,
+                        recoverable=?e.error_type(),
                     );
-                    attribution_failure = Some(e);
+                    attribution_failures.push((unidentified_node_key.to_owned(), e));
                     continue;
                 }
             };
@@ -133,7 +146,7 @@ where
             identified_graph.add_node(identified_node);
         }
 
-        (identified_nodekey_map, attribution_failure)
+        (identified_nodekey_map, attribution_failures)
     }
 
     /// Takes the edges in the `unidentified_graph` and inserts ones that can be properly identified
@@ -180,15 +193,16 @@ where
                                 to_key=?edge.to_node_key
                             )
                         )
-                        .map(|identified_to_edge| (identified_to_edge, &edge.edge_name))
+                        .map(|identified_to_edge| (identified_to_edge, edge))
                 });
 
             // add all identified edges into the `identified_graph`
-            for (identified_to_key, edge_name) in identified_edges {
-                identified_graph.add_edge(
-                    edge_name.to_owned(),
+            for (identified_to_key, edge) in identified_edges {
+                identified_graph.add_edge_with_properties(
+                    edge.edge_name.to_owned(),
                     identified_from_key.to_owned(),
                     identified_to_key.to_owned(),
+                    edge.properties.clone(),
                 );
             }
         }
@@ -196,9 +210,9 @@ where
 }
 
 #[async_trait]
-impl<D, CacheT> EventHandler for NodeIdentifier<D, CacheT>
+impl<S, CacheT> EventHandler for NodeIdentifier<S, CacheT>
 where
-    D: GraplDynamoDbClientExt,
+    S: SessionStore,
     CacheT: Cache,
 {
     type InputEvent = GraphDescription;
@@ -239,7 +253,7 @@ where
 
         let mut identified_graph = IdentifiedGraph::new();
 
-        let (identified_nodekey_map, attribution_failure) = self
+        let (identified_nodekey_map, attribution_failures) = self
             .identify_nodes(&unidentified_subgraph, &mut identified_graph)
             .await;
 
@@ -261,26 +275,29 @@ where
             identified_edges = identified_graph.edges.len()
         );
 
-        match attribution_failure {
-            Some(_) if identified_graph.is_empty() => Err(Err(NodeIdentifierError::Unexpected)),
-            Some(_) => {
-                /* todo: error message is misleading. someone reading this would believe we identified
-                 * a smaller number of nodes that actually identified (due to identities of nodes coalescing)
-                 */
-                warn!(
-                    message = "Partial Success",
-                    identified_nodes = identified_graph.nodes.len(),
-                    identified_edges = identified_graph.edges.len(),
-                );
+        if attribution_failures.is_empty() {
+            info!("Identified all nodes");
 
-                Err(Ok((identified_graph, NodeIdentifierError::Unexpected))) // todo: Use a real error here
-            }
-            None => {
-                info!("Identified all nodes");
+            return Ok(identified_graph);
+        }
 
-                Ok(identified_graph)
-            }
+        let error = NodeIdentifierError::NodeFailures(attribution_failures);
+
+        if identified_graph.is_empty() {
+            return Err(Err(error));
         }
+
+        /* todo: error message is misleading. someone reading this would believe we identified
+         * a smaller number of nodes that actually identified (due to identities of nodes coalescing)
+         */
+        warn!(
+            message = "Partial Success",
+            identified_nodes = identified_graph.nodes.len(),
+            identified_edges = identified_graph.edges.len(),
+            recoverable = ?error.error_type(),
+        );
+
+        Err(Ok((identified_graph, error)))
     }
 }
 
@@ -297,58 +314,93 @@ where
 /// be received before we determine that a node needs a new session).
 pub async fn handler(should_default: bool) -> Result<(), Box<dyn std::error::Error>> {
     let (env, _guard) = grapl_config::init_grapl_env!();
-    let source_queue_url = grapl_config::source_queue_url();
 
-    tracing::info!(
-        source_queue_url=?source_queue_url,
-        env=?env,
-        "handler_init"
-    );
+    tracing::info!(env=?env, "handler_init");
 
-    let sqs_client = SqsClient::from_env();
     let cache = &mut event_caches(&env).await;
-    let serializer = &mut make_ten(async { IdentifiedGraphSerializer::default() }).await;
-    let s3_emitter = &mut s3_event_emitters_from_env(&env, time_based_key_fn).await;
-
-    let s3_payload_retriever = &mut make_ten(async {
-        S3PayloadRetriever::new(
-            |region_str| grapl_config::env_helpers::init_s3_client(&region_str),
-            ProtoDecoder::default(),
-            MetricReporter::new(&env.service_name),
-        )
+    let serializer = &mut make_workers(grapl_config::worker_count(), async {
+        IdentifiedGraphSerializer::default()
     })
     .await;
-
     let dynamo = DynamoDbClient::from_env();
     let dyn_session_db = SessionDb::new(dynamo.clone(), grapl_config::dynamic_session_table_name());
-    let dyn_mapping_db = DynamicMappingDb::new(dynamo.clone());
-
-    let dyn_node_identifier =
-        NodeDescriptionIdentifier::new(dyn_session_db, dyn_mapping_db, should_default);
 
-    let node_identifier = &mut make_ten(async {
-        NodeIdentifier::new(
-            dyn_node_identifier,
+    let heartbeat = grapl_config::health_server()
+        .with_check(cache[0].clone())
+        .with_check(DynamoDbTableCheck::new(
             dynamo,
-            should_default,
-            cache[0].to_owned(),
-        )
+            grapl_config::dynamic_session_table_name(),
+        ))
+        .spawn();
+
+    let dyn_node_identifier = NodeDescriptionIdentifier::new(
+        dyn_session_db,
+        should_default,
+        SkewTolerances::from_env()?,
+        MetricReporter::new(&env.service_name),
+    );
+
+    let node_identifier = &mut make_workers(grapl_config::worker_count(), async {
+        NodeIdentifier::new(dyn_node_identifier, should_default, cache[0].to_owned())
     })
     .await;
 
-    info!("Starting process_loop");
-    sqs_executor::process_loop(
-        grapl_config::source_queue_url(),
-        grapl_config::dead_letter_queue_url(),
-        cache,
-        sqs_client.clone(),
-        node_identifier,
-        s3_payload_retriever,
-        s3_emitter,
-        serializer,
-        MetricReporter::new(&env.service_name),
-    )
-    .await;
+    if let Some((input_dir, output_dir)) = grapl_config::local_fs_dirs() {
+        let payload_retriever = &mut make_workers(grapl_config::worker_count(), async {
+            LocalFsPayloadRetriever::new(
+                LocalFsPayloadStore,
+                ProtoDecoder::default(),
+                MetricReporter::new(&env.service_name),
+            )
+        })
+        .await;
+        let emitter = &mut local_fs_emitters_from_env(&env, output_dir, time_based_key_fn).await;
+
+        info!("Starting process_loop over {:?}", input_dir);
+        sqs_executor::process_loop(
+            local_fs_message_source_from_env(&env, input_dir),
+            cache,
+            node_identifier,
+            payload_retriever,
+            emitter,
+            serializer,
+            grapl_config::retry_policy(),
+            grapl_config::concurrency_policy(),
+            grapl_config::shutdown(),
+            heartbeat,
+            MetricReporter::new(&env.service_name),
+        )
+        .await;
+    } else {
+        // Keyed by the zstd dictionary the serializer compressed each payload with
+        let key_fn = zstd_proto_key_fn(env.service_name.clone());
+        let s3_emitter = &mut s3_event_emitters_from_env(&env, key_fn).await;
+
+        let s3_payload_retriever = &mut make_workers(grapl_config::worker_count(), async {
+            S3PayloadRetriever::new(
+                |region_str| grapl_config::env_helpers::init_s3_client(&region_str),
+                ProtoDecoder::default(),
+                MetricReporter::new(&env.service_name),
+            )
+        })
+        .await;
+
+        info!(source_queue_url=?grapl_config::source_queue_url(), "Starting process_loop");
+        sqs_executor::process_loop(
+            sqs_message_source_from_env(&env),
+            cache,
+            node_identifier,
+            s3_payload_retriever,
+            s3_emitter,
+            serializer,
+            grapl_config::retry_policy(),
+            grapl_config::concurrency_policy(),
+            grapl_config::shutdown(),
+            heartbeat,
+            MetricReporter::new(&env.service_name),
+        )
+        .await;
+    }
 
     info!("Exiting");
     Ok(())
@@ -0,0 +1,43 @@
+use async_trait::async_trait;
+use rusoto_dynamodb::{
+    DescribeTableInput,
+    DynamoDb,
+    DynamoDbClient,
+};
+use sqs_executor::health::{
+    CheckError,
+    ReadinessCheck,
+};
+
+/// Ready while the table can be described, which takes both reaching DynamoDB and the table
+/// existing
+#[derive(Clone)]
+pub struct DynamoDbTableCheck {
+    dynamo: DynamoDbClient,
+    table_name: String,
+}
+
+impl DynamoDbTableCheck {
+    pub fn new(dynamo: DynamoDbClient, table_name: impl Into<String>) -> Self {
+        Self {
+            dynamo,
+            table_name: table_name.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl ReadinessCheck for DynamoDbTableCheck {
+    fn name(&self) -> &str {
+        "dynamodb"
+    }
+
+    async fn check(&self) -> Result<(), CheckError> {
+        self.dynamo
+            .describe_table(DescribeTableInput {
+                table_name: self.table_name.clone(),
+            })
+            .await?;
+        Ok(())
+    }
+}
@@ -1,11 +1,7 @@
 use std::convert::TryFrom;
 
-use failure::{
-    bail,
-    Error,
-};
+use async_trait::async_trait;
 use hmap::hmap;
-use rusoto_core::RusotoError;
 use rusoto_dynamodb::{
     AttributeValue,
     AttributeValueUpdate,
@@ -25,8 +21,219 @@ use tracing::{
 };
 use uuid::Uuid;
 
-use crate::sessions::*;
+use crate::{
+    error::NodeIdentifierError,
+    sessions::*,
+};
+
+/// Where sessions are kept. The primitives read and write individual sessions, while
+/// identifying a `UnidSession` is built on top of them, so every store attributes sessions
+/// the same way.
+#[async_trait]
+pub trait SessionStore: Clone + Send + Sync + 'static {
+    /// The earliest session for the pseudo key created at or after the unid's timestamp
+    async fn find_first_session_after(
+        &self,
+        unid: &UnidSession,
+    ) -> Result<Option<Session>, NodeIdentifierError>;
+
+    /// The latest session for the pseudo key created at or before the unid's timestamp
+    async fn find_last_session_before(
+        &self,
+        unid: &UnidSession,
+    ) -> Result<Option<Session>, NodeIdentifierError>;
+
+    async fn update_session_create_time(
+        &self,
+        session: &Session,
+        new_time: u64,
+        is_canon: bool,
+    ) -> Result<(), NodeIdentifierError>;
+
+    /// Fails with `ConditionalCheckConflict` if `session` is no longer the stored version
+    async fn make_create_time_canonical(
+        &self,
+        session: &Session,
+    ) -> Result<(), NodeIdentifierError>;
+
+    /// Fails with `ConditionalCheckConflict` if `session` is no longer the stored version
+    async fn update_session_end_time(
+        &self,
+        session: &Session,
+        new_time: u64,
+        is_canon: bool,
+    ) -> Result<(), NodeIdentifierError>;
+
+    async fn create_session(&self, session: &Session) -> Result<(), NodeIdentifierError>;
+
+    async fn delete_session(&self, session: &Session) -> Result<(), NodeIdentifierError>;
+
+    #[tracing::instrument(skip(self, unid), err)]
+    async fn handle_creation_event(
+        &self,
+        unid: UnidSession,
+    ) -> Result<String, NodeIdentifierError> {
+        info!(
+            message="Handling unid session creation",
+            pseudo_key=?unid.pseudo_key, timestamp=?unid.timestamp
+        );
+
+        // Look for first session where session.create_time >= unid.create_time
+        let session = self.find_first_session_after(&unid).await?;
+
+        if let Some(session) = session {
+            // If session.is_create_canon is false,
+            // This means that there is a 'Guessed' session in the future,
+            // and we should consider this the canonical ID for that session
+            if !session.is_create_canon && session.create_time != unid.timestamp {
+                info!(message = "Extending session create_time");
+                self.update_session_create_time(&session, unid.timestamp, true)
+                    .await?;
+                return Ok(session.session_id);
+            }
+
+            tracing::debug!(
+                "UNID: {} - {} - {}",
+                unid.pseudo_key,
+                unid.timestamp,
+                unid.pseudo_key
+            );
 
+            // If the timestamps are the same, we've found the session_id
+            // No need to update the database here - it's already canonical,
+            // with an accurate timestamp
+            if skewed_cmp(unid.timestamp, session.create_time, unid.skew_tolerance) {
+                info!(message = "Found existing session with exact create time");
+                return Ok(session.session_id);
+            }
+
+            // We should never be looking at a case where the query returned
+            // a create_time less than the unid.timestamp
+            if unid.timestamp > session.create_time {
+                return Err(NodeIdentifierError::ClockSkewViolation {
+                    unid_timestamp: unid.timestamp,
+                    session_create_time: session.create_time,
+                });
+            }
+        }
+
+        // Look for last session where session.create_time <= unid.create_time
+        let session = self.find_last_session_before(&unid).await?;
+
+        if let Some(session) = session {
+            // If session.end_time >= unid.create_time (indicates overlapping sessions, error)
+            // This will correct that session so that it does not overlap anymore.
+            if session.end_time >= unid.timestamp {
+                warn!(
+                    "Found session created before new session. Fixing overlapping end_time.
+                    {:?}
+                    {:?}
+                ",
+                    session, unid
+                );
+                // if session.end_time is NOT canonical, we can update it
+                //                self.update_session_end_time(&session, unid.timestamp - 100, session.is_end_canon)?;
+            }
+        }
+
+        // Create new session, return new session id
+        let session = Session {
+            session_id: Uuid::new_v4().to_string(),
+            create_time: unid.timestamp,
+            end_time: unid.timestamp + 101,
+            is_create_canon: true,
+            is_end_canon: false,
+            version: 0,
+            pseudo_key: unid.pseudo_key,
+        };
+
+        info!(message = "Creating session");
+        self.create_session(&session).await?;
+        Ok(session.session_id)
+    }
+
+    #[tracing::instrument(skip(self, unid), err)]
+    async fn handle_last_seen(
+        &self,
+        unid: UnidSession,
+        should_default: bool,
+    ) -> Result<String, NodeIdentifierError> {
+        info!(
+            message="Handling unid session",
+            pseudo_key=?unid.pseudo_key, timestamp=?unid.timestamp
+        );
+
+        // Look for session where session.create_time <= unid.create_time <= session.end_time
+        // Look for last session where session.create_time <= unid.create_time
+        let session = self.find_last_session_before(&unid).await?;
+        if let Some(mut session) = session {
+            if unid.timestamp < session.end_time
+                || skewed_cmp(unid.timestamp, session.end_time, unid.skew_tolerance)
+            {
+                info!(message = "Identified session because it fell within a timeline.");
+                return Ok(session.session_id);
+            }
+
+            if !session.is_end_canon {
+                session.end_time = unid.timestamp;
+                info!(message = "Updating session end_time.");
+                //                self.update_session_end_time(&session, unid.timestamp, false)?;
+
+                return Ok(session.session_id);
+            }
+        }
+
+        let session = self.find_first_session_after(&unid).await?;
+        if let Some(session) = session {
+            if !session.is_create_canon {
+                info!(message = "Found a later, non canonical session. Extending create_time.");
+
+                self.update_session_create_time(&session, unid.timestamp, false)
+                    .await?;
+                return Ok(session.session_id);
+            }
+        }
+
+        if should_default {
+            info!(message = "Defaulting and creating new session.");
+            let session_id = Uuid::new_v4().to_string();
+            let session = Session {
+                session_id: session_id.clone(),
+                create_time: unid.timestamp,
+                end_time: unid.timestamp + 101,
+                is_create_canon: false,
+                is_end_canon: false,
+                version: 0,
+                pseudo_key: unid.pseudo_key,
+            };
+            self.create_session(&session).await?;
+
+            Ok(session_id)
+        } else {
+            warn!(message = "Could not attribute session. Not defaulting.");
+            Err(NodeIdentifierError::UnattributedSession {
+                pseudo_key: unid.pseudo_key,
+                should_default,
+            })
+        }
+    }
+
+    #[tracing::instrument(skip(self), err)]
+    async fn handle_unid_session(
+        &self,
+        mut unid: UnidSession,
+        should_default: bool,
+    ) -> Result<String, NodeIdentifierError> {
+        unid.timestamp = shave_int(unid.timestamp, 1);
+        if unid.is_creation {
+            self.handle_creation_event(unid).await
+        } else {
+            self.handle_last_seen(unid, should_default).await
+        }
+    }
+}
+
+/// A `SessionStore` backed by a DynamoDB table keyed by `pseudo_key` and `create_time`
 #[derive(Debug, Clone)]
 pub struct SessionDb<D>
 where
@@ -46,12 +253,18 @@ where
             table_name: table_name.into(),
         }
     }
+}
 
+#[async_trait]
+impl<D> SessionStore for SessionDb<D>
+where
+    D: DynamoDb + Clone + Send + Sync + 'static,
+{
     #[tracing::instrument(skip(self, unid), err)]
-    pub async fn find_first_session_after(
+    async fn find_first_session_after(
         &self,
         unid: &UnidSession,
-    ) -> Result<Option<Session>, Error> {
+    ) -> Result<Option<Session>, NodeIdentifierError> {
         info!(message="Finding first session after", table_name=?&self.table_name);
         let query = QueryInput {
             consistent_read: Some(true),
@@ -73,16 +286,13 @@ where
             ..Default::default()
         };
 
-        let res = self.dynamo.query(query).await;
-        if let Err(RusotoError::Unknown(ref e)) = res {
-            bail!("Query failed with error: {:?}", e);
-        };
+        let res = self.dynamo.query(query).await?;
 
-        if let Some(items) = res?.items {
+        if let Some(items) = res.items {
             match &items[..] {
                 [] => Ok(None),
                 [item] => Session::try_from(item.clone()).map(Option::from),
-                _ => bail!("Unexpected number of items returned"),
+                items => Err(NodeIdentifierError::UnexpectedItemCount(items.len())),
             }
         } else {
             Ok(None)
@@ -90,10 +300,10 @@ where
     }
 
     #[tracing::instrument(skip(self, unid), err)]
-    pub async fn find_last_session_before(
+    async fn find_last_session_before(
         &self,
         unid: &UnidSession,
-    ) -> Result<Option<Session>, Error> {
+    ) -> Result<Option<Session>, NodeIdentifierError> {
         info!(message = "Finding last session before");
         let query = QueryInput {
             consistent_read: Some(true),
@@ -122,7 +332,7 @@ where
             match &items[..] {
                 [] => Ok(None),
                 [item] => Session::try_from(item.clone()).map(Option::from),
-                _ => bail!("Unexpected number of items returned"),
+                items => Err(NodeIdentifierError::UnexpectedItemCount(items.len())),
             }
         } else {
             Ok(None)
@@ -134,12 +344,12 @@ where
     // new create_time
     // This method assumes that the `session` passed in has already been modified
     #[tracing::instrument(skip(self, session), err)]
-    pub async fn update_session_create_time(
+    async fn update_session_create_time(
         &self,
         session: &Session,
         new_time: u64,
         is_canon: bool,
-    ) -> Result<(), Error> {
+    ) -> Result<(), NodeIdentifierError> {
         info!(message = "Updating session create time");
         let mut new_session = session.to_owned();
         new_session.create_time = new_time;
@@ -188,7 +398,10 @@ where
     }
 
     #[tracing::instrument(skip(self, session), err)]
-    pub async fn make_create_time_canonical(&self, session: &Session) -> Result<(), Error> {
+    async fn make_create_time_canonical(
+        &self,
+        session: &Session,
+    ) -> Result<(), NodeIdentifierError> {
         info!(message = "Updating session end time");
         // Use version as a constraint
         let upd_req = UpdateItemInput {
@@ -236,12 +449,12 @@ where
 
     // Update version, and use it as a constraint
     #[tracing::instrument(skip(self, session), err)]
-    pub async fn update_session_end_time(
+    async fn update_session_end_time(
         &self,
         session: &Session,
         new_time: u64,
         is_canon: bool,
-    ) -> Result<(), Error> {
+    ) -> Result<(), NodeIdentifierError> {
         info!(message = "Updating session end time");
         // Use version as a constraint
         let upd_req = UpdateItemInput {
@@ -296,7 +509,7 @@ where
     }
 
     #[tracing::instrument(skip(self, session), err)]
-    pub async fn create_session(&self, session: &Session) -> Result<(), Error> {
+    async fn create_session(&self, session: &Session) -> Result<(), NodeIdentifierError> {
         let put_req = PutItemInput {
             item: serde_dynamodb::to_hashmap(session).unwrap(),
             table_name: self.table_name.clone(),
@@ -309,7 +522,7 @@ where
     }
 
     #[tracing::instrument(skip(self, session), err)]
-    pub async fn delete_session(&self, session: &Session) -> Result<(), Error> {
+    async fn delete_session(&self, session: &Session) -> Result<(), NodeIdentifierError> {
         let del_req = DeleteItemInput {
             key: hmap! {
                 "pseudo_key".to_owned() => AttributeValue {
@@ -328,168 +541,9 @@ where
         self.dynamo.delete_item(del_req).await?;
         Ok(())
     }
-
-    #[tracing::instrument(skip(self, unid), err)]
-    pub async fn handle_creation_event(&self, unid: UnidSession) -> Result<String, Error> {
-        info!(
-            message="Handling unid session creation",
-            pseudo_key=?unid.pseudo_key, timestamp=?unid.timestamp
-        );
-
-        // Look for first session where session.create_time >= unid.create_time
-        let session = self.find_first_session_after(&unid).await?;
-
-        if let Some(session) = session {
-            // If session.is_create_canon is false,
-            // This means that there is a 'Guessed' session in the future,
-            // and we should consider this the canonical ID for that session
-            if !session.is_create_canon && session.create_time != unid.timestamp {
-                info!(message = "Extending session create_time");
-                self.update_session_create_time(&session, unid.timestamp, true)
-                    .await?;
-                return Ok(session.session_id);
-            }
-
-            tracing::debug!(
-                "UNID: {} - {} - {}",
-                unid.pseudo_key,
-                unid.timestamp,
-                unid.pseudo_key
-            );
-
-            // If the timestamps are the same, we've found the session_id
-            // No need to update the database here - it's already canonical,
-            // with an accurate timestamp
-            if skewed_cmp(unid.timestamp, session.create_time) {
-                info!(message = "Found existing session with exact create time");
-                return Ok(session.session_id);
-            }
-
-            // We should never be looking at a case where the query returned
-            // a create_time less than the unid.timestamp
-            if unid.timestamp > session.create_time {
-                bail!(
-                    "unid.timestamp > session.create_time {} {}",
-                    unid.timestamp,
-                    session.create_time
-                );
-            }
-        }
-
-        // Look for last session where session.create_time <= unid.create_time
-        let session = self.find_last_session_before(&unid).await?;
-
-        if let Some(session) = session {
-            // If session.end_time >= unid.create_time (indicates overlapping sessions, error)
-            // This will correct that session so that it does not overlap anymore.
-            if session.end_time >= unid.timestamp {
-                warn!(
-                    "Found session created before new session. Fixing overlapping end_time.
-                    {:?}
-                    {:?}
-                ",
-                    session, unid
-                );
-                // if session.end_time is NOT canonical, we can update it
-                //                self.update_session_end_time(&session, unid.timestamp - 100, session.is_end_canon)?;
-            }
-        }
-
-        // Create new session, return new session id
-        let session = Session {
-            session_id: Uuid::new_v4().to_string(),
-            create_time: unid.timestamp,
-            end_time: unid.timestamp + 101,
-            is_create_canon: true,
-            is_end_canon: false,
-            version: 0,
-            pseudo_key: unid.pseudo_key,
-        };
-
-        info!(message = "Creating session");
-        self.create_session(&session).await?;
-        Ok(session.session_id)
-    }
-
-    #[tracing::instrument(skip(self, unid), err)]
-    pub async fn handle_last_seen(
-        &self,
-        unid: UnidSession,
-        should_default: bool,
-    ) -> Result<String, Error> {
-        info!(
-            message="Handling unid session",
-            pseudo_key=?unid.pseudo_key, timestamp=?unid.timestamp
-        );
-
-        // Look for session where session.create_time <= unid.create_time <= session.end_time
-        // Look for last session where session.create_time <= unid.create_time
-        let session = self.find_last_session_before(&unid).await?;
-        if let Some(mut session) = session {
-            if unid.timestamp < session.end_time || skewed_cmp(unid.timestamp, session.end_time) {
-                info!(message = "Identified session because it fell within a timeline.");
-                return Ok(session.session_id);
-            }
-
-            if !session.is_end_canon {
-                session.end_time = unid.timestamp;
-                info!(message = "Updating session end_time.");
-                //                self.update_session_end_time(&session, unid.timestamp, false)?;
-
-                return Ok(session.session_id);
-            }
-        }
-
-        let session = self.find_first_session_after(&unid).await?;
-        if let Some(session) = session {
-            if !session.is_create_canon {
-                info!(message = "Found a later, non canonical session. Extending create_time.");
-
-                self.update_session_create_time(&session, unid.timestamp, false)
-                    .await?;
-                return Ok(session.session_id);
-            }
-        }
-
-        if should_default {
-            info!(message = "Defaulting and creating new session.");
-            let session_id = Uuid::new_v4().to_string();
-            let session = Session {
-                session_id: session_id.clone(),
-                create_time: unid.timestamp,
-                end_time: unid.timestamp + 101,
-                is_create_canon: false,
-                is_end_canon: false,
-                version: 0,
-                pseudo_key: unid.pseudo_key,
-            };
-            self.create_session(&session).await?;
-
-            Ok(session_id)
-        } else {
-            warn!(message = "Could not attribute session. Not defaulting.");
-            bail!(
-                "Could not attribute session. should_default {}. Not defaulting.",
-                should_default
-            )
-        }
-    }
-
-    #[tracing::instrument(skip(self), err)]
-    pub async fn handle_unid_session(
-        &self,
-        mut unid: UnidSession,
-        should_default: bool,
-    ) -> Result<String, Error> {
-        unid.timestamp = shave_int(unid.timestamp, 1);
-        if unid.is_creation {
-            self.handle_creation_event(unid).await
-        } else {
-            self.handle_last_seen(unid, should_default).await
-        }
-    }
 }
 
-pub fn skewed_cmp(ts_1: u64, ts_2: u64) -> bool {
-    ts_1 - 10 < ts_2 && ts_1 + 10 > ts_2
+/// Whether two timestamps are within `tolerance` of each other, to account for clock skew
+pub fn skewed_cmp(ts_1: u64, ts_2: u64, tolerance: u64) -> bool {
+    ts_1.saturating_sub(tolerance) <= ts_2 && ts_1.saturating_add(tolerance) >= ts_2
 }
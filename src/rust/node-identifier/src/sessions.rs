@@ -3,18 +3,20 @@ use std::{
     convert::TryFrom,
 };
 
-use failure::Error;
 use rusoto_dynamodb::AttributeValue;
 use serde::{
     Deserialize,
     Serialize,
 };
 
+use crate::error::NodeIdentifierError;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UnidSession {
     pub pseudo_key: String,
-    pub timestamp: u64,
+    pub timestamp: u64,    // Normalised to milliseconds
     pub is_creation: bool, // Is this a creation event
+    pub skew_tolerance: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,8 +31,8 @@ pub struct Session {
 }
 
 impl TryFrom<HashMap<String, AttributeValue>> for Session {
-    type Error = Error;
-    fn try_from(map: HashMap<String, AttributeValue>) -> Result<Self, Error> {
+    type Error = NodeIdentifierError;
+    fn try_from(map: HashMap<String, AttributeValue>) -> Result<Self, NodeIdentifierError> {
         Ok(serde_dynamodb::from_hashmap(map)?)
     }
 }
@@ -39,3 +41,137 @@ pub fn shave_int(input: u64, digits: u8) -> u64 {
     let digits = 10u64.pow((digits as u32) + 1u32);
     input - (input % digits)
 }
+
+/// The tolerance, in milliseconds, used when no tolerance is configured for a node type
+pub const DEFAULT_SKEW_TOLERANCE_MS: u64 = 10;
+
+// 2000-01-01T00:00:00Z and 2100-01-01T00:00:00Z. Anything outside of this range almost certainly
+// means that a generator emitted the timestamp in a different unit than it declared.
+pub const MIN_PLAUSIBLE_TIMESTAMP_MS: u64 = 946_684_800_000;
+pub const MAX_PLAUSIBLE_TIMESTAMP_MS: u64 = 4_102_444_800_000;
+
+pub fn is_plausible_timestamp(timestamp_ms: u64) -> bool {
+    (MIN_PLAUSIBLE_TIMESTAMP_MS..=MAX_PLAUSIBLE_TIMESTAMP_MS).contains(&timestamp_ms)
+}
+
+/// Per node type clock-skew tolerances used when matching timestamps against existing sessions.
+#[derive(Debug, Clone)]
+pub struct SkewTolerances {
+    default_tolerance: u64,
+    node_type_tolerances: HashMap<String, u64>,
+}
+
+impl SkewTolerances {
+    pub fn new(default_tolerance: u64, node_type_tolerances: HashMap<String, u64>) -> Self {
+        Self {
+            default_tolerance,
+            node_type_tolerances,
+        }
+    }
+
+    /// Reads the default tolerance from `GRAPL_SESSION_SKEW_TOLERANCE_MS`, and per node type
+    /// overrides from `GRAPL_SESSION_SKEW_TOLERANCES`, formatted as `Process=100,File=1000`
+    pub fn from_env() -> Result<Self, SkewToleranceConfigError> {
+        let default_tolerance = match std::env::var("GRAPL_SESSION_SKEW_TOLERANCE_MS") {
+            Ok(tolerance) => tolerance.trim().parse().map_err(|_| {
+                SkewToleranceConfigError::InvalidDefaultTolerance(tolerance.clone())
+            })?,
+            Err(_) => DEFAULT_SKEW_TOLERANCE_MS,
+        };
+
+        let node_type_tolerances = match std::env::var("GRAPL_SESSION_SKEW_TOLERANCES") {
+            Ok(tolerances) => parse_node_type_tolerances(&tolerances)?,
+            Err(_) => HashMap::new(),
+        };
+
+        Ok(Self::new(default_tolerance, node_type_tolerances))
+    }
+
+    pub fn for_node_type(&self, node_type: &str) -> u64 {
+        self.node_type_tolerances
+            .get(node_type)
+            .copied()
+            .unwrap_or(self.default_tolerance)
+    }
+}
+
+impl Default for SkewTolerances {
+    fn default() -> Self {
+        Self::new(DEFAULT_SKEW_TOLERANCE_MS, HashMap::new())
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum SkewToleranceConfigError {
+    #[error("GRAPL_SESSION_SKEW_TOLERANCE_MS must be a number of milliseconds, not {0:?}")]
+    InvalidDefaultTolerance(String),
+    #[error("Invalid GRAPL_SESSION_SKEW_TOLERANCES entry, expected NodeType=ms: {0:?}")]
+    InvalidEntry(String),
+    #[error("Invalid GRAPL_SESSION_SKEW_TOLERANCES tolerance for {node_type}: {tolerance:?}")]
+    InvalidTolerance {
+        node_type: String,
+        tolerance: String,
+    },
+}
+
+fn parse_node_type_tolerances(
+    tolerances: &str,
+) -> Result<HashMap<String, u64>, SkewToleranceConfigError> {
+    tolerances
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            let (node_type, tolerance) = entry
+                .split_once('=')
+                .ok_or_else(|| SkewToleranceConfigError::InvalidEntry(entry.to_owned()))?;
+            let node_type = node_type.trim();
+            let tolerance = tolerance.trim().parse().map_err(|_| {
+                SkewToleranceConfigError::InvalidTolerance {
+                    node_type: node_type.to_owned(),
+                    tolerance: tolerance.trim().to_owned(),
+                }
+            })?;
+            Ok((node_type.to_owned(), tolerance))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_node_type_tolerances() {
+        let tolerances = SkewTolerances::new(
+            DEFAULT_SKEW_TOLERANCE_MS,
+            parse_node_type_tolerances("Process=100, NetworkConnection = 2000,").unwrap(),
+        );
+
+        assert_eq!(tolerances.for_node_type("Process"), 100);
+        assert_eq!(tolerances.for_node_type("NetworkConnection"), 2000);
+        assert_eq!(tolerances.for_node_type("File"), DEFAULT_SKEW_TOLERANCE_MS);
+    }
+
+    #[test]
+    fn test_malformed_node_type_tolerances_are_errors() {
+        assert_eq!(
+            parse_node_type_tolerances("Process"),
+            Err(SkewToleranceConfigError::InvalidEntry("Process".to_owned()))
+        );
+        assert_eq!(
+            parse_node_type_tolerances("Process=100,File=soon"),
+            Err(SkewToleranceConfigError::InvalidTolerance {
+                node_type: "File".to_owned(),
+                tolerance: "soon".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_is_plausible_timestamp() {
+        // 2021-01-01T00:00:00Z, in seconds, milliseconds and FILETIME
+        assert!(!is_plausible_timestamp(1_609_459_200));
+        assert!(is_plausible_timestamp(1_609_459_200_000));
+        assert!(!is_plausible_timestamp(132_539_328_000_000_000));
+    }
+}
@@ -4,10 +4,14 @@ use std::time::Duration;
 
 use grapl_config::env_helpers::FromEnv;
 use node_identifier::{
-    sessiondb::SessionDb,
+    sessiondb::{
+        SessionDb,
+        SessionStore,
+    },
     sessions::{
         Session,
         UnidSession,
+        DEFAULT_SKEW_TOLERANCE_MS,
     },
 };
 use quickcheck_macros::quickcheck;
@@ -93,6 +97,7 @@ fn canon_create_on_empty_timeline(asset_id: String, pid: u64) {
         pseudo_key: format!("{}{}", asset_id, pid),
         timestamp: 1544301484600,
         is_creation: true,
+        skew_tolerance: DEFAULT_SKEW_TOLERANCE_MS,
     };
 
     let session_id = runtime
@@ -139,6 +144,7 @@ fn canon_create_update_existing_non_canon_create(asset_id: String, pid: u64) {
         pseudo_key: format!("{}{}", asset_id, pid),
         timestamp: 1_544_301_484_500,
         is_creation: true,
+        skew_tolerance: DEFAULT_SKEW_TOLERANCE_MS,
     };
 
     let session_id = runtime
@@ -185,6 +191,7 @@ fn noncanon_create_update_existing_non_canon_create(asset_id: String, pid: u64)
         pseudo_key: format!("{}{}", asset_id, pid),
         timestamp: 1_544_301_484_500,
         is_creation: false,
+        skew_tolerance: DEFAULT_SKEW_TOLERANCE_MS,
     };
 
     let session_id = runtime
@@ -212,6 +219,7 @@ fn noncanon_create_on_empty_timeline_with_default(asset_id: String, pid: u64) {
         pseudo_key: format!("{}{}", asset_id, pid),
         timestamp: 1_544_301_484_500,
         is_creation: false,
+        skew_tolerance: DEFAULT_SKEW_TOLERANCE_MS,
     };
 
     let session_id = runtime
@@ -238,6 +246,7 @@ fn noncanon_create_on_empty_timeline_without_default() {
         pseudo_key: "asset_id_a1234".into(),
         timestamp: 1_544_301_484_500,
         is_creation: false,
+        skew_tolerance: DEFAULT_SKEW_TOLERANCE_MS,
     };
 
     let session_id = runtime.block_on(session_db.handle_unid_session(unid, false));
@@ -276,6 +285,7 @@ fn update_end_time(asset_id: String, pid: u64) {
         pseudo_key: format!("{}{}", asset_id, pid),
         timestamp: 1_544_301_484_800,
         is_creation: false,
+        skew_tolerance: DEFAULT_SKEW_TOLERANCE_MS,
     };
 
     let session_id = runtime
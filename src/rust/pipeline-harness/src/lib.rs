@@ -0,0 +1,400 @@
+//! Runs every stage of the pipeline in one process, from a generator through to the analyzer
+//! dispatcher, so that changes which break the stages' agreement with each other, such as a
+//! change to how node keys are formatted, are caught by an ordinary test.
+//!
+//! The stages are connected by channels rather than queues and buckets, sessions are kept by
+//! an `InMemorySessionDb` and the merged graph by an `InMemoryGraphStore`. Otherwise each
+//! stage runs the same `EventHandler`, decoder and serializer, in the same `process_loop`, as
+//! it does when deployed.
+
+use std::{
+    collections::VecDeque,
+    io::Stdout,
+    sync::Mutex,
+    time::Duration,
+};
+
+use analyzer_dispatcher_lib::{
+    dispatch_event::AnalyzerDispatchSerializer,
+    AnalyzerDispatcher,
+    AnalyzerSource,
+};
+use graph_merger_lib::{
+    graph_store::InMemoryGraphStore,
+    reverse_resolver::{
+        ReverseEdgeResolver,
+        ReverseEdgeSchema,
+    },
+    service::GraphMerger,
+};
+use grapl_observe::metric_reporter::MetricReporter;
+use grapl_service::{
+    decoder::ProtoDecoder,
+    serialization::{
+        GraphDescriptionSerializer,
+        IdentifiedGraphSerializer,
+        MergedGraphSerializer,
+    },
+};
+use node_identifier::{
+    dynamic_sessiondb::NodeDescriptionIdentifier,
+    in_memory_sessiondb::InMemorySessionDb,
+    sessions::SkewTolerances,
+    NodeIdentifier,
+};
+use prost::Message;
+use rusoto_s3::S3Client;
+use rust_proto::{
+    graph_descriptions::MergedGraph,
+    pipeline::{
+        Envelope,
+        Metadata,
+    },
+};
+use serde::Deserialize;
+use sqs_executor::{
+    cache::NopCache,
+    channel::{
+        channel,
+        ChannelEmitter,
+        ChannelMessage,
+        ChannelMessageSource,
+        ChannelPayloadRetriever,
+        ChannelPayloadStore,
+    },
+    concurrency::ConcurrencyPolicy,
+    event_decoder::{
+        BatchedDecoder,
+        DEFAULT_EVENT_BATCH_SIZE,
+    },
+    event_emitter::Emitter,
+    event_handler::BatchedEventHandler,
+    failure_metadata::FailureMetadata,
+    health::Heartbeat,
+    message_source::MessageSource,
+    retry_policy::RetryPolicy,
+    shutdown::{
+        Shutdown,
+        ShutdownTrigger,
+    },
+};
+use sysmon_generator_lib::{
+    generator::SysmonGenerator,
+    metrics::SysmonGeneratorMetrics,
+    serialization::{
+        SysmonDecoder,
+        SysmonDecoderError,
+    },
+};
+use tempfile::TempDir;
+
+/// The `inner_type` of the envelopes raw logs are sent in
+pub const RAW_LOG_TYPE: &str = "RawLog";
+
+#[derive(thiserror::Error, Debug)]
+pub enum HarnessError {
+    #[error("IoError: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("EnvelopeDecode error")]
+    EnvelopeDecode(#[from] prost::DecodeError),
+    #[error("Failed to decode dispatch event: {0}")]
+    DispatchDecode(#[from] serde_json::Error),
+    #[error("Channel closed")]
+    Closed,
+}
+
+/// What the analyzer dispatcher emitted for one analyzer
+#[derive(Clone, Debug)]
+pub struct AnalyzerDispatch {
+    pub key: String,
+    pub subgraph: MergedGraph,
+}
+
+/// How the `AnalyzerDispatchSerializer` writes each `AnalyzerDispatch`
+#[derive(Deserialize)]
+struct SerializedDispatch {
+    key: String,
+    subgraph: Vec<u8>,
+}
+
+impl AnalyzerDispatch {
+    fn decode(payload: &[u8]) -> Result<Self, HarnessError> {
+        let envelope = Envelope::decode(payload)?;
+        let dispatch: SerializedDispatch = serde_json::from_slice(&envelope.inner_message)?;
+        Ok(Self {
+            key: dispatch.key,
+            subgraph: MergedGraph::decode(&dispatch.subgraph[..])?,
+        })
+    }
+}
+
+/// Both ends of the channel between two stages
+struct Link {
+    emitter: ChannelEmitter,
+    source: ChannelMessageSource,
+}
+
+impl Link {
+    fn new() -> Self {
+        let (emitter, source) = channel();
+        Self { emitter, source }
+    }
+}
+
+/// The Sysmon generator, node identifier, graph merger and analyzer dispatcher, chained
+/// together. Raw logs go in with `send_raw_logs`, and what's dispatched to analyzers comes out
+/// of `next_dispatch`, while the stages are being `run`.
+pub struct PipelineHarness {
+    raw_logs: Link,
+    unidentified: Link,
+    identified: Link,
+    merged: Link,
+    dispatched: Link,
+    /// Dispatches received from the `dispatched` link but not yet returned
+    pending: Mutex<VecDeque<AnalyzerDispatch>>,
+    session_db: InMemorySessionDb,
+    graph_store: InMemoryGraphStore,
+    reverse_edge_schema: ReverseEdgeSchema,
+    analyzers_dir: TempDir,
+    retry_policy: RetryPolicy,
+    shutdown: Shutdown,
+    trigger: ShutdownTrigger,
+}
+
+impl PipelineHarness {
+    /// A pipeline that merges with `reverse_edge_schema`, and dispatches to an analyzer for
+    /// each of `analyzer_keys`
+    pub fn new(
+        reverse_edge_schema: ReverseEdgeSchema,
+        analyzer_keys: &[&str],
+    ) -> Result<Self, HarnessError> {
+        let analyzers_dir = tempfile::tempdir()?;
+        for key in analyzer_keys {
+            let path = analyzers_dir.path().join(key);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(path, b"")?;
+        }
+
+        let (trigger, shutdown) = Shutdown::new(Duration::from_secs(5));
+        Ok(Self {
+            raw_logs: Link::new(),
+            unidentified: Link::new(),
+            identified: Link::new(),
+            merged: Link::new(),
+            dispatched: Link::new(),
+            pending: Mutex::new(VecDeque::new()),
+            session_db: InMemorySessionDb::new(),
+            graph_store: InMemoryGraphStore::new(),
+            reverse_edge_schema,
+            analyzers_dir,
+            // Retries happen in milliseconds, so that a test waiting on one doesn't time out
+            retry_policy: RetryPolicy {
+                max_retries: 3,
+                initial_delay: Duration::from_millis(10),
+                max_delay: Duration::from_millis(100),
+            },
+            shutdown,
+            trigger,
+        })
+    }
+
+    pub fn session_db(&self) -> &InMemorySessionDb {
+        &self.session_db
+    }
+
+    /// The graph as merged so far
+    pub fn graph_store(&self) -> &InMemoryGraphStore {
+        &self.graph_store
+    }
+
+    /// Sends newline-separated Sysmon events to the generator
+    pub async fn send_raw_logs(&self, logs: Vec<u8>) -> Result<(), HarnessError> {
+        let envelope = Envelope {
+            metadata: Some(Metadata::default()),
+            inner_type: RAW_LOG_TYPE.to_owned(),
+            inner_message: logs,
+            compression_dictionary_id: 0,
+        };
+        self.raw_logs
+            .emitter
+            .clone()
+            .emit_event(vec![envelope.encode_to_vec()])
+            .await
+            .map_err(|_| HarnessError::Closed)
+    }
+
+    /// The next `AnalyzerDispatch`, or `None` if there isn't one within `timeout`
+    pub async fn next_dispatch(
+        &self,
+        timeout: Duration,
+    ) -> Result<Option<AnalyzerDispatch>, HarnessError> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut source = self.dispatched.source.clone();
+        loop {
+            if let Some(dispatch) = self.pending.lock().unwrap().pop_front() {
+                return Ok(Some(dispatch));
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Ok(None);
+            }
+
+            let messages = source
+                .receive_messages()
+                .await
+                .map_err(|_| HarnessError::Closed)?;
+            let dispatches = messages
+                .iter()
+                .map(|message| AnalyzerDispatch::decode(&message.payload))
+                .collect::<Result<Vec<_>, _>>()?;
+            self.pending.lock().unwrap().extend(dispatches);
+        }
+    }
+
+    /// Every message a stage has dead-lettered so far
+    pub fn dead_letters(&self) -> Vec<(ChannelMessage, FailureMetadata)> {
+        [
+            &self.raw_logs,
+            &self.unidentified,
+            &self.identified,
+            &self.merged,
+        ]
+        .iter()
+        .flat_map(|link| link.source.dead_letters())
+        .collect()
+    }
+
+    /// Stops every stage, once the messages they're processing are done
+    pub fn shutdown(&self) {
+        self.trigger.trigger();
+    }
+
+    /// Runs every stage until `shutdown` is called
+    pub async fn run(&self) {
+        futures::join!(
+            self.run_generator(),
+            self.run_node_identifier(),
+            self.run_graph_merger(),
+            self.run_analyzer_dispatcher(),
+        );
+    }
+
+    async fn run_generator(&self) {
+        let service_name = "sysmon-generator";
+        sqs_executor::process_loop(
+            self.raw_logs.source.clone(),
+            &mut [NopCache {}],
+            &mut [BatchedEventHandler::<_, SysmonDecoderError>::new(
+                SysmonGenerator::new(NopCache {}, SysmonGeneratorMetrics::new(service_name)),
+            )],
+            &mut [ChannelPayloadRetriever::new(
+                ChannelPayloadStore,
+                BatchedDecoder::new(SysmonDecoder::default(), DEFAULT_EVENT_BATCH_SIZE),
+                metric_reporter(service_name),
+            )],
+            &mut [self.unidentified.emitter.clone()],
+            &mut [GraphDescriptionSerializer::default()],
+            self.retry_policy,
+            ConcurrencyPolicy::default(),
+            self.shutdown.clone(),
+            Heartbeat::default(),
+            metric_reporter(service_name),
+        )
+        .await;
+    }
+
+    async fn run_node_identifier(&self) {
+        let service_name = "node-identifier";
+        // There's no retry stage behind this one, so sessions are guessed on the first pass,
+        // as the retry node identifier would
+        let should_default = true;
+        let dynamic_identifier = NodeDescriptionIdentifier::new(
+            self.session_db.clone(),
+            should_default,
+            SkewTolerances::default(),
+            metric_reporter(service_name),
+        );
+        sqs_executor::process_loop(
+            self.unidentified.source.clone(),
+            &mut [NopCache {}],
+            &mut [NodeIdentifier::new(
+                dynamic_identifier,
+                should_default,
+                NopCache {},
+            )],
+            &mut [ChannelPayloadRetriever::new(
+                ChannelPayloadStore,
+                ProtoDecoder::default(),
+                metric_reporter(service_name),
+            )],
+            &mut [self.identified.emitter.clone()],
+            &mut [IdentifiedGraphSerializer::default()],
+            self.retry_policy,
+            ConcurrencyPolicy::default(),
+            self.shutdown.clone(),
+            Heartbeat::default(),
+            metric_reporter(service_name),
+        )
+        .await;
+    }
+
+    async fn run_graph_merger(&self) {
+        let service_name = "graph-merger";
+        let reverse_edge_resolver = ReverseEdgeResolver::new(
+            self.reverse_edge_schema.clone(),
+            metric_reporter(service_name),
+        );
+        sqs_executor::process_loop(
+            self.identified.source.clone(),
+            &mut [NopCache {}],
+            &mut [GraphMerger::new(
+                self.graph_store.clone(),
+                reverse_edge_resolver,
+                metric_reporter(service_name),
+                NopCache {},
+            )],
+            &mut [ChannelPayloadRetriever::new(
+                ChannelPayloadStore,
+                ProtoDecoder::default(),
+                metric_reporter(service_name),
+            )],
+            &mut [self.merged.emitter.clone()],
+            &mut [MergedGraphSerializer::default()],
+            self.retry_policy,
+            ConcurrencyPolicy::default(),
+            self.shutdown.clone(),
+            Heartbeat::default(),
+            metric_reporter(service_name),
+        )
+        .await;
+    }
+
+    async fn run_analyzer_dispatcher(&self) {
+        let service_name = "analyzer-dispatcher";
+        sqs_executor::process_loop(
+            self.merged.source.clone(),
+            &mut [NopCache {}],
+            &mut [AnalyzerDispatcher::<S3Client>::new(
+                AnalyzerSource::LocalDir(self.analyzers_dir.path().to_path_buf()),
+            )],
+            &mut [ChannelPayloadRetriever::new(
+                ChannelPayloadStore,
+                ProtoDecoder::default(),
+                metric_reporter(service_name),
+            )],
+            &mut [self.dispatched.emitter.clone()],
+            &mut [AnalyzerDispatchSerializer::default()],
+            self.retry_policy,
+            ConcurrencyPolicy::default(),
+            self.shutdown.clone(),
+            Heartbeat::default(),
+            metric_reporter(service_name),
+        )
+        .await;
+    }
+}
+
+fn metric_reporter(service_name: &str) -> MetricReporter<Stdout> {
+    MetricReporter::new(service_name)
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+
+use graph_merger_lib::{
+    graph_store::InMemoryGraphStore,
+    reverse_resolver::{
+        ReverseEdgeResolver,
+        ReverseEdgeSchema,
+    },
+    service::GraphMerger,
+};
+use grapl_observe::metric_reporter::MetricReporter;
+use node_identifier::{
+    dynamic_sessiondb::NodeDescriptionIdentifier,
+    in_memory_sessiondb::InMemorySessionDb,
+    sessions::SkewTolerances,
+    NodeIdentifier,
+};
+use rust_proto::graph_descriptions::{
+    DecrementOnlyUintProp,
+    GraphDescription,
+    ImmutableStrProp,
+    NodeDescription,
+    NodeProperty,
+    Static,
+};
+use sqs_executor::{
+    cache::NopCache,
+    event_handler::{
+        CompletedEvents,
+        EventHandler,
+    },
+};
+
+const SCHEMA: &str = r#"
+    type Process @grapl(identity_algorithm: "session") {
+        process_id: UInt! @pseudo_key,
+        created_at: UInt! @create_time,
+        last_seen_at: UInt! @last_seen_time,
+        terminated_at: UInt! @terminate_time,
+        children: [Process!] @edge(reverse: "parent", reverse_relationship: "ToOne"),
+    }
+"#;
+
+fn process(node_key: &str, process_name: &str) -> NodeDescription {
+    let mut node = NodeDescription {
+        node_key: node_key.to_owned(),
+        node_type: "Process".to_owned(),
+        id_strategy: vec![Static {
+            primary_key_properties: vec!["process_name".to_owned()],
+            primary_key_requires_asset_id: false,
+        }
+        .into()],
+        ..Default::default()
+    };
+    node.set_property(
+        "process_name",
+        ImmutableStrProp {
+            prop: process_name.to_owned(),
+        },
+    );
+    node
+}
+
+/// An edge's properties survive being re-keyed by the node identifier, and are written, along
+/// with the reverse edge's, by the graph merger
+#[tokio::test]
+async fn test_edge_properties_are_identified_and_merged() -> Result<(), Box<dyn std::error::Error>>
+{
+    let mut properties: HashMap<String, NodeProperty> = HashMap::new();
+    properties.insert(
+        "first_seen".to_owned(),
+        DecrementOnlyUintProp { prop: 1_000 }.into(),
+    );
+
+    let mut unidentified = GraphDescription::new();
+    unidentified.add_node(process("parent", "svchost.exe"));
+    unidentified.add_node(process("child", "cmd.exe"));
+    unidentified.add_edge_with_properties("children", "parent", "child", properties.clone());
+
+    let should_default = true;
+    let mut node_identifier = NodeIdentifier::new(
+        NodeDescriptionIdentifier::new(
+            InMemorySessionDb::new(),
+            should_default,
+            SkewTolerances::default(),
+            MetricReporter::new("node-identifier"),
+        ),
+        should_default,
+        NopCache {},
+    );
+    let identified = node_identifier
+        .handle_event(unidentified, &mut CompletedEvents::default())
+        .await
+        .expect("identification failed");
+
+    let parent_key = identified
+        .edges
+        .keys()
+        .next()
+        .expect("edge wasn't identified")
+        .clone();
+    let identified_edge = &identified.edges[&parent_key].edges[0];
+    assert_eq!(identified_edge.properties, properties);
+    let child_key = identified_edge.to_node_key.clone();
+
+    let graph_store = InMemoryGraphStore::new();
+    let mut graph_merger = GraphMerger::new(
+        graph_store.clone(),
+        ReverseEdgeResolver::new(
+            ReverseEdgeSchema::from_graphql(SCHEMA)?,
+            MetricReporter::new("graph-merger"),
+        ),
+        MetricReporter::new("graph-merger"),
+        NopCache {},
+    );
+    let merged = graph_merger
+        .handle_event(identified, &mut CompletedEvents::default())
+        .await
+        .expect("merging failed");
+
+    assert_eq!(merged.edges[&parent_key].edges[0].properties, properties);
+    assert_eq!(
+        graph_store.get_edge_properties(&parent_key, "children", &child_key),
+        Some(properties.clone())
+    );
+    assert_eq!(
+        graph_store.get_edge_properties(&child_key, "parent", &parent_key),
+        Some(properties)
+    );
+    Ok(())
+}
@@ -0,0 +1,177 @@
+use std::{
+    collections::HashMap,
+    time::Duration,
+};
+
+use graph_merger_lib::reverse_resolver::ReverseEdgeSchema;
+use grapl_observe::metric_reporter::MetricReporter;
+use grapl_service::serialization::{
+    chunking::chunk,
+    ChunkLimits,
+};
+use node_identifier::{
+    dynamic_sessiondb::NodeDescriptionIdentifier,
+    in_memory_sessiondb::InMemorySessionDb,
+    sessions::SkewTolerances,
+    NodeIdentifier,
+};
+use pipeline_harness::PipelineHarness;
+use rust_proto::graph_descriptions::{
+    EdgeList,
+    ImmutableUintProp,
+    MergedNode,
+    NodeProperty,
+};
+use sqs_executor::{
+    cache::NopCache,
+    event_decoder::PayloadDecoder,
+    event_handler::{
+        CompletedEvents,
+        EventHandler,
+    },
+};
+use sysmon_generator_lib::{
+    generator::SysmonGenerator,
+    metrics::SysmonGeneratorMetrics,
+    serialization::SysmonDecoder,
+};
+
+const ANALYZER_KEY: &str = "analyzers/suspicious_svchost/main.py";
+
+const SCHEMA: &str = r#"
+    type Process @grapl(identity_algorithm: "session") {
+        process_id: UInt! @pseudo_key,
+        created_at: UInt! @create_time,
+        last_seen_at: UInt! @last_seen_time,
+        terminated_at: UInt! @terminate_time,
+        children: [Process!] @edge(reverse: "parent", reverse_relationship: "ToOne"),
+        process_asset: Asset! @edge(reverse: "asset_processes", reverse_relationship: "ToMany"),
+    }
+"#;
+
+// svchost.exe (6132) creating cmd.exe (5752)
+const PROCESS_CREATE: &str = r#"<Event xmlns='http://schemas.microsoft.com/win/2004/08/events/event'><System><Provider Name='Microsoft-Windows-Sysmon' Guid='{5770385F-C22A-43E0-BF4C-06F5698FFBD9}'/><EventID>1</EventID><Version>5</Version><Level>4</Level><Task>1</Task><Opcode>0</Opcode><Keywords>0x8000000000000000</Keywords><TimeCreated SystemTime='2019-07-24T18:05:14.402156600Z'/><EventRecordID>550</EventRecordID><Correlation/><Execution ProcessID='3324' ThreadID='3220'/><Channel>Microsoft-Windows-Sysmon/Operational</Channel><Computer>DESKTOP-FVSHABR</Computer><Security UserID='S-1-5-18'/></System><EventData><Data Name='RuleName'></Data><Data Name='UtcTime'>2019-07-24 18:05:14.399</Data><Data Name='ProcessGuid'>{87E8D3BD-9DDA-5D38-0000-0010A3941D00}</Data><Data Name='ProcessId'>5752</Data><Data Name='Image'>C:\Windows\System32\cmd.exe</Data><Data Name='FileVersion'>10.0.10240.16384 (th1.150709-1700)</Data><Data Name='Description'>Windows Command Processor</Data><Data Name='Product'>Microsoft Windows Operating System</Data><Data Name='Company'>Microsoft Corporation</Data><Data Name='OriginalFileName'>Cmd.Exe</Data><Data Name='CommandLine'>"cmd" /C "msiexec /quiet /i cmd.msi"</Data><Data Name='CurrentDirectory'>C:\Users\grapltest\Downloads\</Data><Data Name='User'>DESKTOP-FVSHABR\grapltest</Data><Data Name='LogonGuid'>{87E8D3BD-99C8-5D38-0000-002088140200}</Data><Data Name='LogonId'>0x21488</Data><Data Name='TerminalSessionId'>1</Data><Data Name='IntegrityLevel'>Medium</Data><Data Name='Hashes'>MD5=A6177D080759CF4A03EF837A38F62401,SHA256=79D1FFABDD7841D9043D4DDF1F93721BCD35D823614411FD4EAB5D2C16A86F35</Data><Data Name='ParentProcessGuid'>{87E8D3BD-9DD8-5D38-0000-00109F871D00}</Data><Data Name='ParentProcessId'>6132</Data><Data Name='ParentImage'>C:\Users\grapltest\Downloads\svchost.exe</Data><Data Name='ParentCommandLine'>.\svchost.exe</Data></EventData></Event>"#;
+
+fn find_process(nodes: &[&MergedNode], process_id: u64) -> MergedNode {
+    let process_id: NodeProperty = ImmutableUintProp { prop: process_id }.into();
+    nodes
+        .iter()
+        .find(|node| {
+            node.node_type == "Process" && node.properties.get("process_id") == Some(&process_id)
+        })
+        .map(|node| (*node).clone())
+        .expect("process missing")
+}
+
+#[tokio::test]
+async fn test_sysmon_process_creation_is_merged_and_dispatched(
+) -> Result<(), Box<dyn std::error::Error>> {
+    let harness = PipelineHarness::new(ReverseEdgeSchema::from_graphql(SCHEMA)?, &[ANALYZER_KEY])?;
+
+    let feed = async {
+        let dispatch = async {
+            harness
+                .send_raw_logs(PROCESS_CREATE.as_bytes().to_vec())
+                .await?;
+            harness.next_dispatch(Duration::from_secs(30)).await
+        }
+        .await;
+        harness.shutdown();
+        dispatch
+    };
+    let ((), dispatch) = tokio::join!(harness.run(), feed);
+    let dispatch = dispatch?.expect("nothing was dispatched");
+
+    assert!(harness.dead_letters().is_empty());
+    assert_eq!(dispatch.key, ANALYZER_KEY);
+
+    let nodes: Vec<_> = dispatch.subgraph.nodes.values().collect();
+    assert_eq!(nodes.len(), 4);
+    let parent = find_process(&nodes, 6132);
+    let child = find_process(&nodes, 5752);
+
+    // Every dispatched node was identified, and merged under the key it was dispatched with
+    for node in &nodes {
+        let stored = harness
+            .graph_store()
+            .get_node(&node.node_key)
+            .expect("dispatched node wasn't merged");
+        assert_eq!(stored.uid, node.uid);
+        assert_eq!(stored.node_type, node.node_type);
+    }
+
+    let graph_store = harness.graph_store();
+    assert_eq!(
+        graph_store.get_edges(&parent.node_key, "children"),
+        vec![child.node_key.clone()]
+    );
+    assert_eq!(
+        graph_store.get_edges(&child.node_key, "parent"),
+        vec![parent.node_key.clone()]
+    );
+    assert_eq!(graph_store.get_edges(&child.node_key, "bin_file").len(), 1);
+
+    let assets = graph_store.get_edges(&child.node_key, "process_asset");
+    assert_eq!(
+        assets,
+        graph_store.get_edges(&parent.node_key, "process_asset")
+    );
+    let asset = graph_store.get_node(&assets[0]).expect("asset missing");
+    assert_eq!(
+        graph_store
+            .get_edges(&asset.node_key, "asset_processes")
+            .len(),
+        2
+    );
+
+    Ok(())
+}
+
+fn edge_count(edges: &HashMap<String, EdgeList>) -> usize {
+    edges.values().map(|edge_list| edge_list.edges.len()).sum()
+}
+
+/// Each payload a generated graph is chunked into holds both ends of its edges, so none of
+/// them are lost when the payloads are identified separately
+#[tokio::test]
+async fn test_chunked_sysmon_graphs_keep_their_edges() -> Result<(), Box<dyn std::error::Error>> {
+    let events = SysmonDecoder::default().decode(PROCESS_CREATE.as_bytes().to_vec())?;
+    let graph = SysmonGenerator::new(NopCache {}, SysmonGeneratorMetrics::new("sysmon-generator"))
+        .handle_event(events, &mut CompletedEvents::default())
+        .await
+        .expect("generation failed");
+    let generated_edges = edge_count(&graph.edges);
+
+    let limits = ChunkLimits {
+        max_nodes: 2,
+        max_edges: 1,
+        ..Default::default()
+    };
+    let chunks = chunk(graph, &limits);
+    assert!(chunks.len() >= generated_edges);
+
+    let should_default = true;
+    let mut node_identifier = NodeIdentifier::new(
+        NodeDescriptionIdentifier::new(
+            InMemorySessionDb::new(),
+            should_default,
+            SkewTolerances::default(),
+            MetricReporter::new("node-identifier"),
+        ),
+        should_default,
+        NopCache {},
+    );
+    let mut identified_edges = 0;
+    for chunk in chunks {
+        let chunk_edges = edge_count(&chunk.edges);
+        let identified = node_identifier
+            .handle_event(chunk, &mut CompletedEvents::default())
+            .await
+            .expect("identification failed");
+        assert_eq!(edge_count(&identified.edges), chunk_edges);
+        identified_edges += edge_count(&identified.edges);
+    }
+    assert_eq!(identified_edges, generated_edges);
+
+    Ok(())
+}
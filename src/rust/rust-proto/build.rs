@@ -13,7 +13,39 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         "#[derive(serde_derive::Serialize, serde_derive::Deserialize)]",
     );
 
-    config = config.type_attribute(".graplinc.grapl.api.graph.v1beta1", "#[derive(Eq)]");
+    // Enums already derive Eq, and `ImmutableFloatProp` implements it by hand since f64 can't
+    for message in &[
+        "Session",
+        "Static",
+        "IdStrategy",
+        "IncrementOnlyUintProp",
+        "ImmutableUintProp",
+        "DecrementOnlyUintProp",
+        "IncrementOnlyIntProp",
+        "DecrementOnlyIntProp",
+        "ImmutableIntProp",
+        "ImmutableStrProp",
+        "ImmutableBoolProp",
+        "ImmutableBytesProp",
+        "TimestampProp",
+        "StrSetProp",
+        "NodeProperty",
+        "NodeDescription",
+        "IdentifiedNode",
+        "MergedNode",
+        "Edge",
+        "MergedEdge",
+        "EdgeList",
+        "MergedEdgeList",
+        "GraphDescription",
+        "IdentifiedGraph",
+        "MergedGraph",
+    ] {
+        config = config.type_attribute(
+            format!(".graplinc.grapl.api.graph.v1beta1.{}", message),
+            "#[derive(Eq)]",
+        );
+    }
 
     config = config.type_attribute(
         ".graplinc.grapl.api.graph.v1beta1.IncrementOnlyIntProp",
@@ -46,12 +78,18 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     );
 
     config = config.type_attribute(
-        ".graplinc.grapl.api.graph.v1beta1.MergedEdge",
-        "#[derive(Ord, PartialOrd)]",
+        ".graplinc.grapl.api.graph.v1beta1.ImmutableBoolProp",
+        "#[derive(Copy, Ord, PartialOrd)]",
+    );
+
+    config = config.type_attribute(
+        ".graplinc.grapl.api.graph.v1beta1.ImmutableFloatProp",
+        "#[derive(Copy, PartialOrd)]",
     );
+
     config = config.type_attribute(
-        ".graplinc.grapl.api.graph.v1beta1.Edge",
-        "#[derive(Ord, PartialOrd)]",
+        ".graplinc.grapl.api.graph.v1beta1.TimestampProp",
+        "#[derive(Copy)]",
     );
 
     config = config.type_attribute(
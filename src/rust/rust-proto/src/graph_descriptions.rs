@@ -1,12 +1,19 @@
+use std::collections::HashMap;
+
 pub use node_property::Property;
 use node_property::Property::{
     DecrementOnlyInt as ProtoDecrementOnlyIntProp,
     DecrementOnlyUint as ProtoDecrementOnlyUintProp,
+    ImmutableBool as ProtoImmutableBoolProp,
+    ImmutableBytes as ProtoImmutableBytesProp,
+    ImmutableFloat as ProtoImmutableFloatProp,
     ImmutableInt as ProtoImmutableIntProp,
     ImmutableStr as ProtoImmutableStrProp,
     ImmutableUint as ProtoImmutableUintProp,
     IncrementOnlyInt as ProtoIncrementOnlyIntProp,
     IncrementOnlyUint as ProtoIncrementOnlyUintProp,
+    StrSet as ProtoStrSetProp,
+    Timestamp as ProtoTimestampProp,
 };
 
 pub use crate::graplinc::grapl::api::graph::v1beta1::*;
@@ -42,11 +49,107 @@ macro_rules ! extra_assert {
     }
 }
 
+fn merge_properties(
+    properties: &mut HashMap<String, NodeProperty>,
+    other: &HashMap<String, NodeProperty>,
+) {
+    for (prop_name, prop_value) in other.iter() {
+        match properties.get_mut(prop_name) {
+            Some(self_prop) => self_prop.merge(prop_value),
+            None => {
+                properties.insert(prop_name.clone(), prop_value.clone());
+            }
+        }
+    }
+}
+
+impl Edge {
+    /// Edges are identified by their endpoints and name, regardless of their properties
+    pub fn is_same_edge(&self, other: &Self) -> bool {
+        self.edge_name == other.edge_name
+            && self.from_node_key == other.from_node_key
+            && self.to_node_key == other.to_node_key
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        extra_assert!(debug_assert!(self.is_same_edge(other)));
+        merge_properties(&mut self.properties, &other.properties);
+    }
+}
+
 impl EdgeList {
     pub fn into_vec(self) -> Vec<Edge> {
         let Self { edges } = self;
         edges
     }
+
+    /// Adds the edge, merging its properties into the last edge if that's the same edge.
+    /// Other duplicates are left for `merge_duplicates`, which serializers call once before
+    /// encoding, rather than searching the list on every add.
+    pub fn add_edge(&mut self, edge: Edge) {
+        match self.edges.last_mut() {
+            Some(last) if last.is_same_edge(&edge) => last.merge(&edge),
+            _ => self.edges.push(edge),
+        }
+    }
+
+    /// Sorts the edges and merges any duplicates
+    pub fn merge_duplicates(&mut self) {
+        self.edges.sort_unstable_by(|a, b| {
+            (&a.from_node_key, &a.to_node_key, &a.edge_name).cmp(&(
+                &b.from_node_key,
+                &b.to_node_key,
+                &b.edge_name,
+            ))
+        });
+        self.edges.dedup_by(|edge, existing| {
+            let duplicate = existing.is_same_edge(edge);
+            if duplicate {
+                existing.merge(edge);
+            }
+            duplicate
+        });
+    }
+}
+
+impl MergedEdge {
+    /// Edges are identified by their endpoints and name, regardless of their properties
+    pub fn is_same_edge(&self, other: &Self) -> bool {
+        self.edge_name == other.edge_name
+            && self.from_uid == other.from_uid
+            && self.to_uid == other.to_uid
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        extra_assert!(debug_assert!(self.is_same_edge(other)));
+        merge_properties(&mut self.properties, &other.properties);
+    }
+}
+
+impl MergedEdgeList {
+    /// Adds the edge, merging its properties into the last edge if that's the same edge.
+    /// Other duplicates are left for `merge_duplicates`, which serializers call once before
+    /// encoding, rather than searching the list on every add.
+    pub fn add_edge(&mut self, edge: MergedEdge) {
+        match self.edges.last_mut() {
+            Some(last) if last.is_same_edge(&edge) => last.merge(&edge),
+            _ => self.edges.push(edge),
+        }
+    }
+
+    /// Sorts the edges and merges any duplicates
+    pub fn merge_duplicates(&mut self) {
+        self.edges.sort_unstable_by(|a, b| {
+            (&a.from_uid, &a.to_uid, &a.edge_name).cmp(&(&b.from_uid, &b.to_uid, &b.edge_name))
+        });
+        self.edges.dedup_by(|edge, existing| {
+            let duplicate = existing.is_same_edge(edge);
+            if duplicate {
+                existing.merge(edge);
+            }
+            duplicate
+        });
+    }
 }
 
 impl ServiceMessage for GraphDescription {
@@ -76,6 +179,19 @@ impl GraphDescription {
         edge_name: impl Into<String>,
         from_node_key: impl Into<String>,
         to_node_key: impl Into<String>,
+    ) {
+        self.add_edge_with_properties(edge_name, from_node_key, to_node_key, HashMap::new());
+    }
+
+    /// Adds an edge along with properties describing the relationship itself, such as when
+    /// it was first observed. Adding an edge that already exists merges the properties, once
+    /// the edge list's duplicates are merged.
+    pub fn add_edge_with_properties(
+        &mut self,
+        edge_name: impl Into<String>,
+        from_node_key: impl Into<String>,
+        to_node_key: impl Into<String>,
+        properties: HashMap<String, NodeProperty>,
     ) {
         let from_node_key = from_node_key.into();
         let to_node_key = to_node_key.into();
@@ -87,16 +203,15 @@ impl GraphDescription {
             from_node_key: from_node_key.clone(),
             to_node_key,
             edge_name,
+            properties,
         };
 
-        let edge_list: &mut Vec<Edge> = &mut self
-            .edges
+        self.edges
             .entry(from_node_key)
             .or_insert_with(|| EdgeList {
                 edges: Vec::with_capacity(1),
             })
-            .edges;
-        edge_list.push(edge);
+            .add_edge(edge);
     }
 
     pub fn merge(&mut self, other: &Self) {
@@ -111,10 +226,11 @@ impl GraphDescription {
 
         for edge_list in other.edges.values() {
             for edge in edge_list.edges.iter() {
-                self.add_edge(
+                self.add_edge_with_properties(
                     edge.edge_name.clone(),
                     edge.from_node_key.clone(),
                     edge.to_node_key.clone(),
+                    edge.properties.clone(),
                 );
             }
         }
@@ -147,26 +263,39 @@ impl IdentifiedGraph {
         edge_name: impl Into<String>,
         from_node_key: impl Into<String>,
         to_node_key: impl Into<String>,
+    ) {
+        self.add_edge_with_properties(edge_name, from_node_key, to_node_key, HashMap::new());
+    }
+
+    /// Adds an edge along with properties describing the relationship itself, such as when
+    /// it was first observed. Adding an edge that already exists merges the properties, once
+    /// the edge list's duplicates are merged.
+    pub fn add_edge_with_properties(
+        &mut self,
+        edge_name: impl Into<String>,
+        from_node_key: impl Into<String>,
+        to_node_key: impl Into<String>,
+        properties: HashMap<String, NodeProperty>,
     ) {
         let from_node_key = from_node_key.into();
         let to_node_key = to_node_key.into();
+        let edge_name = edge_name.into();
+
         assert_ne!(from_node_key, to_node_key);
 
-        let edge_name = edge_name.into();
         let edge = Edge {
             from_node_key: from_node_key.clone(),
             to_node_key,
             edge_name,
+            properties,
         };
 
-        let edge_list: &mut Vec<Edge> = &mut self
-            .edges
+        self.edges
             .entry(from_node_key)
             .or_insert_with(|| EdgeList {
                 edges: Vec::with_capacity(1),
             })
-            .edges;
-        edge_list.push(edge);
+            .add_edge(edge);
     }
 
     pub fn merge(&mut self, other: &Self) {
@@ -181,10 +310,11 @@ impl IdentifiedGraph {
 
         for edge_list in other.edges.values() {
             for edge in edge_list.edges.iter() {
-                self.add_edge(
+                self.add_edge_with_properties(
                     edge.edge_name.clone(),
                     edge.from_node_key.clone(),
                     edge.to_node_key.clone(),
+                    edge.properties.clone(),
                 );
             }
         }
@@ -217,15 +347,12 @@ impl MergedGraph {
     }
 
     pub fn add_merged_edge(&mut self, edge: MergedEdge) {
-        let from_node_key = edge.from_node_key.clone();
-        let edge_list: &mut Vec<MergedEdge> = &mut self
-            .edges
-            .entry(from_node_key)
+        self.edges
+            .entry(edge.from_node_key.clone())
             .or_insert_with(|| MergedEdgeList {
                 edges: Vec::with_capacity(1),
             })
-            .edges;
-        edge_list.push(edge);
+            .add_edge(edge);
     }
 
     pub fn add_edge(
@@ -235,6 +362,28 @@ impl MergedGraph {
         from_uid: impl Into<String>,
         to_node_key: impl Into<String>,
         to_uid: impl Into<String>,
+    ) {
+        self.add_edge_with_properties(
+            edge_name,
+            from_node_key,
+            from_uid,
+            to_node_key,
+            to_uid,
+            HashMap::new(),
+        );
+    }
+
+    /// Adds an edge along with properties describing the relationship itself. Adding an
+    /// edge that already exists merges the properties, once the edge list's duplicates are
+    /// merged.
+    pub fn add_edge_with_properties(
+        &mut self,
+        edge_name: impl Into<String>,
+        from_node_key: impl Into<String>,
+        from_uid: impl Into<String>,
+        to_node_key: impl Into<String>,
+        to_uid: impl Into<String>,
+        properties: HashMap<String, NodeProperty>,
     ) {
         let edge_name = edge_name.into();
         let from_node_key = from_node_key.into();
@@ -244,21 +393,15 @@ impl MergedGraph {
         assert_ne!(from_node_key, to_node_key);
         assert_ne!(from_uid, to_uid);
         let edge = MergedEdge {
-            from_node_key: from_node_key.clone(),
+            from_node_key,
             from_uid,
             to_node_key,
             to_uid,
             edge_name,
+            properties,
         };
 
-        let edge_list: &mut Vec<MergedEdge> = &mut self
-            .edges
-            .entry(from_node_key)
-            .or_insert_with(|| MergedEdgeList {
-                edges: Vec::with_capacity(1),
-            })
-            .edges;
-        edge_list.push(edge);
+        self.add_merged_edge(edge);
     }
 
     pub fn merge(&mut self, other: &Self) {
@@ -273,12 +416,13 @@ impl MergedGraph {
 
         for edge_list in other.edges.values() {
             for edge in edge_list.edges.iter() {
-                self.add_edge(
+                self.add_edge_with_properties(
                     edge.edge_name.clone(),
                     edge.from_node_key.clone(),
                     edge.from_uid.clone(),
                     edge.to_node_key.clone(),
                     edge.to_uid.clone(),
+                    edge.properties.clone(),
                 );
             }
         }
@@ -293,15 +437,7 @@ impl NodeDescription {
     pub fn merge(&mut self, other: &Self) {
         extra_assert!(debug_assert_eq!(self.node_type, other.node_type));
         extra_assert!(debug_assert_eq!(self.node_key, other.node_key));
-        for (prop_name, prop_value) in other.properties.iter() {
-            match self.properties.get_mut(prop_name) {
-                Some(self_prop) => self_prop.merge(prop_value),
-                None => {
-                    self.properties
-                        .insert(prop_name.clone(), prop_value.clone());
-                }
-            }
-        }
+        merge_properties(&mut self.properties, &other.properties);
     }
     pub fn get_node_key(&self) -> &str {
         self.node_key.as_str()
@@ -320,15 +456,7 @@ impl IdentifiedNode {
     pub fn merge(&mut self, other: &Self) {
         extra_assert!(debug_assert_eq!(self.node_type, other.node_type));
         extra_assert!(debug_assert_eq!(self.node_key, other.node_key));
-        for (prop_name, prop_value) in other.properties.iter() {
-            match self.properties.get_mut(prop_name) {
-                Some(self_prop) => self_prop.merge(prop_value),
-                None => {
-                    self.properties
-                        .insert(prop_name.clone(), prop_value.clone());
-                }
-            }
-        }
+        merge_properties(&mut self.properties, &other.properties);
     }
 
     pub fn get_cache_identities_for_predicates(&self) -> Vec<Vec<u8>> {
@@ -401,6 +529,23 @@ impl Property {
             (ProtoImmutableStrProp(ref mut self_prop), ProtoImmutableStrProp(ref other_prop)) => {
                 self_prop.merge_property(other_prop)
             }
+            (ProtoImmutableBoolProp(ref mut self_prop), ProtoImmutableBoolProp(ref other_prop)) => {
+                self_prop.merge_property(other_prop)
+            }
+            (
+                ProtoImmutableFloatProp(ref mut self_prop),
+                ProtoImmutableFloatProp(ref other_prop),
+            ) => self_prop.merge_property(other_prop),
+            (
+                ProtoImmutableBytesProp(ref mut self_prop),
+                ProtoImmutableBytesProp(ref other_prop),
+            ) => self_prop.merge_property(other_prop),
+            (ProtoTimestampProp(ref mut self_prop), ProtoTimestampProp(ref other_prop)) => {
+                self_prop.merge_property(other_prop)
+            }
+            (ProtoStrSetProp(ref mut self_prop), ProtoStrSetProp(ref other_prop)) => {
+                self_prop.merge_property(other_prop)
+            }
             // technically we could improve type safety here by exhausting the combinations,
             // but I'm not going to type that all out right now
             (p, op) => {
@@ -432,6 +577,24 @@ impl From<Session> for IdStrategy {
         }
     }
 }
+
+/// Milliseconds between the Windows FILETIME epoch (1601-01-01) and the unix epoch
+const FILETIME_UNIX_EPOCH_OFFSET_MS: u64 = 11_644_473_600_000;
+
+impl TimeUnit {
+    /// Converts a timestamp in this unit into milliseconds since the unix epoch.
+    /// `Unspecified` timestamps are assumed to already be in milliseconds.
+    pub fn to_millis(self, timestamp: u64) -> u64 {
+        match self {
+            TimeUnit::Unspecified | TimeUnit::Milliseconds => timestamp,
+            TimeUnit::Seconds => timestamp.saturating_mul(1000),
+            TimeUnit::WindowsFiletime => {
+                (timestamp / 10_000).saturating_sub(FILETIME_UNIX_EPOCH_OFFSET_MS)
+            }
+        }
+    }
+}
+
 impl std::string::ToString for Property {
     fn to_string(&self) -> String {
         match self {
@@ -450,6 +613,11 @@ impl std::string::ToString for Property {
             }
             ProtoImmutableIntProp(immutable_int_prop) => immutable_int_prop.to_string(),
             ProtoImmutableStrProp(immutable_str_prop) => immutable_str_prop.to_string(),
+            ProtoImmutableBoolProp(immutable_bool_prop) => immutable_bool_prop.to_string(),
+            ProtoImmutableFloatProp(immutable_float_prop) => immutable_float_prop.to_string(),
+            ProtoImmutableBytesProp(immutable_bytes_prop) => immutable_bytes_prop.to_string(),
+            ProtoTimestampProp(timestamp_prop) => timestamp_prop.to_string(),
+            ProtoStrSetProp(str_set_prop) => str_set_prop.to_string(),
         }
     }
 }
@@ -517,6 +685,101 @@ impl ImmutableStrProp {
         extra_assert!(debug_assert_eq!(*self, *other_prop));
     }
 }
+impl ImmutableBoolProp {
+    pub fn as_inner(&self) -> bool {
+        self.prop
+    }
+
+    pub fn merge_property(&mut self, other_prop: &Self) {
+        tracing::trace!(message="ImmutableBoolProp merge", self_prop=?self, other_prop=?other_prop);
+        extra_assert!(debug_assert_eq!(*self, *other_prop));
+    }
+}
+
+// f64 isn't Eq because NaN != NaN. Generators never produce NaN properties, and since the
+// value is immutable it's only ever compared to decide whether two descriptions are identical.
+impl Eq for ImmutableFloatProp {}
+
+impl ImmutableFloatProp {
+    pub fn as_inner(&self) -> f64 {
+        self.prop
+    }
+
+    pub fn merge_property(&mut self, other_prop: &Self) {
+        tracing::trace!(message="ImmutableFloatProp merge", self_prop=?self, other_prop=?other_prop);
+        extra_assert!(debug_assert_eq!(*self, *other_prop));
+    }
+}
+impl ImmutableBytesProp {
+    pub fn as_inner(&self) -> &[u8] {
+        self.prop.as_slice()
+    }
+
+    pub fn merge_property(&mut self, other_prop: &Self) {
+        tracing::trace!(message="ImmutableBytesProp merge", self_prop=?self, other_prop=?other_prop);
+        extra_assert!(debug_assert_eq!(*self, *other_prop));
+    }
+}
+impl TimestampProp {
+    /// A timestamp that keeps the earliest value it's merged with, such as a 'first seen' time
+    pub fn earliest(millis: u64) -> Self {
+        Self {
+            millis,
+            merge: TimestampMerge::Earliest as i32,
+        }
+    }
+
+    /// A timestamp that keeps the latest value it's merged with, such as a 'last seen' time
+    pub fn latest(millis: u64) -> Self {
+        Self {
+            millis,
+            merge: TimestampMerge::Latest as i32,
+        }
+    }
+
+    /// A timestamp that keeps its first value, such as a creation time
+    pub fn immutable(millis: u64) -> Self {
+        Self {
+            millis,
+            merge: TimestampMerge::Unspecified as i32,
+        }
+    }
+
+    pub fn as_inner(&self) -> u64 {
+        self.millis
+    }
+
+    pub fn merge_property(&mut self, other_prop: &Self) {
+        tracing::trace!(message="TimestampProp merge", self_prop=?self, other_prop=?other_prop);
+        extra_assert!(debug_assert_eq!(self.merge, other_prop.merge));
+        match self.merge() {
+            TimestampMerge::Earliest => self.millis = std::cmp::min(self.millis, other_prop.millis),
+            TimestampMerge::Latest => self.millis = std::cmp::max(self.millis, other_prop.millis),
+            TimestampMerge::Unspecified => {
+                extra_assert!(debug_assert_eq!(self.millis, other_prop.millis));
+            }
+        }
+    }
+}
+impl StrSetProp {
+    pub fn new<S: Into<String>>(props: impl IntoIterator<Item = S>) -> Self {
+        let mut props: Vec<String> = props.into_iter().map(Into::into).collect();
+        props.sort_unstable();
+        props.dedup();
+        Self { props }
+    }
+
+    pub fn as_inner(&self) -> &[String] {
+        self.props.as_slice()
+    }
+
+    pub fn merge_property(&mut self, other_prop: &Self) {
+        tracing::trace!(message="StrSetProp merge", self_prop=?self, other_prop=?other_prop);
+        self.props.extend(other_prop.props.iter().cloned());
+        self.props.sort_unstable();
+        self.props.dedup();
+    }
+}
 
 impl std::string::ToString for IncrementOnlyUintProp {
     fn to_string(&self) -> String {
@@ -560,6 +823,36 @@ impl std::string::ToString for ImmutableStrProp {
     }
 }
 
+impl std::string::ToString for ImmutableBoolProp {
+    fn to_string(&self) -> String {
+        self.prop.to_string()
+    }
+}
+
+impl std::string::ToString for ImmutableFloatProp {
+    fn to_string(&self) -> String {
+        self.prop.to_string()
+    }
+}
+
+impl std::string::ToString for ImmutableBytesProp {
+    fn to_string(&self) -> String {
+        self.prop.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+impl std::string::ToString for TimestampProp {
+    fn to_string(&self) -> String {
+        self.millis.to_string()
+    }
+}
+
+impl std::string::ToString for StrSetProp {
+    fn to_string(&self) -> String {
+        format!("{:?}", self.props)
+    }
+}
+
 impl std::string::ToString for NodeProperty {
     fn to_string(&self) -> String {
         match &self.property {
@@ -584,6 +877,17 @@ impl std::string::ToString for NodeProperty {
             Some(node_property::Property::ImmutableStr(immutable_str_prop)) => {
                 immutable_str_prop.to_string()
             }
+            Some(node_property::Property::ImmutableBool(immutable_bool_prop)) => {
+                immutable_bool_prop.to_string()
+            }
+            Some(node_property::Property::ImmutableFloat(immutable_float_prop)) => {
+                immutable_float_prop.to_string()
+            }
+            Some(node_property::Property::ImmutableBytes(immutable_bytes_prop)) => {
+                immutable_bytes_prop.to_string()
+            }
+            Some(node_property::Property::Timestamp(timestamp_prop)) => timestamp_prop.to_string(),
+            Some(node_property::Property::StrSet(str_set_prop)) => str_set_prop.to_string(),
             None => panic!("Invalid property : {:?}", self),
         }
     }
@@ -637,15 +941,7 @@ impl MergedNode {
     pub fn merge(&mut self, other: &Self) {
         extra_assert!(debug_assert_eq!(self.node_type, other.node_type));
         extra_assert!(debug_assert_eq!(self.node_key, other.node_key));
-        for (prop_name, prop_value) in other.properties.iter() {
-            match self.properties.get_mut(prop_name) {
-                Some(self_prop) => self_prop.merge(prop_value),
-                None => {
-                    self.properties
-                        .insert(prop_name.clone(), prop_value.clone());
-                }
-            }
-        }
+        merge_properties(&mut self.properties, &other.properties);
     }
 
     pub fn get_node_key(&self) -> &str {
@@ -750,6 +1046,15 @@ impl_from_for_unit!(
     &i8
 );
 impl_from_for_unit!(ImmutableStrProp, prop, String, &String, &str);
+impl_from_for_unit!(ImmutableBoolProp, prop, bool, &bool);
+impl_from_for_unit!(ImmutableFloatProp, prop, f64, f32, &f64, &f32);
+impl_from_for_unit!(ImmutableBytesProp, prop, Vec<u8>, &Vec<u8>, &[u8]);
+
+impl From<Vec<String>> for StrSetProp {
+    fn from(props: Vec<String>) -> Self {
+        Self::new(props)
+    }
+}
 
 impl From<ImmutableUintProp> for Property {
     fn from(p: ImmutableUintProp) -> Self {
@@ -786,6 +1091,31 @@ impl From<ImmutableStrProp> for Property {
         Self::ImmutableStr(p)
     }
 }
+impl From<ImmutableBoolProp> for Property {
+    fn from(p: ImmutableBoolProp) -> Self {
+        Self::ImmutableBool(p)
+    }
+}
+impl From<ImmutableFloatProp> for Property {
+    fn from(p: ImmutableFloatProp) -> Self {
+        Self::ImmutableFloat(p)
+    }
+}
+impl From<ImmutableBytesProp> for Property {
+    fn from(p: ImmutableBytesProp) -> Self {
+        Self::ImmutableBytes(p)
+    }
+}
+impl From<TimestampProp> for Property {
+    fn from(p: TimestampProp) -> Self {
+        Self::Timestamp(p)
+    }
+}
+impl From<StrSetProp> for Property {
+    fn from(p: StrSetProp) -> Self {
+        Self::StrSet(p)
+    }
+}
 
 impl NodeProperty {
     pub fn as_increment_only_uint(&self) -> Option<IncrementOnlyUintProp> {
@@ -836,6 +1166,41 @@ impl NodeProperty {
             _ => None,
         }
     }
+
+    pub fn as_immutable_bool(&self) -> Option<ImmutableBoolProp> {
+        match self.property {
+            Some(ProtoImmutableBoolProp(ref prop)) => Some(*prop),
+            _ => None,
+        }
+    }
+
+    pub fn as_immutable_float(&self) -> Option<ImmutableFloatProp> {
+        match self.property {
+            Some(ProtoImmutableFloatProp(ref prop)) => Some(*prop),
+            _ => None,
+        }
+    }
+
+    pub fn as_immutable_bytes(&self) -> Option<&ImmutableBytesProp> {
+        match self.property {
+            Some(ProtoImmutableBytesProp(ref prop)) => Some(prop),
+            _ => None,
+        }
+    }
+
+    pub fn as_timestamp(&self) -> Option<TimestampProp> {
+        match self.property {
+            Some(ProtoTimestampProp(ref prop)) => Some(*prop),
+            _ => None,
+        }
+    }
+
+    pub fn as_str_set(&self) -> Option<&StrSetProp> {
+        match self.property {
+            Some(ProtoStrSetProp(ref prop)) => Some(prop),
+            _ => None,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -1075,6 +1440,47 @@ pub mod test {
         assert_eq!(first_x, max_value);
     }
 
+    #[quickcheck]
+    fn test_merge_timestamp_earliest(x: u64, y: u64) {
+        init_test_env();
+        let mut timestamp = TimestampProp::earliest(x);
+        timestamp.merge_property(&TimestampProp::earliest(y));
+        assert_eq!(timestamp, TimestampProp::earliest(std::cmp::min(x, y)));
+    }
+
+    #[quickcheck]
+    fn test_merge_timestamp_latest(x: u64, y: u64) {
+        init_test_env();
+        let mut timestamp = TimestampProp::latest(x);
+        timestamp.merge_property(&TimestampProp::latest(y));
+        assert_eq!(timestamp, TimestampProp::latest(std::cmp::max(x, y)));
+    }
+
+    #[quickcheck]
+    fn test_merge_str_set_union(x: Vec<String>, y: Vec<String>) {
+        init_test_env();
+        let mut x_then_y = StrSetProp::new(x.clone());
+        x_then_y.merge_property(&StrSetProp::new(y.clone()));
+        let mut y_then_x = StrSetProp::new(y.clone());
+        y_then_x.merge_property(&StrSetProp::new(x.clone()));
+
+        assert_eq!(x_then_y, y_then_x);
+        assert_eq!(x_then_y, StrSetProp::new(x.into_iter().chain(y)));
+    }
+
+    #[test]
+    fn test_time_unit_to_millis() {
+        // 2021-01-01T00:00:00Z
+        let expected_ms = 1_609_459_200_000;
+        assert_eq!(TimeUnit::Unspecified.to_millis(expected_ms), expected_ms);
+        assert_eq!(TimeUnit::Milliseconds.to_millis(expected_ms), expected_ms);
+        assert_eq!(TimeUnit::Seconds.to_millis(1_609_459_200), expected_ms);
+        assert_eq!(
+            TimeUnit::WindowsFiletime.to_millis(132_539_328_000_000_000),
+            expected_ms
+        );
+    }
+
     #[quickcheck]
     fn test_merge_identified_node(mut node_0: IdentifiedNode, node_1: IdentifiedNode) {
         if node_0.node_key != node_1.node_key {
@@ -1087,4 +1493,78 @@ pub mod test {
         //     let mut copy = o_pred_val.clone();
         // }
     }
+
+    #[quickcheck]
+    fn test_add_edge_merges_duplicate_properties(
+        first_seen: Vec<DecrementOnlyUintProp>,
+        last_seen: Vec<IncrementOnlyUintProp>,
+    ) {
+        init_test_env();
+        let mut graph = GraphDescription::new();
+        for (first_seen, last_seen) in first_seen.iter().zip(last_seen.iter()) {
+            let mut properties = HashMap::new();
+            properties.insert("first_seen".to_owned(), first_seen.clone().into());
+            properties.insert("last_seen".to_owned(), last_seen.clone().into());
+            graph.add_edge_with_properties("children", "parent", "child", properties);
+        }
+        let len = std::cmp::min(first_seen.len(), last_seen.len());
+        if len == 0 {
+            return;
+        }
+
+        let edges = &graph.edges["parent"].edges;
+        assert_eq!(edges.len(), 1);
+        assert_eq!(
+            edges[0].properties["first_seen"].as_decrement_only_uint(),
+            first_seen[..len].iter().min().cloned()
+        );
+        assert_eq!(
+            edges[0].properties["last_seen"].as_increment_only_uint(),
+            last_seen[..len].iter().max().cloned()
+        );
+    }
+
+    #[test]
+    fn test_merge_duplicate_edges() {
+        let edge = |to_node_key: &str, last_seen: u64| {
+            let mut properties = HashMap::new();
+            properties.insert(
+                "last_seen".to_owned(),
+                IncrementOnlyUintProp { prop: last_seen }.into(),
+            );
+            Edge {
+                from_node_key: "parent".to_owned(),
+                to_node_key: to_node_key.to_owned(),
+                edge_name: "children".to_owned(),
+                properties,
+            }
+        };
+        let mut edge_list = EdgeList {
+            edges: vec![edge("b", 1), edge("a", 5), edge("b", 3), edge("a", 2)],
+        };
+        edge_list.merge_duplicates();
+
+        assert_eq!(edge_list.edges, vec![edge("a", 5), edge("b", 3)]);
+    }
+
+    #[test]
+    fn test_merged_graphs_keep_duplicate_edges_until_merged() {
+        let mut graph = GraphDescription::new();
+        graph.add_edge("children", "parent", "a");
+        graph.add_edge("children", "parent", "b");
+
+        let mut other = GraphDescription::new();
+        other.add_edge("children", "parent", "a");
+        graph.merge(&other);
+        assert_eq!(graph.edges["parent"].edges.len(), 3);
+
+        let edge_list = graph.edges.get_mut("parent").unwrap();
+        edge_list.merge_duplicates();
+        let to_node_keys: Vec<_> = edge_list
+            .edges
+            .iter()
+            .map(|edge| edge.to_node_key.as_str())
+            .collect();
+        assert_eq!(to_node_keys, vec!["a", "b"]);
+    }
 }
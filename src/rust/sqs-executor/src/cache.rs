@@ -1,31 +1,92 @@
-use std::{
-    collections::hash_map::DefaultHasher,
-    hash::{
-        Hash,
-        Hasher,
-    },
-};
+use std::hash::Hasher;
 
 use async_trait::async_trait;
+use siphasher::sip128::{
+    Hasher128,
+    SipHasher13,
+};
 
 use crate::errors::{
     CheckedError,
     Recoverable,
 };
 
+/// Prefixed to every identity, so that a change to how identities are derived
+/// can be rolled out by bumping the version rather than colliding with, or
+/// silently missing, entries written by older executors.
+pub const CACHE_IDENTITY_VERSION: &[u8] = b"v2:";
+
+/// Fixed SipHash keys. Identities are shared across processes and deploys
+/// through Redis, so they must never be randomized.
+const CACHE_IDENTITY_KEYS: (u64, u64) = (0, 0);
+
 pub trait Cacheable {
-    fn identity(&self) -> Vec<u8>;
+    /// Appends the value's canonical encoding to `bytes`. Its identity is derived from nothing
+    /// else, so the encoding must not change between builds, Rust releases or architectures,
+    /// as a `std::hash::Hash` implementation may.
+    fn write_canonical_bytes(&self, bytes: &mut Vec<u8>);
+
+    /// The 128bit SipHash-1-3 of the value's canonical encoding, prefixed by
+    /// `CACHE_IDENTITY_VERSION`
+    fn identity(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        self.write_canonical_bytes(&mut bytes);
+
+        let (key0, key1) = CACHE_IDENTITY_KEYS;
+        let mut hasher = SipHasher13::new_with_keys(key0, key1);
+        hasher.write(&bytes);
+        let hash: u128 = hasher.finish128().into();
+
+        let mut identity = Vec::with_capacity(CACHE_IDENTITY_VERSION.len() + 16);
+        identity.extend_from_slice(CACHE_IDENTITY_VERSION);
+        identity.extend_from_slice(&hash.to_le_bytes());
+        identity
+    }
 }
 
-impl<H> Cacheable for H
+/// Appends `field` prefixed by its length, so that a value encoded as several fields has
+/// exactly one encoding
+pub fn write_canonical_field(bytes: &mut Vec<u8>, field: &[u8]) {
+    bytes.extend_from_slice(&(field.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(field);
+}
+
+impl<T> Cacheable for &T
 where
-    H: Hash,
+    T: Cacheable + ?Sized,
 {
-    fn identity(&self) -> Vec<u8> {
-        let mut hasher = DefaultHasher::new();
-        self.hash(&mut hasher);
-        let hash = hasher.finish();
-        hash.to_le_bytes().to_vec()
+    fn write_canonical_bytes(&self, bytes: &mut Vec<u8>) {
+        (**self).write_canonical_bytes(bytes)
+    }
+}
+
+impl Cacheable for str {
+    fn write_canonical_bytes(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(self.as_bytes())
+    }
+}
+
+impl Cacheable for String {
+    fn write_canonical_bytes(&self, bytes: &mut Vec<u8>) {
+        self.as_str().write_canonical_bytes(bytes)
+    }
+}
+
+impl Cacheable for [u8] {
+    fn write_canonical_bytes(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(self)
+    }
+}
+
+impl Cacheable for Vec<u8> {
+    fn write_canonical_bytes(&self, bytes: &mut Vec<u8>) {
+        self.as_slice().write_canonical_bytes(bytes)
+    }
+}
+
+impl Cacheable for u64 {
+    fn write_canonical_bytes(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.to_le_bytes())
     }
 }
 
@@ -59,6 +120,29 @@ pub trait Cache: Clone + Send + Sync {
     async fn filter_cached<CA>(&mut self, cacheables: &[CA]) -> Vec<CA>
     where
         CA: Cacheable + Send + Sync + Clone + 'static;
+
+    /// Records that processing `cacheable` failed, returning how many times it has failed so far.
+    /// Caches that don't track failures always return 0.
+    async fn record_failure<CA>(&mut self, _cacheable: CA) -> Result<u32, Self::CacheErrorT>
+    where
+        CA: Cacheable + Send + Sync + Clone + 'static,
+    {
+        Ok(0)
+    }
+
+    /// Returns true if `cacheable` has failed often enough that it should no longer be retried.
+    async fn failed_too_often<CA>(&mut self, _cacheable: &CA) -> bool
+    where
+        CA: Cacheable + Send + Sync + Clone + 'static,
+    {
+        false
+    }
+
+    /// Waits for any writes the cache has buffered to be made, such as when shutting down.
+    /// Caches that write before `store` returns have nothing to do.
+    async fn flush(&mut self) -> Result<(), Self::CacheErrorT> {
+        Ok(())
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -101,4 +185,59 @@ impl Cache for NopCache {
     {
         cacheables.to_vec()
     }
+
+    async fn flush(&mut self) -> Result<(), Self::CacheErrorT> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // These values are written to Redis and shared between deployed executors.
+    // If this test fails, the identity derivation has changed, and
+    // CACHE_IDENTITY_VERSION must be bumped along with the pinned values.
+    #[test]
+    fn test_identities_are_pinned() {
+        assert_eq!(
+            hex::encode("grapl".identity()),
+            "76323a3ee87f93a155015103f2de4fa112dd4f"
+        );
+        assert_eq!(
+            hex::encode(42u64.identity()),
+            "76323abd2c0b84d2b9a938af3b0c2a8000e3d3"
+        );
+        assert_eq!(
+            hex::encode(b"message-id".to_vec().identity()),
+            "76323a9461923018b25a47d6cad0a666e36d9f"
+        );
+        // Byte strings and strings with the same bytes share an identity
+        assert_eq!(b"message-id"[..].identity(), "message-id".identity());
+    }
+
+    struct Fields(&'static str, &'static str);
+
+    impl Cacheable for Fields {
+        fn write_canonical_bytes(&self, bytes: &mut Vec<u8>) {
+            write_canonical_field(bytes, self.0.as_bytes());
+            write_canonical_field(bytes, self.1.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_field_encodings_are_unambiguous() {
+        assert_eq!(
+            hex::encode(Fields("a", "bc").identity()),
+            "76323af3d172202c55ec66c75e6f0dae530267"
+        );
+        assert_ne!(Fields("a", "bc").identity(), Fields("ab", "c").identity());
+    }
+
+    #[test]
+    fn test_identity_is_version_prefixed() {
+        let identity = "grapl".identity();
+        assert!(identity.starts_with(CACHE_IDENTITY_VERSION));
+        assert_eq!(identity.len(), CACHE_IDENTITY_VERSION.len() + 16);
+    }
 }
@@ -0,0 +1,307 @@
+//! In-memory implementations of the executor's transports, for chaining stages within one
+//! process, such as in tests.
+//!
+//! An emitter and a message source are created as a pair by `channel`, with everything the
+//! emitter emits being received by the source. Payloads are carried in the messages
+//! themselves, so the `ChannelPayloadStore` only has to hand them back.
+
+use std::{
+    sync::{
+        Arc,
+        Mutex,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use rust_proto::pipeline::Metadata;
+use tokio::sync::mpsc::{
+    self,
+    UnboundedReceiver,
+    UnboundedSender,
+};
+
+use crate::{
+    errors::{
+        CheckedError,
+        Recoverable,
+    },
+    event_emitter::Emitter,
+    failure_metadata::FailureMetadata,
+    message_source::MessageSource,
+    payload_store::PayloadStore,
+    retriever::event_retriever::replace_metadata,
+    store_retriever::PayloadStoreRetriever,
+};
+
+/// How long to wait for the first message of a batch. Shorter than an SQS long poll, since
+/// nothing is paid per receive.
+const RECEIVE_WAIT: Duration = Duration::from_millis(100);
+
+#[derive(thiserror::Error, Debug)]
+pub enum ChannelError {
+    #[error("Channel closed")]
+    Closed,
+    #[error("EnvelopeDecode error")]
+    EnvelopeDecode(#[from] prost::DecodeError),
+}
+
+impl CheckedError for ChannelError {
+    fn error_type(&self) -> Recoverable {
+        match self {
+            // Nothing is left to receive what's sent
+            Self::Closed => Recoverable::Persistent,
+            Self::EnvelopeDecode(_) => Recoverable::Persistent,
+        }
+    }
+}
+
+/// A payload sent over a channel
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChannelMessage {
+    pub id: String,
+    pub payload: Vec<u8>,
+}
+
+impl ChannelMessage {
+    fn new(payload: Vec<u8>) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            payload,
+        }
+    }
+}
+
+/// Creates a connected emitter and message source
+pub fn channel() -> (ChannelEmitter, ChannelMessageSource) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let source = ChannelMessageSource {
+        sender: sender.clone(),
+        receiver: Arc::new(tokio::sync::Mutex::new(receiver)),
+        batch_size: 10,
+        dead_letters: Arc::new(Mutex::new(Vec::new())),
+    };
+    (ChannelEmitter { sender }, source)
+}
+
+/// Receives what the paired `ChannelEmitter` emits.
+///
+/// A message is removed from the channel when it's received, so acking it does nothing.
+/// Retrying or releasing a message sends it again, and dead-lettering it keeps it, along with
+/// its `FailureMetadata`, to be inspected with `dead_letters`. Clones receive from the same
+/// channel.
+#[derive(Clone)]
+pub struct ChannelMessageSource {
+    /// For handing messages back
+    sender: UnboundedSender<ChannelMessage>,
+    receiver: Arc<tokio::sync::Mutex<UnboundedReceiver<ChannelMessage>>>,
+    batch_size: usize,
+    dead_letters: Arc<Mutex<Vec<(ChannelMessage, FailureMetadata)>>>,
+}
+
+impl ChannelMessageSource {
+    /// Every message dead-lettered so far, in the order they were dead-lettered
+    pub fn dead_letters(&self) -> Vec<(ChannelMessage, FailureMetadata)> {
+        self.dead_letters.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl MessageSource for ChannelMessageSource {
+    type Message = ChannelMessage;
+    // A received message is no longer in the channel, so it can't be redelivered while it's
+    // being processed
+    type KeepAlive = ();
+    type Error = ChannelError;
+
+    async fn receive_messages(&mut self) -> Result<Vec<Self::Message>, Self::Error> {
+        let mut receiver = self.receiver.lock().await;
+        let first = match tokio::time::timeout(RECEIVE_WAIT, receiver.recv()).await {
+            Ok(Some(message)) => message,
+            Ok(None) => return Err(ChannelError::Closed),
+            Err(_) => return Ok(vec![]),
+        };
+
+        let mut messages = vec![first];
+        while messages.len() < self.batch_size {
+            match receiver.try_recv() {
+                Ok(message) => messages.push(message),
+                Err(_) => break,
+            }
+        }
+        Ok(messages)
+    }
+
+    fn message_id(&self, message: &Self::Message) -> String {
+        message.id.clone()
+    }
+
+    fn keep_alive(&self, _message: &Self::Message) -> Self::KeepAlive {}
+
+    fn payload_size(&self, message: &Self::Message) -> Option<usize> {
+        Some(message.payload.len())
+    }
+
+    async fn ack(&mut self, _message: &Self::Message) {}
+
+    async fn retry(
+        &mut self,
+        message: &Self::Message,
+        metadata: Option<&Metadata>,
+        delay: Duration,
+    ) -> Result<(), Self::Error> {
+        let mut message = message.clone();
+        if let Some(metadata) = metadata {
+            message.payload = replace_metadata(&message.payload, metadata)?;
+        }
+
+        let sender = self.sender.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            // The source was dropped in the meantime, so there's nothing left to retry it
+            let _ = sender.send(message);
+        });
+        Ok(())
+    }
+
+    async fn release(&mut self, message: &Self::Message) -> Result<(), Self::Error> {
+        self.sender
+            .send(message.clone())
+            .map_err(|_| ChannelError::Closed)
+    }
+
+    async fn dead_letter(
+        &mut self,
+        message: &Self::Message,
+        failure: &FailureMetadata,
+    ) -> Result<(), Self::Error> {
+        self.dead_letters
+            .lock()
+            .unwrap()
+            .push((message.clone(), failure.clone()));
+        Ok(())
+    }
+}
+
+/// Hands back the payloads carried by `ChannelMessage`s
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ChannelPayloadStore;
+
+#[async_trait]
+impl PayloadStore for ChannelPayloadStore {
+    type Message = ChannelMessage;
+    type Error = ChannelError;
+
+    async fn load_payload(&mut self, msg: &Self::Message) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(Some(msg.payload.clone()))
+    }
+}
+
+pub type ChannelPayloadRetriever<D, E> = PayloadStoreRetriever<ChannelPayloadStore, D, E>;
+
+/// Sends each event to the paired `ChannelMessageSource`
+#[derive(Clone)]
+pub struct ChannelEmitter {
+    sender: UnboundedSender<ChannelMessage>,
+}
+
+#[async_trait]
+impl Emitter for ChannelEmitter {
+    type Event = Vec<u8>;
+    type Error = ChannelError;
+
+    async fn emit_event(&mut self, events: Vec<Self::Event>) -> Result<(), Self::Error> {
+        for event in events {
+            self.sender
+                .send(ChannelMessage::new(event))
+                .map_err(|_| ChannelError::Closed)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_emitted_events_are_received_in_order() {
+        let (mut emitter, mut source) = channel();
+        emitter
+            .emit_event(vec![b"1".to_vec(), b"2".to_vec()])
+            .await
+            .expect("emit_event");
+
+        let messages = source.receive_messages().await.expect("receive_messages");
+        let payloads: Vec<_> = messages.iter().map(|m| m.payload.clone()).collect();
+        assert_eq!(payloads, vec![b"1".to_vec(), b"2".to_vec()]);
+        assert_ne!(
+            source.message_id(&messages[0]),
+            source.message_id(&messages[1])
+        );
+
+        let payload = ChannelPayloadStore
+            .load_payload(&messages[0])
+            .await
+            .expect("load_payload");
+        assert_eq!(payload, Some(b"1".to_vec()));
+
+        // Nothing left to receive
+        let empty = source.receive_messages().await.expect("receive_messages");
+        assert!(empty.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_retry_rewrites_metadata_and_resends_after_the_delay() {
+        let (mut emitter, mut source) = channel();
+        let envelope = rust_proto::pipeline::Envelope {
+            metadata: Some(Metadata::default()),
+            inner_type: "test".to_owned(),
+            inner_message: b"inner".to_vec(),
+            compression_dictionary_id: 0,
+        };
+        emitter
+            .emit_event(vec![prost::Message::encode_to_vec(&envelope)])
+            .await
+            .expect("emit_event");
+        let messages = source.receive_messages().await.expect("receive_messages");
+
+        let metadata = Metadata {
+            retry_count: 1,
+            ..Default::default()
+        };
+        source
+            .retry(&messages[0], Some(&metadata), Duration::from_millis(50))
+            .await
+            .expect("retry");
+
+        let retried = source.receive_messages().await.expect("receive_messages");
+        assert_eq!(retried.len(), 1);
+        assert_eq!(retried[0].id, messages[0].id);
+        let retried: rust_proto::pipeline::Envelope =
+            prost::Message::decode(&retried[0].payload[..]).unwrap();
+        assert_eq!(retried.metadata.unwrap().retry_count, 1);
+        assert_eq!(retried.inner_message, b"inner".to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_dead_letters_are_kept() {
+        let (mut emitter, mut source) = channel();
+        emitter
+            .emit_event(vec![b"dead".to_vec()])
+            .await
+            .expect("emit_event");
+        let messages = source.receive_messages().await.expect("receive_messages");
+
+        let failure = FailureMetadata::new("channel_test", "failed", Recoverable::Persistent);
+        source
+            .dead_letter(&messages[0], &failure)
+            .await
+            .expect("dead_letter");
+
+        assert_eq!(
+            source.clone().dead_letters(),
+            vec![(messages[0].clone(), failure)]
+        );
+    }
+}
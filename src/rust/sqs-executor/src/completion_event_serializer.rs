@@ -6,4 +6,9 @@ pub trait CompletionEventSerializer {
         &mut self,
         completed_events: &[Self::CompletedEvent],
     ) -> Result<Vec<Self::Output>, Self::Error>;
+
+    /// The id of the zstd dictionary the output is compressed with, or 0 if there isn't one
+    fn compression_dictionary_id(&self) -> u32 {
+        0
+    }
 }
@@ -0,0 +1,173 @@
+use std::time::Duration;
+
+use tokio::sync::{
+    Semaphore,
+    SemaphorePermit,
+};
+
+/// How the executor backs off when the services downstream of its handler are struggling,
+/// and how much payload it may hold in memory at once.
+///
+/// The number of workers is the most messages processed at once. Starting from there,
+/// the concurrency limit is halved after a batch whose handlers were slower than
+/// `target_latency` on average, or failed transiently more often than `max_error_rate`,
+/// and grows by one after every other batch.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConcurrencyPolicy {
+    pub target_latency: Duration,
+    pub max_error_rate: f64,
+    /// Payloads are admitted until their combined size reaches this, with the rest waiting
+    /// for room. A single payload larger than this is still admitted, on its own.
+    pub max_in_flight_bytes: usize,
+}
+
+impl Default for ConcurrencyPolicy {
+    fn default() -> Self {
+        Self {
+            target_latency: Duration::from_secs(5),
+            max_error_rate: 0.2,
+            max_in_flight_bytes: 512 * 1024 * 1024,
+        }
+    }
+}
+
+/// How long a message's handler took, and whether it failed in a way that suggests the
+/// services downstream of it are struggling
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Outcome {
+    pub latency: Duration,
+    pub transient_failure: bool,
+}
+
+/// An additive-increase, multiplicative-decrease concurrency limit
+#[derive(Clone, Debug)]
+pub struct AdaptiveConcurrency {
+    policy: ConcurrencyPolicy,
+    workers: usize,
+    limit: usize,
+}
+
+impl AdaptiveConcurrency {
+    pub fn new(policy: ConcurrencyPolicy, workers: usize) -> Self {
+        let workers = workers.max(1);
+        Self {
+            policy,
+            workers,
+            limit: workers,
+        }
+    }
+
+    /// How many messages to process at once, between 1 and the number of workers
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn observe(&mut self, outcomes: &[Outcome]) {
+        if outcomes.is_empty() {
+            return;
+        }
+        let total_latency: Duration = outcomes.iter().map(|outcome| outcome.latency).sum();
+        let mean_latency = total_latency / outcomes.len() as u32;
+        let failures = outcomes
+            .iter()
+            .filter(|outcome| outcome.transient_failure)
+            .count();
+        let error_rate = failures as f64 / outcomes.len() as f64;
+
+        if mean_latency > self.policy.target_latency || error_rate > self.policy.max_error_rate {
+            self.limit = (self.limit / 2).max(1);
+        } else {
+            self.limit = (self.limit + 1).min(self.workers);
+        }
+    }
+}
+
+/// Bounds the combined size of the payloads being processed at once
+pub struct InFlightBytes {
+    semaphore: Semaphore,
+    max_kib: u32,
+}
+
+impl InFlightBytes {
+    pub fn new(max_in_flight_bytes: usize) -> Self {
+        // Counted in KiB, since a semaphore only has u32::MAX permits to hand out at once
+        let max_kib = kib(max_in_flight_bytes).max(1);
+        Self {
+            semaphore: Semaphore::new(max_kib as usize),
+            max_kib,
+        }
+    }
+
+    /// Waits until there's room for a payload of `size` bytes, which stays reserved for as
+    /// long as the permit is held. Payloads of unknown size aren't counted.
+    pub async fn acquire(&self, size: Option<usize>) -> SemaphorePermit<'_> {
+        let permits = size.map(kib).unwrap_or_default().min(self.max_kib);
+        self.semaphore
+            .acquire_many(permits)
+            .await
+            .expect("InFlightBytes semaphore is never closed")
+    }
+}
+
+fn kib(bytes: usize) -> u32 {
+    let kib = (bytes as u64 + 1023) / 1024;
+    kib.min(u32::MAX as u64) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcome(latency_ms: u64, transient_failure: bool) -> Outcome {
+        Outcome {
+            latency: Duration::from_millis(latency_ms),
+            transient_failure,
+        }
+    }
+
+    #[test]
+    fn test_limit_shrinks_under_pressure_and_recovers() {
+        let policy = ConcurrencyPolicy {
+            target_latency: Duration::from_millis(100),
+            max_error_rate: 0.25,
+            ..Default::default()
+        };
+        let mut concurrency = AdaptiveConcurrency::new(policy, 8);
+        assert_eq!(concurrency.limit(), 8);
+
+        concurrency.observe(&[outcome(500, false), outcome(50, false)]);
+        assert_eq!(concurrency.limit(), 4);
+
+        concurrency.observe(&[outcome(10, true), outcome(10, false)]);
+        assert_eq!(concurrency.limit(), 2);
+
+        concurrency.observe(&[outcome(10, true)]);
+        concurrency.observe(&[outcome(10, true)]);
+        assert_eq!(concurrency.limit(), 1);
+
+        concurrency.observe(&[]);
+        assert_eq!(concurrency.limit(), 1);
+
+        for _ in 0..20 {
+            concurrency.observe(&[outcome(10, false)]);
+        }
+        assert_eq!(concurrency.limit(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_in_flight_bytes_waits_for_room() {
+        let in_flight = InFlightBytes::new(4 * 1024);
+
+        let first = in_flight.acquire(Some(3 * 1024)).await;
+        // Larger than the whole budget, so it's clamped to it, and waits for all of it
+        let oversized = in_flight.acquire(Some(1024 * 1024));
+        tokio::pin!(oversized);
+        assert!(futures::poll!(&mut oversized).is_pending());
+
+        // Payloads of unknown size are never held up
+        let _unknown = in_flight.acquire(None).await;
+
+        drop(first);
+        let _oversized = oversized.await;
+    }
+}
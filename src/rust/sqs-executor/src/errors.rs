@@ -1,6 +1,10 @@
+use serde::{
+    Deserialize,
+    Serialize,
+};
 use thiserror::Error;
 
-#[derive(Eq, PartialEq, Debug)]
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub enum Recoverable {
     Transient,
     Persistent,
@@ -1,5 +1,11 @@
+use std::iter::Fuse;
+
 use crate::errors::CheckedError;
 
+/// Payloads are handed to `BatchedEventHandler`s this many events at a time, unless
+/// `EVENT_BATCH_SIZE` says otherwise
+pub const DEFAULT_EVENT_BATCH_SIZE: usize = 1000;
+
 pub trait PayloadDecoder<E> {
     type DecoderError: CheckedError;
     fn decode(&mut self, bytes: Vec<u8>) -> Result<E, Self::DecoderError>;
@@ -15,3 +21,152 @@ where
         (self)(body)
     }
 }
+
+/// A payload's events, decoded as they're iterated over
+pub type EventStream<E, DecoderErrorT> = Box<dyn Iterator<Item = Result<E, DecoderErrorT>> + Send>;
+
+/// Decodes a payload one event at a time, so that neither the decompressed payload nor all of
+/// its events have to be in memory at once.
+///
+/// The stream ends after the first error it yields. Only a payload that can't be read any
+/// further, or whose events all fail to decode, is an error, as with a `PayloadDecoder`.
+pub trait StreamingPayloadDecoder<E> {
+    type DecoderError: CheckedError;
+    fn decode_stream(
+        &mut self,
+        bytes: Vec<u8>,
+    ) -> Result<EventStream<E, Self::DecoderError>, Self::DecoderError>;
+}
+
+/// Groups a payload's `EventStream` into batches of up to `batch_size` events.
+///
+/// Iteration stops at the first error, which is then kept for `take_error`.
+pub struct EventBatches<E, DecoderErrorT> {
+    events: Fuse<EventStream<E, DecoderErrorT>>,
+    batch_size: usize,
+    error: Option<DecoderErrorT>,
+    done: bool,
+}
+
+impl<E, DecoderErrorT> EventBatches<E, DecoderErrorT> {
+    pub fn new(events: EventStream<E, DecoderErrorT>, batch_size: usize) -> Self {
+        Self {
+            events: events.fuse(),
+            batch_size: batch_size.max(1),
+            error: None,
+            done: false,
+        }
+    }
+
+    /// The error that ended the stream, if one did
+    pub fn take_error(&mut self) -> Option<DecoderErrorT> {
+        self.error.take()
+    }
+}
+
+impl<E, DecoderErrorT> Iterator for EventBatches<E, DecoderErrorT> {
+    type Item = Vec<E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let mut batch = vec![];
+        while batch.len() < self.batch_size {
+            match self.events.next() {
+                Some(Ok(event)) => batch.push(event),
+                Some(Err(error)) => {
+                    self.error = Some(error);
+                    self.done = true;
+                    break;
+                }
+                None => break,
+            }
+        }
+
+        if batch.is_empty() {
+            None
+        } else {
+            Some(batch)
+        }
+    }
+}
+
+/// A `PayloadDecoder` that decodes payloads into `EventBatches` with a
+/// `StreamingPayloadDecoder`, for a `BatchedEventHandler` to handle.
+///
+/// Only the start of the payload, such as its compression header, is read by `decode`. The
+/// rest is read as the batches are.
+#[derive(Debug, Clone)]
+pub struct BatchedDecoder<D> {
+    decoder: D,
+    batch_size: usize,
+}
+
+impl<D> BatchedDecoder<D> {
+    pub fn new(decoder: D, batch_size: usize) -> Self {
+        Self {
+            decoder,
+            batch_size,
+        }
+    }
+}
+
+impl<D, E> PayloadDecoder<EventBatches<E, <D as StreamingPayloadDecoder<E>>::DecoderError>>
+    for BatchedDecoder<D>
+where
+    D: StreamingPayloadDecoder<E>,
+{
+    type DecoderError = <D as StreamingPayloadDecoder<E>>::DecoderError;
+
+    fn decode(
+        &mut self,
+        bytes: Vec<u8>,
+    ) -> Result<EventBatches<E, Self::DecoderError>, Self::DecoderError> {
+        let events = self.decoder.decode_stream(bytes)?;
+        Ok(EventBatches::new(events, self.batch_size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::Recoverable;
+
+    #[derive(thiserror::Error, Debug, PartialEq)]
+    #[error("Truncated")]
+    struct Truncated;
+
+    impl CheckedError for Truncated {
+        fn error_type(&self) -> Recoverable {
+            Recoverable::Persistent
+        }
+    }
+
+    #[test]
+    fn test_batches_are_bounded() {
+        let events: EventStream<u32, Truncated> = Box::new((0..5u32).map(Ok));
+        let mut batches = EventBatches::new(events, 2);
+
+        assert_eq!(
+            batches.by_ref().collect::<Vec<_>>(),
+            vec![vec![0, 1], vec![2, 3], vec![4]]
+        );
+        assert_eq!(batches.take_error(), None);
+    }
+
+    #[test]
+    fn test_batches_stop_at_the_first_error() {
+        let events: Vec<Result<u32, Truncated>> = vec![Ok(0), Ok(1), Ok(2), Err(Truncated), Ok(3)];
+        let events: EventStream<u32, Truncated> = Box::new(events.into_iter());
+        let mut batches = EventBatches::new(events, 2);
+
+        assert_eq!(
+            batches.by_ref().collect::<Vec<_>>(),
+            vec![vec![0, 1], vec![2]]
+        );
+        assert_eq!(batches.take_error(), Some(Truncated));
+        assert_eq!(batches.next(), None);
+    }
+}
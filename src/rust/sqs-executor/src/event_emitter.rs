@@ -1,8 +1,15 @@
 use async_trait::async_trait;
 
+/// Where the executor writes its output, such as S3 objects or a Kafka topic.
 #[async_trait]
-pub trait EventEmitter {
+pub trait Emitter {
     type Event;
     type Error: std::fmt::Debug + Send;
     async fn emit_event(&mut self, completed_events: Vec<Self::Event>) -> Result<(), Self::Error>;
+
+    /// Waits for any events the emitter has buffered to be written, such as when shutting
+    /// down. Emitters that write events before `emit_event` returns have nothing to do.
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
 }
@@ -1,10 +1,18 @@
-use std::fmt::Debug;
+use std::{
+    fmt::Debug,
+    marker::PhantomData,
+};
 
 use async_trait::async_trait;
+use rust_proto::graph_descriptions::GraphDescription;
 
 use crate::{
     cache::Cacheable,
-    errors::CheckedError,
+    errors::{
+        CheckedError,
+        Recoverable,
+    },
+    event_decoder::EventBatches,
     event_status::EventStatus,
 };
 
@@ -31,6 +39,13 @@ impl CompletedEvents {
     }
 }
 
+/// Takes a handler's output before it's done with all of its input, such as to emit it, so
+/// that the output for a large payload needn't all be held at once
+#[async_trait]
+pub trait OutputSink<T>: Send {
+    async fn flush(&mut self, output: T);
+}
+
 #[async_trait]
 pub trait EventHandler {
     type InputEvent;
@@ -42,4 +57,326 @@ pub trait EventHandler {
         input: Self::InputEvent,
         identities: &mut CompletedEvents,
     ) -> Result<Self::OutputEvent, Result<(Self::OutputEvent, Self::Error), Self::Error>>;
+
+    /// Like `handle_event`, but output may be handed to `sink` as it's produced, and only
+    /// what's left is returned. Output that's been flushed isn't taken back if the input later
+    /// fails. Handlers that only have output once they're done return all of it.
+    async fn handle_event_with_sink(
+        &mut self,
+        input: Self::InputEvent,
+        identities: &mut CompletedEvents,
+        _sink: &mut dyn OutputSink<Self::OutputEvent>,
+    ) -> Result<Self::OutputEvent, Result<(Self::OutputEvent, Self::Error), Self::Error>>
+    where
+        Self::InputEvent: Send,
+    {
+        self.handle_event(input, identities).await
+    }
+}
+
+/// An `OutputEvent` that a handler's outputs for separate batches of events can be combined
+/// into
+pub trait MergeableOutput {
+    fn merge_output(&mut self, other: Self);
+}
+
+impl MergeableOutput for GraphDescription {
+    fn merge_output(&mut self, other: Self) {
+        self.merge(&other)
+    }
+}
+
+impl<T> MergeableOutput for Vec<T> {
+    fn merge_output(&mut self, other: Self) {
+        self.extend(other)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum BatchedEventHandlerError<HandlerErrorT, DecoderErrorT>
+where
+    HandlerErrorT: CheckedError + 'static,
+    DecoderErrorT: CheckedError + 'static,
+{
+    #[error("HandlerError: {0}")]
+    Handler(HandlerErrorT),
+    #[error("DecodeError: {0}")]
+    Decode(DecoderErrorT),
+}
+
+impl<HandlerErrorT, DecoderErrorT> CheckedError
+    for BatchedEventHandlerError<HandlerErrorT, DecoderErrorT>
+where
+    HandlerErrorT: CheckedError + 'static,
+    DecoderErrorT: CheckedError + 'static,
+{
+    fn error_type(&self) -> Recoverable {
+        match self {
+            Self::Handler(e) => e.error_type(),
+            Self::Decode(e) => e.error_type(),
+        }
+    }
+}
+
+/// Merges whatever output is flushed to it
+struct MergingSink<T> {
+    output: Option<T>,
+}
+
+#[async_trait]
+impl<T> OutputSink<T> for MergingSink<T>
+where
+    T: MergeableOutput + Send,
+{
+    async fn flush(&mut self, output: T) {
+        match self.output.as_mut() {
+            Some(merged) => merged.merge_output(output),
+            None => self.output = Some(output),
+        }
+    }
+}
+
+/// Hands `EventBatches` to a handler of `Vec`s of events one batch at a time, so that only one
+/// batch of a payload's events is in memory at once. With `handle_event_with_sink`, each
+/// batch's output is flushed once the next batch has output, so only one batch's output is
+/// held at once either. With `handle_event`, the outputs are merged.
+///
+/// Every batch is handled, even after one fails, and the first error is returned along with
+/// whatever output is left. An error that ends the `EventBatches` early is returned the same
+/// way, after the batches before it are handled.
+pub struct BatchedEventHandler<H, DecoderErrorT> {
+    handler: H,
+    phantom: PhantomData<DecoderErrorT>,
+}
+
+impl<H, DecoderErrorT> BatchedEventHandler<H, DecoderErrorT> {
+    pub fn new(handler: H) -> Self {
+        Self {
+            handler,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<H, DecoderErrorT> Clone for BatchedEventHandler<H, DecoderErrorT>
+where
+    H: Clone,
+{
+    fn clone(&self) -> Self {
+        Self::new(self.handler.clone())
+    }
+}
+
+#[async_trait]
+impl<H, E, DecoderErrorT> EventHandler for BatchedEventHandler<H, DecoderErrorT>
+where
+    H: EventHandler<InputEvent = Vec<E>> + Send,
+    H::OutputEvent: MergeableOutput,
+    E: Send + 'static,
+    DecoderErrorT: Debug + CheckedError + Send + Sync + 'static,
+{
+    type InputEvent = EventBatches<E, DecoderErrorT>;
+    type OutputEvent = H::OutputEvent;
+    type Error = BatchedEventHandlerError<H::Error, DecoderErrorT>;
+
+    async fn handle_event(
+        &mut self,
+        batches: Self::InputEvent,
+        completed: &mut CompletedEvents,
+    ) -> Result<Self::OutputEvent, Result<(Self::OutputEvent, Self::Error), Self::Error>> {
+        let mut merged = MergingSink { output: None };
+        let result = self
+            .handle_event_with_sink(batches, completed, &mut merged)
+            .await;
+
+        match (merged.output, result) {
+            (None, result) => result,
+            (Some(mut output), Ok(rest)) => {
+                output.merge_output(rest);
+                Ok(output)
+            }
+            (Some(mut output), Err(Ok((rest, error)))) => {
+                output.merge_output(rest);
+                Err(Ok((output, error)))
+            }
+            (Some(output), Err(Err(error))) => Err(Ok((output, error))),
+        }
+    }
+
+    async fn handle_event_with_sink(
+        &mut self,
+        mut batches: Self::InputEvent,
+        completed: &mut CompletedEvents,
+        sink: &mut dyn OutputSink<Self::OutputEvent>,
+    ) -> Result<Self::OutputEvent, Result<(Self::OutputEvent, Self::Error), Self::Error>> {
+        let mut output: Option<H::OutputEvent> = None;
+        let mut first_error = None;
+
+        for batch in batches.by_ref() {
+            tracing::debug!(message = "Handling batch", batch_size = batch.len());
+            let batch_output = match self.handler.handle_event(batch, completed).await {
+                Ok(batch_output) => Some(batch_output),
+                Err(Ok((partial, error))) => {
+                    first_error.get_or_insert(BatchedEventHandlerError::Handler(error));
+                    Some(partial)
+                }
+                Err(Err(error)) => {
+                    first_error.get_or_insert(BatchedEventHandlerError::Handler(error));
+                    None
+                }
+            };
+
+            // The last batch's output is held back, to be returned
+            if let Some(batch_output) = batch_output {
+                if let Some(output) = output.replace(batch_output) {
+                    sink.flush(output).await;
+                }
+            }
+        }
+
+        if let Some(error) = batches.take_error() {
+            first_error.get_or_insert(BatchedEventHandlerError::Decode(error));
+        }
+
+        match (output, first_error) {
+            (Some(output), None) => Ok(output),
+            (Some(output), Some(error)) => Err(Ok((output, error))),
+            (None, Some(error)) => Err(Err(error)),
+            // The payload had no events, which the handler may still want to know about
+            (None, None) => self
+                .handler
+                .handle_event(vec![], completed)
+                .await
+                .map_err(|result| match result {
+                    Ok((partial, error)) => Ok((partial, BatchedEventHandlerError::Handler(error))),
+                    Err(error) => Err(BatchedEventHandlerError::Handler(error)),
+                }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_decoder::EventStream;
+
+    #[derive(thiserror::Error, Debug)]
+    #[error("TestError")]
+    struct TestError(Recoverable);
+
+    impl CheckedError for TestError {
+        fn error_type(&self) -> Recoverable {
+            self.0
+        }
+    }
+
+    /// Outputs the sizes of the batches it's given, failing on batches containing a zero
+    #[derive(Clone, Default)]
+    struct BatchSizes;
+
+    #[async_trait]
+    impl EventHandler for BatchSizes {
+        type InputEvent = Vec<u32>;
+        type OutputEvent = Vec<usize>;
+        type Error = TestError;
+
+        async fn handle_event(
+            &mut self,
+            input: Self::InputEvent,
+            _completed: &mut CompletedEvents,
+        ) -> Result<Self::OutputEvent, Result<(Self::OutputEvent, Self::Error), Self::Error>>
+        {
+            if input.contains(&0) {
+                return Err(Err(TestError(Recoverable::Transient)));
+            }
+            Ok(vec![input.len()])
+        }
+    }
+
+    fn batches(
+        events: Vec<Result<u32, TestError>>,
+        batch_size: usize,
+    ) -> EventBatches<u32, TestError> {
+        let events: EventStream<u32, TestError> = Box::new(events.into_iter());
+        EventBatches::new(events, batch_size)
+    }
+
+    #[tokio::test]
+    async fn test_merges_the_output_of_every_batch() {
+        let mut handler = BatchedEventHandler::new(BatchSizes);
+        let events = (1..=5).map(Ok).collect();
+
+        let output = handler
+            .handle_event(batches(events, 2), &mut CompletedEvents::default())
+            .await
+            .expect("handle_event");
+        assert_eq!(output, vec![2, 2, 1]);
+    }
+
+    /// Records the outputs flushed to it
+    #[derive(Default)]
+    struct Flushed(Vec<Vec<usize>>);
+
+    #[async_trait]
+    impl OutputSink<Vec<usize>> for Flushed {
+        async fn flush(&mut self, output: Vec<usize>) {
+            self.0.push(output);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flushes_the_output_of_every_batch_but_the_last() {
+        let mut handler = BatchedEventHandler::new(BatchSizes);
+        let events = (1..=5).map(Ok).collect();
+        let mut flushed = Flushed::default();
+
+        let output = handler
+            .handle_event_with_sink(
+                batches(events, 2),
+                &mut CompletedEvents::default(),
+                &mut flushed,
+            )
+            .await
+            .expect("handle_event_with_sink");
+        assert_eq!(flushed.0, vec![vec![2], vec![2]]);
+        assert_eq!(output, vec![1]);
+    }
+
+    #[tokio::test]
+    async fn test_returns_the_first_error_with_the_other_batches_output() {
+        let mut handler = BatchedEventHandler::new(BatchSizes);
+        let events = vec![
+            Ok(1),
+            Ok(0),
+            Ok(2),
+            Ok(3),
+            Err(TestError(Recoverable::Persistent)),
+        ];
+
+        let result = handler
+            .handle_event(batches(events, 2), &mut CompletedEvents::default())
+            .await;
+        match result {
+            Err(Ok((partial, error))) => {
+                assert_eq!(partial, vec![2]);
+                assert!(matches!(error, BatchedEventHandlerError::Handler(_)));
+                assert!(error.is_transient());
+            }
+            _ => panic!("expected a partial error"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_decode_errors_without_events_fail_the_payload() {
+        let mut handler = BatchedEventHandler::new(BatchSizes);
+        let events = vec![Err(TestError(Recoverable::Persistent))];
+
+        let result = handler
+            .handle_event(batches(events, 2), &mut CompletedEvents::default())
+            .await;
+        assert!(matches!(
+            result,
+            Err(Err(BatchedEventHandlerError::Decode(_)))
+        ));
+    }
 }
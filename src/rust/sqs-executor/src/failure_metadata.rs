@@ -0,0 +1,74 @@
+use std::time::{
+    SystemTime,
+    UNIX_EPOCH,
+};
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::errors::Recoverable;
+
+/// The SQS message attribute, or Kafka header, that carries a dead lettered message's
+/// `FailureMetadata` as JSON
+pub const FAILURE_METADATA_KEY: &str = "grapl-failure";
+
+/// Why a message was moved to the dead letter queue, which travels with it there
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FailureMetadata {
+    /// The service that dead lettered the message
+    pub service: String,
+    pub error: String,
+    pub recoverable: Recoverable,
+    /// Milliseconds since the unix epoch
+    pub failed_at: u64,
+}
+
+impl FailureMetadata {
+    pub fn new(
+        service: impl Into<String>,
+        error: impl Into<String>,
+        recoverable: Recoverable,
+    ) -> Self {
+        let failed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        Self {
+            service: service.into(),
+            error: error.into(),
+            recoverable,
+            failed_at,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("FailureMetadata is always serializable")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_round_trip() {
+        let failure = FailureMetadata {
+            service: "graph_merger".to_owned(),
+            error: "Timeout".to_owned(),
+            recoverable: Recoverable::Transient,
+            failed_at: 1_600_000_000_000,
+        };
+        let json = failure.to_json();
+        assert_eq!(
+            json,
+            r#"{"service":"graph_merger","error":"Timeout","recoverable":"Transient","failed_at":1600000000000}"#
+        );
+        assert_eq!(FailureMetadata::from_json(&json).unwrap(), failure);
+    }
+}
@@ -0,0 +1,275 @@
+//! Whether the service is up, for whatever's running it.
+//!
+//! A small HTTP server answers `/healthz` while the process is alive, `/readyz` while the
+//! process loop is making progress and every `ReadinessCheck` passes, and `/metrics` with
+//! everything `MetricReporter`s have written, for Prometheus to scrape.
+
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{
+        atomic::{
+            AtomicU64,
+            Ordering,
+        },
+        Arc,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use async_trait::async_trait;
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{
+        make_service_fn,
+        service_fn,
+    },
+    Body,
+    Method,
+    Request,
+    Response,
+    Server,
+    StatusCode,
+};
+use tracing::{
+    error,
+    info,
+};
+
+pub type CheckError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A dependency the service can't do its work without, such as a database
+#[async_trait]
+pub trait ReadinessCheck: Send + Sync {
+    /// Names the dependency in `/readyz` responses
+    fn name(&self) -> &str;
+
+    /// Succeeds if the dependency is reachable
+    async fn check(&self) -> Result<(), CheckError>;
+}
+
+/// Beaten by the process loop whenever it makes progress, so that a loop that's stuck can be
+/// told apart from one that's waiting on an empty queue
+#[derive(Clone, Debug)]
+pub struct Heartbeat {
+    started: Instant,
+    /// Millis since `started`
+    last_beat: Arc<AtomicU64>,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            last_beat: Arc::new(AtomicU64::new(0)),
+        }
+    }
+}
+
+impl Heartbeat {
+    pub fn beat(&self) {
+        let millis = self.started.elapsed().as_millis() as u64;
+        self.last_beat.store(millis, Ordering::Relaxed);
+    }
+
+    pub fn since_last_beat(&self) -> Duration {
+        let last_beat = Duration::from_millis(self.last_beat.load(Ordering::Relaxed));
+        self.started.elapsed().saturating_sub(last_beat)
+    }
+}
+
+pub struct HealthServer {
+    address: SocketAddr,
+    checks: Vec<Box<dyn ReadinessCheck>>,
+    /// How long the process loop can go without progress before it's considered stuck
+    max_stall: Duration,
+    check_timeout: Duration,
+    heartbeat: Heartbeat,
+}
+
+impl HealthServer {
+    pub fn new(address: SocketAddr) -> Self {
+        Self {
+            address,
+            checks: vec![],
+            // Longer than a batch of messages is allowed to take
+            max_stall: Duration::from_secs(600),
+            check_timeout: Duration::from_secs(2),
+            heartbeat: Heartbeat::default(),
+        }
+    }
+
+    pub fn with_check(mut self, check: impl ReadinessCheck + 'static) -> Self {
+        self.checks.push(Box::new(check));
+        self
+    }
+
+    pub fn with_max_stall(mut self, max_stall: Duration) -> Self {
+        self.max_stall = max_stall;
+        self
+    }
+
+    /// Serves the endpoints in the background, returning the `Heartbeat` for the process loop
+    /// to beat. Must be called within a tokio runtime.
+    pub fn spawn(self) -> Heartbeat {
+        let heartbeat = self.heartbeat.clone();
+        let address = self.address;
+        let server = Arc::new(self);
+
+        let make_service = make_service_fn(move |_| {
+            let server = server.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let server = server.clone();
+                    async move { Ok::<_, Infallible>(server.respond(request).await) }
+                }))
+            }
+        });
+
+        tokio::spawn(async move {
+            let bound = match Server::try_bind(&address) {
+                Ok(bound) => bound,
+                Err(e) => {
+                    error!(error = e.to_string().as_str(), address = %address, "Failed to bind health server");
+                    return;
+                }
+            };
+            info!(address = %address, "Serving health checks");
+            if let Err(e) = bound.serve(make_service).await {
+                error!(error = e.to_string().as_str(), "Health server failed");
+            }
+        });
+
+        heartbeat
+    }
+
+    async fn respond(&self, request: Request<Body>) -> Response<Body> {
+        match (request.method(), request.uri().path()) {
+            (&Method::GET, "/healthz") => text(StatusCode::OK, "ok\n".to_owned()),
+            (&Method::GET, "/readyz") => self.readiness().await,
+            (&Method::GET, "/metrics") => Response::builder()
+                .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+                .body(Body::from(grapl_observe::prometheus::registry().render()))
+                .expect("Invalid metrics response"),
+            _ => text(StatusCode::NOT_FOUND, "not found\n".to_owned()),
+        }
+    }
+
+    async fn readiness(&self) -> Response<Body> {
+        let results = futures::future::join_all(self.checks.iter().map(|check| async move {
+            let result = match tokio::time::timeout(self.check_timeout, check.check()).await {
+                Ok(result) => result,
+                Err(_) => Err(format!("timed out after {:?}", self.check_timeout).into()),
+            };
+            (check.name(), result)
+        }))
+        .await;
+
+        let mut ready = true;
+        let mut body = String::new();
+        for (name, result) in results {
+            match result {
+                Ok(()) => body.push_str(&format!("{}: ok\n", name)),
+                Err(e) => {
+                    ready = false;
+                    body.push_str(&format!("{}: {}\n", name, e));
+                }
+            }
+        }
+
+        let since_last_beat = self.heartbeat.since_last_beat();
+        if since_last_beat <= self.max_stall {
+            body.push_str("process_loop: ok\n");
+        } else {
+            ready = false;
+            body.push_str(&format!(
+                "process_loop: no progress in {}s\n",
+                since_last_beat.as_secs()
+            ));
+        }
+
+        let status = if ready {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+        text(status, body)
+    }
+}
+
+fn text(status: StatusCode, body: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "text/plain")
+        .body(Body::from(body))
+        .expect("Invalid response")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Unreachable;
+
+    #[async_trait]
+    impl ReadinessCheck for Unreachable {
+        fn name(&self) -> &str {
+            "unreachable"
+        }
+
+        async fn check(&self) -> Result<(), CheckError> {
+            Err("connection refused".into())
+        }
+    }
+
+    fn get(path: &str) -> Request<Body> {
+        Request::get(path).body(Body::empty()).unwrap()
+    }
+
+    async fn body_of(response: Response<Body>) -> String {
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_ready_until_a_check_fails_or_the_loop_stalls() {
+        let server = HealthServer::new(([127, 0, 0, 1], 0).into());
+        let response = server.respond(get("/readyz")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_of(response).await, "process_loop: ok\n");
+
+        let server = server.with_check(Unreachable);
+        let response = server.respond(get("/readyz")).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(body_of(response)
+            .await
+            .contains("unreachable: connection refused\n"));
+
+        let server = HealthServer::new(([127, 0, 0, 1], 0).into()).with_max_stall(Duration::ZERO);
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        let response = server.respond(get("/readyz")).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let response = server.respond(get("/healthz")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_serves_metrics() {
+        let server = HealthServer::new(([127, 0, 0, 1], 0).into());
+        grapl_observe::metric_reporter::MetricReporter::new("health-test")
+            .counter_notags("health_test.served", 1.0, None)
+            .unwrap();
+        let response = server.respond(get("/metrics")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_of(response)
+            .await
+            .contains("health_test_served_total 1\n"));
+
+        let response = server.respond(get("/nope")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,638 @@
+//! Kafka implementations of the executor's transports, for running the pipeline on a
+//! self-hosted Kafka cluster rather than on SQS and S3.
+//!
+//! Unlike SQS, where messages point at payloads in S3, Kafka records carry their encoded
+//! `Envelope` inline.
+
+use std::{
+    collections::{
+        BTreeMap,
+        HashMap,
+    },
+    io::Stdout,
+    sync::{
+        Arc,
+        Mutex,
+    },
+    time::{
+        Duration,
+        Instant,
+        SystemTime,
+        UNIX_EPOCH,
+    },
+};
+
+use async_trait::async_trait;
+use grapl_observe::{
+    metric_reporter::{
+        tag,
+        MetricReporter,
+    },
+    timers::TimedFutureExt,
+};
+use rdkafka::{
+    consumer::{
+        Consumer,
+        StreamConsumer,
+    },
+    error::{
+        KafkaError,
+        KafkaResult,
+    },
+    message::{
+        Headers,
+        OwnedHeaders,
+    },
+    producer::{
+        FutureProducer,
+        FutureRecord,
+    },
+    ClientConfig,
+    Message,
+};
+use rust_proto::pipeline::Metadata;
+use tracing::error;
+
+use crate::{
+    errors::{
+        CheckedError,
+        Recoverable,
+    },
+    event_emitter::Emitter,
+    failure_metadata::{
+        FailureMetadata,
+        FAILURE_METADATA_KEY,
+    },
+    message_source::MessageSource,
+    payload_store::PayloadStore,
+    retriever::event_retriever::replace_metadata,
+    store_retriever::PayloadStoreRetriever,
+};
+
+/// How long to wait for the first message of a batch, mirroring an SQS long poll
+const RECEIVE_WAIT: Duration = Duration::from_secs(20);
+/// How long to wait for each further message once a batch has started
+const BATCH_LINGER: Duration = Duration::from_millis(10);
+/// How long a produced record may wait in the producer's queue
+const SEND_TIMEOUT: Duration = Duration::from_secs(3);
+/// Carries a message's id across re-publishes, so that a retried message keeps its identity
+const MESSAGE_ID_HEADER: &str = "grapl-message-id";
+/// When a retried message may be processed, in milliseconds since the unix epoch. Kafka can't
+/// delay delivery, so the consumer holds the message aside until then instead.
+const DELIVER_AFTER_HEADER: &str = "grapl-deliver-after";
+
+#[derive(thiserror::Error, Debug)]
+pub enum KafkaTransportError {
+    #[error("KafkaError: {0}")]
+    Kafka(#[from] KafkaError),
+    #[error("EnvelopeDecode error")]
+    EnvelopeDecode(#[from] prost::DecodeError),
+}
+
+impl CheckedError for KafkaTransportError {
+    fn error_type(&self) -> Recoverable {
+        match self {
+            Self::Kafka(_) => Recoverable::Transient,
+            Self::EnvelopeDecode(_) => Recoverable::Persistent,
+        }
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Consumer config for a `KafkaMessageSource`. Offsets are only stored once a message, and
+/// every message before it in its partition, is acked, and stored offsets are committed
+/// periodically.
+pub fn consumer_config(bootstrap_servers: &str, group_id: &str) -> ClientConfig {
+    let mut config = ClientConfig::new();
+    config
+        .set("bootstrap.servers", bootstrap_servers)
+        .set("group.id", group_id)
+        .set("enable.auto.commit", "true")
+        .set("enable.auto.offset.store", "false")
+        .set("enable.partition.eof", "false")
+        .set("auto.offset.reset", "earliest");
+    config
+}
+
+pub fn producer_config(bootstrap_servers: &str) -> ClientConfig {
+    let mut config = ClientConfig::new();
+    config
+        .set("bootstrap.servers", bootstrap_servers)
+        .set("enable.idempotence", "true");
+    config
+}
+
+/// A record received from Kafka, detached from the consumer that received it
+#[derive(Clone, Debug)]
+pub struct KafkaMessage {
+    /// The `MESSAGE_ID_HEADER` if the record was re-published, otherwise its original position
+    pub message_id: String,
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    pub key: Option<Vec<u8>>,
+    pub payload: Vec<u8>,
+    /// The `DELIVER_AFTER_HEADER` if the record was retried
+    pub deliver_after: Option<u64>,
+}
+
+fn header<M: Message>(message: &M, name: &str) -> Option<String> {
+    let headers = message.headers()?;
+    (0..headers.count())
+        .filter_map(|i| headers.get(i))
+        .find(|(header_name, _)| *header_name == name)
+        .map(|(_, value)| String::from_utf8_lossy(value).into_owned())
+}
+
+impl<M: Message> From<&M> for KafkaMessage {
+    fn from(message: &M) -> Self {
+        let message_id = header(message, MESSAGE_ID_HEADER).unwrap_or_else(|| {
+            format!(
+                "{}/{}/{}",
+                message.topic(),
+                message.partition(),
+                message.offset()
+            )
+        });
+
+        Self {
+            message_id,
+            topic: message.topic().to_owned(),
+            partition: message.partition(),
+            offset: message.offset(),
+            key: message.key().map(<[u8]>::to_vec),
+            payload: message.payload().map(<[u8]>::to_vec).unwrap_or_default(),
+            deliver_after: header(message, DELIVER_AFTER_HEADER)
+                .and_then(|deliver_after| deliver_after.parse().ok()),
+        }
+    }
+}
+
+async fn publish(
+    producer: &FutureProducer,
+    topic: &str,
+    key: Option<&[u8]>,
+    payload: &[u8],
+    headers: Option<OwnedHeaders>,
+) -> Result<(), KafkaTransportError> {
+    let mut record = FutureRecord::<[u8], [u8]>::to(topic).payload(payload);
+    if let Some(key) = key {
+        record = record.key(key);
+    }
+    if let Some(headers) = headers {
+        record = record.headers(headers);
+    }
+    producer
+        .send(record, SEND_TIMEOUT)
+        .await
+        .map_err(|(e, _)| KafkaTransportError::from(e))?;
+    Ok(())
+}
+
+/// The offsets received from each partition that haven't been finished yet.
+///
+/// Storing an offset commits every offset before it in its partition, but the executor
+/// finishes messages out of order, so an offset is only stored once every offset before it
+/// that was received has been finished too.
+#[derive(Debug, Default)]
+struct PartitionOffsets {
+    /// Whether each received offset has been finished, by topic and partition
+    partitions: HashMap<(String, i32), BTreeMap<i64, bool>>,
+}
+
+impl PartitionOffsets {
+    fn received(&mut self, topic: &str, partition: i32, offset: i64) {
+        self.partitions
+            .entry((topic.to_owned(), partition))
+            .or_default()
+            .insert(offset, false);
+    }
+
+    /// Marks the offset as finished, returning the highest offset in its partition that can
+    /// now be stored, if any can
+    fn finished(&mut self, topic: &str, partition: i32, offset: i64) -> Option<i64> {
+        let offsets = self.partitions.get_mut(&(topic.to_owned(), partition))?;
+        *offsets.get_mut(&offset)? = true;
+
+        let mut contiguous = None;
+        while let Some((&lowest, &true)) = offsets.iter().next() {
+            offsets.remove(&lowest);
+            contiguous = Some(lowest);
+        }
+        contiguous
+    }
+}
+
+/// Retried messages that were received before they're due
+#[derive(Debug, Default)]
+struct DelayedMessages {
+    messages: Vec<KafkaMessage>,
+}
+
+impl DelayedMessages {
+    fn is_due(message: &KafkaMessage, now: u64) -> bool {
+        message
+            .deliver_after
+            .map_or(true, |deliver_after| deliver_after <= now)
+    }
+
+    fn hold(&mut self, message: KafkaMessage) {
+        self.messages.push(message);
+    }
+
+    /// Removes up to `limit` of the messages that are due, in the order they were held
+    fn take_due(&mut self, now: u64, limit: usize) -> Vec<KafkaMessage> {
+        let mut due = Vec::new();
+        let mut i = 0;
+        while i < self.messages.len() && due.len() < limit {
+            if Self::is_due(&self.messages[i], now) {
+                due.push(self.messages.remove(i));
+            } else {
+                i += 1;
+            }
+        }
+        due
+    }
+
+    /// When the next held message is due, in milliseconds since the unix epoch
+    fn next_due(&self) -> Option<u64> {
+        self.messages.iter().filter_map(|m| m.deliver_after).min()
+    }
+}
+
+/// Receives records from a Kafka topic.
+///
+/// Kafka commits offsets in order, so a message that failed transiently is re-published to the
+/// end of the topic and finished, rather than left unfinished, where it would hold back the
+/// offsets of every message after it. A retried message that isn't due yet is held aside while
+/// the consumer keeps polling, and received once it's due.
+#[derive(Clone)]
+pub struct KafkaMessageSource {
+    consumer: Arc<StreamConsumer>,
+    producer: FutureProducer,
+    topic: String,
+    dead_letter_topic: String,
+    batch_size: usize,
+    offsets: Arc<Mutex<PartitionOffsets>>,
+    delayed: Arc<Mutex<DelayedMessages>>,
+    metric_reporter: MetricReporter<Stdout>,
+}
+
+impl KafkaMessageSource {
+    /// `consumer` should be created from a `consumer_config`
+    pub fn new(
+        consumer: StreamConsumer,
+        producer: FutureProducer,
+        topic: impl Into<String>,
+        dead_letter_topic: impl Into<String>,
+        metric_reporter: MetricReporter<Stdout>,
+    ) -> KafkaResult<Self> {
+        let topic = topic.into();
+        consumer.subscribe(&[&topic])?;
+
+        Ok(Self {
+            consumer: Arc::new(consumer),
+            producer,
+            topic,
+            dead_letter_topic: dead_letter_topic.into(),
+            batch_size: 10,
+            offsets: Arc::new(Mutex::new(PartitionOffsets::default())),
+            delayed: Arc::new(Mutex::new(DelayedMessages::default())),
+            metric_reporter,
+        })
+    }
+
+    async fn recv(&self, wait: Duration) -> Option<KafkaResult<KafkaMessage>> {
+        let message = match tokio::time::timeout(wait, self.consumer.recv()).await {
+            Ok(message) => message.map(|message| KafkaMessage::from(&message)),
+            Err(_) => return None,
+        };
+        if let Ok(message) = &message {
+            self.offsets.lock().unwrap().received(
+                &message.topic,
+                message.partition,
+                message.offset,
+            );
+        }
+        Some(message)
+    }
+
+    /// Receives a batch of messages that are due, waiting up to `RECEIVE_WAIT` for the first.
+    /// Messages that aren't due yet are held aside rather than waited on.
+    async fn poll(&self) -> Result<Vec<KafkaMessage>, KafkaTransportError> {
+        let deadline = Instant::now() + RECEIVE_WAIT;
+        let mut messages = self
+            .delayed
+            .lock()
+            .unwrap()
+            .take_due(unix_millis(SystemTime::now()), self.batch_size);
+
+        while messages.len() < self.batch_size {
+            let wait = if messages.is_empty() {
+                // Stop waiting for the first message once a held one is due
+                let mut wait = deadline.saturating_duration_since(Instant::now());
+                if let Some(next_due) = self.delayed.lock().unwrap().next_due() {
+                    let now = unix_millis(SystemTime::now());
+                    wait = wait.min(Duration::from_millis(next_due.saturating_sub(now)));
+                }
+                wait
+            } else {
+                BATCH_LINGER
+            };
+
+            match self.recv(wait).await {
+                Some(Ok(message)) => {
+                    if DelayedMessages::is_due(&message, unix_millis(SystemTime::now())) {
+                        messages.push(message);
+                    } else {
+                        self.delayed.lock().unwrap().hold(message);
+                    }
+                }
+                Some(Err(e)) => {
+                    // Their offsets are tracked, so they can't be dropped
+                    let mut delayed = self.delayed.lock().unwrap();
+                    messages
+                        .into_iter()
+                        .for_each(|message| delayed.hold(message));
+                    return Err(e.into());
+                }
+                None if messages.is_empty() && Instant::now() < deadline => {
+                    messages = self
+                        .delayed
+                        .lock()
+                        .unwrap()
+                        .take_due(unix_millis(SystemTime::now()), self.batch_size);
+                }
+                None => break,
+            }
+        }
+
+        Ok(messages)
+    }
+}
+
+#[async_trait]
+impl MessageSource for KafkaMessageSource {
+    type Message = KafkaMessage;
+    // Kafka has no visibility timeout, a message is only redelivered after a rebalance
+    type KeepAlive = ();
+    type Error = KafkaTransportError;
+
+    async fn receive_messages(&mut self) -> Result<Vec<Self::Message>, Self::Error> {
+        let (messages, ms) = self.poll().timed().await;
+        let messages = messages?;
+        self.metric_reporter
+            .histogram(
+                "kafka_message_source.receive_messages.ms",
+                ms as f64,
+                &[tag("empty_receive", messages.is_empty())],
+            )
+            .unwrap_or_else(|e| {
+                error!(
+                    "failed to report kafka_message_source.receive_messages.ms: {:?}",
+                    e
+                )
+            });
+
+        Ok(messages)
+    }
+
+    fn message_id(&self, message: &Self::Message) -> String {
+        message.message_id.clone()
+    }
+
+    fn keep_alive(&self, _message: &Self::Message) -> Self::KeepAlive {}
+
+    fn payload_size(&self, message: &Self::Message) -> Option<usize> {
+        Some(message.payload.len())
+    }
+
+    async fn ack(&mut self, message: &Self::Message) {
+        let offset = self.offsets.lock().unwrap().finished(
+            &message.topic,
+            message.partition,
+            message.offset,
+        );
+        if let Some(offset) = offset {
+            self.consumer
+                .store_offset(&message.topic, message.partition, offset)
+                .unwrap_or_else(|e| error!(message="store_offset failed", error=?e));
+        }
+    }
+
+    async fn retry(
+        &mut self,
+        message: &Self::Message,
+        metadata: Option<&Metadata>,
+        delay: Duration,
+    ) -> Result<(), Self::Error> {
+        let payload = match metadata {
+            Some(metadata) => replace_metadata(&message.payload, metadata)?,
+            None => message.payload.clone(),
+        };
+        let deliver_after = unix_millis(SystemTime::now() + delay).to_string();
+        let headers = OwnedHeaders::new()
+            .add(MESSAGE_ID_HEADER, &message.message_id)
+            .add(DELIVER_AFTER_HEADER, &deliver_after);
+
+        publish(
+            &self.producer,
+            &self.topic,
+            message.key.as_deref(),
+            &payload,
+            Some(headers),
+        )
+        .await?;
+        self.ack(message).await;
+        Ok(())
+    }
+
+    async fn release(&mut self, _message: &Self::Message) -> Result<(), Self::Error> {
+        // A released message is left unfinished, so neither its offset nor any later one in its
+        // partition is stored, and it's redelivered once its partition is next assigned, such
+        // as after a restart
+        Ok(())
+    }
+
+    async fn dead_letter(
+        &mut self,
+        message: &Self::Message,
+        failure: &FailureMetadata,
+    ) -> Result<(), Self::Error> {
+        let headers = OwnedHeaders::new()
+            .add(MESSAGE_ID_HEADER, &message.message_id)
+            .add(FAILURE_METADATA_KEY, &failure.to_json());
+
+        publish(
+            &self.producer,
+            &self.dead_letter_topic,
+            message.key.as_deref(),
+            &message.payload,
+            Some(headers),
+        )
+        .await?;
+        self.ack(message).await;
+        Ok(())
+    }
+}
+
+/// Kafka records carry their payload inline, so there's nothing to load
+#[derive(Clone, Copy, Debug, Default)]
+pub struct KafkaPayloadStore;
+
+#[async_trait]
+impl PayloadStore for KafkaPayloadStore {
+    type Message = KafkaMessage;
+    type Error = KafkaTransportError;
+
+    async fn load_payload(&mut self, msg: &Self::Message) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(Some(msg.payload.clone()))
+    }
+}
+
+pub type KafkaPayloadRetriever<D, E> = PayloadStoreRetriever<KafkaPayloadStore, D, E>;
+
+/// Emits each event as a record on a Kafka topic.
+#[derive(Clone)]
+pub struct KafkaEmitter {
+    producer: FutureProducer,
+    topic: String,
+    metric_reporter: MetricReporter<Stdout>,
+}
+
+impl KafkaEmitter {
+    pub fn new(
+        producer: FutureProducer,
+        topic: impl Into<String>,
+        metric_reporter: MetricReporter<Stdout>,
+    ) -> Self {
+        Self {
+            producer,
+            topic: topic.into(),
+            metric_reporter,
+        }
+    }
+}
+
+#[async_trait]
+impl Emitter for KafkaEmitter {
+    type Event = Vec<u8>;
+    type Error = KafkaTransportError;
+
+    #[tracing::instrument(skip(self, events))]
+    async fn emit_event(&mut self, events: Vec<Self::Event>) -> Result<(), Self::Error> {
+        let sends = events
+            .iter()
+            .map(|event| publish(&self.producer, &self.topic, None, event, None));
+
+        let (results, ms) = futures::future::join_all(sends).timed().await;
+        let results: Result<Vec<()>, _> = results.into_iter().collect();
+
+        self.metric_reporter
+            .histogram(
+                "kafka_emitter.emit_event.ms",
+                ms as f64,
+                &[tag("success", results.is_ok())],
+            )
+            .unwrap_or_else(|e| error!("failed to report kafka_emitter.emit_event.ms: {:?}", e));
+
+        results?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rdkafka::{
+        message::OwnedMessage,
+        Timestamp,
+    };
+
+    use super::*;
+
+    fn owned_message(headers: Option<OwnedHeaders>) -> OwnedMessage {
+        OwnedMessage::new(
+            Some(b"payload".to_vec()),
+            None,
+            "events".to_owned(),
+            Timestamp::NotAvailable,
+            3,
+            42,
+            headers,
+        )
+    }
+
+    #[test]
+    fn test_message_id_from_position() {
+        let message = KafkaMessage::from(&owned_message(None));
+        assert_eq!(message.message_id, "events/3/42");
+        assert_eq!(message.payload, b"payload".to_vec());
+    }
+
+    #[test]
+    fn test_message_id_survives_republish() {
+        let headers = OwnedHeaders::new().add(MESSAGE_ID_HEADER, "events/1/7");
+        let message = KafkaMessage::from(&owned_message(Some(headers)));
+        assert_eq!(message.message_id, "events/1/7");
+        assert_eq!(message.offset, 42);
+        assert_eq!(message.deliver_after, None);
+    }
+
+    #[test]
+    fn test_only_contiguous_finished_offsets_are_stored() {
+        let mut offsets = PartitionOffsets::default();
+        for offset in 5..9 {
+            offsets.received("events", 0, offset);
+        }
+        offsets.received("events", 1, 5);
+
+        // 5 is still in flight, so storing 6 would commit past it
+        assert_eq!(offsets.finished("events", 0, 6), None);
+        assert_eq!(offsets.finished("events", 0, 5), Some(6));
+        // 7 is never finished, such as when it's released, so 8 is never stored
+        assert_eq!(offsets.finished("events", 0, 8), None);
+        // Partitions are tracked separately
+        assert_eq!(offsets.finished("events", 1, 5), Some(5));
+        // As are offsets that weren't received
+        assert_eq!(offsets.finished("events", 2, 5), None);
+    }
+
+    #[test]
+    fn test_deliver_after_header() {
+        let headers = OwnedHeaders::new().add(DELIVER_AFTER_HEADER, "1600000000000");
+        let message = KafkaMessage::from(&owned_message(Some(headers)));
+        assert_eq!(message.deliver_after, Some(1600000000000));
+    }
+
+    #[test]
+    fn test_delayed_messages_are_taken_once_due() {
+        let message = |offset, deliver_after| {
+            let mut message = KafkaMessage::from(&owned_message(None));
+            message.offset = offset;
+            message.deliver_after = deliver_after;
+            message
+        };
+        let offsets = |messages: Vec<KafkaMessage>| -> Vec<i64> {
+            messages.into_iter().map(|m| m.offset).collect()
+        };
+
+        let mut delayed = DelayedMessages::default();
+        delayed.hold(message(1, Some(2000)));
+        delayed.hold(message(2, Some(1000)));
+        delayed.hold(message(3, None));
+        delayed.hold(message(4, Some(1000)));
+        assert_eq!(delayed.next_due(), Some(1000));
+
+        assert_eq!(offsets(delayed.take_due(500, 10)), vec![3]);
+        assert_eq!(offsets(delayed.take_due(1000, 1)), vec![2]);
+        assert_eq!(offsets(delayed.take_due(1500, 10)), vec![4]);
+        assert_eq!(delayed.next_due(), Some(2000));
+        assert_eq!(offsets(delayed.take_due(2000, 10)), vec![1]);
+        assert_eq!(delayed.next_due(), None);
+    }
+}
@@ -5,6 +5,9 @@ use std::time::{
     UNIX_EPOCH,
 };
 
+use prost::Message;
+use rust_proto::pipeline::Envelope;
+
 pub trait KeyGenerator {
     fn generate_key(&mut self, capability: u64) -> String;
 }
@@ -56,6 +59,18 @@ impl ZstdProtoKeyGenerator {
     pub fn new(bucket: impl Into<String>) -> Self {
         Self(S3KeyGenerator::new(bucket, "proto_v0", "zstd_nodict"))
     }
+
+    /// Tags keys with the id of the dictionary their payloads are compressed with, if any
+    pub fn with_dictionary(bucket: impl Into<String>, dictionary_id: Option<u32>) -> Self {
+        match dictionary_id {
+            Some(id) => Self(S3KeyGenerator::new(
+                bucket,
+                "proto_v0",
+                format!("zstd_dict{}", id),
+            )),
+            None => Self::new(bucket),
+        }
+    }
 }
 
 impl KeyGenerator for ZstdProtoKeyGenerator {
@@ -63,3 +78,75 @@ impl KeyGenerator for ZstdProtoKeyGenerator {
         self.0.generate_key(capability)
     }
 }
+
+/// A key function for S3 emitters of zstd compressed protobuf payloads, such as the graph
+/// serializers', which keys each emitted `Envelope` with a `ZstdProtoKeyGenerator` for the
+/// dictionary its payload was compressed with
+pub fn zstd_proto_key_fn(
+    prefix: impl Into<String>,
+) -> impl Fn(&[u8]) -> String + Clone + Send + Sync + 'static {
+    let prefix = prefix.into();
+    move |envelope: &[u8]| zstd_proto_envelope_key(&prefix, envelope)
+}
+
+fn zstd_proto_envelope_key(prefix: &str, envelope: &[u8]) -> String {
+    let dictionary_id = match Envelope::decode(envelope) {
+        Ok(envelope) => envelope.compression_dictionary_id,
+        Err(e) => {
+            tracing::warn!(message="Failed to decode emitted envelope", error=?e);
+            0
+        }
+    };
+    // 0 means the payload was compressed without a dictionary
+    let dictionary_id = Some(dictionary_id).filter(|id| *id != 0);
+
+    // Keys are only unique to the second, so the capability tells payloads apart
+    let capability = uuid::Uuid::new_v4().as_u128() as u64;
+    ZstdProtoKeyGenerator::with_dictionary(prefix, dictionary_id).generate_key(capability)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn envelope(compression_dictionary_id: u32) -> Vec<u8> {
+        let envelope = Envelope {
+            metadata: None,
+            inner_type: "GraphDescription".to_owned(),
+            inner_message: vec![],
+            compression_dictionary_id,
+        };
+        let mut encoded = vec![];
+        envelope.encode(&mut encoded).expect("encode");
+        encoded
+    }
+
+    #[test]
+    fn test_keys_are_tagged_with_the_compression_dictionary() {
+        let key_fn = zstd_proto_key_fn("graph-generator");
+
+        let key = key_fn(&envelope(7));
+        let parts: Vec<_> = key.split('/').collect();
+        assert_eq!(parts.len(), 5, "unexpected key: {}", key);
+        assert_eq!(parts[0], "graph-generator");
+        assert_eq!(parts[3], "proto_v0");
+        assert!(
+            parts[4].starts_with("zstd_dict7-"),
+            "unexpected key: {}",
+            key
+        );
+
+        let key = key_fn(&envelope(0));
+        assert!(
+            key.contains("/proto_v0/zstd_nodict-"),
+            "unexpected key: {}",
+            key
+        );
+    }
+
+    #[test]
+    fn test_keys_are_unique() {
+        let key_fn = zstd_proto_key_fn("graph-generator");
+        assert_ne!(key_fn(&envelope(7)), key_fn(&envelope(7)));
+    }
+}
@@ -11,8 +11,16 @@ use std::{
     },
 };
 
-use event_emitter::EventEmitter;
-use event_handler::EventHandler;
+use async_trait::async_trait;
+use event_emitter::Emitter;
+use event_handler::{
+    EventHandler,
+    OutputSink,
+};
+use futures::stream::{
+    FuturesUnordered,
+    StreamExt,
+};
 use futures_util::FutureExt;
 use grapl_observe::{
     metric_reporter::{
@@ -21,76 +29,87 @@ use grapl_observe::{
     },
     timers::TimedFutureExt,
 };
+use message_source::MessageSource;
 use prost::Message;
 pub use retriever::{
     event_retriever,
     s3_event_retriever,
+    store_retriever,
 };
 use rusoto_core::RusotoError;
-use rusoto_s3::S3;
 use rusoto_sqs::{
     ListQueuesError,
     ListQueuesRequest,
-    Message as SqsMessage,
     Sqs,
 };
-use rust_proto::pipeline::ServiceMessage;
-use s3_event_emitter::S3EventEmitter;
-use s3_event_retriever::S3PayloadRetriever;
+use rust_proto::pipeline::{
+    Metadata,
+    ServiceMessage,
+};
 use tracing::{
     debug,
     error,
     info,
+    warn,
 };
 
 use crate::{
     cache::Cache,
     completion_event_serializer::CompletionEventSerializer,
+    concurrency::{
+        AdaptiveConcurrency,
+        ConcurrencyPolicy,
+        InFlightBytes,
+        Outcome,
+    },
     errors::{
         CheckedError,
         Recoverable,
     },
-    event_decoder::PayloadDecoder,
     event_handler::CompletedEvents,
     event_retriever::PayloadRetriever,
     event_status::EventStatus,
-    sqs_timeout_manager::keep_alive,
+    failure_metadata::FailureMetadata,
+    health::Heartbeat,
+    retry_policy::RetryPolicy,
+    shutdown::Shutdown,
 };
 
 pub mod retriever;
 
 pub mod cache;
+pub mod channel;
 pub mod completion_event_serializer;
+pub mod concurrency;
 pub mod errors;
 pub mod event_decoder;
 pub mod event_emitter;
 pub mod event_handler;
 pub mod event_status;
+pub mod failure_metadata;
+pub mod health;
+#[cfg(feature = "kafka")]
+pub mod kafka;
 pub mod key_creator;
+pub mod local_fs;
+pub mod message_source;
+pub mod payload_store;
 pub mod redis_cache;
+pub mod retry_policy;
 pub mod rusoto_helpers;
 pub mod s3_event_emitter;
+pub mod shutdown;
+pub mod sqs_message_source;
 pub mod sqs_timeout_manager;
+pub mod trace;
 
-pub async fn make_ten<F, T>(f: F) -> [T; 10]
+/// Creates one `T` for each of the `workers` that process messages concurrently
+pub async fn make_workers<F, T>(workers: usize, f: F) -> Vec<T>
 where
     F: Future<Output = T>,
     T: Clone,
 {
-    let t = f.await;
-
-    [
-        t.clone(),
-        t.clone(),
-        t.clone(),
-        t.clone(),
-        t.clone(),
-        t.clone(),
-        t.clone(),
-        t.clone(),
-        t.clone(),
-        t,
-    ]
+    vec![f.await; workers.max(1)]
 }
 
 async fn cache_completed<CacheT>(cache: &mut CacheT, completed: &mut CompletedEvents)
@@ -117,152 +136,347 @@ where
     });
 }
 
-#[tracing::instrument(skip(
-    next_message,
-    queue_url,
-    dead_letter_queue_url,
-    cache,
-    sqs_client,
-    event_handler,
-    s3_payload_retriever,
-    s3_emitter,
-    serializer,
-    metric_reporter,
-))]
+/// Counts a transient failure against the message, so that one which never succeeds
+/// is eventually moved to the dead letter queue rather than retried forever
+async fn record_failure<CacheT>(cache: &mut CacheT, message_id: &str) -> u32
+where
+    CacheT: Cache + Send,
+{
+    match cache.record_failure(message_id.to_owned()).await {
+        Ok(failures) => {
+            debug!(
+                message_id = message_id,
+                failures = failures,
+                "Recorded failure"
+            );
+            failures
+        }
+        Err(e) => {
+            error!(message="cache.record_failure failed", error=?e);
+            0
+        }
+    }
+}
+
+/// Wraps each serialized event in an `Envelope` and emits them
+async fn emit_events<OutputEventT, EmitterT>(
+    emitter: &mut EmitterT,
+    meta: &Metadata,
+    compression_dictionary_id: u32,
+    events: Vec<Vec<u8>>,
+) where
+    OutputEventT: ServiceMessage,
+    EmitterT: Emitter<Event = Vec<u8>> + Send,
+{
+    if events.is_empty() {
+        tracing::debug!(message = "Serialized events produced no output");
+        return;
+    }
+
+    // Retries are counted per stage, so the output starts over
+    let meta = Metadata {
+        retry_count: 0,
+        ..meta.clone()
+    };
+    let mut envelopes = vec![];
+    for event in events {
+        let envelope = rust_proto::pipeline::Envelope {
+            metadata: Some(meta.clone()),
+            inner_type: OutputEventT::TYPE_NAME.to_string(),
+            inner_message: event,
+            compression_dictionary_id,
+        };
+        let mut encoded = vec![];
+        if let Err(e) = envelope.encode(&mut encoded) {
+            tracing::error!(message="Failed to encode message", error=?e);
+            continue;
+        };
+        envelopes.push(encoded);
+    }
+
+    // todo: we should retry event emission
+    emitter
+        .emit_event(envelopes)
+        .await
+        .expect("Failed to emit event");
+}
+
+/// Serializes and emits the output an `EventHandler` flushes before it's done with a payload
+struct EmittingSink<'a, SerializerT, EmitterT> {
+    serializer: &'a mut SerializerT,
+    emitter: &'a mut EmitterT,
+    meta: &'a Metadata,
+}
+
+#[async_trait]
+impl<'a, OutputEventT, SerializerT, EmitterT> OutputSink<OutputEventT>
+    for EmittingSink<'a, SerializerT, EmitterT>
+where
+    OutputEventT: ServiceMessage + Send + 'static,
+    SerializerT: CompletionEventSerializer<CompletedEvent = OutputEventT, Output = Vec<u8>> + Send,
+    SerializerT::Error: Debug,
+    EmitterT: Emitter<Event = Vec<u8>> + Send,
+{
+    async fn flush(&mut self, output: OutputEventT) {
+        let events = self
+            .serializer
+            .serialize_completed_events(&[output])
+            .expect("Serializing failed");
+        emit_events::<OutputEventT, _>(
+            self.emitter,
+            self.meta,
+            self.serializer.compression_dictionary_id(),
+            events,
+        )
+        .await;
+    }
+}
+
+/// Dead letters the message along with why it failed, which also acks it
+async fn dead_letter<SourceT>(
+    source: &mut SourceT,
+    message: &SourceT::Message,
+    error: &str,
+    recoverable: Recoverable,
+    metric_reporter: &mut MetricReporter<Stdout>,
+) where
+    SourceT: MessageSource,
+{
+    metric_reporter
+        .counter("sqs_executor.dead_letter", 1.0, None, &[])
+        .unwrap_or_else(|e| error!("failed to report sqs_executor.dead_letter: {:?}", e));
+    let failure = FailureMetadata::new(metric_reporter.service_name(), error, recoverable);
+    source
+        .dead_letter(message, &failure)
+        .await
+        .unwrap_or_else(|e| error!(message="dead_letter failed", error=?e));
+}
+
+/// Hands the message back to the source after a transient failure, to be delivered again
+/// after a backoff, or dead letters it once `retry_policy` has been exhausted.
+///
+/// Messages whose payload was loaded carry their `retry_count` in their `meta`. For those
+/// that couldn't be loaded, failures are counted in the cache instead, and a message that
+/// fails too often is dead lettered before its payload is loaded again.
+async fn retry<CacheT, SourceT>(
+    cache: &mut CacheT,
+    source: &mut SourceT,
+    message: &SourceT::Message,
+    message_id: &str,
+    meta: Option<&Metadata>,
+    error: &str,
+    retry_policy: &RetryPolicy,
+    metric_reporter: &mut MetricReporter<Stdout>,
+) where
+    CacheT: Cache + Send,
+    SourceT: MessageSource,
+{
+    let (meta, retry_count) = match meta {
+        Some(meta) if retry_policy.exhausted(meta.retry_count) => {
+            warn!(
+                message_id = message_id,
+                retry_count = meta.retry_count,
+                "Message has exhausted its retries, moving to dead letter queue",
+            );
+            dead_letter(
+                source,
+                message,
+                error,
+                Recoverable::Transient,
+                metric_reporter,
+            )
+            .await;
+            return;
+        }
+        Some(meta) => {
+            let mut meta = meta.clone();
+            meta.retry_count += 1;
+            let retry_count = meta.retry_count;
+            (Some(meta), retry_count)
+        }
+        None => (None, record_failure(cache, message_id).await),
+    };
+
+    let delay = retry_policy.delay(retry_count);
+    debug!(
+        message_id = message_id,
+        retry_count = retry_count,
+        delay_ms = delay.as_millis() as u64,
+        "Retrying message"
+    );
+    metric_reporter
+        .histogram("sqs_executor.retry_count", retry_count as f64, &[])
+        .unwrap_or_else(|e| error!("failed to report sqs_executor.retry_count: {:?}", e));
+    source
+        .retry(message, meta.as_ref(), delay)
+        .await
+        .unwrap_or_else(|e| error!(message="retry failed", error=?e));
+}
+
+#[tracing::instrument(
+    skip(
+        next_message,
+        msg_handle,
+        source,
+        cache,
+        event_handler,
+        payload_retriever,
+        emitter,
+        serializer,
+        retry_policy,
+        in_flight_bytes,
+        metric_reporter,
+    ),
+    fields(message_id = tracing::field::Empty, trace_id = tracing::field::Empty)
+)]
 async fn process_message<
     CacheT,
-    SInit,
-    SqsT,
-    DecoderT,
-    DecoderErrorT,
+    SourceT,
+    PayloadRetrieverT,
+    EmitterT,
     EventHandlerT,
     InputEventT,
     OutputEventT,
     HandlerErrorT,
     SerializerErrorT,
-    S3ClientT,
-    F,
     CompletionEventSerializerT,
 >(
-    next_message: SqsMessage,
-    queue_url: String,
-    dead_letter_queue_url: String,
+    next_message: SourceT::Message,
+    // Keeps the message from being redelivered until we're done, from when it was received
+    msg_handle: SourceT::KeepAlive,
+    mut source: SourceT,
     cache: &mut CacheT,
-    sqs_client: SqsT,
     event_handler: &mut EventHandlerT,
-    s3_payload_retriever: &mut S3PayloadRetriever<
-        S3ClientT,
-        SInit,
-        DecoderT,
-        InputEventT,
-        DecoderErrorT,
-    >,
-    s3_emitter: &mut S3EventEmitter<S3ClientT, F>,
+    payload_retriever: &mut PayloadRetrieverT,
+    emitter: &mut EmitterT,
     serializer: &mut CompletionEventSerializerT,
+    retry_policy: &RetryPolicy,
+    in_flight_bytes: &InFlightBytes,
     mut metric_reporter: MetricReporter<Stdout>,
-) where
+) -> Option<Outcome>
+where
     CacheT: crate::cache::Cache + Clone + Send + Sync + 'static,
-    SInit: (Fn(String) -> S3ClientT) + Clone + Send + Sync + 'static,
-    SqsT: Sqs + Clone + Send + Sync + 'static,
-    DecoderT: PayloadDecoder<InputEventT, DecoderError = DecoderErrorT> + Clone + Send + 'static,
-    DecoderErrorT: CheckedError + Send + 'static,
+    SourceT: MessageSource,
+    PayloadRetrieverT: PayloadRetriever<InputEventT, Message = SourceT::Message> + Send,
+    EmitterT: Emitter<Event = Vec<u8>> + Send,
     InputEventT: Send,
     EventHandlerT:
         EventHandler<InputEvent = InputEventT, OutputEvent = OutputEventT, Error = HandlerErrorT>,
+    EventHandlerT: Send,
     OutputEventT: ServiceMessage + Clone + Send + Sync + 'static,
     HandlerErrorT: CheckedError + Debug + Send + Sync + 'static,
     SerializerErrorT: Error + Debug + Send + Sync + 'static,
-    S3ClientT: S3 + Clone + Send + Sync + 'static,
-    F: Clone + Fn(&[u8]) -> String + Send + Sync + 'static,
     CompletionEventSerializerT: CompletionEventSerializer<
-        CompletedEvent = OutputEventT,
-        Output = Vec<u8>,
-        Error = SerializerErrorT,
-    >,
+            CompletedEvent = OutputEventT,
+            Output = Vec<u8>,
+            Error = SerializerErrorT,
+        > + Send,
 {
-    let message_id = next_message.message_id.as_ref().unwrap().as_str();
-    let inner_loop_span = tracing::trace_span!(
-        "inner_loop_span",
-        message_id = message_id,
-        trace_id = tracing::field::Empty
-    );
-    let _enter = inner_loop_span.enter();
+    let message_id = source.message_id(&next_message);
+    let message_id = message_id.as_str();
+    // The retriever's, handler's and emitter's spans are all within this one, so they carry
+    // its message and trace ids
+    let message_span = tracing::Span::current();
+    message_span.record("message_id", &message_id);
 
     if cache.all_exist(&[message_id.to_owned()]).await {
         debug!(
             message_id = message_id,
             "Message has already been processed",
         );
-        rusoto_helpers::delete_message(
-            sqs_client.clone(),
-            queue_url.to_owned(),
-            next_message.receipt_handle.expect("missing receipt_handle"),
-            metric_reporter,
+        drop(msg_handle);
+        source.ack(&next_message).await;
+        return None;
+    }
+    if cache.failed_too_often(&message_id.to_owned()).await {
+        warn!(
+            message_id = message_id,
+            "Message has failed too many times, moving to dead letter queue",
+        );
+        drop(msg_handle);
+        dead_letter(
+            &mut source,
+            &next_message,
+            "Failed too many times to retrieve payload",
+            Recoverable::Transient,
+            &mut metric_reporter,
         )
-        .await
-        .unwrap_or_else(|e| error!(message="delete_message failed", error=?e));
-        return;
+        .await;
+        return None;
     }
-    debug!(message = "Retrieving payload from s3");
-    let receipt_handle = next_message
-        .receipt_handle
-        .as_ref()
-        .expect("missing receipt_handle")
-        .to_owned();
-    // Maintain an invisibility timeout for the message until we're done
-    let msg_handle = keep_alive(
-        sqs_client.clone(),
-        receipt_handle.clone(),
-        message_id.to_owned(),
-        queue_url.clone(),
-        30,
-    );
-    let payload = s3_payload_retriever.retrieve_event(&next_message).await;
+    // Held until the message has been processed, so the payload counts while it's in memory
+    let _in_flight = in_flight_bytes
+        .acquire(source.payload_size(&next_message))
+        .await;
+    debug!(message = "Retrieving payload");
+    let payload = payload_retriever.retrieve_event(&next_message).await;
 
-    let (meta, events) = match payload {
+    let (mut meta, events) = match payload {
         Ok(Some((meta, events))) => (meta, events),
         Ok(None) => {
-            rusoto_helpers::delete_message(
-                sqs_client.clone(),
-                queue_url.to_owned(),
-                receipt_handle,
-                metric_reporter.clone(),
-            );
-            return;
+            drop(msg_handle);
+            source.ack(&next_message).await;
+            return None;
         }
         Err(e) => {
             error!(
-                queue_url = queue_url.as_str(),
                 message_id = message_id,
                 error = e.to_string().as_str(),
                 "Failed to retrieve payload with"
             );
-            if let Recoverable::Persistent = e.error_type() {
-                rusoto_helpers::move_to_dead_letter(
-                    sqs_client.clone(),
-                    next_message.body.as_ref().unwrap(),
-                    dead_letter_queue_url,
-                    queue_url.to_owned(),
-                    receipt_handle,
-                    metric_reporter.clone(),
-                )
-                .await
-                .unwrap_or_else(|e| error!(message="move_to_dead_letter failed", error=?e));
+            drop(msg_handle);
+            let error = e.to_string();
+            match e.error_type() {
+                Recoverable::Transient => {
+                    retry(
+                        cache,
+                        &mut source,
+                        &next_message,
+                        message_id,
+                        None,
+                        &error,
+                        retry_policy,
+                        &mut metric_reporter,
+                    )
+                    .await;
+                    // The handler never ran, but the payload store is downstream too
+                    return Some(Outcome {
+                        latency: Duration::ZERO,
+                        transient_failure: true,
+                    });
+                }
+                Recoverable::Persistent => {
+                    dead_letter(
+                        &mut source,
+                        &next_message,
+                        &error,
+                        Recoverable::Persistent,
+                        &mut metric_reporter,
+                    )
+                    .await;
+                    return None;
+                }
             }
-            return;
         }
     };
 
-    let trace_id: uuid::Uuid = meta.trace_id.clone().unwrap().into();
-    let trace_id = trace_id.to_string();
-    inner_loop_span.record("trace_id", &trace_id.as_str());
+    // Carried into the emitted envelopes along with the rest of `meta`
+    let trace_id = trace::ensure_trace_id(&mut meta);
+    message_span.record("trace_id", &trace_id.to_string().as_str());
+    trace::join_trace(&message_span, trace_id);
 
     // todo: We can lift this
     let mut completed = CompletedEvents::default();
 
-    let processing_result = async {
+    let (processing_result, ms) = async {
+        // Output the handler has done with, such as a batch's, is emitted as it's flushed
+        let mut sink = EmittingSink {
+            serializer: &mut *serializer,
+            emitter: &mut *emitter,
+            meta: &meta,
+        };
         let (processing_result, ms) = event_handler
-            .handle_event(events, &mut completed)
+            .handle_event_with_sink(events, &mut completed, &mut sink)
             .timed()
             .await;
         metric_reporter
@@ -274,9 +488,16 @@ async fn process_message<
             .unwrap_or_else(
                 |e| error!(message="failed to report event_handler.handle_event.ms", error=?e),
             );
-        processing_result
+        (processing_result, ms)
     }
     .await;
+    let outcome = Outcome {
+        latency: Duration::from_millis(ms),
+        transient_failure: matches!(
+            &processing_result,
+            Err(Ok((_, e))) | Err(Err(e)) if e.error_type() == Recoverable::Transient
+        ),
+    };
 
     match processing_result {
         Ok(total) => {
@@ -285,49 +506,23 @@ async fn process_message<
                 .serialize_completed_events(&[total])
                 .expect("Serializing failed");
 
-            if events.is_empty() {
-                tracing::debug!(message = "Serialized events produced no output");
-                return;
-            }
-
-            let mut envelopes = vec![];
-            for event in events {
-                let envelope = rust_proto::pipeline::Envelope {
-                    metadata: Some(meta.clone()),
-                    inner_type: OutputEventT::TYPE_NAME.to_string(),
-                    inner_message: event,
-                };
-                let mut encoded = vec![];
-                if let Err(e) = envelope.encode(&mut encoded) {
-                    tracing::error!(message="Failed to encode message", error=?e);
-                    continue;
-                };
-                envelopes.push(encoded);
-            }
-
-            // emit event
-            // todo: we should retry event emission
-            s3_emitter
-                .emit_event(envelopes)
-                .await
-                .expect("Failed to emit event");
+            emit_events::<OutputEventT, _>(
+                emitter,
+                &meta,
+                serializer.compression_dictionary_id(),
+                events,
+            )
+            .await;
 
             cache
-                .store(next_message.message_id.clone().unwrap().into_bytes())
+                .store(message_id.as_bytes().to_vec())
                 .await
                 .unwrap_or_else(|e| error!(message="cache.store failed", error=?e));
             cache_completed(cache, &mut completed).await;
             // ack the message - we could probably not block on this
 
-            msg_handle.stop();
-            rusoto_helpers::delete_message(
-                sqs_client.clone(),
-                queue_url.to_owned(),
-                receipt_handle,
-                metric_reporter.clone(),
-            )
-            .await
-            .unwrap_or_else(|e| error!(message="delete_message failed", error=?e));
+            drop(msg_handle);
+            source.ack(&next_message).await;
         }
         Err(Ok((partial, e))) => {
             error!(
@@ -338,48 +533,41 @@ async fn process_message<
             let events = serializer
                 .serialize_completed_events(&[partial])
                 .expect("Serializing failed");
-            // emit event
-            // todo: we should retry event emission
-            if events.is_empty() {
-                tracing::debug!(message = "Serialized events produced no output");
-                return;
-            }
-
-            let mut envelopes = vec![];
-            for event in events {
-                let envelope = rust_proto::pipeline::Envelope {
-                    metadata: Some(meta.clone()),
-                    inner_type: OutputEventT::TYPE_NAME.to_string(),
-                    inner_message: event,
-                };
-                let mut encoded = vec![];
-                if let Err(e) = envelope.encode(&mut encoded) {
-                    tracing::error!(message="Failed to encode message", error=?e);
-                    continue;
-                };
-                envelopes.push(encoded);
-            }
-
-            // emit event
-            // todo: we should retry event emission
-            s3_emitter
-                .emit_event(envelopes)
-                .await
-                .expect("Failed to emit event");
+            emit_events::<OutputEventT, _>(
+                emitter,
+                &meta,
+                serializer.compression_dictionary_id(),
+                events,
+            )
+            .await;
             cache_completed(cache, &mut completed).await;
 
-            if let Recoverable::Persistent = e.error_type() {
-                msg_handle.stop();
-                rusoto_helpers::move_to_dead_letter(
-                    sqs_client.clone(),
-                    next_message.body.as_ref().unwrap(),
-                    dead_letter_queue_url,
-                    queue_url.to_owned(),
-                    receipt_handle,
-                    metric_reporter.clone(),
-                )
-                .await
-                .unwrap_or_else(|e| error!(message="move_to_dead_letter failed", error=?e));
+            drop(msg_handle);
+            let error = format!("{:?}", e);
+            match e.error_type() {
+                Recoverable::Transient => {
+                    retry(
+                        cache,
+                        &mut source,
+                        &next_message,
+                        message_id,
+                        Some(&meta),
+                        &error,
+                        retry_policy,
+                        &mut metric_reporter,
+                    )
+                    .await
+                }
+                Recoverable::Persistent => {
+                    dead_letter(
+                        &mut source,
+                        &next_message,
+                        &error,
+                        Recoverable::Persistent,
+                        &mut metric_reporter,
+                    )
+                    .await
+                }
             }
         }
         Err(Err(e)) => {
@@ -388,84 +576,102 @@ async fn process_message<
                 e,
                 e.error_type()
             );
-            if let Recoverable::Persistent = e.error_type() {
-                msg_handle.stop();
-                rusoto_helpers::move_to_dead_letter(
-                    sqs_client.clone(),
-                    next_message.body.as_ref().unwrap(),
-                    dead_letter_queue_url,
-                    queue_url.to_owned(),
-                    receipt_handle,
-                    metric_reporter.clone(),
-                )
-                .await
-                .unwrap_or_else(|e| error!(message="move_to_dead_letter failed", error=?e));
+            drop(msg_handle);
+            let error = format!("{:?}", e);
+            match e.error_type() {
+                Recoverable::Transient => {
+                    retry(
+                        cache,
+                        &mut source,
+                        &next_message,
+                        message_id,
+                        Some(&meta),
+                        &error,
+                        retry_policy,
+                        &mut metric_reporter,
+                    )
+                    .await
+                }
+                Recoverable::Persistent => {
+                    dead_letter(
+                        &mut source,
+                        &next_message,
+                        &error,
+                        Recoverable::Persistent,
+                        &mut metric_reporter,
+                    )
+                    .await
+                }
             }
-            // should we retry? idk
-            // otherwise we can just do nothing
         }
     }
+
+    Some(outcome)
 }
 
 async fn _process_loop<
     CacheT,
-    SInit,
-    SqsT,
-    DecoderT,
-    DecoderErrorT,
+    SourceT,
+    PayloadRetrieverT,
+    EmitterT,
     EventHandlerT,
     InputEventT,
     OutputEventT,
     HandlerErrorT,
     SerializerErrorT,
-    S3ClientT,
-    F,
     CompletionEventSerializerT,
 >(
-    queue_url: String,
-    dead_letter_queue_url: String,
-    cache: &mut [CacheT; 10],
-    sqs_client: SqsT,
-    event_handler: &mut [EventHandlerT; 10],
-    s3_payload_retriever: &mut [S3PayloadRetriever<S3ClientT, SInit, DecoderT, InputEventT, DecoderErrorT>;
-             10],
-    s3_emitter: &mut [S3EventEmitter<S3ClientT, F>; 10],
-    serializer: &mut [CompletionEventSerializerT; 10],
+    source: &mut SourceT,
+    cache: &mut [CacheT],
+    event_handler: &mut [EventHandlerT],
+    payload_retriever: &mut [PayloadRetrieverT],
+    emitter: &mut [EmitterT],
+    serializer: &mut [CompletionEventSerializerT],
+    retry_policy: &RetryPolicy,
+    concurrency_policy: &ConcurrencyPolicy,
+    shutdown: &mut Shutdown,
+    heartbeat: &Heartbeat,
     mut metric_reporter: MetricReporter<Stdout>,
 ) where
     CacheT: crate::cache::Cache + Clone + Send + Sync + 'static,
-    SInit: (Fn(String) -> S3ClientT) + Clone + Send + Sync + 'static,
-    SqsT: Sqs + Clone + Send + Sync + 'static,
-    DecoderT: PayloadDecoder<InputEventT, DecoderError = DecoderErrorT> + Clone + Send + 'static,
-    DecoderErrorT: CheckedError + Send + 'static,
+    SourceT: MessageSource,
+    PayloadRetrieverT: PayloadRetriever<InputEventT, Message = SourceT::Message> + Send,
+    EmitterT: Emitter<Event = Vec<u8>> + Send,
     InputEventT: Send,
     EventHandlerT:
         EventHandler<InputEvent = InputEventT, OutputEvent = OutputEventT, Error = HandlerErrorT>,
+    EventHandlerT: Send,
     OutputEventT: ServiceMessage + Clone + Send + Sync + 'static,
     HandlerErrorT: CheckedError + Debug + Send + Sync + 'static,
     SerializerErrorT: Error + Debug + Send + Sync + 'static,
-    S3ClientT: S3 + Clone + Send + Sync + 'static,
-    F: Clone + Fn(&[u8]) -> String + Send + Sync + 'static,
     CompletionEventSerializerT: CompletionEventSerializer<
-        CompletedEvent = OutputEventT,
-        Output = Vec<u8>,
-        Error = SerializerErrorT,
-    >,
+            CompletedEvent = OutputEventT,
+            Output = Vec<u8>,
+            Error = SerializerErrorT,
+        > + Send,
 {
+    let workers = cache
+        .len()
+        .min(event_handler.len())
+        .min(payload_retriever.len())
+        .min(emitter.len())
+        .min(serializer.len());
+    let mut concurrency = AdaptiveConcurrency::new(*concurrency_policy, workers);
+    let in_flight_bytes = InFlightBytes::new(concurrency_policy.max_in_flight_bytes);
+
     let mut i = 1;
     loop {
         if i >= 15 {
             i = 2;
         }
 
-        let span = tracing::trace_span!("inner_process_loop", queue_url = queue_url.as_str(),);
+        let span = tracing::trace_span!("inner_process_loop");
         let _enter = span.enter();
-        let message_batch = rusoto_helpers::get_message(
-            queue_url.to_string(),
-            sqs_client.clone(),
-            &mut metric_reporter,
-        )
-        .await;
+        let message_batch = tokio::select! {
+            message_batch = source.receive_messages() => message_batch,
+            _ = shutdown.triggered() => break,
+        };
+        heartbeat.beat();
 
         let message_batch = match message_batch {
             Ok(message_batch) => {
@@ -474,15 +680,38 @@ async fn _process_loop<
             }
             Err(e) => {
                 error!(
-                    queue_url = queue_url.as_str(),
                     error = e.to_string().as_str(),
-                    "Failed to get_message from queue"
+                    "Failed to receive messages from source"
                 );
                 tokio::time::sleep(std::time::Duration::from_millis(i * 250)).await;
                 i += 1;
                 continue;
             }
         };
+        let mut message_batch = message_batch;
+        // Keep receiving up to the concurrency limit for as long as the source has more
+        let full_batch_len = message_batch.len();
+        while !message_batch.is_empty()
+            && message_batch.len() < concurrency.limit()
+            && !shutdown.is_triggered()
+        {
+            match source.receive_messages().await {
+                Ok(more) => {
+                    let drained = more.len() < full_batch_len;
+                    message_batch.extend(more);
+                    if drained {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    error!(
+                        error = e.to_string().as_str(),
+                        "Failed to receive messages from source"
+                    );
+                    break;
+                }
+            }
+        }
         let message_batch_len = message_batch.len();
 
         info!(message_batch_len = message_batch_len, "Received messages");
@@ -491,138 +720,258 @@ async fn _process_loop<
             tokio::time::sleep(std::time::Duration::from_millis(250)).await;
             continue;
         }
-        let combos = message_batch
+
+        // The batch can be larger than the concurrency limit, so every message is kept from
+        // being redelivered from the moment it's received, not just once it's started on
+        let mut messages = message_batch
             .into_iter()
-            .zip(&mut *event_handler)
-            .zip(&mut *s3_payload_retriever)
-            .zip(&mut *s3_emitter)
-            .zip(&mut *serializer)
-            .zip(&mut *cache);
-
-        let mut process_futs = Vec::with_capacity(10);
-
-        for combo in combos {
-            let (
-                ((((next_message, event_handler), s3_payload_retriever), s3_emitter), serializer),
-                cache,
-            ) = combo;
-            let p = process_message(
-                next_message,
-                queue_url.clone(),
-                dead_letter_queue_url.clone(),
-                cache,
-                sqs_client.clone(),
-                event_handler,
-                s3_payload_retriever,
-                s3_emitter,
-                serializer,
-                metric_reporter.clone(),
-            );
-            process_futs.push(p);
-        }
+            .map(|message| {
+                let msg_handle = source.keep_alive(&message);
+                (message, msg_handle)
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .peekable();
+        while messages.peek().is_some() && !shutdown.is_triggered() {
+            let limit = concurrency.limit();
+            let chunk: Vec<_> = messages.by_ref().take(limit).collect();
+            // Kept until each message is done with, to be released if shutdown cuts it short
+            let mut unfinished: Vec<_> = chunk
+                .iter()
+                .map(|(message, _)| Some(message.clone()))
+                .collect();
+            let combos = chunk
+                .into_iter()
+                .zip(&mut *event_handler)
+                .zip(&mut *payload_retriever)
+                .zip(&mut *emitter)
+                .zip(&mut *serializer)
+                .zip(&mut *cache)
+                .enumerate();
 
-        let all_processing = tokio::time::timeout(
-            Duration::from_secs(30 * 15),
-            futures::future::join_all(process_futs).timed(),
-        );
-        match all_processing.await {
-            Ok((_r, ms)) => {
+            let mut process_futs = FuturesUnordered::new();
+
+            for (idx, combo) in combos {
+                let (
+                    (
+                        ((((next_message, msg_handle), event_handler), payload_retriever), emitter),
+                        serializer,
+                    ),
+                    cache,
+                ) = combo;
+                let p = process_message(
+                    next_message,
+                    msg_handle,
+                    source.clone(),
+                    cache,
+                    event_handler,
+                    payload_retriever,
+                    emitter,
+                    serializer,
+                    retry_policy,
+                    &in_flight_bytes,
+                    metric_reporter.clone(),
+                );
+                process_futs.push(p.map(move |outcome| (idx, outcome)));
+            }
+
+            let timeout = Duration::from_secs(30 * 15);
+            let start = tokio::time::Instant::now();
+            let mut deadline = start + timeout;
+            let mut draining = false;
+            let mut outcomes = Vec::with_capacity(limit);
+            let mut timed_out = false;
+            loop {
+                tokio::select! {
+                    next = process_futs.next() => match next {
+                        Some((idx, outcome)) => {
+                            unfinished[idx] = None;
+                            outcomes.extend(outcome);
+                            heartbeat.beat();
+                        }
+                        None => break,
+                    },
+                    _ = tokio::time::sleep_until(deadline) => {
+                        timed_out = true;
+                        break;
+                    }
+                    _ = shutdown.triggered(), if !draining => {
+                        draining = true;
+                        deadline = deadline.min(tokio::time::Instant::now() + shutdown.deadline());
+                    }
+                }
+            }
+            drop(process_futs);
+
+            if !timed_out {
+                let ms = start.elapsed().as_millis() as u64;
                 metric_reporter
                     .histogram("sqs_executor.all_processing.ms", ms as f64, &[])
                     .unwrap_or_else(|e| {
                         error!("failed to report sqs_executor.all_processing.ms: {:?}", e)
                     });
+                concurrency.observe(&outcomes);
+            } else if draining {
+                let unfinished: Vec<_> = unfinished.into_iter().flatten().collect();
+                warn!(
+                    unfinished = unfinished.len(),
+                    "Shutdown deadline passed while processing messages, releasing them"
+                );
+                release_messages(source, unfinished).await;
+            } else {
+                error!(
+                    timeout_ms = timeout.as_millis() as u64,
+                    "Timed out when processing messages"
+                );
+                concurrency.observe(&[Outcome {
+                    latency: timeout,
+                    transient_failure: true,
+                }]);
             }
-            Err(e) => error!("Timed out when processing messages: {:?}", e),
-        };
+            metric_reporter
+                .gauge(
+                    "sqs_executor.concurrency_limit",
+                    concurrency.limit() as f64,
+                    &[],
+                )
+                .unwrap_or_else(|e| {
+                    error!("failed to report sqs_executor.concurrency_limit: {:?}", e)
+                });
+        }
+
+        if shutdown.is_triggered() {
+            // Whatever was received but not started on can go straight to another instance, once
+            // it's no longer being kept alive
+            let messages: Vec<_> = messages.map(|(message, _msg_handle)| message).collect();
+            release_messages(source, messages).await;
+            break;
+        }
     }
+
+    for emitter in emitter.iter_mut() {
+        if let Err(e) = emitter.flush().await {
+            error!(error = ?e, "Failed to flush emitter");
+        }
+    }
+    // Only once the emitters are flushed is what was emitted safe to skip if it's redelivered
+    for cache in cache.iter_mut() {
+        if let Err(e) = cache.flush().await {
+            error!(error = ?e, "Failed to flush cache");
+        }
+    }
+    info!("Drained, shutting down");
 }
 
+/// Hands `messages` back to `source` unprocessed, so they're redelivered right away
+async fn release_messages<SourceT>(
+    source: &mut SourceT,
+    messages: impl IntoIterator<Item = SourceT::Message>,
+) where
+    SourceT: MessageSource,
+{
+    for message in messages {
+        if let Err(e) = source.release(&message).await {
+            error!(
+                error = e.to_string().as_str(),
+                message_id = source.message_id(&message).as_str(),
+                "Failed to release message"
+            );
+        }
+    }
+}
+
+/// Receives messages from `source`, retrieves their payloads, hands them to the `event_handler`
+/// and emits the serialized results, until `shutdown` is triggered and the messages in flight
+/// are drained.
 #[tracing::instrument(skip(
+    source,
     cache,
-    sqs_client,
     event_handler,
-    s3_payload_retriever,
-    s3_emitter,
+    payload_retriever,
+    emitter,
     serializer,
+    retry_policy,
+    concurrency_policy,
+    shutdown,
+    heartbeat,
     metric_reporter,
 ))]
 pub async fn process_loop<
     CacheT,
-    SInit,
-    SqsT,
-    DecoderT,
-    DecoderErrorT,
+    SourceT,
+    PayloadRetrieverT,
+    EmitterT,
     EventHandlerT,
     InputEventT,
     OutputEventT,
     HandlerErrorT,
     SerializerErrorT,
-    S3ClientT,
-    F,
     CompletionEventSerializerT,
 >(
-    queue_url: String,
-    dead_letter_queue_url: String,
-    cache: &mut [CacheT; 10],
-    sqs_client: SqsT,
-    event_handler: &mut [EventHandlerT; 10],
-    s3_payload_retriever: &mut [S3PayloadRetriever<S3ClientT, SInit, DecoderT, InputEventT, DecoderErrorT>;
-             10],
-    s3_emitter: &mut [S3EventEmitter<S3ClientT, F>; 10],
-    serializer: &mut [CompletionEventSerializerT; 10],
+    mut source: SourceT,
+    cache: &mut [CacheT],
+    event_handler: &mut [EventHandlerT],
+    payload_retriever: &mut [PayloadRetrieverT],
+    emitter: &mut [EmitterT],
+    serializer: &mut [CompletionEventSerializerT],
+    retry_policy: RetryPolicy,
+    concurrency_policy: ConcurrencyPolicy,
+    mut shutdown: Shutdown,
+    heartbeat: Heartbeat,
     metric_reporter: MetricReporter<Stdout>,
 ) where
     CacheT: crate::cache::Cache + Clone + Send + Sync + 'static,
-    SInit: (Fn(String) -> S3ClientT) + Clone + Send + Sync + 'static,
-    SqsT: Sqs + Clone + Send + Sync + 'static,
-    DecoderT: PayloadDecoder<InputEventT, DecoderError = DecoderErrorT> + Clone + Send + 'static,
-    DecoderErrorT: CheckedError + Send + 'static,
+    SourceT: MessageSource,
+    PayloadRetrieverT: PayloadRetriever<InputEventT, Message = SourceT::Message> + Send,
+    EmitterT: Emitter<Event = Vec<u8>> + Send,
     InputEventT: Send,
     EventHandlerT:
         EventHandler<InputEvent = InputEventT, OutputEvent = OutputEventT, Error = HandlerErrorT>,
+    EventHandlerT: Send,
     OutputEventT: ServiceMessage + Clone + Send + Sync + 'static,
     HandlerErrorT: CheckedError + Debug + Send + Sync + 'static,
     SerializerErrorT: Error + Debug + Send + Sync + 'static,
-    S3ClientT: S3 + Clone + Send + Sync + 'static,
-    F: Clone + Fn(&[u8]) -> String + Send + Sync + 'static,
     CompletionEventSerializerT: CompletionEventSerializer<
-        CompletedEvent = OutputEventT,
-        Output = Vec<u8>,
-        Error = SerializerErrorT,
-    >,
+            CompletedEvent = OutputEventT,
+            Output = Vec<u8>,
+            Error = SerializerErrorT,
+        > + Send,
 {
     loop {
         tracing::trace!("Outer process loop");
         let f = _process_loop(
-            queue_url.clone(),
-            dead_letter_queue_url.clone(),
+            &mut source,
             cache,
-            sqs_client.clone(),
             event_handler,
-            s3_payload_retriever,
-            s3_emitter,
+            payload_retriever,
+            emitter,
             serializer,
+            &retry_policy,
+            &concurrency_policy,
+            &mut shutdown,
+            &heartbeat,
             metric_reporter.clone(),
         );
         let f = AssertUnwindSafe(f);
 
-        if let Err(e) = f.catch_unwind().await {
-            if let Some(e) = e.downcast_ref::<Box<dyn std::error::Error + 'static>>() {
-                error!(
-                    queue_url = queue_url.as_str(),
-                    error = e.to_string().as_str(),
-                    "Processing loop panicked"
-                );
-            } else if let Some(e) = e.downcast_ref::<Box<dyn std::fmt::Debug>>() {
-                error!(
-                    error = format!("{:?}", e).as_str(),
-                    "Processing loop panicked"
-                );
-            } else {
-                error!("Unexpected error");
+        match f.catch_unwind().await {
+            Ok(()) => break,
+            Err(e) => {
+                if let Some(e) = e.downcast_ref::<Box<dyn std::error::Error + 'static>>() {
+                    error!(error = e.to_string().as_str(), "Processing loop panicked");
+                } else if let Some(e) = e.downcast_ref::<Box<dyn std::fmt::Debug>>() {
+                    error!(
+                        error = format!("{:?}", e).as_str(),
+                        "Processing loop panicked"
+                    );
+                } else {
+                    error!("Unexpected error");
+                }
+                if shutdown.is_triggered() {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(200)).await;
             }
-            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
         }
     }
 }
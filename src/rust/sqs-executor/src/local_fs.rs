@@ -0,0 +1,575 @@
+//! Local filesystem implementations of the executor's transports, for running the whole
+//! pipeline on one machine without SQS, S3 or localstack.
+//!
+//! Each stage watches an input directory and writes its output to an output directory, which
+//! can be the next stage's input directory. The output directory stands in for the output
+//! bucket: payloads are written to the same keys they would have in S3, with each `/` in a key
+//! becoming a subdirectory.
+//!
+//! A directory should only be watched by one process, since there's no visibility timeout to
+//! stop two processes from receiving the same file.
+
+use std::{
+    collections::{
+        HashMap,
+        HashSet,
+    },
+    io::Stdout,
+    path::{
+        Path,
+        PathBuf,
+    },
+    sync::{
+        Arc,
+        Mutex,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use grapl_observe::{
+    metric_reporter::{
+        tag,
+        MetricReporter,
+    },
+    timers::TimedFutureExt,
+};
+use rust_proto::pipeline::Metadata;
+use tokio::{
+    fs,
+    time::Instant,
+};
+use tracing::error;
+
+use crate::{
+    errors::{
+        CheckedError,
+        Recoverable,
+    },
+    event_emitter::Emitter,
+    failure_metadata::FailureMetadata,
+    message_source::MessageSource,
+    payload_store::PayloadStore,
+    retriever::event_retriever::replace_metadata,
+    store_retriever::PayloadStoreRetriever,
+};
+
+/// Dead-lettered payloads are moved here, under the input directory, keeping their keys
+pub const DEAD_LETTER_DIR: &str = "dead";
+/// Appended to a dead-lettered payload's path for the file holding its `FailureMetadata`
+pub const FAILURE_METADATA_SUFFIX: &str = ".failure.json";
+/// Payloads are written here, under the output directory, before being moved into place, so
+/// that a watcher never receives a partially written payload
+const TEMP_DIR: &str = ".tmp";
+/// How long to keep polling for the first message of a batch, mirroring an SQS long poll
+const RECEIVE_WAIT: Duration = Duration::from_secs(20);
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(thiserror::Error, Debug)]
+pub enum LocalFsError {
+    #[error("IoError: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("EnvelopeDecode error")]
+    EnvelopeDecode(#[from] prost::DecodeError),
+}
+
+impl CheckedError for LocalFsError {
+    fn error_type(&self) -> Recoverable {
+        match self {
+            Self::Io(_) => Recoverable::Transient,
+            Self::EnvelopeDecode(_) => Recoverable::Persistent,
+        }
+    }
+}
+
+/// Writes `contents` to a temporary file under `temp_dir` and then moves it to `path`, so
+/// that a watcher never sees it partially written
+async fn write_atomically(temp_dir: &Path, path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    fs::create_dir_all(temp_dir).await?;
+    let temp_path = temp_dir.join(uuid::Uuid::new_v4().to_string());
+    fs::write(&temp_path, contents).await?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::rename(&temp_path, path).await
+}
+
+/// A payload file waiting in an input directory
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LocalFsMessage {
+    /// The payload's path relative to the input directory, which is its S3 key
+    pub key: String,
+    pub path: PathBuf,
+    /// The payload's size in bytes when it was listed
+    pub size: u64,
+}
+
+/// Lists up to `limit` payloads under `input_dir`, oldest key first. Hidden entries, the
+/// `DEAD_LETTER_DIR` and payloads that `is_due` rejects are skipped.
+async fn list_payloads(
+    input_dir: &Path,
+    limit: usize,
+    is_due: impl Fn(&str) -> bool,
+) -> std::io::Result<Vec<LocalFsMessage>> {
+    let mut messages = Vec::new();
+    let mut dirs = vec![(input_dir.to_path_buf(), String::new())];
+
+    while let Some((dir, prefix)) = dirs.pop() {
+        let mut entries = match fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            // The previous stage hasn't written anything yet
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') || (prefix.is_empty() && name == DEAD_LETTER_DIR) {
+                continue;
+            }
+
+            let key = format!("{}{}", prefix, name);
+            let metadata = entry.metadata().await?;
+            if metadata.is_dir() {
+                dirs.push((entry.path(), format!("{}/", key)));
+            } else if is_due(&key) {
+                messages.push(LocalFsMessage {
+                    key,
+                    path: entry.path(),
+                    size: metadata.len(),
+                });
+            }
+        }
+    }
+
+    messages.sort_by(|a, b| a.key.cmp(&b.key));
+    messages.truncate(limit);
+    Ok(messages)
+}
+
+/// Receives the payload files written to an input directory.
+///
+/// Acking a message deletes its file, and dead-lettering it moves the file to the
+/// `DEAD_LETTER_DIR`. A message that's retried is left in place, with its `Envelope`
+/// rewritten, and is received again by a poll once its delay has passed. Delays are only kept
+/// in memory, so after a restart every retried message is due at once.
+///
+/// A received message isn't received again until it's been acked, retried, released or
+/// dead-lettered, since its file is still in the input directory while it's processed.
+#[derive(Clone)]
+pub struct LocalFsMessageSource {
+    input_dir: PathBuf,
+    batch_size: usize,
+    /// When each retried message is next due, by key
+    retry_after: Arc<Mutex<HashMap<String, Instant>>>,
+    /// The keys of the messages that have been received but not yet handed back
+    in_flight: Arc<Mutex<HashSet<String>>>,
+    metric_reporter: MetricReporter<Stdout>,
+}
+
+impl LocalFsMessageSource {
+    pub fn new(input_dir: impl Into<PathBuf>, metric_reporter: MetricReporter<Stdout>) -> Self {
+        Self {
+            input_dir: input_dir.into(),
+            batch_size: 10,
+            retry_after: Arc::new(Mutex::new(HashMap::new())),
+            in_flight: Arc::new(Mutex::new(HashSet::new())),
+            metric_reporter,
+        }
+    }
+
+    fn is_due(&self, key: &str) -> bool {
+        let mut retry_after = self.retry_after.lock().unwrap();
+        match retry_after.get(key) {
+            Some(due) if *due > Instant::now() => false,
+            Some(_) => {
+                retry_after.remove(key);
+                true
+            }
+            None => true,
+        }
+    }
+
+    fn is_in_flight(&self, key: &str) -> bool {
+        self.in_flight.lock().unwrap().contains(key)
+    }
+
+    /// Stops tracking the message as in flight, so that it can be received again
+    fn hand_back(&self, message: &LocalFsMessage) {
+        self.in_flight.lock().unwrap().remove(&message.key);
+    }
+
+    async fn rewrite_metadata(
+        &self,
+        message: &LocalFsMessage,
+        metadata: &Metadata,
+    ) -> Result<(), LocalFsError> {
+        let payload = replace_metadata(&fs::read(&message.path).await?, metadata)?;
+        write_atomically(&self.input_dir.join(TEMP_DIR), &message.path, &payload).await?;
+        Ok(())
+    }
+
+    async fn move_to_dead_letter_dir(
+        &self,
+        message: &LocalFsMessage,
+        failure: &FailureMetadata,
+    ) -> Result<(), LocalFsError> {
+        let dead_letter_path = self.input_dir.join(DEAD_LETTER_DIR).join(&message.key);
+        let mut failure_path = dead_letter_path.clone().into_os_string();
+        failure_path.push(FAILURE_METADATA_SUFFIX);
+        write_atomically(
+            &self.input_dir.join(TEMP_DIR),
+            Path::new(&failure_path),
+            failure.to_json().as_bytes(),
+        )
+        .await?;
+        fs::rename(&message.path, &dead_letter_path).await?;
+        Ok(())
+    }
+
+    async fn poll(&self) -> Result<Vec<LocalFsMessage>, LocalFsError> {
+        let deadline = Instant::now() + RECEIVE_WAIT;
+        loop {
+            let mut messages = list_payloads(&self.input_dir, self.batch_size, |key| {
+                !self.is_in_flight(key) && self.is_due(key)
+            })
+            .await?;
+            // Another clone may have received some of them since they were listed
+            {
+                let mut in_flight = self.in_flight.lock().unwrap();
+                messages.retain(|message| in_flight.insert(message.key.clone()));
+            }
+            if !messages.is_empty() || Instant::now() >= deadline {
+                return Ok(messages);
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+}
+
+#[async_trait]
+impl MessageSource for LocalFsMessageSource {
+    type Message = LocalFsMessage;
+    // Nothing else reads the input directory, and received messages are tracked until they're
+    // handed back, so a message can't be redelivered while it's being processed
+    type KeepAlive = ();
+    type Error = LocalFsError;
+
+    async fn receive_messages(&mut self) -> Result<Vec<Self::Message>, Self::Error> {
+        let (messages, ms) = self.poll().timed().await;
+        self.metric_reporter
+            .histogram(
+                "local_fs_message_source.receive_messages.ms",
+                ms as f64,
+                &[tag(
+                    "empty_receive",
+                    messages.as_ref().map(Vec::is_empty).unwrap_or(true),
+                )],
+            )
+            .unwrap_or_else(|e| {
+                error!(
+                    "failed to report local_fs_message_source.receive_messages.ms: {:?}",
+                    e
+                )
+            });
+        messages
+    }
+
+    fn message_id(&self, message: &Self::Message) -> String {
+        message.key.clone()
+    }
+
+    fn keep_alive(&self, _message: &Self::Message) -> Self::KeepAlive {}
+
+    fn payload_size(&self, message: &Self::Message) -> Option<usize> {
+        Some(message.size as usize)
+    }
+
+    async fn ack(&mut self, message: &Self::Message) {
+        match fs::remove_file(&message.path).await {
+            Ok(()) => (),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => error!(message="remove_file failed", path=?message.path, error=?e),
+        }
+        self.hand_back(message);
+    }
+
+    async fn retry(
+        &mut self,
+        message: &Self::Message,
+        metadata: Option<&Metadata>,
+        delay: Duration,
+    ) -> Result<(), Self::Error> {
+        // Even if the metadata can't be rewritten, the message is delayed and handed back, so
+        // that it's retried with its old metadata rather than never received again
+        let rewritten = match metadata {
+            Some(metadata) => self.rewrite_metadata(message, metadata).await,
+            None => Ok(()),
+        };
+        self.retry_after
+            .lock()
+            .unwrap()
+            .insert(message.key.clone(), Instant::now() + delay);
+        self.hand_back(message);
+        rewritten
+    }
+
+    async fn release(&mut self, message: &Self::Message) -> Result<(), Self::Error> {
+        self.hand_back(message);
+        Ok(())
+    }
+
+    async fn dead_letter(
+        &mut self,
+        message: &Self::Message,
+        failure: &FailureMetadata,
+    ) -> Result<(), Self::Error> {
+        // A message that couldn't be moved is still handed back, to be received again
+        let dead_lettered = self.move_to_dead_letter_dir(message, failure).await;
+        self.hand_back(message);
+        dead_lettered
+    }
+}
+
+/// Reads payloads from the files received by a `LocalFsMessageSource`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LocalFsPayloadStore;
+
+#[async_trait]
+impl PayloadStore for LocalFsPayloadStore {
+    type Message = LocalFsMessage;
+    type Error = LocalFsError;
+
+    async fn load_payload(&mut self, msg: &Self::Message) -> Result<Option<Vec<u8>>, Self::Error> {
+        match fs::read(&msg.path).await {
+            Ok(payload) => Ok(Some(payload)),
+            // Already processed and acked by an earlier delivery
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+pub type LocalFsPayloadRetriever<D, E> = PayloadStoreRetriever<LocalFsPayloadStore, D, E>;
+
+/// Writes each event to a file under an output directory, at the key `key_fn` generates for it.
+pub struct LocalFsEmitter<F>
+where
+    F: Clone + Fn(&[u8]) -> String + Send + Sync + 'static,
+{
+    output_dir: PathBuf,
+    key_fn: F,
+    metric_reporter: MetricReporter<Stdout>,
+}
+
+impl<F> Clone for LocalFsEmitter<F>
+where
+    F: Clone + Fn(&[u8]) -> String + Send + Sync + 'static,
+{
+    fn clone(&self) -> Self {
+        Self {
+            output_dir: self.output_dir.clone(),
+            key_fn: self.key_fn.clone(),
+            metric_reporter: self.metric_reporter.clone(),
+        }
+    }
+}
+
+impl<F> LocalFsEmitter<F>
+where
+    F: Clone + Fn(&[u8]) -> String + Send + Sync + 'static,
+{
+    pub fn new(
+        output_dir: impl Into<PathBuf>,
+        key_fn: F,
+        metric_reporter: MetricReporter<Stdout>,
+    ) -> Self {
+        Self {
+            output_dir: output_dir.into(),
+            key_fn,
+            metric_reporter,
+        }
+    }
+
+    async fn write_event(&self, key: &str, event: &[u8]) -> Result<(), LocalFsError> {
+        write_atomically(
+            &self.output_dir.join(TEMP_DIR),
+            &self.output_dir.join(key),
+            event,
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<F> Emitter for LocalFsEmitter<F>
+where
+    F: Clone + Fn(&[u8]) -> String + Send + Sync + 'static,
+{
+    type Event = Vec<u8>;
+    type Error = LocalFsError;
+
+    #[tracing::instrument(skip(self, events))]
+    async fn emit_event(&mut self, events: Vec<Self::Event>) -> Result<(), Self::Error> {
+        let emitter = &*self;
+        let writes = events.iter().map(|event| async move {
+            let key = (emitter.key_fn)(event);
+            tracing::info!("writing event to: {}", key);
+            emitter.write_event(&key, event).await
+        });
+
+        let (results, ms) = futures::future::join_all(writes).timed().await;
+        let results: Result<Vec<()>, _> = results.into_iter().collect();
+
+        self.metric_reporter
+            .histogram(
+                "local_fs_emitter.emit_event.ms",
+                ms as f64,
+                &[tag("success", results.is_ok())],
+            )
+            .unwrap_or_else(|e| error!("failed to report local_fs_emitter.emit_event.ms: {:?}", e));
+
+        results?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metric_reporter() -> MetricReporter<Stdout> {
+        MetricReporter::new("local_fs_test")
+    }
+
+    #[tokio::test]
+    async fn test_emitted_events_are_received_by_key() {
+        let dir = tempfile::tempdir().expect("tempdir");
+
+        let mut emitter = LocalFsEmitter::new(
+            dir.path(),
+            |event: &[u8]| format!("day/{}", String::from_utf8_lossy(event)),
+            metric_reporter(),
+        );
+        emitter
+            .emit_event(vec![b"2".to_vec(), b"1".to_vec()])
+            .await
+            .expect("emit_event");
+
+        let mut source = LocalFsMessageSource::new(dir.path(), metric_reporter());
+        let messages = source.receive_messages().await.expect("receive_messages");
+        let keys: Vec<_> = messages.iter().map(|m| source.message_id(m)).collect();
+        assert_eq!(keys, vec!["day/1", "day/2"]);
+
+        let payload = LocalFsPayloadStore
+            .load_payload(&messages[0])
+            .await
+            .expect("load_payload");
+        assert_eq!(payload, Some(b"1".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_ack_and_dead_letter() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        std::fs::create_dir_all(dir.path().join("day")).unwrap();
+        std::fs::write(dir.path().join("day/acked"), b"").unwrap();
+        std::fs::write(dir.path().join("day/dead"), b"").unwrap();
+
+        let mut source = LocalFsMessageSource::new(dir.path(), metric_reporter());
+        let messages = source.receive_messages().await.expect("receive_messages");
+        assert_eq!(messages.len(), 2);
+
+        let failure = FailureMetadata::new("local_fs_test", "failed", Recoverable::Persistent);
+        source.ack(&messages[0]).await;
+        source
+            .dead_letter(&messages[1], &failure)
+            .await
+            .expect("dead_letter");
+
+        assert!(!messages[0].path.exists());
+        let dead_dir = dir.path().join(DEAD_LETTER_DIR);
+        assert!(dead_dir.join("day/dead").exists());
+        let written = std::fs::read_to_string(dead_dir.join("day/dead.failure.json")).unwrap();
+        assert_eq!(FailureMetadata::from_json(&written).unwrap(), failure);
+
+        // Dead-lettered payloads aren't received again
+        let remaining = list_payloads(dir.path(), 10, |_| true)
+            .await
+            .expect("list_payloads");
+        assert!(remaining.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_retry_rewrites_metadata_and_delays() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let envelope = rust_proto::pipeline::Envelope {
+            metadata: Some(Metadata::default()),
+            inner_type: "test".to_owned(),
+            inner_message: b"inner".to_vec(),
+            compression_dictionary_id: 0,
+        };
+        std::fs::write(
+            dir.path().join("retried"),
+            prost::Message::encode_to_vec(&envelope),
+        )
+        .unwrap();
+
+        let mut source = LocalFsMessageSource::new(dir.path(), metric_reporter());
+        let messages = source.receive_messages().await.expect("receive_messages");
+        let metadata = Metadata {
+            retry_count: 1,
+            ..Default::default()
+        };
+        source
+            .retry(&messages[0], Some(&metadata), Duration::from_secs(60))
+            .await
+            .expect("retry");
+
+        let retried: rust_proto::pipeline::Envelope =
+            prost::Message::decode(&std::fs::read(&messages[0].path).unwrap()[..]).unwrap();
+        assert_eq!(retried.metadata.unwrap().retry_count, 1);
+        assert_eq!(retried.inner_message, b"inner".to_vec());
+
+        // Not due again until the delay has passed
+        let due = list_payloads(dir.path(), 10, |key| source.is_due(key))
+            .await
+            .expect("list_payloads");
+        assert!(due.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_messages_in_flight_are_not_received_again() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        std::fs::write(dir.path().join("a"), b"").unwrap();
+        std::fs::write(dir.path().join("b"), b"").unwrap();
+
+        let mut source = LocalFsMessageSource::new(dir.path(), metric_reporter());
+        let keys = |messages: Vec<LocalFsMessage>| -> Vec<String> {
+            messages.into_iter().map(|message| message.key).collect()
+        };
+        let messages = source.receive_messages().await.expect("receive_messages");
+        assert_eq!(keys(messages.clone()), vec!["a", "b"]);
+
+        // Receiving again before either is acked only returns what's new
+        std::fs::write(dir.path().join("c"), b"").unwrap();
+        let received = source.receive_messages().await.expect("receive_messages");
+        assert_eq!(keys(received.clone()), vec!["c"]);
+
+        // Each way of handing a message back lets it be received again
+        source.release(&messages[0]).await.expect("release");
+        let released = source.receive_messages().await.expect("receive_messages");
+        assert_eq!(keys(released), vec!["a"]);
+
+        source
+            .retry(&messages[1], None, Duration::from_secs(0))
+            .await
+            .expect("retry");
+        let retried = source.receive_messages().await.expect("receive_messages");
+        assert_eq!(keys(retried), vec!["b"]);
+
+        source.ack(&received[0]).await;
+        assert!(!source.is_in_flight("c"));
+    }
+}
@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use rust_proto::pipeline::Metadata;
+
+use crate::{
+    errors::CheckedError,
+    failure_metadata::FailureMetadata,
+};
+
+/// Where the executor receives its work from, such as an SQS queue or a Kafka topic.
+///
+/// A message only refers to a payload - the payload itself is loaded with a `PayloadStore`.
+#[async_trait]
+pub trait MessageSource: Clone + Send + Sync + 'static {
+    type Message: Clone + Send + Sync + 'static;
+    /// Prevents a message from being redelivered while it's being processed, for as long
+    /// as the handle is held
+    type KeepAlive: Send + 'static;
+    type Error: CheckedError + Send + Sync + 'static;
+
+    /// Receives the next batch of messages. An empty batch means no messages are available.
+    async fn receive_messages(&mut self) -> Result<Vec<Self::Message>, Self::Error>;
+
+    /// A unique id for the message, which stays the same if the message is redelivered
+    fn message_id(&self, message: &Self::Message) -> String;
+
+    fn keep_alive(&self, message: &Self::Message) -> Self::KeepAlive;
+
+    /// The size in bytes of the payload the message refers to, if it's known before the
+    /// payload is loaded
+    fn payload_size(&self, _message: &Self::Message) -> Option<usize> {
+        None
+    }
+
+    /// Marks the message as processed, so that it won't be delivered again
+    async fn ack(&mut self, message: &Self::Message);
+
+    /// Hands the message back after a transient failure, so that it will be delivered again
+    /// once `delay` has passed. `metadata` replaces the message's `Metadata`, with its
+    /// `retry_count` incremented, or is `None` if the message's payload couldn't be loaded.
+    async fn retry(
+        &mut self,
+        message: &Self::Message,
+        metadata: Option<&Metadata>,
+        delay: Duration,
+    ) -> Result<(), Self::Error>;
+
+    /// Hands the message back unprocessed, so that it will be delivered again right away,
+    /// such as when shutting down. Sources that redeliver unacked messages right away
+    /// anyway have nothing to do.
+    async fn release(&mut self, _message: &Self::Message) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Moves the message to the dead letter queue along with why it failed, and acks it
+    async fn dead_letter(
+        &mut self,
+        message: &Self::Message,
+        failure: &FailureMetadata,
+    ) -> Result<(), Self::Error>;
+}
@@ -0,0 +1,13 @@
+use async_trait::async_trait;
+
+use crate::errors::CheckedError;
+
+/// Loads the payload a message refers to, such as an S3 object named in an SQS notification.
+#[async_trait]
+pub trait PayloadStore {
+    type Message;
+    type Error: CheckedError;
+
+    /// Returns the encoded `Envelope` for the message, or `None` if there's nothing to process
+    async fn load_payload(&mut self, msg: &Self::Message) -> Result<Option<Vec<u8>>, Self::Error>;
+}
@@ -32,6 +32,10 @@ use crate::{
         CheckedError,
         Recoverable,
     },
+    health::{
+        CheckError,
+        ReadinessCheck,
+    },
 };
 
 lazy_static! {
@@ -44,6 +48,34 @@ const REDIS_SET_VALUE: &[u8; 1] = b"1";
 /// Value we set in LRU cache to represent a "set" key
 const LRU_SET_VALUE: () = ();
 
+/// Configuration for a `RedisCache`.
+#[derive(Clone, Debug)]
+pub struct RedisCacheConfig {
+    /// Prefixed to every key, so that services sharing a Redis instance can't collide
+    pub namespace: String,
+    /// Capacity of the in-process LRU cache that sits in front of Redis
+    pub lru_capacity: usize,
+    /// How long a processed entry lives in Redis. `None` keeps entries forever.
+    pub ttl: Option<Duration>,
+    /// How long a failure count lives in Redis after the most recent failure
+    pub failure_ttl: Duration,
+    /// The number of failures after which an entry is no longer retried. `None` never gives up.
+    pub max_failures: Option<u32>,
+}
+
+impl RedisCacheConfig {
+    pub fn new(namespace: impl Into<String>) -> Self {
+        Self {
+            namespace: namespace.into(),
+            lru_capacity: 100_000,
+            // SQS retains messages for 4 days by default, after which a redelivery is impossible
+            ttl: Some(Duration::from_secs(60 * 60 * 24 * 4)),
+            failure_ttl: Duration::from_secs(60 * 60),
+            max_failures: Some(5),
+        }
+    }
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum RedisCacheError {
     #[error("RedisError: {0}")]
@@ -63,6 +95,7 @@ impl CheckedError for RedisCacheError {
 #[derive(Clone)]
 pub struct RedisCache {
     address: String,
+    config: RedisCacheConfig,
     connection_manager: redis::aio::ConnectionManager,
     metric_reporter: MetricReporter<Stdout>,
     lru_cache: Arc<Mutex<lru::LruCache<Vec<u8>, ()>>>,
@@ -70,27 +103,66 @@ pub struct RedisCache {
 
 impl RedisCache {
     pub async fn new(
-        address: String,
-        metric_reporter: MetricReporter<Stdout>,
-    ) -> Result<Self, RedisError> {
-        RedisCache::with_lru_capacity(100_000, address, metric_reporter).await
-    }
-
-    pub async fn with_lru_capacity(
-        cap: usize,
+        config: RedisCacheConfig,
         address: String,
         metric_reporter: MetricReporter<Stdout>,
     ) -> Result<Self, RedisError> {
         let client = redis::Client::open(address.clone())?;
         let connection_manager = client.get_tokio_connection_manager().await?;
+        let lru_cache = lru::LruCache::new(config.lru_capacity);
 
         Ok(Self {
             address,
+            config,
             connection_manager,
             metric_reporter,
-            lru_cache: Arc::new(Mutex::new(lru::LruCache::new(cap))),
+            lru_cache: Arc::new(Mutex::new(lru_cache)),
         })
     }
+
+    /// The Redis key marking `identity` as processed
+    fn entry_key(&self, identity: &[u8]) -> Vec<u8> {
+        namespaced_key(&self.config.namespace, b"", identity)
+    }
+
+    /// The Redis key counting how many times processing `identity` has failed
+    fn failure_key(&self, identity: &[u8]) -> Vec<u8> {
+        namespaced_key(&self.config.namespace, b"failures:", identity)
+    }
+
+    fn report_count(&mut self, metric_name: &str, count: usize, layer: &str) {
+        self.metric_reporter
+            .counter(metric_name, count as f64, None, &[tag("layer", layer)])
+            .unwrap_or_else(|e| error!("failed to report {}: {:?}", metric_name, e));
+    }
+}
+
+fn namespaced_key(namespace: &str, kind: &[u8], identity: &[u8]) -> Vec<u8> {
+    let mut key = Vec::with_capacity(namespace.len() + 1 + kind.len() + identity.len());
+    key.extend_from_slice(namespace.as_bytes());
+    key.push(b':');
+    key.extend_from_slice(kind);
+    key.extend_from_slice(identity);
+    key
+}
+
+/// Puts `identity` into the LRU cache, returning whether it was newly inserted.
+/// Any entry evicted to make room is counted in `evicted`.
+fn push_lru(
+    lru_cache: &mut lru::LruCache<Vec<u8>, ()>,
+    identity: Vec<u8>,
+    evicted: &mut usize,
+) -> bool {
+    let full = lru_cache.len() == lru_cache.cap();
+    match lru_cache.put(identity, LRU_SET_VALUE) {
+        Some(_) => false,
+        None => {
+            if full {
+                *evicted += 1;
+            }
+            true
+        }
+    }
 }
 
 impl RedisCache {
@@ -112,6 +184,9 @@ impl RedisCache {
                 .partition(|c| lru_cache.get(&c.identity()).is_some())
         };
 
+        self.report_count("redis_cache.hit", lru_hits.len(), "lru");
+        self.report_count("redis_cache.miss", lru_misses.len(), "lru");
+
         if !lru_hits.is_empty() {
             info!(
                 "Event cache hits from LRU cache: {:?}",
@@ -126,7 +201,10 @@ impl RedisCache {
         }
 
         // Check Redis for misses from the LRU cache
-        let lru_miss_ids = get_identities(&lru_misses);
+        let lru_miss_ids: Vec<Vec<u8>> = get_identities(&lru_misses)
+            .iter()
+            .map(|identity| self.entry_key(identity))
+            .collect();
         // connection_manager.get will return Option<T> if the input Vec has a single element,
         // otherwise it returns a Vec<Option<T>>. We want to work with the Vec<Option<T>>.
         let redis_result: Result<Vec<Option<u8>>, RedisError> = if lru_miss_ids.len() == 1 {
@@ -148,6 +226,9 @@ impl RedisCache {
                         None => Either::Right(c),
                     });
 
+                self.report_count("redis_cache.hit", redis_hits.len(), "redis");
+                self.report_count("redis_cache.miss", redis_misses.len(), "redis");
+
                 if !redis_hits.is_empty() {
                     info!(
                         "Event cache hits from Redis: {:?}",
@@ -158,14 +239,15 @@ impl RedisCache {
                 // If we have entries in redis_hits here it means they weren't found
                 // in the LRU cache. So we'll put them back in LRU cache since they
                 // were some of the latest seen
+                let mut evicted = 0;
                 {
                     let mut lru_cache = self.lru_cache.lock().unwrap();
                     for cacheable in &redis_hits {
-                        // We don't care if identity already exists, so we discard the Option
-                        // returned from put.
-                        lru_cache.put(cacheable.identity(), LRU_SET_VALUE);
+                        // We don't care if identity already exists, only about what it evicts
+                        push_lru(&mut lru_cache, cacheable.identity(), &mut evicted);
                     }
                 }
+                self.report_count("redis_cache.evict", evicted, "lru");
 
                 // Return Redis cache missis, which should also be LRU cache misses
                 redis_misses
@@ -203,27 +285,89 @@ impl RedisCache {
         // entries are evicted from the LRU cache, we'll just reset the value in the Redis cache.
 
         // LRU PUT
-        let cacheables_for_redis: Vec<_> = {
+        let mut evicted = 0;
+        let identities_for_redis: Vec<_> = {
             let mut lru_cache = self.lru_cache.lock().unwrap();
 
             cacheables
                 .iter()
-                .filter(|cacheable| lru_cache.put(cacheable.identity(), LRU_SET_VALUE).is_none())
+                .map(|cacheable| cacheable.identity())
+                .filter(|identity| push_lru(&mut lru_cache, identity.clone(), &mut evicted))
                 .collect()
         };
+        self.report_count("redis_cache.evict", evicted, "lru");
 
-        // Redis SET
-        let kv_pairs: Vec<(Vec<u8>, &[u8; 1])> = cacheables_for_redis
-            .into_iter()
-            .map(|k| (k.identity(), REDIS_SET_VALUE))
-            .collect();
-        self.connection_manager
-            .set_multiple(&kv_pairs)
+        if identities_for_redis.is_empty() {
+            return Ok(());
+        }
+
+        // Redis SET, with an expiry so that Redis memory is bounded
+        let mut pipeline = redis::pipe();
+        for identity in identities_for_redis {
+            let key = self.entry_key(&identity);
+            match self.config.ttl {
+                Some(ttl) => pipeline
+                    .set_ex(key, REDIS_SET_VALUE, ttl.as_secs().max(1) as usize)
+                    .ignore(),
+                None => pipeline.set(key, REDIS_SET_VALUE).ignore(),
+            };
+        }
+        pipeline
+            .query_async::<_, ()>(&mut self.connection_manager)
             .timeout(REDIS_REQUEST_TIMEOUT.clone())
             .await??;
 
         Ok(())
     }
+
+    async fn _record_failure<CA>(&mut self, cacheable: CA) -> Result<u32, RedisCacheError>
+    where
+        CA: Cacheable + Send + Sync + Clone + 'static,
+    {
+        let key = self.failure_key(&cacheable.identity());
+        let failure_ttl = self.config.failure_ttl.as_secs().max(1) as usize;
+
+        let (failures,): (u32,) = redis::pipe()
+            .atomic()
+            .incr(&key, 1)
+            .expire(&key, failure_ttl)
+            .ignore()
+            .query_async(&mut self.connection_manager)
+            .timeout(REDIS_REQUEST_TIMEOUT.clone())
+            .await??;
+
+        Ok(failures)
+    }
+
+    async fn _failed_too_often<CA>(&mut self, cacheable: &CA) -> bool
+    where
+        CA: Cacheable + Send + Sync + Clone + 'static,
+    {
+        let max_failures = match self.config.max_failures {
+            Some(max_failures) => max_failures,
+            None => return false,
+        };
+        let key = self.failure_key(&cacheable.identity());
+
+        let failures: Result<Result<Option<u32>, RedisError>, Elapsed> = self
+            .connection_manager
+            .get(key)
+            .timeout(REDIS_REQUEST_TIMEOUT.clone())
+            .await;
+
+        match failures {
+            Ok(Ok(failures)) => failures.unwrap_or(0) >= max_failures,
+            Ok(Err(e)) => {
+                // If Redis fails, we'd rather retry the event than drop it
+                error!("{:?}", e);
+                false
+            }
+            Err(e) => {
+                error!("{:?}", e);
+                false
+            }
+        }
+    }
 }
 
 use std::sync::{
@@ -339,4 +483,91 @@ impl Cache for RedisCache {
 
         res
     }
+
+    #[tracing::instrument(skip(self, cacheable))]
+    async fn record_failure<CA>(&mut self, cacheable: CA) -> Result<u32, Self::CacheErrorT>
+    where
+        CA: Cacheable + Send + Sync + Clone + 'static,
+    {
+        let (res, ms) = self._record_failure(cacheable).timed().await;
+        self.metric_reporter
+            .histogram(
+                "redis_cache.record_failure.ms",
+                ms as f64,
+                &[tag("success", res.is_ok())],
+            )
+            .unwrap_or_else(|e| error!("failed to report redis_cache.record_failure.ms: {:?}", e));
+        res
+    }
+
+    #[tracing::instrument(skip(self, cacheable))]
+    async fn failed_too_often<CA>(&mut self, cacheable: &CA) -> bool
+    where
+        CA: Cacheable + Send + Sync + Clone + 'static,
+    {
+        let (res, ms) = self._failed_too_often(cacheable).timed().await;
+        self.metric_reporter
+            .histogram(
+                "redis_cache.failed_too_often.ms",
+                ms as f64,
+                &[tag("success", true)],
+            )
+            .unwrap_or_else(|e| {
+                error!("failed to report redis_cache.failed_too_often.ms: {:?}", e)
+            });
+        res
+    }
+}
+
+#[async_trait]
+impl ReadinessCheck for RedisCache {
+    fn name(&self) -> &str {
+        "redis"
+    }
+
+    async fn check(&self) -> Result<(), CheckError> {
+        let mut connection = self.connection_manager.clone();
+        redis::cmd("PING")
+            .query_async::<_, String>(&mut connection)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_namespaced_keys_do_not_collide() {
+        let identity = b"v1:identity";
+        assert_eq!(
+            namespaced_key("graph-merger", b"", identity),
+            b"graph-merger:v1:identity".to_vec()
+        );
+        assert_ne!(
+            namespaced_key("graph-merger", b"", identity),
+            namespaced_key("node-identifier", b"", identity)
+        );
+        assert_ne!(
+            namespaced_key("graph-merger", b"", identity),
+            namespaced_key("graph-merger", b"failures:", identity)
+        );
+    }
+
+    #[test]
+    fn test_push_lru_counts_evictions() {
+        let mut lru_cache = lru::LruCache::new(2);
+        let mut evicted = 0;
+
+        assert!(push_lru(&mut lru_cache, b"a".to_vec(), &mut evicted));
+        assert!(push_lru(&mut lru_cache, b"b".to_vec(), &mut evicted));
+        // Re-inserting an existing entry is neither new nor an eviction
+        assert!(!push_lru(&mut lru_cache, b"a".to_vec(), &mut evicted));
+        assert_eq!(evicted, 0);
+
+        assert!(push_lru(&mut lru_cache, b"c".to_vec(), &mut evicted));
+        assert_eq!(evicted, 1);
+        assert!(!lru_cache.contains(&b"b".to_vec()));
+    }
 }
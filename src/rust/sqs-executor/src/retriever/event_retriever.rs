@@ -1,7 +1,21 @@
+use std::io::Stdout;
+
 use async_trait::async_trait;
+use grapl_observe::{
+    metric_reporter::{
+        tag,
+        HistogramUnit,
+        MetricReporter,
+    },
+    timers::time_it,
+};
 use rust_proto::pipeline::Metadata;
+use tracing::error;
 
-use crate::errors::CheckedError;
+use crate::{
+    errors::CheckedError,
+    event_decoder::PayloadDecoder,
+};
 
 #[async_trait]
 pub trait PayloadRetriever<T> {
@@ -12,3 +26,45 @@ pub trait PayloadRetriever<T> {
         msg: &Self::Message,
     ) -> Result<Option<(Metadata, T)>, Self::Error>;
 }
+
+/// Decodes an encoded `Envelope`, and then decodes its inner message with `decoder`.
+/// The outer error is for the envelope, the inner one is from `decoder`.
+pub(crate) fn decode_envelope<D, E>(
+    decoder: &mut D,
+    payload: &[u8],
+    metric_name: &str,
+    metric_reporter: &mut MetricReporter<Stdout>,
+) -> Result<(Metadata, Result<E, D::DecoderError>), prost::DecodeError>
+where
+    D: PayloadDecoder<E>,
+{
+    let envelope: rust_proto::pipeline::Envelope = prost::Message::decode(payload)?;
+    let meta = envelope
+        .metadata
+        .expect("Metadata must be set at the front of the pipeline");
+    let body = envelope.inner_message;
+
+    let (decoded, ms) = time_it(|| decoder.decode(body));
+
+    metric_reporter
+        .histogram_with_units(
+            metric_name,
+            ms.as_micros() as f64,
+            HistogramUnit::Micros,
+            &[tag("success", true)][..],
+        )
+        .unwrap_or_else(|e| error!(message="failed to report decoded.micros", metric_name=metric_name, error=?e));
+
+    Ok((meta, decoded))
+}
+
+/// Re-encodes an encoded `Envelope` with `metadata` in place of its own, such as when
+/// re-enqueueing a message with an incremented `retry_count`
+pub(crate) fn replace_metadata(
+    payload: &[u8],
+    metadata: &Metadata,
+) -> Result<Vec<u8>, prost::DecodeError> {
+    let mut envelope: rust_proto::pipeline::Envelope = prost::Message::decode(payload)?;
+    envelope.metadata = Some(metadata.clone());
+    Ok(prost::Message::encode_to_vec(&envelope))
+}
@@ -1,5 +1,7 @@
 pub mod event_retriever;
 pub mod s3_event_retriever;
+pub mod store_retriever;
 
 pub use event_retriever::PayloadRetriever;
 pub use s3_event_retriever::S3PayloadRetriever;
+pub use store_retriever::PayloadStoreRetriever;
@@ -10,13 +10,9 @@ use futures::FutureExt;
 use grapl_observe::{
     metric_reporter::{
         tag,
-        HistogramUnit,
         MetricReporter,
     },
-    timers::{
-        time_it,
-        TimedFutureExt,
-    },
+    timers::TimedFutureExt,
 };
 use rusoto_core::RusotoError;
 use rusoto_s3::{
@@ -41,6 +37,9 @@ use crate::{
         Recoverable,
     },
     event_decoder::PayloadDecoder,
+    payload_store::PayloadStore,
+    retriever::event_retriever::decode_envelope,
+    sqs_message_source,
     PayloadRetriever,
 };
 
@@ -146,7 +145,7 @@ where
 }
 
 #[async_trait]
-impl<S, SInit, D, E, DecoderErrorT> PayloadRetriever<E>
+impl<S, SInit, D, E, DecoderErrorT> PayloadStore
     for S3PayloadRetriever<S, SInit, D, E, DecoderErrorT>
 where
     S: S3 + Clone + Send + Sync + 'static,
@@ -159,10 +158,7 @@ where
     type Error = S3PayloadRetrieverError<DecoderErrorT>;
 
     #[tracing::instrument(skip(self, msg))]
-    async fn retrieve_event(
-        &mut self,
-        msg: &Self::Message,
-    ) -> Result<Option<(Metadata, E)>, Self::Error> {
+    async fn load_payload(&mut self, msg: &Self::Message) -> Result<Option<Vec<u8>>, Self::Error> {
         let body = msg.body.as_ref().unwrap();
         debug!("Got body from message: {}", body);
         let event: serde_json::Value = serde_json::from_str(body)?;
@@ -248,24 +244,42 @@ where
 
         debug!("Read s3 payload body");
 
-        let envelope: rust_proto::pipeline::Envelope = prost::Message::decode(&body[..])?;
-        let meta = envelope
-            .metadata
-            .expect("Metadata must be set at the front of the pipeline");
-        let body = envelope.inner_message;
+        Ok(Some(body))
+    }
+}
 
-        let (decoded, ms) = time_it(|| self.decoder.decode(body));
+#[async_trait]
+impl<S, SInit, D, E, DecoderErrorT> PayloadRetriever<E>
+    for S3PayloadRetriever<S, SInit, D, E, DecoderErrorT>
+where
+    S: S3 + Clone + Send + Sync + 'static,
+    SInit: (Fn(String) -> S) + Clone + Send + Sync + 'static,
+    D: PayloadDecoder<E, DecoderError = DecoderErrorT> + Clone + Send + 'static,
+    E: Send + 'static,
+    DecoderErrorT: CheckedError + Send + 'static,
+{
+    type Message = SqsMessage;
+    type Error = S3PayloadRetrieverError<DecoderErrorT>;
 
-        self.metric_reporter
-            .histogram_with_units(
-                "s3_retriever.decoded.micros",
-                ms.as_micros() as f64,
-                HistogramUnit::Micros,
-                &[tag("success", true)][..],
-            )
-            .unwrap_or_else(
-                |e| error!(message="failed to report s3_retriever.decoded.micros", error=?e),
-            );
+    #[tracing::instrument(skip(self, msg))]
+    async fn retrieve_event(
+        &mut self,
+        msg: &Self::Message,
+    ) -> Result<Option<(Metadata, E)>, Self::Error> {
+        let body = match self.load_payload(msg).await? {
+            Some(body) => body,
+            None => return Ok(None),
+        };
+
+        let (mut meta, decoded) = decode_envelope(
+            &mut self.decoder,
+            &body,
+            "s3_retriever.decoded.micros",
+            &mut self.metric_reporter,
+        )?;
+        if let Some(retry_count) = sqs_message_source::retry_count(msg) {
+            meta.retry_count = retry_count;
+        }
 
         Ok(Some((meta, decoded?)))
     }
@@ -0,0 +1,130 @@
+use std::{
+    io::Stdout,
+    marker::PhantomData,
+};
+
+use async_trait::async_trait;
+use grapl_observe::metric_reporter::MetricReporter;
+use rust_proto::pipeline::Metadata;
+
+use crate::{
+    errors::{
+        CheckedError,
+        Recoverable,
+    },
+    event_decoder::PayloadDecoder,
+    payload_store::PayloadStore,
+    retriever::event_retriever::decode_envelope,
+    PayloadRetriever,
+};
+
+/// A `PayloadRetriever` for any `PayloadStore`, which decodes the stored envelopes with `D`.
+pub struct PayloadStoreRetriever<StoreT, D, E>
+where
+    StoreT: PayloadStore,
+    D: PayloadDecoder<E>,
+{
+    store: StoreT,
+    decoder: D,
+    metric_reporter: MetricReporter<Stdout>,
+    phantom: PhantomData<E>,
+}
+
+impl<StoreT, D, E> Clone for PayloadStoreRetriever<StoreT, D, E>
+where
+    StoreT: PayloadStore + Clone,
+    D: PayloadDecoder<E> + Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+            decoder: self.decoder.clone(),
+            metric_reporter: self.metric_reporter.clone(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<StoreT, D, E> PayloadStoreRetriever<StoreT, D, E>
+where
+    StoreT: PayloadStore,
+    D: PayloadDecoder<E>,
+{
+    pub fn new(store: StoreT, decoder: D, metric_reporter: MetricReporter<Stdout>) -> Self {
+        Self {
+            store,
+            decoder,
+            metric_reporter,
+            phantom: PhantomData,
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PayloadStoreRetrieverError<StoreErrorT, DecoderErrorT>
+where
+    StoreErrorT: CheckedError + 'static,
+    DecoderErrorT: CheckedError + 'static,
+{
+    #[error("StoreError: {0}")]
+    StoreError(StoreErrorT),
+    #[error("Decode error")]
+    DecodeError(DecoderErrorT),
+    #[error("EnvelopeDecode error")]
+    EnvelopeDecode(#[from] prost::DecodeError),
+}
+
+impl<StoreErrorT, DecoderErrorT> CheckedError
+    for PayloadStoreRetrieverError<StoreErrorT, DecoderErrorT>
+where
+    StoreErrorT: CheckedError + 'static,
+    DecoderErrorT: CheckedError + 'static,
+{
+    fn error_type(&self) -> Recoverable {
+        match self {
+            Self::StoreError(e) => e.error_type(),
+            Self::DecodeError(_) => Recoverable::Persistent,
+            Self::EnvelopeDecode(_) => Recoverable::Persistent,
+        }
+    }
+}
+
+#[async_trait]
+impl<StoreT, D, E> PayloadRetriever<E> for PayloadStoreRetriever<StoreT, D, E>
+where
+    StoreT: PayloadStore + Send,
+    StoreT::Message: Sync,
+    StoreT::Error: Send + 'static,
+    D: PayloadDecoder<E> + Send,
+    D::DecoderError: Send + 'static,
+    E: Send + 'static,
+{
+    type Message = StoreT::Message;
+    type Error = PayloadStoreRetrieverError<StoreT::Error, D::DecoderError>;
+
+    #[tracing::instrument(skip(self, msg))]
+    async fn retrieve_event(
+        &mut self,
+        msg: &Self::Message,
+    ) -> Result<Option<(Metadata, E)>, Self::Error> {
+        let payload = match self
+            .store
+            .load_payload(msg)
+            .await
+            .map_err(PayloadStoreRetrieverError::StoreError)?
+        {
+            Some(payload) => payload,
+            None => return Ok(None),
+        };
+
+        let (meta, decoded) = decode_envelope(
+            &mut self.decoder,
+            &payload,
+            "payload_retriever.decoded.micros",
+            &mut self.metric_reporter,
+        )?;
+        let decoded = decoded.map_err(PayloadStoreRetrieverError::DecodeError)?;
+
+        Ok(Some((meta, decoded)))
+    }
+}
@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+/// How a message that failed transiently is retried. Each retry increments the
+/// `retry_count` in the message's `Metadata`, and once it reaches `max_retries`
+/// the next failure moves the message to the dead letter queue.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    /// The delay before the first retry, which doubles with each further retry
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            initial_delay: Duration::from_secs(1),
+            // The longest delay SQS supports
+            max_delay: Duration::from_secs(15 * 60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The delay before the message is delivered for its `retry_count`th retry
+    pub fn delay(&self, retry_count: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(retry_count.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.initial_delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+
+    pub fn exhausted(&self, retry_count: u32) -> bool {
+        retry_count >= self.max_retries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay_doubles_up_to_max() {
+        let policy = RetryPolicy {
+            max_retries: 100,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+        };
+
+        let delays: Vec<_> = (0..=8).map(|n| policy.delay(n).as_secs()).collect();
+        assert_eq!(delays, vec![1, 1, 2, 4, 8, 16, 32, 60, 60]);
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_exhausted() {
+        let policy = RetryPolicy::default();
+        assert!(!policy.exhausted(4));
+        assert!(policy.exhausted(5));
+    }
+}
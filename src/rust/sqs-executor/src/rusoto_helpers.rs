@@ -1,4 +1,7 @@
-use std::io::Stdout;
+use std::{
+    collections::HashMap,
+    io::Stdout,
+};
 
 use grapl_observe::{
     metric_reporter::{
@@ -16,6 +19,7 @@ use rusoto_sqs::{
     DeleteMessageError as InnerDeleteMessageError,
     DeleteMessageRequest,
     Message as SqsMessage,
+    MessageAttributeValue,
     ReceiveMessageError as InnerReceiveMessageError,
     ReceiveMessageRequest,
     SendMessageRequest,
@@ -34,9 +38,15 @@ use tracing::{
     Instrument,
 };
 
-use crate::errors::{
-    CheckedError,
-    Recoverable,
+use crate::{
+    errors::{
+        CheckedError,
+        Recoverable,
+    },
+    failure_metadata::{
+        FailureMetadata,
+        FAILURE_METADATA_KEY,
+    },
 };
 
 impl CheckedError for InnerDeleteMessageError {
@@ -90,6 +100,7 @@ where
         queue_url,
         visibility_timeout: Some(30),
         wait_time_seconds: Some(WAIT_TIME_SECONDS),
+        message_attribute_names: Some(vec!["All".to_owned()]),
         ..Default::default()
     });
 
@@ -163,6 +174,26 @@ pub fn send_message<SqsT>(
     queue_url: String,
     message_body: String,
     sqs_client: SqsT,
+    metric_reporter: MetricReporter<Stdout>,
+) -> JoinHandle<Result<(), SendMessageError>>
+where
+    SqsT: Sqs + Clone + Send + Sync + 'static,
+{
+    send_message_request(
+        SendMessageRequest {
+            queue_url,
+            message_body,
+            ..Default::default()
+        },
+        sqs_client,
+        metric_reporter,
+    )
+}
+
+/// Like `send_message`, for requests that set attributes or a delay
+pub fn send_message_request<SqsT>(
+    request: SendMessageRequest,
+    sqs_client: SqsT,
     mut metric_reporter: MetricReporter<Stdout>,
 ) -> JoinHandle<Result<(), SendMessageError>>
 where
@@ -170,14 +201,11 @@ where
 {
     tokio::task::spawn(async move {
         let metric_reporter = &mut metric_reporter;
+        let queue_url = request.queue_url.clone();
         let mut last_err = None;
         for i in 0..5u64 {
             let sqs_client = sqs_client.clone();
-            let res = sqs_client.send_message(SendMessageRequest {
-                queue_url: queue_url.clone(),
-                message_body: message_body.clone(),
-                ..Default::default()
-            });
+            let res = sqs_client.send_message(request.clone());
 
             let res = tokio::time::timeout(std::time::Duration::from_secs(21), res)
                 .timed()
@@ -315,9 +343,18 @@ pub enum MoveToDeadLetterError {
     JoinError(#[from] JoinError),
 }
 
+pub fn string_attribute(value: impl Into<String>) -> MessageAttributeValue {
+    MessageAttributeValue {
+        data_type: "String".to_owned(),
+        string_value: Some(value.into()),
+        ..Default::default()
+    }
+}
+
 pub async fn move_to_dead_letter<SqsT>(
     sqs_client: SqsT,
     message: &impl serde::Serialize,
+    failure: &FailureMetadata,
     publish_to_queue: String,
     delete_from_queue: String,
     receipt_handle: String,
@@ -333,9 +370,18 @@ where
     );
     let message = serde_json::to_string(&message);
     let message = message?;
-    send_message(
-        publish_to_queue,
-        message,
+    let mut message_attributes = HashMap::new();
+    message_attributes.insert(
+        FAILURE_METADATA_KEY.to_owned(),
+        string_attribute(failure.to_json()),
+    );
+    send_message_request(
+        SendMessageRequest {
+            queue_url: publish_to_queue,
+            message_body: message,
+            message_attributes: Some(message_attributes),
+            ..Default::default()
+        },
         sqs_client.clone(),
         metric_reporter.clone(),
     )
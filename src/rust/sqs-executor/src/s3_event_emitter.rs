@@ -26,7 +26,7 @@ use crate::{
         CheckedError,
         Recoverable,
     },
-    event_emitter::EventEmitter,
+    event_emitter::Emitter,
 };
 
 #[derive(thiserror::Error, Debug)]
@@ -85,7 +85,7 @@ where
 }
 
 #[async_trait]
-impl<S, F> EventEmitter for S3EventEmitter<S, F>
+impl<S, F> Emitter for S3EventEmitter<S, F>
 where
     S: Clone + S3 + Send + Sync,
     F: Clone + Fn(&[u8]) -> String + Send + Sync + 'static,
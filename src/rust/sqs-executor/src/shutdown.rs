@@ -0,0 +1,128 @@
+//! Stopping the executor gracefully, such as when a deploy replaces a service.
+//!
+//! Once shutdown is triggered, the executor stops receiving messages and releases the ones
+//! it's received but not started on, so that they're redelivered right away rather than
+//! once their visibility timeout expires. Messages already being processed have until the
+//! deadline to finish, after which they're released too. The emitters are then flushed, and
+//! `process_loop` returns.
+
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+/// Whether to shut down, shared by everything that needs to know
+#[derive(Clone, Debug)]
+pub struct Shutdown {
+    triggered: watch::Receiver<bool>,
+    deadline: Duration,
+}
+
+/// Triggers the `Shutdown` it was created with
+#[derive(Debug)]
+pub struct ShutdownTrigger(watch::Sender<bool>);
+
+impl ShutdownTrigger {
+    pub fn trigger(&self) {
+        // Only fails if every `Shutdown` is gone, in which case nothing's left to stop
+        let _ = self.0.send(true);
+    }
+}
+
+impl Shutdown {
+    /// A shutdown triggered by the `ShutdownTrigger`. If the trigger is dropped without
+    /// being triggered, it never is.
+    pub fn new(deadline: Duration) -> (ShutdownTrigger, Self) {
+        let (sender, triggered) = watch::channel(false);
+        (
+            ShutdownTrigger(sender),
+            Self {
+                triggered,
+                deadline,
+            },
+        )
+    }
+
+    /// A shutdown triggered by SIGTERM, or SIGINT. Must be called within a tokio runtime.
+    pub fn on_signal(deadline: Duration) -> Self {
+        let (trigger, shutdown) = Self::new(deadline);
+        tokio::spawn(async move {
+            wait_for_signal().await;
+            tracing::info!(
+                deadline_ms = deadline.as_millis() as u64,
+                "Received shutdown signal, draining"
+            );
+            trigger.trigger();
+        });
+        shutdown
+    }
+
+    /// How long messages being processed have to finish once shutdown is triggered
+    pub fn deadline(&self) -> Duration {
+        self.deadline
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.triggered.borrow()
+    }
+
+    /// Resolves once shutdown is triggered
+    pub async fn triggered(&mut self) {
+        while !self.is_triggered() {
+            if self.triggered.changed().await.is_err() {
+                // The trigger is gone without having been triggered
+                futures::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+async fn wait_for_signal() {
+    use tokio::signal::unix::{
+        signal,
+        SignalKind,
+    };
+
+    let mut sigterm = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+    let mut sigint = signal(SignalKind::interrupt()).expect("Failed to listen for SIGINT");
+    tokio::select! {
+        _ = sigterm.recv() => {}
+        _ = sigint.recv() => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_triggered_resolves_once_triggered() {
+        let (trigger, mut shutdown) = Shutdown::new(Duration::from_secs(1));
+        let waiting = shutdown.clone();
+        assert!(!waiting.is_triggered());
+
+        let triggered = shutdown.triggered();
+        tokio::pin!(triggered);
+        assert!(futures::poll!(&mut triggered).is_pending());
+
+        trigger.trigger();
+        triggered.await;
+        assert!(waiting.is_triggered());
+
+        // Still triggered, once the trigger is gone
+        drop(trigger);
+        let mut shutdown = waiting;
+        shutdown.triggered().await;
+    }
+
+    #[tokio::test]
+    async fn test_never_triggered_without_trigger() {
+        let (trigger, mut shutdown) = Shutdown::new(Duration::from_secs(1));
+        drop(trigger);
+        {
+            let triggered = shutdown.triggered();
+            tokio::pin!(triggered);
+            assert!(futures::poll!(&mut triggered).is_pending());
+        }
+        assert!(!shutdown.is_triggered());
+    }
+}
@@ -0,0 +1,267 @@
+use std::{
+    io::Stdout,
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use grapl_observe::metric_reporter::MetricReporter;
+use rusoto_core::RusotoError;
+use rusoto_sqs::{
+    ChangeMessageVisibilityError,
+    ChangeMessageVisibilityRequest,
+    Message as SqsMessage,
+    MessageAttributeValue,
+    ReceiveMessageError,
+    SendMessageRequest,
+    Sqs,
+};
+use rust_proto::pipeline::Metadata;
+use tracing::error;
+
+use crate::{
+    errors::{
+        CheckedError,
+        Recoverable,
+    },
+    failure_metadata::FailureMetadata,
+    message_source::MessageSource,
+    rusoto_helpers::{
+        self,
+        MoveToDeadLetterError,
+        SendMessageError,
+    },
+    sqs_timeout_manager::{
+        keep_alive,
+        Sender,
+    },
+};
+
+/// The message attribute that carries a re-enqueued message's `retry_count`. Payloads live
+/// in S3, so rather than rewriting the payload's `Envelope`, the count travels with the
+/// message and overrides the one in the `Envelope` when the payload is retrieved.
+pub const RETRY_COUNT_ATTRIBUTE: &str = "retry_count";
+/// SQS can't delay a message for longer than this
+const MAX_DELAY: Duration = Duration::from_secs(15 * 60);
+
+#[derive(thiserror::Error, Debug)]
+pub enum SqsMessageSourceError {
+    #[error("ReceiveMessageError: {0}")]
+    ReceiveMessage(#[from] RusotoError<ReceiveMessageError>),
+    #[error("ChangeMessageVisibilityError: {0}")]
+    ChangeMessageVisibility(#[from] RusotoError<ChangeMessageVisibilityError>),
+    #[error("SendMessageError: {0}")]
+    SendMessage(#[from] SendMessageError),
+    #[error("JoinError: {0}")]
+    Join(#[from] tokio::task::JoinError),
+    #[error("MoveToDeadLetterError: {0}")]
+    MoveToDeadLetter(#[from] MoveToDeadLetterError),
+}
+
+impl CheckedError for SqsMessageSourceError {
+    fn error_type(&self) -> Recoverable {
+        Recoverable::Transient
+    }
+}
+
+/// Receives S3 notifications from an SQS queue, which point at the payloads in S3.
+#[derive(Clone)]
+pub struct SqsMessageSource<SqsT>
+where
+    SqsT: Sqs + Clone + Send + Sync + 'static,
+{
+    sqs_client: SqsT,
+    queue_url: String,
+    dead_letter_queue_url: String,
+    metric_reporter: MetricReporter<Stdout>,
+}
+
+impl<SqsT> SqsMessageSource<SqsT>
+where
+    SqsT: Sqs + Clone + Send + Sync + 'static,
+{
+    pub fn new(
+        sqs_client: SqsT,
+        queue_url: impl Into<String>,
+        dead_letter_queue_url: impl Into<String>,
+        metric_reporter: MetricReporter<Stdout>,
+    ) -> Self {
+        Self {
+            sqs_client,
+            queue_url: queue_url.into(),
+            dead_letter_queue_url: dead_letter_queue_url.into(),
+            metric_reporter,
+        }
+    }
+}
+
+#[async_trait]
+impl<SqsT> MessageSource for SqsMessageSource<SqsT>
+where
+    SqsT: Sqs + Clone + Send + Sync + 'static,
+{
+    type Message = SqsMessage;
+    type KeepAlive = Sender;
+    type Error = SqsMessageSourceError;
+
+    async fn receive_messages(&mut self) -> Result<Vec<Self::Message>, Self::Error> {
+        let messages = rusoto_helpers::get_message(
+            self.queue_url.clone(),
+            self.sqs_client.clone(),
+            &mut self.metric_reporter,
+        )
+        .await?;
+        Ok(messages)
+    }
+
+    fn message_id(&self, message: &Self::Message) -> String {
+        message.message_id.clone().expect("missing message_id")
+    }
+
+    fn keep_alive(&self, message: &Self::Message) -> Self::KeepAlive {
+        keep_alive(
+            self.sqs_client.clone(),
+            receipt_handle(message),
+            self.message_id(message),
+            self.queue_url.clone(),
+            30,
+        )
+    }
+
+    fn payload_size(&self, message: &Self::Message) -> Option<usize> {
+        let event: serde_json::Value = serde_json::from_str(message.body.as_ref()?).ok()?;
+        event["Records"][0]["s3"]["object"]["size"]
+            .as_u64()
+            .map(|size| size as usize)
+    }
+
+    async fn ack(&mut self, message: &Self::Message) {
+        rusoto_helpers::delete_message(
+            self.sqs_client.clone(),
+            self.queue_url.clone(),
+            receipt_handle(message),
+            self.metric_reporter.clone(),
+        )
+        .await
+        .unwrap_or_else(|e| error!(message="delete_message failed", error=?e));
+    }
+
+    async fn retry(
+        &mut self,
+        message: &Self::Message,
+        metadata: Option<&Metadata>,
+        delay: Duration,
+    ) -> Result<(), Self::Error> {
+        let delay = delay.min(MAX_DELAY).as_secs() as i64;
+        let metadata = match metadata {
+            Some(metadata) => metadata,
+            None => {
+                // SQS redelivers the message on its own once its visibility timeout expires
+                self.sqs_client
+                    .change_message_visibility(ChangeMessageVisibilityRequest {
+                        queue_url: self.queue_url.clone(),
+                        receipt_handle: receipt_handle(message),
+                        visibility_timeout: delay,
+                    })
+                    .await?;
+                return Ok(());
+            }
+        };
+
+        let mut message_attributes = message.message_attributes.clone().unwrap_or_default();
+        message_attributes.insert(
+            RETRY_COUNT_ATTRIBUTE.to_owned(),
+            MessageAttributeValue {
+                data_type: "Number".to_owned(),
+                string_value: Some(metadata.retry_count.to_string()),
+                ..Default::default()
+            },
+        );
+        rusoto_helpers::send_message_request(
+            SendMessageRequest {
+                queue_url: self.queue_url.clone(),
+                message_body: message.body.clone().expect("missing body"),
+                message_attributes: Some(message_attributes),
+                delay_seconds: Some(delay),
+                ..Default::default()
+            },
+            self.sqs_client.clone(),
+            self.metric_reporter.clone(),
+        )
+        .await??;
+        self.ack(message).await;
+        Ok(())
+    }
+
+    async fn release(&mut self, message: &Self::Message) -> Result<(), Self::Error> {
+        self.sqs_client
+            .change_message_visibility(ChangeMessageVisibilityRequest {
+                queue_url: self.queue_url.clone(),
+                receipt_handle: receipt_handle(message),
+                visibility_timeout: 0,
+            })
+            .await?;
+        Ok(())
+    }
+
+    async fn dead_letter(
+        &mut self,
+        message: &Self::Message,
+        failure: &FailureMetadata,
+    ) -> Result<(), Self::Error> {
+        rusoto_helpers::move_to_dead_letter(
+            self.sqs_client.clone(),
+            message.body.as_ref().expect("missing body"),
+            failure,
+            self.dead_letter_queue_url.clone(),
+            self.queue_url.clone(),
+            receipt_handle(message),
+            self.metric_reporter.clone(),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+/// The `RETRY_COUNT_ATTRIBUTE` of a message that was re-enqueued by `SqsMessageSource::retry`
+pub fn retry_count(message: &SqsMessage) -> Option<u32> {
+    message
+        .message_attributes
+        .as_ref()?
+        .get(RETRY_COUNT_ATTRIBUTE)?
+        .string_value
+        .as_ref()?
+        .parse()
+        .ok()
+}
+
+fn receipt_handle(message: &SqsMessage) -> String {
+    message
+        .receipt_handle
+        .clone()
+        .expect("missing receipt_handle")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_retry_count_attribute() {
+        let mut message = SqsMessage::default();
+        assert_eq!(retry_count(&message), None);
+
+        let mut message_attributes = HashMap::new();
+        message_attributes.insert(
+            RETRY_COUNT_ATTRIBUTE.to_owned(),
+            MessageAttributeValue {
+                data_type: "Number".to_owned(),
+                string_value: Some("3".to_owned()),
+                ..Default::default()
+            },
+        );
+        message.message_attributes = Some(message_attributes);
+        assert_eq!(retry_count(&message), Some(3));
+    }
+}
@@ -0,0 +1,79 @@
+//! Following a payload through the pipeline.
+//!
+//! Every `Envelope`'s `Metadata` carries the id of the trace it belongs to. Payloads
+//! uploaded without one start a new trace at the generator that ingests them. Every stage
+//! records the id on the span it processes the message in, and copies it into the
+//! envelopes it emits.
+//!
+//! With the `otel` feature, that span is also made the child of a remote span in the same
+//! trace, so that each stage's spans are exported under one trace, whichever service they
+//! came from.
+
+use rust_proto::pipeline::Metadata;
+use uuid::Uuid;
+
+/// The trace the message belongs to. A message that doesn't belong to one yet, because
+/// it's just been ingested, starts a new one.
+pub fn ensure_trace_id(meta: &mut Metadata) -> Uuid {
+    let trace_id = meta
+        .trace_id
+        .clone()
+        .map(Uuid::from)
+        .filter(|trace_id| !trace_id.is_nil());
+    match trace_id {
+        Some(trace_id) => trace_id,
+        None => {
+            let trace_id = Uuid::new_v4();
+            meta.trace_id = Some(trace_id.into());
+            trace_id
+        }
+    }
+}
+
+/// Makes `span` part of the trace, for spans exported with OpenTelemetry. Spans created
+/// within `span` after this are part of the trace too.
+#[cfg(feature = "otel")]
+pub fn join_trace(span: &tracing::Span, trace_id: Uuid) {
+    use opentelemetry::trace::{
+        SpanContext,
+        SpanId,
+        TraceContextExt,
+        TraceFlags,
+        TraceId,
+        TraceState,
+    };
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    // The span of the stage before isn't passed along, so every stage's span has the same
+    // stand-in parent, derived from the trace id
+    let parent = SpanContext::new(
+        TraceId::from_u128(trace_id.as_u128()),
+        SpanId::from_u64((trace_id.as_u128() as u64).max(1)),
+        TraceFlags::SAMPLED,
+        true,
+        TraceState::default(),
+    );
+    span.set_parent(opentelemetry::Context::new().with_remote_span_context(parent));
+}
+
+#[cfg(not(feature = "otel"))]
+pub fn join_trace(_span: &tracing::Span, _trace_id: Uuid) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_starts_a_trace_only_when_there_is_none() {
+        let mut meta = Metadata::default();
+        let trace_id = ensure_trace_id(&mut meta);
+        assert!(!trace_id.is_nil());
+        assert_eq!(ensure_trace_id(&mut meta), trace_id);
+
+        let mut nil = Metadata {
+            trace_id: Some(Uuid::nil().into()),
+            ..Default::default()
+        };
+        assert_ne!(ensure_trace_id(&mut nil), Uuid::nil());
+    }
+}
@@ -1,7 +1,13 @@
 #![cfg(feature = "integration")]
 
 use grapl_observe::metric_reporter::MetricReporter;
-use sqs_executor::cache::Cache;
+use sqs_executor::{
+    cache::Cache,
+    redis_cache::{
+        RedisCache,
+        RedisCacheConfig,
+    },
+};
 
 #[tokio::test]
 async fn redis_cache() {
@@ -17,8 +23,11 @@ async fn redis_cache() {
 
     let redis_endpoint = std::env::var("REDIS_ENDPOINT").expect("REDIS_ENDPOINT");
 
-    let mut cache = sqs_executor::redis_cache::RedisCache::with_lru_capacity(
-        LRU_SIZE,
+    let mut config = RedisCacheConfig::new("sqs-executor-tests");
+    config.lru_capacity = LRU_SIZE;
+
+    let mut cache = RedisCache::new(
+        config,
         redis_endpoint,
         MetricReporter::<std::io::Stdout>::new("redis_cache"),
     )
@@ -38,3 +47,30 @@ async fn redis_cache() {
 
     assert!(!cache.all_exist(&["non-existent-key"]).await);
 }
+
+#[tokio::test]
+async fn redis_cache_failures() {
+    let redis_endpoint = std::env::var("REDIS_ENDPOINT").expect("REDIS_ENDPOINT");
+
+    let mut config = RedisCacheConfig::new("sqs-executor-tests");
+    config.max_failures = Some(2);
+
+    let mut cache = RedisCache::new(
+        config,
+        redis_endpoint,
+        MetricReporter::<std::io::Stdout>::new("redis_cache"),
+    )
+    .await
+    .expect("redis client");
+
+    let cacheable = format!(
+        "sqs-executor::tests::redis_cache_failures-{}",
+        uuid::Uuid::new_v4()
+    );
+
+    assert!(!cache.failed_too_often(&cacheable).await);
+    assert_eq!(cache.record_failure(cacheable.clone()).await.unwrap(), 1);
+    assert!(!cache.failed_too_often(&cacheable).await);
+    assert_eq!(cache.record_failure(cacheable.clone()).await.unwrap(), 2);
+    assert!(cache.failed_too_often(&cacheable).await);
+}